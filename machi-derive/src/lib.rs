@@ -208,6 +208,25 @@
 /// }
 /// ```
 ///
+/// ## Run Context
+///
+/// A parameter of type `&ToolContext` is not exposed to the model; instead it
+/// receives the per-call context (run state, dependencies, call ID). The
+/// generated `call` passes an empty context.
+///
+/// ```rust,ignore
+/// use machi::prelude::*;
+///
+/// /// Look up an order for the current tenant.
+/// #[tool]
+/// async fn get_order(ctx: &ToolContext, order_id: String) -> ToolResult<String> {
+///     let tenant = ctx
+///         .dependency::<TenantId>()
+///         .ok_or_else(|| ToolError::execution("no tenant"))?;
+///     Ok(format!("{}/{order_id}", tenant.0))
+/// }
+/// ```
+///
 /// ## Synchronous Functions
 ///
 /// Non-async functions are also supported:
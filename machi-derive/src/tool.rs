@@ -475,6 +475,20 @@ fn extract_result_types(return_type: &ReturnType) -> (TokenStream2, TokenStream2
     (quote!(()), quote!(::machi::ToolError))
 }
 
+/// Returns `true` if the argument is a `&ToolContext` parameter.
+fn is_context_param(arg: &FnArg) -> bool {
+    let FnArg::Typed(pat_type) = arg else {
+        return false;
+    };
+    let Type::Reference(reference) = &*pat_type.ty else {
+        return false;
+    };
+    matches!(
+        &*reference.elem,
+        Type::Path(path) if path.path.segments.last().is_some_and(|s| s.ident == "ToolContext")
+    )
+}
+
 /// Parameter information extracted from function signature.
 struct ParamInfo {
     name: Ident,
@@ -486,6 +500,9 @@ struct ParamInfo {
 
 impl ParamInfo {
     fn from_fn_arg(arg: &FnArg, macro_args: &ToolArgs, doc_info: &DocInfo) -> Option<Self> {
+        if is_context_param(arg) {
+            return None;
+        }
         let FnArg::Typed(pat_type) = arg else {
             return None;
         };
@@ -584,14 +601,49 @@ pub fn tool_impl(args: TokenStream, input: TokenStream) -> TokenStream {
         .map(|p| p.name.to_string())
         .collect();
 
+    // Arguments in signature order; a `&ToolContext` parameter receives `ctx`.
+    let uses_context = input_fn.sig.inputs.iter().any(is_context_param);
+    let call_args: Vec<TokenStream2> = input_fn
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|arg| {
+            if is_context_param(arg) {
+                return Some(quote!(ctx));
+            }
+            ParamInfo::from_fn_arg(arg, &macro_args, &doc_info).map(|p| {
+                let name = p.name;
+                quote!(args.#name)
+            })
+        })
+        .collect();
+    let invoke = if is_async {
+        quote!(#fn_name(#(#call_args,)*).await)
+    } else {
+        quote!(#fn_name(#(#call_args,)*))
+    };
+
     // Generate call implementation
-    let call_impl = if is_async {
+    let call_impl = if uses_context {
         quote! {
             async fn call(
                 &self,
                 args: Self::Args,
             ) -> ::std::result::Result<Self::Output, Self::Error> {
-                #fn_name(#(args.#param_names,)*).await
+                ::machi::tool::Tool::call_with_context(
+                    self,
+                    args,
+                    &::machi::tool::ToolContext::default(),
+                )
+                .await
+            }
+
+            async fn call_with_context(
+                &self,
+                args: Self::Args,
+                ctx: &::machi::tool::ToolContext,
+            ) -> ::std::result::Result<Self::Output, Self::Error> {
+                #invoke
             }
         }
     } else {
@@ -600,7 +652,7 @@ async fn call(
                 &self,
                 args: Self::Args,
             ) -> ::std::result::Result<Self::Output, Self::Error> {
-                #fn_name(#(args.#param_names,)*)
+                #invoke
             }
         }
     };
@@ -35,9 +35,15 @@
 use crate::callback::SharedAgentHooks;
 use crate::chat::{ResponseFormat, SharedChatProvider};
 use crate::error::Result;
-use crate::guardrail::{InputGuardrail, OutputGuardrail};
+use crate::guardrail::{
+    InputGuardrail, OutputGuardrail, StreamGuardrail, ToolInputGuardrail, ToolOutputGuardrail,
+};
+use crate::policy::ToolRules;
 use crate::tool::{BoxedTool, ToolDefinition, ToolExecutionPolicy};
+use crate::workflow::{SharedWorkflow, Workflow};
 
+use super::human_input::{AskUserTool, SharedHumanInputHandler};
+use super::instructions::{InstructionProvider, Instructions};
 use super::result::{RunConfig, RunEvent, RunResult, UserInput};
 
 /// Schema specification for structured agent output.
@@ -177,47 +183,6 @@ pub fn from_type<T: schemars::JsonSchema>() -> Self {
     }
 }
 
-/// Instructions that guide the agent's behavior.
-///
-/// Can be either a static string set at construction time, or a dynamic
-/// closure that generates instructions based on runtime context.
-#[derive(Clone)]
-pub enum Instructions {
-    /// Static instruction string.
-    Static(String),
-    /// Dynamic instruction generator.
-    ///
-    /// Receives the current agent name and returns instructions. Wrapped
-    /// in `Arc` for cheap cloning and `Send + Sync` safety.
-    Dynamic(Arc<dyn Fn(&str) -> String + Send + Sync>),
-}
-
-impl Instructions {
-    /// Resolve the instructions to a string for the given agent name.
-    #[must_use]
-    pub fn resolve(&self, agent_name: &str) -> String {
-        match self {
-            Self::Static(s) => s.clone(),
-            Self::Dynamic(f) => f(agent_name),
-        }
-    }
-}
-
-impl fmt::Debug for Instructions {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Static(s) => f.debug_tuple("Static").field(s).finish(),
-            Self::Dynamic(_) => f.debug_tuple("Dynamic").field(&"<closure>").finish(),
-        }
-    }
-}
-
-impl<S: Into<String>> From<S> for Instructions {
-    fn from(s: S) -> Self {
-        Self::Static(s.into())
-    }
-}
-
 /// A pure configuration struct defining an AI agent.
 ///
 /// `Agent` contains no execution logic. It describes *what* the agent is and
@@ -241,6 +206,9 @@ pub struct Agent {
     /// System-level instructions (prompt) for the agent.
     pub(crate) instructions: Instructions,
 
+    /// Whether instructions are re-resolved before every step.
+    pub(crate) refresh_instructions: bool,
+
     /// LLM model identifier to use for this agent.
     pub(crate) model: String,
 
@@ -259,6 +227,9 @@ pub struct Agent {
     /// enabling parallel execution via `futures::future::join_all`.
     pub(crate) managed_agents: Vec<Self>,
 
+    /// Workflows that can be dispatched as tools, like managed agents.
+    pub(crate) managed_workflows: Vec<SharedWorkflow>,
+
     /// Optional per-agent lifecycle hooks.
     pub(crate) hooks: Option<SharedAgentHooks>,
 
@@ -276,6 +247,9 @@ pub struct Agent {
     /// Tools not listed here default to [`ToolExecutionPolicy::Auto`].
     pub(crate) tool_policies: HashMap<String, ToolExecutionPolicy>,
 
+    /// Argument-aware policy rules, checked before `tool_policies`.
+    pub(crate) tool_rules: ToolRules,
+
     /// Optional schema for structured JSON output.
     ///
     /// When set, the Runner constrains LLM responses to produce valid JSON
@@ -299,6 +273,19 @@ pub struct Agent {
     /// If any guardrail's tripwire is triggered, the output is discarded and
     /// [`Error::OutputGuardrailTriggered`](crate::Error::OutputGuardrailTriggered) is returned.
     pub(crate) output_guardrails: Vec<OutputGuardrail>,
+
+    /// How many times output guardrails may send the agent back for another
+    /// answer before the run fails.
+    pub(crate) max_output_retries: usize,
+
+    /// Guardrails that check the model's text while it streams.
+    pub(crate) stream_guardrails: Vec<StreamGuardrail>,
+
+    /// Guardrails that check tool arguments before each tool call.
+    pub(crate) tool_input_guardrails: Vec<ToolInputGuardrail>,
+
+    /// Guardrails that check tool results before they are sent to the LLM.
+    pub(crate) tool_output_guardrails: Vec<ToolOutputGuardrail>,
 }
 
 impl fmt::Debug for Agent {
@@ -306,6 +293,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Agent")
             .field("name", &self.name)
             .field("instructions", &self.instructions)
+            .field("refresh_instructions", &self.refresh_instructions)
             .field("model", &self.model)
             .field("provider", &self.provider.is_some())
             .field(
@@ -320,16 +308,29 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                     .map(|a| &a.name)
                     .collect::<Vec<_>>(),
             )
+            .field(
+                "managed_workflows",
+                &self
+                    .managed_workflows
+                    .iter()
+                    .map(|w| w.name())
+                    .collect::<Vec<_>>(),
+            )
             .field("hooks", &self.hooks.is_some())
             .field("max_steps", &self.max_steps)
             .field("description", &self.description)
             .field("tool_policies", &self.tool_policies)
+            .field("tool_rules", &self.tool_rules)
             .field(
                 "output_schema",
                 &self.output_schema.as_ref().map(OutputSchema::name),
             )
             .field("input_guardrails", &self.input_guardrails)
             .field("output_guardrails", &self.output_guardrails)
+            .field("max_output_retries", &self.max_output_retries)
+            .field("stream_guardrails", &self.stream_guardrails)
+            .field("tool_input_guardrails", &self.tool_input_guardrails)
+            .field("tool_output_guardrails", &self.tool_output_guardrails)
             .finish()
     }
 }
@@ -338,6 +339,9 @@ impl Agent {
     /// Default maximum number of reasoning steps.
     pub const DEFAULT_MAX_STEPS: usize = 10;
 
+    /// Default number of output guardrail retries.
+    pub const DEFAULT_MAX_OUTPUT_RETRIES: usize = 2;
+
     /// Create a new agent with the given name and sensible defaults.
     #[must_use]
     pub fn new(name: impl Into<String>) -> Self {
@@ -346,16 +350,23 @@ pub fn new(name: impl Into<String>) -> Self {
             description: format!("Agent: {name}"),
             name,
             instructions: Instructions::Static(String::new()),
+            refresh_instructions: false,
             model: String::new(),
             provider: None,
             tools: Vec::new(),
             managed_agents: Vec::new(),
+            managed_workflows: Vec::new(),
             hooks: None,
             max_steps: Self::DEFAULT_MAX_STEPS,
             tool_policies: HashMap::new(),
+            tool_rules: ToolRules::new(),
             output_schema: None,
             input_guardrails: Vec::new(),
             output_guardrails: Vec::new(),
+            max_output_retries: Self::DEFAULT_MAX_OUTPUT_RETRIES,
+            stream_guardrails: Vec::new(),
+            tool_input_guardrails: Vec::new(),
+            tool_output_guardrails: Vec::new(),
         }
     }
 
@@ -376,6 +387,34 @@ pub fn dynamic_instructions<F>(mut self, f: F) -> Self
         self
     }
 
+    /// Set async instructions produced from the run context.
+    ///
+    /// The provider sees the run's state, dependencies, step and session.
+    #[must_use]
+    pub fn instruction_provider(mut self, provider: impl InstructionProvider + 'static) -> Self {
+        self.instructions = Instructions::provider(provider);
+        self
+    }
+
+    /// Append an instruction section after the current instructions.
+    ///
+    /// Sections are joined with a blank line; empty sections are skipped.
+    #[must_use]
+    pub fn instruction_section(mut self, section: impl Into<Instructions>) -> Self {
+        self.instructions = self.instructions.then(section);
+        self
+    }
+
+    /// Re-resolve instructions before every step instead of once per run.
+    ///
+    /// Useful when a provider depends on state that changes during the run,
+    /// such as the step number or values written by tools.
+    #[must_use]
+    pub const fn refresh_instructions(mut self, refresh: bool) -> Self {
+        self.refresh_instructions = refresh;
+        self
+    }
+
     /// Set the LLM model identifier.
     #[must_use]
     pub fn model(mut self, model: impl Into<String>) -> Self {
@@ -397,6 +436,15 @@ pub fn tool(mut self, tool: BoxedTool) -> Self {
         self
     }
 
+    /// Let the agent ask the user clarifying questions.
+    ///
+    /// Registers an [`AskUserTool`] answered by `handler`. Streamed runs
+    /// surface questions as [`RunEvent::InputRequested`] instead.
+    #[must_use]
+    pub fn human_input(self, handler: SharedHumanInputHandler) -> Self {
+        self.tool(Box::new(AskUserTool::new(handler)))
+    }
+
     /// Set all tools for this agent.
     #[must_use]
     pub fn tools(mut self, tools: Vec<BoxedTool>) -> Self {
@@ -418,6 +466,15 @@ pub fn managed_agents(mut self, agents: Vec<Self>) -> Self {
         self
     }
 
+    /// Add a managed workflow, dispatched like a managed agent.
+    ///
+    /// See [`workflow`](crate::workflow) for the available compositions.
+    #[must_use]
+    pub fn managed_workflow(mut self, workflow: impl Workflow + 'static) -> Self {
+        self.managed_workflows.push(Arc::new(workflow));
+        self
+    }
+
     /// Set per-agent lifecycle hooks.
     #[must_use]
     pub fn hooks(mut self, hooks: SharedAgentHooks) -> Self {
@@ -476,6 +533,16 @@ pub fn tool_policy(mut self, name: impl Into<String>, policy: ToolExecutionPolic
         self
     }
 
+    /// Set argument-aware policy rules.
+    ///
+    /// Rules are checked before the per-tool policies set with
+    /// [`tool_policy`](Self::tool_policy); the first matching rule decides.
+    #[must_use]
+    pub fn tool_rules(mut self, rules: ToolRules) -> Self {
+        self.tool_rules = rules;
+        self
+    }
+
     /// Set the output schema for structured JSON output.
     ///
     /// When set, the LLM is constrained to produce JSON conforming to this
@@ -506,6 +573,49 @@ pub fn output_guardrail(mut self, guardrail: OutputGuardrail) -> Self {
         self
     }
 
+    /// Set how many times output guardrails may ask the agent to retry its
+    /// final answer (default: [`DEFAULT_MAX_OUTPUT_RETRIES`](Self::DEFAULT_MAX_OUTPUT_RETRIES)).
+    ///
+    /// Each retry uses another reasoning step. When a guardrail asks for a
+    /// retry after the limit is reached, the run fails with
+    /// [`Error::OutputGuardrailTriggered`](crate::Error::OutputGuardrailTriggered).
+    #[must_use]
+    pub const fn max_output_retries(mut self, retries: usize) -> Self {
+        self.max_output_retries = retries;
+        self
+    }
+
+    /// Add a stream guardrail to this agent.
+    ///
+    /// Stream guardrails check the model's text while
+    /// [`Runner::run_streamed`](super::Runner::run_streamed) streams it.
+    /// See [`StreamGuardrail`] for details.
+    #[must_use]
+    pub fn stream_guardrail(mut self, guardrail: StreamGuardrail) -> Self {
+        self.stream_guardrails.push(guardrail);
+        self
+    }
+
+    /// Add a tool input guardrail to this agent.
+    ///
+    /// Tool input guardrails check the arguments of each tool call before
+    /// it runs. See [`ToolInputGuardrail`] for details.
+    #[must_use]
+    pub fn tool_input_guardrail(mut self, guardrail: ToolInputGuardrail) -> Self {
+        self.tool_input_guardrails.push(guardrail);
+        self
+    }
+
+    /// Add a tool output guardrail to this agent.
+    ///
+    /// Tool output guardrails check each tool result before it is sent back
+    /// to the LLM. See [`ToolOutputGuardrail`] for details.
+    #[must_use]
+    pub fn tool_output_guardrail(mut self, guardrail: ToolOutputGuardrail) -> Self {
+        self.tool_output_guardrails.push(guardrail);
+        self
+    }
+
     /// Set structured output by inferring the JSON Schema from a Rust type.
     ///
     /// This is the most ergonomic way to enable structured output. The type
@@ -562,6 +672,12 @@ pub const fn get_max_steps(&self) -> usize {
         self.max_steps
     }
 
+    /// Returns the maximum number of output guardrail retries.
+    #[must_use]
+    pub const fn get_max_output_retries(&self) -> usize {
+        self.max_output_retries
+    }
+
     /// Returns `true` if a provider is configured.
     #[must_use]
     pub fn has_provider(&self) -> bool {
@@ -575,21 +691,24 @@ pub fn tool_count(&self) -> usize {
     }
 
     /// Resolve the system instructions for this agent.
+    ///
+    /// Async [`InstructionProvider`] sections are skipped; the runner resolves
+    /// them with the run context.
     #[must_use]
     pub fn resolve_instructions(&self) -> String {
         self.instructions.resolve(&self.name)
     }
 
-    /// Returns `true` if this agent has any managed sub-agents.
+    /// Returns `true` if this agent has any managed sub-agents or workflows.
     #[must_use]
     pub const fn has_managed_agents(&self) -> bool {
-        !self.managed_agents.is_empty()
+        !self.managed_agents.is_empty() || !self.managed_workflows.is_empty()
     }
 
-    /// Returns the total number of tools including managed agent tools.
+    /// Returns the total number of tools including managed agent and workflow tools.
     #[must_use]
     pub fn total_tool_count(&self) -> usize {
-        self.tools.len() + self.managed_agents.len()
+        self.tools.len() + self.managed_agents.len() + self.managed_workflows.len()
     }
 
     /// Run this agent to completion with the given input.
@@ -630,6 +749,20 @@ pub fn run_streamed<'a>(
         super::Runner::run_streamed(self, input, config)
     }
 
+    /// Execute a streaming agent run that can be steered while it runs.
+    ///
+    /// This is a convenience wrapper around [`Runner::run_steered`](super::Runner::run_steered).
+    pub fn run_steered<'a>(
+        &'a self,
+        input: impl Into<UserInput>,
+        config: RunConfig,
+    ) -> (
+        super::SteeringHandle,
+        Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>>,
+    ) {
+        super::Runner::run_steered(self, input, config)
+    }
+
     /// Build a [`ToolDefinition`] for this agent when used as a managed sub-agent.
     ///
     /// The definition exposes a single `task` string parameter, which the parent
@@ -639,17 +772,7 @@ pub fn tool_definition(&self) -> ToolDefinition {
         ToolDefinition::new(
             &self.name,
             &self.description,
-            serde_json::json!({
-                "type": "object",
-                "properties": {
-                    "task": {
-                        "type": "string",
-                        "description": "The task to delegate to this agent."
-                    }
-                },
-                "required": ["task"],
-                "additionalProperties": false
-            }),
+            crate::workflow::task_parameters(),
         )
     }
 }
@@ -36,6 +36,33 @@ pub enum AgentError {
         /// Diagnostic information from the guardrail.
         info: serde_json::Value,
     },
+
+    /// Tool guardrail tripwire was triggered.
+    #[error("Tool guardrail '{name}' tripwire triggered on tool '{tool}'")]
+    ToolGuardrailTriggered {
+        /// Name of the guardrail that triggered.
+        name: String,
+        /// Name of the tool being called.
+        tool: String,
+        /// Diagnostic information from the guardrail.
+        info: serde_json::Value,
+    },
+
+    /// A guardrail is misconfigured or its check could not complete.
+    #[error("Guardrail error: {0}")]
+    Guardrail(String),
+
+    /// A declarative agent spec is malformed or references something unknown.
+    #[error("Invalid agent spec: {0}")]
+    Spec(String),
+
+    /// An instruction template is malformed or cannot be rendered.
+    #[error("Instruction template error: {0}")]
+    Template(String),
+
+    /// A tool policy rule set is malformed.
+    #[error("Invalid tool policy: {0}")]
+    Policy(String),
 }
 
 impl AgentError {
@@ -68,4 +95,42 @@ pub fn output_guardrail_triggered(name: impl Into<String>, info: serde_json::Val
             info,
         }
     }
+
+    /// Create a tool guardrail triggered error.
+    #[must_use]
+    pub fn tool_guardrail_triggered(
+        name: impl Into<String>,
+        tool: impl Into<String>,
+        info: serde_json::Value,
+    ) -> Self {
+        Self::ToolGuardrailTriggered {
+            name: name.into(),
+            tool: tool.into(),
+            info,
+        }
+    }
+
+    /// Create a guardrail error.
+    #[must_use]
+    pub fn guardrail(msg: impl Into<String>) -> Self {
+        Self::Guardrail(msg.into())
+    }
+
+    /// Create an invalid agent spec error.
+    #[must_use]
+    pub fn spec(msg: impl Into<String>) -> Self {
+        Self::Spec(msg.into())
+    }
+
+    /// Create an instruction template error.
+    #[must_use]
+    pub fn template(msg: impl Into<String>) -> Self {
+        Self::Template(msg.into())
+    }
+
+    /// Create an invalid tool policy error.
+    #[must_use]
+    pub fn policy(msg: impl Into<String>) -> Self {
+        Self::Policy(msg.into())
+    }
 }
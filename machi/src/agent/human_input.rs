@@ -0,0 +1,345 @@
+//! Asking the user for input mid-run.
+//!
+//! [`AskUserTool`] lets the LLM ask the user a clarifying question instead of
+//! guessing. Answers come from a [`HumanInputHandler`]: [`TerminalInputHandler`]
+//! by default, or any custom implementation set with
+//! [`Agent::human_input`](super::Agent::human_input).
+//!
+//! In [`Runner::run_streamed`](super::Runner::run_streamed), questions are
+//! surfaced as [`RunEvent::InputRequested`](super::RunEvent::InputRequested)
+//! instead, and the consumer answers through the attached [`InputResponder`].
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead as _, Write as _};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::tool::{Tool, ToolContext, ToolError};
+
+/// A question the agent wants the user to answer.
+#[derive(Debug, Clone)]
+pub struct HumanInputRequest {
+    /// The tool call ID.
+    pub id: String,
+    /// The question to ask.
+    pub question: String,
+    /// Suggested answers; empty for a free-form question.
+    pub choices: Vec<String>,
+}
+
+impl HumanInputRequest {
+    /// Create a free-form question.
+    #[must_use]
+    pub fn new(id: impl Into<String>, question: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            question: question.into(),
+            choices: Vec::new(),
+        }
+    }
+
+    /// Offer suggested answers.
+    #[must_use]
+    pub fn with_choices(mut self, choices: Vec<String>) -> Self {
+        self.choices = choices;
+        self
+    }
+
+    /// Map a 1-based choice number to its choice; other answers pass through.
+    #[must_use]
+    pub fn resolve_choice(&self, answer: &str) -> String {
+        answer
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| self.choices.get(i))
+            .map_or_else(|| answer.trim().to_owned(), Clone::clone)
+    }
+}
+
+/// Handler that obtains answers to [`HumanInputRequest`]s.
+#[async_trait]
+pub trait HumanInputHandler: Send + Sync {
+    /// Ask the user a question. Returns `None` if no answer is available.
+    async fn ask(&self, request: &HumanInputRequest) -> Option<String>;
+}
+
+/// A boxed human input handler for dynamic dispatch.
+pub type BoxedHumanInputHandler = Box<dyn HumanInputHandler>;
+
+/// A shared human input handler for use across cloneable contexts.
+pub type SharedHumanInputHandler = Arc<dyn HumanInputHandler>;
+
+/// Handler that asks on stdout and reads the answer from stdin.
+///
+/// Choices are listed with numbers; answering with a number selects that
+/// choice. End of input counts as no answer.
+///
+/// Stdin is read on Tokio's blocking pool, so this handler needs a Tokio
+/// runtime. A blocking read cannot be interrupted: if the `ask` future is
+/// dropped (e.g. the run is cancelled), the read stays pending until the
+/// next line arrives, and that line is discarded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalInputHandler;
+
+#[async_trait]
+impl HumanInputHandler for TerminalInputHandler {
+    async fn ask(&self, request: &HumanInputRequest) -> Option<String> {
+        let mut prompt = format!("\n{}\n", request.question);
+        for (i, choice) in request.choices.iter().enumerate() {
+            let _ = writeln!(prompt, "  {}. {choice}", i + 1);
+        }
+        prompt.push_str("> ");
+
+        // Stdin blocks, so read on the blocking pool.
+        let answer = tokio::task::spawn_blocking(move || {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(prompt.as_bytes());
+            let _ = stdout.flush();
+            let mut line = String::new();
+            let read = io::stdin().lock().read_line(&mut line);
+            match read {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line),
+            }
+        })
+        .await
+        .ok()
+        .flatten()?;
+        Some(request.resolve_choice(&answer))
+    }
+}
+
+/// Answers an [`RunEvent::InputRequested`](super::RunEvent::InputRequested).
+///
+/// Dropping the responder without answering counts as no answer.
+#[derive(Debug)]
+pub struct InputResponder {
+    tx: oneshot::Sender<Option<String>>,
+}
+
+impl InputResponder {
+    /// Send the user's answer. Numbered choices are resolved as in
+    /// [`HumanInputRequest::resolve_choice`].
+    pub fn respond(self, answer: impl Into<String>) {
+        let _ = self.tx.send(Some(answer.into()));
+    }
+
+    /// Decline to answer.
+    pub fn decline(self) {
+        let _ = self.tx.send(None);
+    }
+}
+
+/// A pending question routed to a streamed run's event stream.
+pub type PendingInput = (HumanInputRequest, InputResponder);
+
+/// Routes [`AskUserTool`] questions to a streamed run's event stream.
+///
+/// The streamed runner installs this as a run dependency; it is inherited by
+/// managed sub-agents, so their questions surface in the parent stream too.
+#[derive(Debug)]
+pub struct InputRouter {
+    tx: mpsc::UnboundedSender<PendingInput>,
+}
+
+impl InputRouter {
+    /// Create a connected router and receiver.
+    #[must_use]
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<PendingInput>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    /// Forward a question and wait for the answer.
+    async fn ask(&self, request: HumanInputRequest) -> Option<String> {
+        let (tx, rx) = oneshot::channel();
+        let choices = request.clone();
+        self.tx.send((request, InputResponder { tx })).ok()?;
+        let answer = rx.await.ok().flatten()?;
+        Some(choices.resolve_choice(&answer))
+    }
+}
+
+/// Arguments for [`AskUserTool`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct AskUserArgs {
+    /// The question to ask.
+    pub question: String,
+    /// Optional suggested answers.
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+/// Tool that asks the user a clarifying question and returns the answer.
+#[derive(Clone)]
+pub struct AskUserTool {
+    handler: SharedHumanInputHandler,
+}
+
+impl std::fmt::Debug for AskUserTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AskUserTool").finish_non_exhaustive()
+    }
+}
+
+impl Default for AskUserTool {
+    fn default() -> Self {
+        Self::new(Arc::new(TerminalInputHandler))
+    }
+}
+
+impl AskUserTool {
+    /// Create the tool with a custom handler.
+    #[must_use]
+    pub fn new(handler: SharedHumanInputHandler) -> Self {
+        Self { handler }
+    }
+}
+
+#[async_trait]
+impl Tool for AskUserTool {
+    const NAME: &'static str = "ask_user";
+    type Args = AskUserArgs;
+    type Output = String;
+    type Error = ToolError;
+
+    fn description(&self) -> String {
+        "Ask the user a clarifying question when the request is ambiguous or \
+         missing information. Returns the user's answer."
+            .to_owned()
+    }
+
+    fn parameters_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "question": {
+                    "type": "string",
+                    "description": "The question to ask the user"
+                },
+                "choices": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Optional suggested answers"
+                }
+            },
+            "required": ["question"]
+        })
+    }
+
+    async fn call(&self, args: AskUserArgs) -> Result<String, ToolError> {
+        self.call_with_context(args, &ToolContext::default()).await
+    }
+
+    async fn call_with_context(
+        &self,
+        args: AskUserArgs,
+        ctx: &ToolContext,
+    ) -> Result<String, ToolError> {
+        let request =
+            HumanInputRequest::new(ctx.call_id(), args.question).with_choices(args.choices);
+        let answer = match ctx.dependency::<InputRouter>() {
+            Some(router) => router.ask(request).await,
+            None => self.handler.ask(&request).await,
+        };
+        answer.ok_or_else(|| ToolError::execution("The user did not answer."))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use futures::StreamExt as _;
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig, RunEvent};
+    use crate::chat::ChatResponse;
+    use crate::message::{Message, Role, ToolCall};
+    use crate::test_util::ScriptedProvider;
+
+    /// Asks which color, then answers with the tool result.
+    fn asking_provider() -> Arc<ScriptedProvider> {
+        Arc::new(ScriptedProvider::new(|req| {
+            req.messages
+                .iter()
+                .find(|m| m.role == Role::Tool)
+                .map_or_else(
+                    || {
+                        ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                            "call_1",
+                            "ask_user",
+                            r#"{"question": "Which color?", "choices": ["red", "blue"]}"#,
+                        )]))
+                    },
+                    |m| ChatResponse::from_text(format!("got {}", m.text().unwrap_or_default())),
+                )
+        }))
+    }
+
+    struct Fixed(Option<&'static str>);
+
+    #[async_trait]
+    impl HumanInputHandler for Fixed {
+        async fn ask(&self, request: &HumanInputRequest) -> Option<String> {
+            assert_eq!(request.question, "Which color?");
+            assert_eq!(request.id, "call_1");
+            self.0.map(|a| request.resolve_choice(a))
+        }
+    }
+
+    #[test]
+    fn numbered_answers_select_choices() {
+        let request =
+            HumanInputRequest::new("1", "?").with_choices(vec!["red".into(), "blue".into()]);
+        assert_eq!(request.resolve_choice(" 2\n"), "blue");
+        assert_eq!(request.resolve_choice("3"), "3");
+        assert_eq!(request.resolve_choice("green"), "green");
+    }
+
+    #[tokio::test]
+    async fn handler_answer_is_fed_back() {
+        let agent = Agent::new("asker")
+            .provider(asking_provider())
+            .human_input(Arc::new(Fixed(Some("2"))));
+        let result = agent.run("paint it", RunConfig::new()).await.unwrap();
+        assert_eq!(result.text(), Some(r#"got "blue""#));
+    }
+
+    #[tokio::test]
+    async fn missing_answer_is_a_tool_error() {
+        let agent = Agent::new("asker")
+            .provider(asking_provider())
+            .human_input(Arc::new(Fixed(None)));
+        let result = agent.run("paint it", RunConfig::new()).await.unwrap();
+        assert!(result.text().unwrap().contains("did not answer"));
+    }
+
+    #[tokio::test]
+    async fn streamed_runs_surface_input_requests() {
+        let agent = Agent::new("asker")
+            .provider(asking_provider())
+            .human_input(Arc::new(Fixed(Some("unused"))));
+        let mut stream = agent.run_streamed("paint it", RunConfig::new());
+        let mut asked = false;
+        let mut output = None;
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                RunEvent::InputRequested { request, responder } => {
+                    assert_eq!(request.choices, ["red", "blue"]);
+                    asked = true;
+                    responder.respond("1");
+                }
+                RunEvent::RunCompleted { result } => output = Some(result.output),
+                _ => {}
+            }
+        }
+        assert!(asked);
+        assert_eq!(output.unwrap(), json!(r#"got "red""#));
+    }
+}
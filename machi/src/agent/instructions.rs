@@ -0,0 +1,537 @@
+//! Agent instructions (the system prompt).
+//!
+//! [`Instructions`] range from a fixed string or [`PromptTemplate`] to async
+//! providers that see the [`RunContext`] and session, and can be layered into
+//! multiple sections:
+//!
+//! ```rust
+//! use machi::agent::{Agent, Instructions};
+//!
+//! let agent = Agent::new("support")
+//!     .instructions("You are a support agent.")
+//!     .instruction_section(Instructions::from_async(|ctx| async move {
+//!         Ok(format!("This is step {}.", ctx.step()))
+//!     }))
+//!     .refresh_instructions(true);
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+
+use super::error::AgentError;
+use super::template::PromptTemplate;
+use crate::callback::RunContext;
+use crate::error::Result;
+use crate::memory::SharedSession;
+
+/// Everything an [`InstructionProvider`] can draw on.
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub struct InstructionContext<'a> {
+    /// Name of the agent being run.
+    pub agent_name: &'a str,
+    /// The run context: step, usage, state and dependencies.
+    pub run: &'a RunContext,
+    /// The run's session, if one is configured.
+    pub session: Option<&'a SharedSession>,
+    /// Template variables supplied for this run.
+    pub variables: Option<&'a Map<String, Value>>,
+}
+
+impl<'a> InstructionContext<'a> {
+    /// Create a context without a session.
+    #[must_use]
+    pub const fn new(agent_name: &'a str, run: &'a RunContext) -> Self {
+        Self {
+            agent_name,
+            run,
+            session: None,
+            variables: None,
+        }
+    }
+
+    /// Attach the run's session.
+    #[must_use]
+    pub const fn with_session(mut self, session: Option<&'a SharedSession>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Attach run-time template variables.
+    #[must_use]
+    pub const fn with_variables(mut self, variables: &'a Map<String, Value>) -> Self {
+        self.variables = Some(variables);
+        self
+    }
+
+    /// Variables visible to templates.
+    ///
+    /// Built-ins `agent_name` and `step` come first, then run-context state,
+    /// then run-time variables; later sources override earlier ones.
+    #[must_use]
+    pub fn template_variables(&self) -> Map<String, Value> {
+        let mut vars = Map::new();
+        vars.insert("agent_name".to_owned(), Value::from(self.agent_name));
+        vars.insert("step".to_owned(), Value::from(self.run.step()));
+        vars.extend(self.run.state().iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(variables) = self.variables {
+            vars.extend(variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        vars
+    }
+}
+
+impl fmt::Debug for InstructionContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstructionContext")
+            .field("agent_name", &self.agent_name)
+            .field("run", &self.run)
+            .field("session", &self.session.map(|s| s.id()))
+            .field("variables", &self.variables)
+            .finish()
+    }
+}
+
+/// Produces instructions asynchronously from the run context.
+///
+/// Use this to build prompts from data that must be fetched at run time, such
+/// as a user profile from a database (via
+/// [`RunContext::dependency`](crate::callback::RunContext::dependency)),
+/// retrieved memories, or the current date.
+#[async_trait]
+pub trait InstructionProvider: Send + Sync {
+    /// Produce the instructions text.
+    ///
+    /// # Errors
+    ///
+    /// An error aborts the run.
+    async fn instructions(&self, ctx: &InstructionContext<'_>) -> Result<String>;
+}
+
+/// [`InstructionProvider`] backed by an async closure over a context snapshot.
+struct FnProvider<F>(F);
+
+#[async_trait]
+impl<F, Fut> InstructionProvider for FnProvider<F>
+where
+    F: Fn(RunContext) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String>> + Send,
+{
+    async fn instructions(&self, ctx: &InstructionContext<'_>) -> Result<String> {
+        (self.0)(ctx.run.clone()).await
+    }
+}
+
+/// Instructions that guide the agent's behavior.
+///
+/// Can be a static string, a closure over the agent name, a
+/// [`PromptTemplate`], an async [`InstructionProvider`], or several of these
+/// layered as sections.
+#[derive(Clone)]
+pub enum Instructions {
+    /// Static instruction string.
+    Static(String),
+    /// Dynamic instruction generator.
+    ///
+    /// Receives the current agent name and returns instructions. Wrapped
+    /// in `Arc` for cheap cloning and `Send + Sync` safety.
+    Dynamic(Arc<dyn Fn(&str) -> String + Send + Sync>),
+    /// Template rendered from run-context state and run-time variables
+    /// (see [`RunConfig::instruction_var`](super::RunConfig::instruction_var)).
+    Template(PromptTemplate),
+    /// Async provider with access to the run context and session.
+    Provider(Arc<dyn InstructionProvider>),
+    /// Sections resolved in order and joined with a blank line.
+    ///
+    /// Sections that resolve to an empty string are skipped.
+    Layered(Vec<Self>),
+}
+
+impl Instructions {
+    /// Instructions from template source.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Template`] if the template is malformed.
+    pub fn template(source: impl Into<String>) -> Result<Self> {
+        PromptTemplate::parse(source).map(Self::Template)
+    }
+
+    /// Instructions from a template file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read, or
+    /// [`AgentError::Template`] if the template is malformed.
+    pub fn template_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        PromptTemplate::from_file(path).map(Self::Template)
+    }
+
+    /// Instructions from an async [`InstructionProvider`].
+    #[must_use]
+    pub fn provider(provider: impl InstructionProvider + 'static) -> Self {
+        Self::Provider(Arc::new(provider))
+    }
+
+    /// Instructions from an async closure that receives a snapshot of the
+    /// [`RunContext`].
+    #[must_use]
+    pub fn from_async<F, Fut>(f: F) -> Self
+    where
+        F: Fn(RunContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        Self::Provider(Arc::new(FnProvider(f)))
+    }
+
+    /// Layer several sections.
+    #[must_use]
+    pub fn layered(sections: impl IntoIterator<Item = Self>) -> Self {
+        Self::Layered(sections.into_iter().collect())
+    }
+
+    /// Append a section after these instructions.
+    #[must_use]
+    pub fn then(self, section: impl Into<Self>) -> Self {
+        let section = section.into();
+        match self {
+            Self::Static(s) if s.is_empty() => section,
+            Self::Layered(mut sections) => {
+                sections.push(section);
+                Self::Layered(sections)
+            }
+            other => Self::Layered(vec![other, section]),
+        }
+    }
+
+    /// Resolve the instructions to a string for the given agent name.
+    ///
+    /// [`Provider`](Self::Provider) sections need a run context and resolve
+    /// to nothing here, as do templates that reference anything but
+    /// `agent_name`; the runner uses [`resolve_with`](Self::resolve_with).
+    #[must_use]
+    pub fn resolve(&self, agent_name: &str) -> String {
+        match self {
+            Self::Static(s) => s.clone(),
+            Self::Dynamic(f) => f(agent_name),
+            Self::Template(t) => {
+                let mut vars = Map::new();
+                vars.insert("agent_name".to_owned(), Value::from(agent_name));
+                t.render(&vars).unwrap_or_default()
+            }
+            Self::Provider(_) => String::new(),
+            Self::Layered(sections) => {
+                join_sections(sections.iter().map(|section| section.resolve(agent_name)))
+            }
+        }
+    }
+
+    /// Resolve the instructions, awaiting any async providers.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by a provider.
+    #[must_use]
+    pub fn resolve_with<'a>(
+        &'a self,
+        ctx: &'a InstructionContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            match self {
+                Self::Provider(provider) => provider.instructions(ctx).await,
+                Self::Template(t) => t.render(&ctx.template_variables()),
+                Self::Layered(sections) => {
+                    let mut parts = Vec::with_capacity(sections.len());
+                    for section in sections {
+                        parts.push(section.resolve_with(ctx).await?);
+                    }
+                    Ok(join_sections(parts))
+                }
+                other => Ok(other.resolve(ctx.agent_name)),
+            }
+        })
+    }
+
+    /// Check that every template section has its required variables.
+    ///
+    /// The runner calls this at run start so a missing variable fails fast
+    /// instead of midway through a run.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Template`] listing all missing variables.
+    pub fn check_variables(&self, ctx: &InstructionContext<'_>) -> Result<()> {
+        let vars = ctx.template_variables();
+        let mut missing = Vec::new();
+        self.collect_missing(&vars, &mut missing);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(AgentError::template(format!("missing variables: {}", missing.join(", "))).into())
+        }
+    }
+
+    fn collect_missing<'a>(&'a self, vars: &Map<String, Value>, missing: &mut Vec<&'a str>) {
+        match self {
+            Self::Template(t) => {
+                for name in t.missing_variables(vars) {
+                    if !missing.contains(&name) {
+                        missing.push(name);
+                    }
+                }
+            }
+            Self::Layered(sections) => {
+                for section in sections {
+                    section.collect_missing(vars, missing);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Join non-empty sections with a blank line.
+fn join_sections(sections: impl IntoIterator<Item = String>) -> String {
+    sections
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+impl fmt::Debug for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static(s) => f.debug_tuple("Static").field(s).finish(),
+            Self::Dynamic(_) => f.debug_tuple("Dynamic").field(&"<closure>").finish(),
+            Self::Template(t) => f.debug_tuple("Template").field(t).finish(),
+            Self::Provider(_) => f.debug_tuple("Provider").field(&"<provider>").finish(),
+            Self::Layered(sections) => f.debug_tuple("Layered").field(sections).finish(),
+        }
+    }
+}
+
+impl From<PromptTemplate> for Instructions {
+    fn from(template: PromptTemplate) -> Self {
+        Self::Template(template)
+    }
+}
+
+impl<S: Into<String>> From<S> for Instructions {
+    fn from(s: S) -> Self {
+        Self::Static(s.into())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig};
+    use crate::chat::ChatResponse;
+    use crate::message::{Message, Role};
+    use crate::test_util::{EchoTool, ScriptedProvider};
+
+    struct Greeting;
+
+    #[async_trait]
+    impl InstructionProvider for Greeting {
+        async fn instructions(&self, ctx: &InstructionContext<'_>) -> Result<String> {
+            let user = ctx
+                .run
+                .get_state("user")
+                .and_then(|v| v.as_str())
+                .unwrap_or("guest")
+                .to_owned();
+            Ok(format!("{} greets {user}.", ctx.agent_name))
+        }
+    }
+
+    /// Reply with the system prompt the provider received.
+    fn system_echo() -> Arc<ScriptedProvider> {
+        Arc::new(ScriptedProvider::new(|req| {
+            let system = req
+                .messages
+                .iter()
+                .find(|m| m.role == Role::System)
+                .and_then(Message::text)
+                .unwrap_or_default();
+            ChatResponse::from_text(system)
+        }))
+    }
+
+    mod resolution {
+        use super::*;
+
+        #[tokio::test]
+        async fn layered_sections_skip_empty_and_join() {
+            let instructions = Instructions::from("Base.")
+                .then("")
+                .then(Instructions::Dynamic(Arc::new(|name| {
+                    format!("I am {name}.")
+                })))
+                .then(Instructions::provider(Greeting));
+            let mut run = RunContext::new();
+            run.set_state("user", json!("ada"));
+            let ctx = InstructionContext::new("bot", &run);
+
+            assert_eq!(
+                instructions.resolve_with(&ctx).await.unwrap(),
+                "Base.\n\nI am bot.\n\nbot greets ada."
+            );
+            // Sync resolution has no context, so providers are skipped.
+            assert_eq!(instructions.resolve("bot"), "Base.\n\nI am bot.");
+        }
+
+        #[test]
+        fn then_on_empty_replaces() {
+            let instructions = Instructions::from("").then("Only.");
+            assert!(matches!(instructions, Instructions::Static(ref s) if s == "Only."));
+        }
+
+        #[tokio::test]
+        async fn provider_errors_propagate() {
+            let instructions = Instructions::from_async(|_| async {
+                Err(AgentError::runtime("profile lookup failed").into())
+            });
+            let run = RunContext::new();
+            let err = instructions
+                .resolve_with(&InstructionContext::new("a", &run))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("profile lookup failed"));
+        }
+    }
+
+    mod runner {
+        use std::sync::Mutex;
+
+        use super::*;
+        use crate::message::ToolCall;
+
+        /// Calls `echo` once, then answers; records each request's system prompt.
+        fn recording_echo(seen: &Arc<Mutex<Vec<String>>>) -> Arc<ScriptedProvider> {
+            let seen = Arc::clone(seen);
+            Arc::new(ScriptedProvider::new(move |req| {
+                let system = req
+                    .messages
+                    .iter()
+                    .find(|m| m.role == Role::System)
+                    .and_then(Message::text)
+                    .unwrap_or_default();
+                seen.lock().unwrap().push(system);
+                if req.messages.iter().any(|m| m.role == Role::Tool) {
+                    ChatResponse::from_text("done")
+                } else {
+                    ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                        "call_1", "echo", "{}",
+                    )]))
+                }
+            }))
+        }
+
+        fn step_agent(seen: &Arc<Mutex<Vec<String>>>) -> Agent {
+            Agent::new("bot")
+                .provider(recording_echo(seen))
+                .tool(Box::new(EchoTool))
+                .instructions("Be brief.")
+                .instruction_section(Instructions::from_async(|ctx| async move {
+                    Ok(format!("Step {}.", ctx.step()))
+                }))
+        }
+
+        #[tokio::test]
+        async fn provider_sees_run_dependencies() {
+            let agent = Agent::new("bot")
+                .provider(system_echo())
+                .instruction_section(Instructions::from_async(|ctx| async move {
+                    let tenant = ctx.dependency::<String>().unwrap();
+                    Ok(format!("Tenant: {tenant}"))
+                }));
+            let config = RunConfig::new().with_dependency(Arc::new(String::from("acme")));
+            let result = agent.run("hi", config).await.unwrap();
+            assert_eq!(result.text(), Some("Tenant: acme"));
+        }
+
+        #[tokio::test]
+        async fn templates_render_state_and_run_variables() {
+            let template = Instructions::template(
+                "{{agent_name}} serves {{tenant}}.{{#if tips}} Tips:{{#each tips}} {{this}}{{/each}}{{/if}}",
+            )
+            .unwrap();
+            let agent = Agent::new("bot")
+                .provider(system_echo())
+                .instruction_section(template);
+
+            let config = RunConfig::new()
+                .instruction_var("tenant", "acme")
+                .instruction_var("tips", json!(["a", "b"]));
+            let result = agent.run("hi", config).await.unwrap();
+            assert_eq!(result.text(), Some("bot serves acme. Tips: a b"));
+        }
+
+        #[tokio::test]
+        async fn missing_template_variables_fail_at_run_start() {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let agent = Agent::new("bot")
+                .provider(recording_echo(&seen))
+                .instruction_section(Instructions::template("{{a}} {{b}}").unwrap())
+                .instruction_section(Instructions::template("{{b}} {{c}}").unwrap())
+                .refresh_instructions(true);
+
+            let err = agent
+                .run("hi", RunConfig::new().instruction_var("b", 1))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("missing variables: a, c"), "{err}");
+            assert!(seen.lock().unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn resolved_once_by_default() {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            step_agent(&seen).run("go", RunConfig::new()).await.unwrap();
+            assert_eq!(
+                *seen.lock().unwrap(),
+                ["Be brief.\n\nStep 0.", "Be brief.\n\nStep 0."]
+            );
+        }
+
+        #[tokio::test]
+        async fn refreshed_every_step() {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            step_agent(&seen)
+                .refresh_instructions(true)
+                .run("go", RunConfig::new())
+                .await
+                .unwrap();
+            assert_eq!(
+                *seen.lock().unwrap(),
+                ["Be brief.\n\nStep 1.", "Be brief.\n\nStep 2."]
+            );
+        }
+
+        #[tokio::test]
+        async fn refreshed_in_streamed_runs() {
+            use futures::StreamExt as _;
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let agent = step_agent(&seen).refresh_instructions(true);
+            let mut stream = agent.run_streamed("go", RunConfig::new());
+            while let Some(event) = stream.next().await {
+                event.unwrap();
+            }
+            assert_eq!(
+                *seen.lock().unwrap(),
+                ["Be brief.\n\nStep 1.", "Be brief.\n\nStep 2."]
+            );
+        }
+    }
+}
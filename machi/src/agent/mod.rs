@@ -48,12 +48,27 @@
 mod config;
 pub mod error;
 mod hook;
+mod human_input;
+mod instructions;
 pub mod result;
 mod runner;
+#[cfg(feature = "spec")]
+mod spec;
+mod steering;
+mod template;
 
-pub use config::{Agent, Instructions, OutputSchema};
+pub use config::{Agent, OutputSchema};
 pub use error::AgentError;
+pub use human_input::{
+    AskUserArgs, AskUserTool, BoxedHumanInputHandler, HumanInputHandler, HumanInputRequest,
+    InputResponder, SharedHumanInputHandler, TerminalInputHandler,
+};
+pub use instructions::{InstructionContext, InstructionProvider, Instructions};
 pub use result::{
     NextStep, RunConfig, RunEvent, RunResult, StepInfo, ToolCallRecord, ToolCallRequest, UserInput,
 };
 pub use runner::Runner;
+#[cfg(feature = "spec")]
+pub use spec::{AgentSpec, McpServerSpec, OutputSchemaSpec, SpecFormat, SpecRegistry, ToolFactory};
+pub use steering::SteeringHandle;
+pub use template::PromptTemplate;
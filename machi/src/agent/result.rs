@@ -8,20 +8,26 @@
 //! - [`RunResult`]: The final outcome of a completed agent run.
 //! - [`StepInfo`]: Metadata about a single reasoning step for observability.
 
+use std::any::Any;
 use std::fmt;
+use std::sync::Arc;
 
-use serde_json::Value;
+use serde_json::{Map, Value};
 
-use crate::callback::SharedRunHooks;
+use crate::callback::{Dependencies, SharedRunHooks, SharedStepHooks};
 use crate::chat::ChatResponse;
 use crate::guardrail::{
-    InputGuardrail, InputGuardrailResult, OutputGuardrail, OutputGuardrailResult,
+    InputGuardrail, InputGuardrailResult, OutputGuardrail, OutputGuardrailResult, StreamGuardrail,
+    ToolGuardrailResult, ToolInputGuardrail, ToolOutputGuardrail,
 };
-use crate::memory::SharedSession;
+use crate::memory::{AutoRecall, SharedSession};
 use crate::message::{Content, ContentPart, ImageMime, Message, Role, ToolCall};
+use crate::policy::PolicyDecision;
 use crate::tool::SharedConfirmationHandler;
 use crate::usage::Usage;
 
+use super::human_input::{HumanInputRequest, InputResponder};
+
 /// Outcome of processing an LLM response within the agent loop.
 ///
 /// The Runner evaluates the model's output and classifies it into one of these
@@ -87,12 +93,26 @@ pub struct RunConfig {
     /// Global run-level lifecycle hooks.
     pub hooks: Option<SharedRunHooks>,
 
-    /// Session for message persistence across runs.
+    /// Hooks that decide how the loop continues after each step.
+    pub step_hooks: Option<SharedStepHooks>,
+
+    /// Session for message and run-state persistence across runs.
     pub session: Option<SharedSession>,
 
+    /// Long-term memories to inject before the user's input.
+    ///
+    /// Recall runs after the sequential input guardrails pass and is skipped
+    /// for input without text. A failed search is logged and the run goes on
+    /// without memories.
+    pub auto_recall: Option<AutoRecall>,
+
     /// Maximum number of reasoning steps (overrides `Agent::max_steps`).
     pub max_steps: Option<usize>,
 
+    /// Maximum number of output guardrail retries (overrides
+    /// `Agent::max_output_retries`).
+    pub max_output_retries: Option<usize>,
+
     /// Maximum number of concurrent tool executions.
     ///
     /// Defaults to unlimited (all tool calls run in parallel).
@@ -115,18 +135,54 @@ pub struct RunConfig {
     /// These are combined with the agent's own [`output_guardrails`](crate::agent::Agent::output_guardrails)
     /// and executed together after the agent produces a final output.
     pub output_guardrails: Vec<OutputGuardrail>,
+
+    /// Additional stream guardrails applied at the run level.
+    ///
+    /// These run after the agent's own stream guardrails.
+    pub stream_guardrails: Vec<StreamGuardrail>,
+
+    /// Additional tool input guardrails applied at the run level.
+    ///
+    /// These run after the agent's own tool input guardrails.
+    pub tool_input_guardrails: Vec<ToolInputGuardrail>,
+
+    /// Additional tool output guardrails applied at the run level.
+    ///
+    /// These run after the agent's own tool output guardrails.
+    pub tool_output_guardrails: Vec<ToolOutputGuardrail>,
+
+    /// Typed dependencies shared with everything in the run.
+    ///
+    /// Available from [`RunContext::dependency`](crate::callback::RunContext::dependency)
+    /// in hooks and guardrails, from [`ToolContext`](crate::tool::ToolContext)
+    /// in tools, and inherited by managed sub-agent runs.
+    pub dependencies: Dependencies,
+
+    /// Variables for [`Instructions::Template`](super::Instructions::Template)
+    /// sections.
+    ///
+    /// These override run-context state of the same name.
+    pub instruction_vars: Map<String, Value>,
 }
 
 impl fmt::Debug for RunConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RunConfig")
             .field("hooks", &self.hooks.is_some())
+            .field("step_hooks", &self.step_hooks.is_some())
             .field("session", &self.session.is_some())
+            .field("auto_recall", &self.auto_recall)
             .field("max_steps", &self.max_steps)
+            .field("max_output_retries", &self.max_output_retries)
             .field("max_tool_concurrency", &self.max_tool_concurrency)
             .field("confirmation_handler", &self.confirmation_handler.is_some())
             .field("input_guardrails", &self.input_guardrails.len())
             .field("output_guardrails", &self.output_guardrails.len())
+            .field("stream_guardrails", &self.stream_guardrails.len())
+            .field("tool_input_guardrails", &self.tool_input_guardrails.len())
+            .field("tool_output_guardrails", &self.tool_output_guardrails.len())
+            .field("dependencies", &self.dependencies)
+            .field("instruction_vars", &self.instruction_vars)
             .finish()
     }
 }
@@ -145,13 +201,31 @@ pub fn hooks(mut self, hooks: SharedRunHooks) -> Self {
         self
     }
 
+    /// Set step hooks that can stop the run, inject messages, or switch
+    /// model after each step.
+    #[must_use]
+    pub fn step_hooks(mut self, hooks: SharedStepHooks) -> Self {
+        self.step_hooks = Some(hooks);
+        self
+    }
+
     /// Set a session for message persistence.
+    ///
+    /// The session also carries the [`RunContext`](crate::callback::RunContext)
+    /// state from one run to the next; see [`Session::get_state`](crate::memory::Session::get_state).
     #[must_use]
     pub fn session(mut self, session: SharedSession) -> Self {
         self.session = Some(session);
         self
     }
 
+    /// Inject relevant long-term memories into this run's messages.
+    #[must_use]
+    pub fn auto_recall(mut self, recall: AutoRecall) -> Self {
+        self.auto_recall = Some(recall);
+        self
+    }
+
     /// Override the agent's `max_steps` for this run.
     #[must_use]
     pub const fn max_steps(mut self, max_steps: usize) -> Self {
@@ -159,6 +233,13 @@ pub const fn max_steps(mut self, max_steps: usize) -> Self {
         self
     }
 
+    /// Override the agent's `max_output_retries` for this run.
+    #[must_use]
+    pub const fn max_output_retries(mut self, retries: usize) -> Self {
+        self.max_output_retries = Some(retries);
+        self
+    }
+
     /// Set the maximum number of concurrent tool executions.
     #[must_use]
     pub const fn max_tool_concurrency(mut self, max: usize) -> Self {
@@ -186,6 +267,41 @@ pub fn output_guardrail(mut self, guardrail: OutputGuardrail) -> Self {
         self.output_guardrails.push(guardrail);
         self
     }
+
+    /// Add a stream guardrail at the run level.
+    #[must_use]
+    pub fn stream_guardrail(mut self, guardrail: StreamGuardrail) -> Self {
+        self.stream_guardrails.push(guardrail);
+        self
+    }
+
+    /// Add a tool input guardrail at the run level.
+    #[must_use]
+    pub fn tool_input_guardrail(mut self, guardrail: ToolInputGuardrail) -> Self {
+        self.tool_input_guardrails.push(guardrail);
+        self
+    }
+
+    /// Add a tool output guardrail at the run level.
+    #[must_use]
+    pub fn tool_output_guardrail(mut self, guardrail: ToolOutputGuardrail) -> Self {
+        self.tool_output_guardrails.push(guardrail);
+        self
+    }
+
+    /// Add a typed dependency, replacing any previous value of the same type.
+    #[must_use]
+    pub fn with_dependency<T: Any + Send + Sync>(mut self, value: Arc<T>) -> Self {
+        self.dependencies.insert(value);
+        self
+    }
+
+    /// Set a variable for instruction templates.
+    #[must_use]
+    pub fn instruction_var(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.instruction_vars.insert(name.into(), value.into());
+        self
+    }
 }
 
 /// The final result of a completed agent run.
@@ -214,6 +330,9 @@ pub struct RunResult {
     pub input_guardrail_results: Vec<InputGuardrailResult>,
 
     /// Results from output guardrail checks (empty if no guardrails configured).
+    ///
+    /// Includes the checks of every attempt, so rewrites and retries stay
+    /// visible after the run succeeds.
     pub output_guardrail_results: Vec<OutputGuardrailResult>,
 }
 
@@ -275,6 +394,10 @@ pub struct StepInfo {
 
     /// Tool calls executed during this step (empty if the LLM produced text only).
     pub tool_calls: Vec<ToolCallRecord>,
+
+    /// The execution policy applied to each requested tool call, including
+    /// calls that were forbidden or sent for confirmation.
+    pub policy_decisions: Vec<PolicyDecision>,
 }
 
 /// Record of a single tool call execution within a step.
@@ -292,6 +415,10 @@ pub struct ToolCallRecord {
     pub success: bool,
     /// Token usage from managed sub-agent runs (zero for regular tools).
     pub sub_usage: Usage,
+    /// Results of the tool input guardrails that checked this call.
+    pub input_guardrail_results: Vec<ToolGuardrailResult>,
+    /// Results of the tool output guardrails that checked the result.
+    pub output_guardrail_results: Vec<ToolGuardrailResult>,
 }
 
 /// An observable event emitted during a streamed agent run.
@@ -363,6 +490,56 @@ pub enum RunEvent {
         step_info: Box<StepInfo>,
     },
 
+    /// A message was added to the conversation by a
+    /// [`SteeringHandle`](super::SteeringHandle) or a
+    /// [`StepDecision::InjectMessages`](crate::callback::StepDecision::InjectMessages).
+    MessageInjected {
+        /// The injected message.
+        message: Message,
+    },
+
+    /// An [`AskUserTool`](super::AskUserTool) call is waiting for the user.
+    ///
+    /// The tool call stays pending until `responder` is answered or dropped.
+    InputRequested {
+        /// The question being asked.
+        request: HumanInputRequest,
+        /// Sends the answer back to the tool.
+        responder: InputResponder,
+    },
+
+    /// A [`StreamGuardrail`] tripped and generation was aborted.
+    ///
+    /// The stream then ends with
+    /// [`Error::OutputGuardrailTriggered`](crate::Error::OutputGuardrailTriggered).
+    GuardrailTriggered {
+        /// Name of the guardrail that tripped.
+        guardrail_name: String,
+        /// Diagnostic information from the guardrail.
+        info: Value,
+    },
+
+    /// A group-chat participant is about to speak.
+    ///
+    /// Events until the matching [`TurnCompleted`](Self::TurnCompleted)
+    /// belong to this speaker.
+    TurnStarted {
+        /// Name of the speaking participant.
+        speaker: String,
+        /// Turn number (1-indexed).
+        turn: usize,
+    },
+
+    /// A group-chat participant has finished speaking.
+    TurnCompleted {
+        /// Name of the speaking participant.
+        speaker: String,
+        /// Turn number (1-indexed).
+        turn: usize,
+        /// The message added to the transcript.
+        content: String,
+    },
+
     /// The agent run completed successfully with a final result.
     RunCompleted {
         /// The final run result.
@@ -12,32 +12,46 @@
 //! All per-run state lives in [`RunState`], initialised once and driven by
 //! [`Runner::run`] (blocking) or [`Runner::run_streamed`] (streaming).
 
-use std::{collections::HashSet, future::Future, pin::Pin};
+use std::{collections::HashSet, future::Future, pin::Pin, sync::Arc};
 
-use futures::{StreamExt as _, stream::Stream};
-use serde_json::Value;
+use futures::{
+    StreamExt as _,
+    future::{self, Either},
+    stream::Stream,
+};
+use serde_json::{Value, json};
 use tracing::{Instrument, debug, error, info, info_span, warn};
 
 use super::{
     config::Agent,
     hook::HookPair,
+    human_input::InputRouter,
+    instructions::InstructionContext,
     result::{
         NextStep, RunConfig, RunEvent, RunResult, StepInfo, ToolCallRecord, ToolCallRequest,
         UserInput,
     },
+    steering::{self, SteeringHandle, SteeringReceiver},
 };
 use crate::{
-    callback::{NoopRunHooks, RunContext, RunHooks},
+    callback::{NoopRunHooks, RunContext, RunHooks, StepDecision},
     chat::{ChatProvider, ChatRequest, ChatResponse, ToolChoice},
     error::{AgentError, Error, Result},
-    guardrail::{InputGuardrail, InputGuardrailResult, OutputGuardrail, OutputGuardrailResult},
+    guardrail::{
+        InputGuardrail, InputGuardrailResult, OutputGuardrail, OutputGuardrailResult, StreamGuard,
+        StreamGuardrail, StreamVerdict, ToolGuardrailOutput, ToolGuardrailResult,
+        ToolInputGuardrail, ToolOutputGuardrail,
+    },
+    memory::{AutoRecall, SharedSession},
     message::Message,
+    policy::{PolicyDecision, PolicyRule},
     stream::{StreamAggregator, StreamChunk},
     tool::{
         BoxedTool, ConfirmationHandler, ToolConfirmationRequest, ToolConfirmationResponse,
-        ToolDefinition, ToolExecutionPolicy,
+        ToolContext, ToolDefinition, ToolExecutionPolicy,
     },
     usage::Usage,
+    workflow::{Workflow, output_text},
 };
 
 /// Outcome of processing one reasoning step.
@@ -48,11 +62,19 @@ enum StepOutcome {
     Continue,
 }
 
+/// Tool guardrails from the agent and run config, in execution order.
+struct ToolGuardrails<'a> {
+    input: Vec<&'a ToolInputGuardrail>,
+    output: Vec<&'a ToolOutputGuardrail>,
+}
+
 /// Per-run mutable state, created once by [`init`](Self::init) and driven
 /// step-by-step by [`Runner::run`] or [`Runner::run_streamed`].
 struct RunState<'a> {
     agent: &'a Agent,
     provider: &'a dyn ChatProvider,
+    /// Model for the next LLM call; starts as the agent's, may be switched by a step hook.
+    model: String,
     context: RunContext,
     messages: Vec<Message>,
     step_history: Vec<StepInfo>,
@@ -63,10 +85,23 @@ struct RunState<'a> {
     all_definitions: Vec<ToolDefinition>,
     all_output_guardrails: Vec<&'a OutputGuardrail>,
     input_guardrail_results: Vec<InputGuardrailResult>,
+    /// Output guardrail results of every final-output attempt so far.
+    output_guardrail_results: Vec<OutputGuardrailResult>,
+    /// Retries requested by output guardrails so far.
+    output_retries: usize,
+    max_output_retries: usize,
     parallel_guardrails: Vec<&'a InputGuardrail>,
+    stream_guardrails: Vec<&'a StreamGuardrail>,
+    tool_guardrails: ToolGuardrails<'a>,
     max_steps: usize,
     max_tool_concurrency: Option<usize>,
     structured_output: bool,
+    /// Queue of a steered run; closed once the run can no longer take messages.
+    steering: Option<SteeringReceiver>,
+    /// Steering messages received but not yet added to `messages`.
+    pending_steering: Vec<Message>,
+    /// Every steering message injected so far, saved with the exchange.
+    steered: Vec<Message>,
 }
 
 impl<'a> RunState<'a> {
@@ -81,10 +116,25 @@ async fn init(agent: &'a Agent, input: UserInput, config: &'a RunConfig) -> Resu
 
         let max_steps = config.max_steps.unwrap_or(agent.max_steps);
 
-        let context = RunContext::new().with_agent_name(&agent.name);
+        let mut context = RunContext::new()
+            .with_agent_name(&agent.name)
+            .with_dependencies(config.dependencies.clone());
+        // Restore state saved by earlier runs before anything can read it.
+        if let Some(ref session) = config.session {
+            context = context.with_state(session.get_state().await?);
+        }
         let mut messages = Vec::new();
 
-        let system_prompt = agent.resolve_instructions();
+        let ctx = InstructionContext::new(&agent.name, &context)
+            .with_session(config.session.as_ref())
+            .with_variables(&config.instruction_vars);
+        agent.instructions.check_variables(&ctx)?;
+        // Refreshed instructions are resolved at the start of each step instead.
+        let system_prompt = if agent.refresh_instructions {
+            String::new()
+        } else {
+            agent.instructions.resolve_with(&ctx).await?
+        };
         if !system_prompt.is_empty() {
             messages.push(Message::system(&system_prompt));
         }
@@ -127,9 +177,15 @@ async fn init(agent: &'a Agent, input: UserInput, config: &'a RunConfig) -> Resu
             input_guardrail_results.extend(results);
         }
 
+        // Recall only once the input has passed the sequential guardrails.
+        if let Some(ref recall) = config.auto_recall {
+            Self::inject_recall(recall, &agent.name, &user_message, &mut messages).await;
+        }
+
         Ok(Self {
             agent,
             provider,
+            model: agent.model.clone(),
             context,
             messages,
             step_history: Vec::new(),
@@ -140,13 +196,113 @@ async fn init(agent: &'a Agent, input: UserInput, config: &'a RunConfig) -> Resu
             all_definitions,
             all_output_guardrails,
             input_guardrail_results,
+            output_guardrail_results: Vec::new(),
+            output_retries: 0,
+            max_output_retries: config
+                .max_output_retries
+                .unwrap_or(agent.max_output_retries),
             parallel_guardrails: parallel,
+            stream_guardrails: agent
+                .stream_guardrails
+                .iter()
+                .chain(config.stream_guardrails.iter())
+                .collect(),
+            tool_guardrails: Runner::collect_tool_guardrails(agent, config),
             max_steps,
             max_tool_concurrency: config.max_tool_concurrency,
             structured_output: agent.output_schema.is_some(),
+            steering: None,
+            pending_steering: Vec::new(),
+            steered: Vec::new(),
         })
     }
 
+    /// Insert memories relevant to the user's input right before it, after
+    /// the history.
+    ///
+    /// Input without text is skipped, and a failed search is logged rather
+    /// than aborting the run.
+    async fn inject_recall(
+        recall: &AutoRecall,
+        agent_name: &str,
+        user_message: &Message,
+        messages: &mut Vec<Message>,
+    ) {
+        let Some(query) = user_message.text().filter(|t| !t.trim().is_empty()) else {
+            return;
+        };
+        match recall.message(&query).await {
+            Ok(Some(memories)) => {
+                let insert_pos = messages.len().saturating_sub(1);
+                messages.insert(insert_pos, memories);
+            }
+            Ok(None) => {}
+            Err(e) => warn!(agent = %agent_name, error = %e, "Failed to recall memories"),
+        }
+    }
+
+    /// Add queued steering messages to the conversation, returning them.
+    ///
+    /// On the last allowed step the queue is closed first, so later sends
+    /// fail instead of being dropped.
+    fn inject_steering(&mut self, step: usize) -> Vec<Message> {
+        let mut messages = std::mem::take(&mut self.pending_steering);
+        if let Some(ref mut rx) = self.steering {
+            if step >= self.max_steps {
+                rx.close();
+            }
+            messages.extend(steering::drain(rx));
+        }
+        self.messages.extend(messages.iter().cloned());
+        self.steered.extend(messages.iter().cloned());
+        messages
+    }
+
+    /// Collect steering messages sent while the final LLM call was in
+    /// flight; `true` if the run must continue to answer them.
+    ///
+    /// If none arrived the queue is closed, so later sends fail.
+    fn steering_arrived(&mut self) -> bool {
+        let Some(ref mut rx) = self.steering else {
+            return false;
+        };
+        self.pending_steering.extend(steering::drain(rx));
+        if self.pending_steering.is_empty() {
+            rx.close();
+            // Messages sent just before the close still count.
+            self.pending_steering.extend(steering::drain(rx));
+        }
+        !self.pending_steering.is_empty()
+    }
+
+    /// Advance the run context to the next step and, if the agent refreshes
+    /// its instructions, re-resolve them for that step.
+    async fn start_step(&mut self, config: &RunConfig) -> Result<()> {
+        self.context.advance_step();
+        self.refresh_instructions(config).await
+    }
+
+    /// Re-resolve the instructions, replacing the leading system message.
+    async fn refresh_instructions(&mut self, config: &RunConfig) -> Result<()> {
+        if !self.agent.refresh_instructions {
+            return Ok(());
+        }
+        let ctx = InstructionContext::new(&self.agent.name, &self.context)
+            .with_session(config.session.as_ref())
+            .with_variables(&config.instruction_vars);
+        let prompt = self.agent.instructions.resolve_with(&ctx).await?;
+        match (self.system_prompt.is_empty(), prompt.is_empty()) {
+            (false, false) => self.messages[0] = Message::system(&prompt),
+            (false, true) => {
+                self.messages.remove(0);
+            }
+            (true, false) => self.messages.insert(0, Message::system(&prompt)),
+            (true, true) => {}
+        }
+        self.system_prompt = prompt;
+        Ok(())
+    }
+
     /// System prompt as `Option<&str>` for hook dispatch.
     fn system_ref(&self) -> Option<&str> {
         (!self.system_prompt.is_empty()).then_some(self.system_prompt.as_str())
@@ -154,7 +310,12 @@ fn system_ref(&self) -> Option<&str> {
 
     /// Build a [`ChatRequest`] for the current step.
     fn build_request(&self) -> ChatRequest {
-        Runner::build_request(self.agent, &self.messages, &self.all_definitions)
+        Runner::build_request(
+            self.agent,
+            &self.model,
+            &self.messages,
+            &self.all_definitions,
+        )
     }
 
     /// Build a streaming [`ChatRequest`] for the current step.
@@ -164,6 +325,31 @@ fn build_stream_request(&self) -> ChatRequest {
         req
     }
 
+    /// Call the LLM for `step`, running parallel input guardrails alongside
+    /// the first call.
+    async fn chat(&mut self, step: usize, request: &ChatRequest) -> Result<ChatResponse> {
+        let response = if step == 1 && !self.parallel_guardrails.is_empty() {
+            let (guardrail_result, llm_result) = tokio::join!(
+                Runner::run_input_guardrails(
+                    &self.parallel_guardrails,
+                    &self.context,
+                    &self.agent.name,
+                    &self.messages,
+                ),
+                self.provider.chat(request),
+            );
+            self.input_guardrail_results.extend(guardrail_result?);
+            llm_result
+        } else {
+            self.provider.chat(request).await
+        };
+        response.map_err(|e| {
+            error!(error = %e, agent = %self.agent.name, step, "LLM call failed");
+            tracing::Span::current().record("error", tracing::field::display(&e));
+            e
+        })
+    }
+
     /// Accumulate usage from an LLM response into the running totals.
     fn accumulate_usage(&mut self, response: &ChatResponse) {
         if let Some(usage) = response.usage {
@@ -194,53 +380,25 @@ async fn process_step(
         config: &RunConfig,
     ) -> Result<StepOutcome> {
         let next_step = Runner::classify_response(&response, self.structured_output);
-        let (next_step, forbidden) =
-            Runner::apply_policies(next_step, self.agent, &self.auto_approved);
+        let (next_step, forbidden, policy_decisions) =
+            Runner::apply_policies(next_step, self.agent, &self.context, &self.auto_approved);
 
         match next_step {
-            NextStep::FinalOutput { ref output } => {
-                self.messages.push(response.message.clone());
+            NextStep::FinalOutput { output } => {
+                let reply = response.message.clone();
+                self.messages.push(reply.clone());
                 self.step_history.push(StepInfo {
                     step,
-                    response: response.clone(),
+                    response,
                     tool_calls: Vec::new(),
+                    policy_decisions,
                 });
 
-                let output_value = output.clone();
-                let output_guardrail_results = Runner::run_output_guardrails(
-                    &self.all_output_guardrails,
-                    &self.context,
-                    &self.agent.name,
-                    &output_value,
-                )
-                .await?;
-
-                hooks.agent_end(&self.context, &output_value).await;
-                if let Some(ref session) = config.session {
-                    let to_save = vec![self.user_message.clone(), response.message.clone()];
-                    let _ = session.add_messages(&to_save).await;
+                if self.steering_arrived() {
+                    debug!(agent = %self.agent.name, step, "Steering message arrived with the final answer; continuing");
+                    return Ok(StepOutcome::Continue);
                 }
-
-                tracing::Span::current().record("agent.result_steps", step);
-                info!(
-                    agent = %self.agent.name,
-                    steps = step,
-                    input_tokens = self.cumulative_usage.input_tokens,
-                    output_tokens = self.cumulative_usage.output_tokens,
-                    "Agent run completed",
-                );
-
-                let result = RunResult {
-                    output: output_value,
-                    usage: self.cumulative_usage,
-                    steps: step,
-                    step_history: std::mem::take(&mut self.step_history),
-                    agent_name: self.agent.name.clone(),
-                    input_guardrail_results: std::mem::take(&mut self.input_guardrail_results),
-                    output_guardrail_results,
-                };
-
-                Ok(StepOutcome::Done(result))
+                self.finish(step, output, reply, hooks, config).await
             }
 
             NextStep::ToolCalls { ref calls } => {
@@ -254,10 +412,11 @@ async fn process_step(
                 let tool_records = Runner::execute_tool_calls(
                     calls,
                     self.agent,
-                    &self.context,
+                    &mut self.context,
                     hooks,
                     &mut self.messages,
                     self.max_tool_concurrency,
+                    &self.tool_guardrails,
                 )
                 .await?;
 
@@ -266,6 +425,7 @@ async fn process_step(
                     step,
                     response,
                     tool_calls: tool_records,
+                    policy_decisions,
                 });
 
                 Ok(StepOutcome::Continue)
@@ -303,10 +463,11 @@ async fn process_step(
                     Runner::execute_tool_calls(
                         &executable,
                         self.agent,
-                        &self.context,
+                        &mut self.context,
                         hooks,
                         &mut self.messages,
                         self.max_tool_concurrency,
+                        &self.tool_guardrails,
                     )
                     .await?
                 };
@@ -316,12 +477,160 @@ async fn process_step(
                     step,
                     response,
                     tool_calls: tool_records,
+                    policy_decisions,
                 });
 
                 Ok(StepOutcome::Continue)
             }
         }
     }
+
+    /// [`process_step`](Self::process_step) followed by
+    /// [`end_step`](Self::end_step) if the run continues.
+    async fn complete_step(
+        &mut self,
+        step: usize,
+        response: ChatResponse,
+        hooks: &HookPair<'_>,
+        config: &RunConfig,
+    ) -> Result<StepOutcome> {
+        match self.process_step(step, response, hooks, config).await? {
+            StepOutcome::Continue => self.end_step(step, hooks, config).await,
+            done @ StepOutcome::Done(_) => Ok(done),
+        }
+    }
+
+    /// Let the configured [`StepHooks`](crate::callback::StepHooks) decide how
+    /// the run continues after `step`.
+    async fn end_step(
+        &mut self,
+        step: usize,
+        hooks: &HookPair<'_>,
+        config: &RunConfig,
+    ) -> Result<StepOutcome> {
+        let (Some(step_hooks), Some(info)) = (&config.step_hooks, self.step_history.last()) else {
+            return Ok(StepOutcome::Continue);
+        };
+
+        match step_hooks.on_step_end(&self.context, info).await {
+            StepDecision::Continue => {}
+            StepDecision::Stop(output) => {
+                info!(agent = %self.agent.name, step, "Run stopped by step hook");
+                let reply = Message::assistant(output_text(&output));
+                self.messages.push(reply.clone());
+                return self.finish(step, output, reply, hooks, config).await;
+            }
+            StepDecision::InjectMessages(messages) => {
+                debug!(agent = %self.agent.name, step, count = messages.len(), "Step hook injected messages");
+                self.messages.extend(messages);
+            }
+            StepDecision::SwitchModel(model) => {
+                info!(agent = %self.agent.name, step, from = %self.model, to = %model, "Step hook switched model");
+                self.model = model;
+            }
+        }
+        Ok(StepOutcome::Continue)
+    }
+
+    /// Append the user message, steering messages and `reply` to `session`
+    /// and save the run state; failures are logged, not returned.
+    async fn save_to_session(&mut self, session: &SharedSession, reply: Message) {
+        let mut to_save = vec![self.user_message.clone()];
+        to_save.append(&mut self.steered);
+        to_save.push(reply);
+        if let Err(e) = session.add_messages(&to_save).await {
+            warn!(agent = %self.agent.name, session = session.id(), error = %e, "Failed to save run messages");
+        }
+        if let Err(e) = session.put_state(self.context.state()).await {
+            warn!(agent = %self.agent.name, session = session.id(), error = %e, "Failed to save run state");
+        }
+    }
+
+    /// Check the final output, fire end hooks, persist the exchange to the
+    /// session, and build the [`RunResult`].
+    ///
+    /// Continues the run instead when an output guardrail asks for a retry.
+    async fn finish(
+        &mut self,
+        step: usize,
+        output: Value,
+        mut reply: Message,
+        hooks: &HookPair<'_>,
+        config: &RunConfig,
+    ) -> Result<StepOutcome> {
+        let (results, output) = Runner::run_output_guardrails(
+            &self.all_output_guardrails,
+            &self.context,
+            &self.agent.name,
+            output,
+            self.output_retries + 1,
+        )
+        .await?;
+
+        let feedback: Vec<&str> = results
+            .iter()
+            .filter_map(|r| r.output.retry_feedback())
+            .collect();
+        if !feedback.is_empty() {
+            // A retry needs another step; without one, report the guardrail
+            // rather than running out of steps.
+            if self.output_retries >= self.max_output_retries || step >= self.max_steps {
+                let name = results
+                    .iter()
+                    .find(|r| r.is_retry())
+                    .map_or("", |r| r.guardrail_name.as_str());
+                return Err(AgentError::output_guardrail_triggered(
+                    name,
+                    json!({"feedback": feedback, "retries": self.output_retries}),
+                )
+                .into());
+            }
+            let feedback = feedback.join("\n");
+            self.output_retries += 1;
+            info!(
+                agent = %self.agent.name,
+                step,
+                retry = self.output_retries,
+                "Output guardrail requested a retry",
+            );
+            self.output_guardrail_results.extend(results);
+            self.messages.push(Message::user(feedback));
+            return Ok(StepOutcome::Continue);
+        }
+
+        if results.iter().any(OutputGuardrailResult::is_rewrite) {
+            // Keep the unredacted answer out of the history and the session.
+            reply = Message::assistant(output_text(&output));
+            if let Some(last) = self.messages.last_mut() {
+                *last = reply.clone();
+            }
+        }
+        self.output_guardrail_results.extend(results);
+
+        hooks.agent_end(&self.context, &output).await;
+        if let Some(ref session) = config.session {
+            self.save_to_session(session, reply).await;
+        }
+
+        tracing::Span::current().record("agent.result_steps", step);
+        info!(
+            agent = %self.agent.name,
+            steps = step,
+            input_tokens = self.cumulative_usage.input_tokens,
+            output_tokens = self.cumulative_usage.output_tokens,
+            "Agent run completed",
+        );
+
+        Ok(StepOutcome::Done(RunResult {
+            output,
+            usage: self.cumulative_usage,
+            steps: step,
+            step_history: std::mem::take(&mut self.step_history),
+            agent_name: self.agent.name.clone(),
+            input_guardrail_results: std::mem::take(&mut self.input_guardrail_results),
+            output_guardrail_results: std::mem::take(&mut self.output_guardrail_results),
+        }))
+    }
 }
 
 /// Stateless execution engine that drives an [`Agent`] through its reasoning
@@ -364,8 +673,8 @@ async fn run_inner(agent: &Agent, input: UserInput, config: RunConfig) -> Result
         hooks.agent_start(&state.context).await;
 
         for step in 1..=state.max_steps {
-            state.context.advance_step();
             debug!(agent = %agent.name, step, "Starting step");
+            state.start_step(&config).await?;
 
             let request = state.build_request();
 
@@ -373,32 +682,12 @@ async fn run_inner(agent: &Agent, input: UserInput, config: RunConfig) -> Result
                 .llm_start(&state.context, state.system_ref(), &state.messages)
                 .await;
 
-            // First step: run parallel guardrails alongside the LLM call.
-            let response = if step == 1 && !state.parallel_guardrails.is_empty() {
-                let (guardrail_result, llm_result) = tokio::join!(
-                    Self::run_input_guardrails(
-                        &state.parallel_guardrails,
-                        &state.context,
-                        &agent.name,
-                        &state.messages,
-                    ),
-                    state.provider.chat(&request),
-                );
-                state.input_guardrail_results.extend(guardrail_result?);
-                llm_result
-            } else {
-                state.provider.chat(&request).await
-            }
-            .map_err(|e| {
-                error!(error = %e, agent = %agent.name, step, "LLM call failed");
-                tracing::Span::current().record("error", tracing::field::display(&e));
-                e
-            })?;
+            let response = state.chat(step, &request).await?;
 
             hooks.llm_end(&state.context, &response).await;
             state.accumulate_usage(&response);
 
-            match state.process_step(step, response, &hooks, &config).await? {
+            match state.complete_step(step, response, &hooks, &config).await? {
                 StepOutcome::Done(result) => return Ok(result),
                 StepOutcome::Continue => {}
             }
@@ -421,7 +710,26 @@ pub fn run_streamed<'a>(
         config: RunConfig,
     ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
         let input = input.into();
-        Box::pin(Self::run_streamed_inner(agent, input, config))
+        Box::pin(Self::run_streamed_inner(agent, input, config, None))
+    }
+
+    /// Execute a streaming agent run that can be steered while it runs.
+    ///
+    /// Like [`run_streamed`](Self::run_streamed), but also returns a
+    /// [`SteeringHandle`] for pushing extra user messages or system notes
+    /// into the run. Queued messages are appended to the conversation before
+    /// the next LLM step and reported as [`RunEvent::MessageInjected`].
+    pub fn run_steered<'a>(
+        agent: &'a Agent,
+        input: impl Into<UserInput>,
+        config: RunConfig,
+    ) -> (
+        SteeringHandle,
+        Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>>,
+    ) {
+        let (handle, rx) = SteeringHandle::channel();
+        let stream = Self::run_streamed_inner(agent, input.into(), config, Some(rx));
+        (handle, Box::pin(stream))
     }
 
     /// Core streaming loop.
@@ -430,14 +738,20 @@ pub fn run_streamed<'a>(
     fn run_streamed_inner(
         agent: &Agent,
         input: UserInput,
-        config: RunConfig,
+        mut config: RunConfig,
+        steering: Option<SteeringReceiver>,
     ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        // Questions from `AskUserTool` become `InputRequested` events.
+        let (router, mut input_rx) = InputRouter::channel();
+        config.dependencies.insert(Arc::new(router));
+
         async_stream::try_stream! {
             let noop = NoopRunHooks;
             let run_hooks: &dyn RunHooks = config.hooks.as_deref().unwrap_or(&noop);
             let hooks = HookPair::new(run_hooks, agent.hooks.as_deref(), &agent.name);
 
             let mut state = RunState::init(agent, input, &config).await?;
+            state.steering = steering;
 
             info!(
                 agent = %agent.name,
@@ -451,11 +765,16 @@ fn run_streamed_inner(
             yield RunEvent::RunStarted { agent_name: agent.name.clone() };
 
             for step in 1..=state.max_steps {
-                state.context.advance_step();
                 debug!(agent = %agent.name, step, "Starting streamed step");
+                state.start_step(&config).await?;
 
                 yield RunEvent::StepStarted { step };
 
+                for message in state.inject_steering(step) {
+                    debug!(agent = %agent.name, step, role = %message.role, "Injecting steering message");
+                    yield RunEvent::MessageInjected { message };
+                }
+
                 let request = state.build_stream_request();
 
                 hooks
@@ -476,13 +795,25 @@ fn run_streamed_inner(
 
                 let mut chunk_stream = state.provider.chat_stream(&request).await?;
                 let mut aggregator = StreamAggregator::new();
+                let mut guard = StreamGuard::new(&state.stream_guardrails);
+                let mut verdict = None;
 
                 while let Some(chunk_result) = chunk_stream.next().await {
                     let chunk = chunk_result?;
 
                     match &chunk {
                         StreamChunk::Text(delta) => {
-                            yield RunEvent::TextDelta(delta.clone());
+                            match guard.push(&state.context, &agent.name, delta).await? {
+                                StreamVerdict::Release(text) => {
+                                    if !text.is_empty() {
+                                        yield RunEvent::TextDelta(text);
+                                    }
+                                }
+                                tripped @ StreamVerdict::Tripped { .. } => {
+                                    verdict = Some(tripped);
+                                    break;
+                                }
+                            }
                         }
                         StreamChunk::ReasoningContent(delta) => {
                             yield RunEvent::ReasoningDelta(delta.clone());
@@ -505,12 +836,47 @@ fn run_streamed_inner(
                     aggregator.apply(&chunk);
                 }
 
+                // Dropping the provider stream aborts generation on a tripwire.
+                drop(chunk_stream);
+                if verdict.is_none() {
+                    verdict = Some(guard.finish(&state.context, &agent.name).await?);
+                }
+                match verdict {
+                    Some(StreamVerdict::Tripped { name, info }) => {
+                        warn!(agent = %agent.name, step, guardrail = %name, "Stream guardrail triggered");
+                        yield RunEvent::GuardrailTriggered {
+                            guardrail_name: name.clone(),
+                            info: info.clone(),
+                        };
+                        let err = Error::from(AgentError::output_guardrail_triggered(name, info));
+                        hooks.error(&state.context, &err).await;
+                        Err(err)?;
+                    }
+                    Some(StreamVerdict::Release(text)) if !text.is_empty() => {
+                        yield RunEvent::TextDelta(text);
+                    }
+                    _ => {}
+                }
+
                 let response = aggregator.into_chat_response();
 
                 hooks.llm_end(&state.context, &response).await;
                 state.accumulate_usage(&response);
 
-                match state.process_step(step, response, &hooks, &config).await? {
+                let outcome = {
+                    let processing = state.process_step(step, response, &hooks, &config);
+                    futures::pin_mut!(processing);
+                    loop {
+                        match future::select(processing.as_mut(), Box::pin(input_rx.recv())).await {
+                            Either::Left((outcome, _)) => break outcome?,
+                            Either::Right((Some((request, responder)), _)) => {
+                                yield RunEvent::InputRequested { request, responder };
+                            }
+                            Either::Right((None, _)) => break processing.await?,
+                        }
+                    }
+                };
+                match outcome {
                     StepOutcome::Done(result) => {
                         if let Some(last_step) = result.step_history.last() {
                             yield RunEvent::StepCompleted {
@@ -532,6 +898,17 @@ fn run_streamed_inner(
                         yield RunEvent::StepCompleted {
                             step_info: Box::new(last.clone()),
                         };
+
+                        let injected_from = state.messages.len();
+                        if let StepOutcome::Done(result) = state.end_step(step, &hooks, &config).await? {
+                            yield RunEvent::RunCompleted {
+                                result: Box::new(result),
+                            };
+                            return;
+                        }
+                        for message in state.messages[injected_from..].iter().cloned() {
+                            yield RunEvent::MessageInjected { message };
+                        }
                     }
                 }
             }
@@ -552,16 +929,18 @@ fn collect_all_definitions(agent: &Agent) -> Vec<ToolDefinition> {
             .iter()
             .map(|t| t.definition())
             .chain(agent.managed_agents.iter().map(Agent::tool_definition))
+            .chain(agent.managed_workflows.iter().map(|w| w.tool_definition()))
             .collect()
     }
 
     /// Build a [`ChatRequest`] for the current step.
     fn build_request(
         agent: &Agent,
+        model: &str,
         messages: &[Message],
         definitions: &[ToolDefinition],
     ) -> ChatRequest {
-        let mut request = ChatRequest::with_messages(&agent.model, messages.to_vec());
+        let mut request = ChatRequest::with_messages(model, messages.to_vec());
         if !definitions.is_empty() {
             request = request
                 .tools(definitions.to_vec())
@@ -600,22 +979,41 @@ fn classify_response(response: &ChatResponse, structured_output: bool) -> NextSt
     fn apply_policies(
         next_step: NextStep,
         agent: &Agent,
+        context: &RunContext,
         auto_approved: &HashSet<String>,
-    ) -> (NextStep, Vec<ToolCallRequest>) {
+    ) -> (NextStep, Vec<ToolCallRequest>, Vec<PolicyDecision>) {
         let NextStep::ToolCalls { calls } = next_step else {
-            return (next_step, Vec::new());
+            return (next_step, Vec::new(), Vec::new());
         };
 
         let mut approved = Vec::new();
         let mut pending = Vec::new();
         let mut forbidden = Vec::new();
+        let mut decisions = Vec::with_capacity(calls.len());
 
         for call in calls {
-            let policy = agent
-                .tool_policies
-                .get(&call.name)
-                .copied()
-                .unwrap_or(ToolExecutionPolicy::Auto);
+            let rule = agent
+                .tool_rules
+                .evaluate(&call.name, &call.arguments, context);
+            let policy = rule.map_or_else(
+                || {
+                    agent
+                        .tool_policies
+                        .get(&call.name)
+                        .copied()
+                        .unwrap_or(ToolExecutionPolicy::Auto)
+                },
+                PolicyRule::action,
+            );
+            if let Some(rule) = rule {
+                debug!(tool = %call.name, rule = rule.name(), policy = %policy, "Policy rule matched");
+            }
+            decisions.push(PolicyDecision {
+                call_id: call.id.clone(),
+                tool: call.name.clone(),
+                policy,
+                rule: rule.map(|r| r.name().to_owned()),
+            });
 
             match policy {
                 ToolExecutionPolicy::Auto => approved.push(call),
@@ -639,17 +1037,22 @@ fn apply_policies(
             }
         };
 
-        (result, forbidden)
+        (result, forbidden, decisions)
     }
 
     /// Execute tool calls with bounded concurrency, appending results to messages.
+    ///
+    /// State written by tools through their [`ToolContext`] is merged into
+    /// `context` after each batch, in call order. A tool guardrail tripwire
+    /// aborts the run.
     async fn execute_tool_calls(
         calls: &[ToolCallRequest],
         agent: &Agent,
-        context: &RunContext,
+        context: &mut RunContext,
         hooks: &HookPair<'_>,
         messages: &mut Vec<Message>,
         max_concurrency: Option<usize>,
+        guardrails: &ToolGuardrails<'_>,
     ) -> Result<Vec<ToolCallRecord>> {
         let concurrency = max_concurrency.unwrap_or(calls.len()).max(1);
         let mut records = Vec::with_capacity(calls.len());
@@ -657,9 +1060,17 @@ async fn execute_tool_calls(
         for chunk in calls.chunks(concurrency) {
             let mut futs = Vec::with_capacity(chunk.len());
             for call in chunk {
-                futs.push(Self::execute_single_tool(call, agent, context, hooks));
+                futs.push(Self::execute_single_tool(
+                    call, agent, context, hooks, guardrails,
+                ));
+            }
+            for executed in future::join_all(futs).await {
+                let (record, tool_context) = executed?;
+                if let Some(tool_context) = tool_context {
+                    tool_context.apply_to(context);
+                }
+                records.push(record);
             }
-            records.extend(futures::future::join_all(futs).await);
         }
 
         for record in &records {
@@ -669,13 +1080,18 @@ async fn execute_tool_calls(
         Ok(records)
     }
 
-    /// Execute a single tool call with lifecycle hooks and tracing.
+    /// Execute a single tool call with lifecycle hooks, tool guardrails and
+    /// tracing.
+    ///
+    /// Returns the record and, for regular tools, the [`ToolContext`] the
+    /// tool ran with so its state writes can be applied.
     async fn execute_single_tool(
         call: &ToolCallRequest,
         agent: &Agent,
         context: &RunContext,
         hooks: &HookPair<'_>,
-    ) -> ToolCallRecord {
+        guardrails: &ToolGuardrails<'_>,
+    ) -> Result<(ToolCallRecord, Option<ToolContext>)> {
         let tool_span = info_span!(
             "tool",
             tool.name = %call.name,
@@ -689,20 +1105,32 @@ async fn execute_single_tool(
         async {
             hooks.tool_start(context, &call.name).await;
 
-            let (result_str, success, sub_usage) =
-                if let Some(sub) = agent.managed_agents.iter().find(|a| a.name == call.name) {
-                    Self::dispatch_managed_agent(sub, &call.arguments).await
-                } else if let Some(tool) = agent.tools.iter().find(|t| t.name() == call.name) {
-                    let (r, s) = Self::dispatch_tool(tool, call).await;
-                    (r, s, Usage::zero())
+            let (arguments, rejection, input_guardrail_results) =
+                Self::run_tool_input_guardrails(&guardrails.input, context, call).await?;
+            let call = &ToolCallRequest {
+                arguments,
+                ..call.clone()
+            };
+
+            let rejected = rejection.is_some();
+            let (mut result_str, mut success, sub_usage, tool_context) =
+                if let Some(message) = rejection {
+                    (message, false, Usage::zero(), None)
                 } else {
-                    warn!(tool = %call.name, "Tool not found");
-                    (
-                        format!("Tool '{}' not found", call.name),
-                        false,
-                        Usage::zero(),
-                    )
+                    Self::dispatch_call(call, agent, context).await
                 };
+            let output_guardrail_results = if rejected {
+                Vec::new()
+            } else {
+                Self::run_tool_output_guardrails(
+                    &guardrails.output,
+                    context,
+                    call,
+                    &mut result_str,
+                    &mut success,
+                )
+                .await?
+            };
 
             let current = tracing::Span::current();
             current.record("tool.success", success);
@@ -712,38 +1140,84 @@ async fn execute_single_tool(
             }
             hooks.tool_end(context, &call.name, &result_str).await;
 
-            ToolCallRecord {
+            let record = ToolCallRecord {
                 id: call.id.clone(),
                 name: call.name.clone(),
                 arguments: call.arguments.clone(),
                 result: result_str,
                 success,
                 sub_usage,
-            }
+                input_guardrail_results,
+                output_guardrail_results,
+            };
+            Ok((record, tool_context))
         }
         .instrument(tool_span)
         .await
     }
 
-    /// Dispatch a managed sub-agent with the given task arguments.
+    /// Route a call to its managed agent, managed workflow or tool.
     ///
-    /// Returns `(output, success, sub_agent_usage)` so the parent can
-    /// accumulate the child's token consumption.
-    async fn dispatch_managed_agent(sub_agent: &Agent, args: &Value) -> (String, bool, Usage) {
+    /// Returns `(output, success, sub_agent_usage, tool_context)`.
+    async fn dispatch_call(
+        call: &ToolCallRequest,
+        agent: &Agent,
+        context: &RunContext,
+    ) -> (String, bool, Usage, Option<ToolContext>) {
+        if let Some(sub) = agent.managed_agents.iter().find(|a| a.name == call.name) {
+            let (r, s, usage) = Self::dispatch_managed_agent(sub, &call.arguments, context).await;
+            (r, s, usage, None)
+        } else if let Some(sub) = agent
+            .managed_workflows
+            .iter()
+            .find(|w| w.name() == call.name)
+        {
+            let (r, s, usage) =
+                Self::dispatch_managed_agent(sub.as_ref(), &call.arguments, context).await;
+            (r, s, usage, None)
+        } else if let Some(tool) = agent.tools.iter().find(|t| t.name() == call.name) {
+            let ctx = ToolContext::new(&call.id).with_run_context(context.clone());
+            let (r, s) = Self::dispatch_tool(tool, call, &ctx).await;
+            (r, s, Usage::zero(), Some(ctx))
+        } else {
+            warn!(tool = %call.name, "Tool not found");
+            (
+                format!("Tool '{}' not found", call.name),
+                false,
+                Usage::zero(),
+                None,
+            )
+        }
+    }
+
+    /// Dispatch a managed sub-agent (or workflow) with the given task arguments.
+    ///
+    /// The sub-run inherits the parent's dependencies. Returns
+    /// `(output, success, sub_agent_usage)` so the parent can accumulate the
+    /// child's token consumption.
+    async fn dispatch_managed_agent(
+        sub_agent: &dyn Workflow,
+        args: &Value,
+        context: &RunContext,
+    ) -> (String, bool, Usage) {
         let task = args.get("task").and_then(Value::as_str).unwrap_or_default();
         info!(
             from_agent = tracing::field::Empty,
-            to_agent = %sub_agent.name,
+            to_agent = %sub_agent.name(),
             "Handoff to managed agent",
         );
-        match Self::run(sub_agent, task, RunConfig::default()).await {
+        let config = RunConfig {
+            dependencies: context.dependencies().clone(),
+            ..RunConfig::default()
+        };
+        match sub_agent.run(task.into(), config).await {
             Ok(result) => {
                 let output = serde_json::to_string(&result.output)
                     .unwrap_or_else(|_| result.output.to_string());
                 (output, true, result.usage)
             }
             Err(e) => (
-                format!("Managed agent '{}' failed: {e}", sub_agent.name),
+                format!("Managed agent '{}' failed: {e}", sub_agent.name()),
                 false,
                 Usage::zero(),
             ),
@@ -751,8 +1225,15 @@ async fn dispatch_managed_agent(sub_agent: &Agent, args: &Value) -> (String, boo
     }
 
     /// Dispatch a regular tool call via [`DynTool`](crate::tool::DynTool).
-    async fn dispatch_tool(tool: &BoxedTool, call: &ToolCallRequest) -> (String, bool) {
-        match tool.call_json(call.arguments.clone()).await {
+    async fn dispatch_tool(
+        tool: &BoxedTool,
+        call: &ToolCallRequest,
+        ctx: &ToolContext,
+    ) -> (String, bool) {
+        match tool
+            .call_json_with_context(call.arguments.clone(), ctx)
+            .await
+        {
             Ok(value) => {
                 let output = serde_json::to_string(&value).unwrap_or_else(|_| value.to_string());
                 (output, true)
@@ -831,6 +1312,116 @@ fn collect_output_guardrails<'a>(
             .collect()
     }
 
+    /// Merge tool guardrails from agent and run config.
+    fn collect_tool_guardrails<'a>(agent: &'a Agent, config: &'a RunConfig) -> ToolGuardrails<'a> {
+        ToolGuardrails {
+            input: agent
+                .tool_input_guardrails
+                .iter()
+                .chain(config.tool_input_guardrails.iter())
+                .collect(),
+            output: agent
+                .tool_output_guardrails
+                .iter()
+                .chain(config.tool_output_guardrails.iter())
+                .collect(),
+        }
+    }
+
+    /// Run the tool input guardrails that apply to `call`, in order.
+    ///
+    /// Returns the arguments after any rewrites and the rejection message,
+    /// if one rejected the call. Stops at the first rejection; a tripwire
+    /// is an error.
+    async fn run_tool_input_guardrails(
+        guardrails: &[&ToolInputGuardrail],
+        context: &RunContext,
+        call: &ToolCallRequest,
+    ) -> Result<(Value, Option<String>, Vec<ToolGuardrailResult>)> {
+        let mut arguments = call.arguments.clone();
+        let mut results = Vec::new();
+
+        for guardrail in guardrails.iter().filter(|g| g.applies_to(&call.name)) {
+            let result = guardrail.run(context, &call.name, &arguments).await?;
+            let rejection = match &result.output {
+                ToolGuardrailOutput::Allow => None,
+                ToolGuardrailOutput::Rewrite(value) => {
+                    debug!(tool = %call.name, guardrail = %result.guardrail_name, "Tool arguments rewritten");
+                    arguments = value.clone();
+                    None
+                }
+                ToolGuardrailOutput::Reject(message) => Some(message.clone()),
+                ToolGuardrailOutput::Tripwire(info) => {
+                    return Err(AgentError::tool_guardrail_triggered(
+                        &result.guardrail_name,
+                        &call.name,
+                        info.clone(),
+                    )
+                    .into());
+                }
+            };
+            if rejection.is_some() {
+                warn!(tool = %call.name, guardrail = %result.guardrail_name, "Tool call rejected by guardrail");
+                results.push(result);
+                return Ok((arguments, rejection, results));
+            }
+            results.push(result);
+        }
+
+        Ok((arguments, None, results))
+    }
+
+    /// Run the tool output guardrails that apply to `call`, in order,
+    /// rewriting `output` in place.
+    ///
+    /// A rejection replaces the output with its message, marks the call as
+    /// failed and stops; a tripwire is an error.
+    async fn run_tool_output_guardrails(
+        guardrails: &[&ToolOutputGuardrail],
+        context: &RunContext,
+        call: &ToolCallRequest,
+        output: &mut String,
+        success: &mut bool,
+    ) -> Result<Vec<ToolGuardrailResult>> {
+        let mut results = Vec::new();
+
+        for guardrail in guardrails.iter().filter(|g| g.applies_to(&call.name)) {
+            let result = guardrail
+                .run(context, &call.name, &call.arguments, output)
+                .await?;
+            let rejected = match &result.output {
+                ToolGuardrailOutput::Allow => false,
+                ToolGuardrailOutput::Rewrite(value) => {
+                    debug!(tool = %call.name, guardrail = %result.guardrail_name, "Tool result rewritten");
+                    *output = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_owned);
+                    false
+                }
+                ToolGuardrailOutput::Reject(message) => {
+                    warn!(tool = %call.name, guardrail = %result.guardrail_name, "Tool result rejected by guardrail");
+                    output.clone_from(message);
+                    *success = false;
+                    true
+                }
+                ToolGuardrailOutput::Tripwire(info) => {
+                    return Err(AgentError::tool_guardrail_triggered(
+                        &result.guardrail_name,
+                        &call.name,
+                        info.clone(),
+                    )
+                    .into());
+                }
+            };
+            results.push(result);
+            if rejected {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+
     /// Run input guardrails sequentially; short-circuits on tripwire.
     async fn run_input_guardrails(
         guardrails: &[&InputGuardrail],
@@ -855,26 +1446,21 @@ async fn run_input_guardrails(
         Ok(results)
     }
 
-    /// Run output guardrails concurrently; short-circuits on tripwire.
+    /// Run output guardrails in registration order and apply their rewrites;
+    /// each guardrail runs once and checks the output as rewritten by the
+    /// ones before it. A triggered tripwire aborts the run; the returned
+    /// results are stamped with `attempt`.
     async fn run_output_guardrails(
         guardrails: &[&OutputGuardrail],
         context: &RunContext,
         agent_name: &str,
-        output: &Value,
-    ) -> Result<Vec<OutputGuardrailResult>> {
-        if guardrails.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        let futs: Vec<_> = guardrails
-            .iter()
-            .map(|g| g.run(context, agent_name, output))
-            .collect();
-        let all_results = futures::future::join_all(futs).await;
-
-        let mut results = Vec::with_capacity(all_results.len());
-        for r in all_results {
-            let result = r?;
+        mut output: Value,
+        attempt: usize,
+    ) -> Result<(Vec<OutputGuardrailResult>, Value)> {
+        let mut results = Vec::with_capacity(guardrails.len());
+        for guardrail in guardrails {
+            let mut result = guardrail.run(context, agent_name, &output).await?;
+            result.attempt = attempt;
             if result.is_triggered() {
                 return Err(AgentError::output_guardrail_triggered(
                     &result.guardrail_name,
@@ -882,9 +1468,13 @@ async fn run_output_guardrails(
                 )
                 .into());
             }
+            if let Some(rewrite) = result.output.rewritten_output() {
+                debug!(guardrail = %result.guardrail_name, agent = agent_name, "Output guardrail rewrote the output");
+                output = rewrite.clone();
+            }
             results.push(result);
         }
 
-        Ok(results)
+        Ok((results, output))
     }
 }
@@ -0,0 +1,808 @@
+//! Declarative agent definitions.
+//!
+//! An [`AgentSpec`] describes an [`Agent`] as data — name, instructions,
+//! model, provider, tools, MCP servers, tool policies, guardrails, output
+//! schema and nested managed agents — so prompts and tool sets can be tuned
+//! in a TOML, YAML or JSON file without recompiling.
+//!
+//! Everything that cannot be expressed as data (LLM providers, custom tools,
+//! guardrail implementations) is referenced *by name* and resolved through a
+//! [`SpecRegistry`]. Unknown references fail with [`AgentError::Spec`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! # tokio_test::block_on(async {
+//! use std::sync::Arc;
+//!
+//! use machi::agent::{AgentSpec, SpecRegistry};
+//! use machi::llms::ReplayProvider;
+//!
+//! let spec = AgentSpec::from_toml_str(r#"
+//!     name = "researcher"
+//!     instructions = "You research topics thoroughly."
+//!     model = "gpt-4o"
+//!     provider = "openai"
+//!     max_steps = 5
+//!
+//!     [tool_policies]
+//!     writer = "require_confirmation"
+//!
+//!     [[managed_agents]]
+//!     name = "writer"
+//!     description = "Writes clear summaries."
+//!     model = "gpt-4o-mini"
+//! "#)?;
+//!
+//! let registry = SpecRegistry::new()
+//!     .provider("openai", Arc::new(ReplayProvider::from_entries(vec![])));
+//!
+//! let agent = registry.build(&spec).await?;
+//! assert_eq!(agent.name(), "researcher");
+//! assert_eq!(agent.get_max_steps(), 5);
+//! assert_eq!(agent.total_tool_count(), 1);
+//! # Ok::<(), machi::Error>(())
+//! # }).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::config::{Agent, OutputSchema};
+use crate::chat::SharedChatProvider;
+use crate::error::{AgentError, Result};
+use crate::guardrail::{InputGuardrail, OutputGuardrail};
+use crate::tool::{BoxedTool, ToolExecutionPolicy};
+
+/// Serialization format of an agent spec file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecFormat {
+    /// JSON (`.json`).
+    Json,
+    /// TOML (`.toml`).
+    Toml,
+    /// YAML (`.yaml` / `.yml`).
+    Yaml,
+}
+
+impl SpecFormat {
+    /// Infers the format from a file extension (case-insensitive).
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Declarative definition of an [`Agent`].
+///
+/// Unknown fields are rejected so that typos surface as errors rather than
+/// silently ignored settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentSpec {
+    /// Unique agent name.
+    pub name: String,
+
+    /// Human-readable description (the tool description when managed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Static system instructions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+
+    /// LLM model identifier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Name of a provider registered on the [`SpecRegistry`].
+    ///
+    /// When absent, the registry's default provider is used, falling back to
+    /// the parent agent's provider for managed agents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+
+    /// Maximum number of reasoning steps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_steps: Option<usize>,
+
+    /// Tool names, resolved against registered tools, then built-in tools.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+
+    /// MCP servers to connect; all of their tools are added to the agent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_servers: Vec<McpServerSpec>,
+
+    /// Per-tool execution policies.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tool_policies: HashMap<String, ToolExecutionPolicy>,
+
+    /// Names of input guardrails registered on the [`SpecRegistry`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_guardrails: Vec<String>,
+
+    /// Names of output guardrails registered on the [`SpecRegistry`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_guardrails: Vec<String>,
+
+    /// JSON Schema for structured output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<OutputSchemaSpec>,
+
+    /// Nested sub-agents.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub managed_agents: Vec<Self>,
+}
+
+impl AgentSpec {
+    /// Parses a spec from a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Spec`] if the document is not a valid spec.
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        serde_json::from_str(s).map_err(|e| AgentError::spec(format!("invalid JSON: {e}")).into())
+    }
+
+    /// Parses a spec from a TOML string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Spec`] if the document is not a valid spec.
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(|e| AgentError::spec(format!("invalid TOML: {e}")).into())
+    }
+
+    /// Parses a spec from a YAML string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Spec`] if the document is not a valid spec.
+    pub fn from_yaml_str(s: &str) -> Result<Self> {
+        serde_norway::from_str(s).map_err(|e| AgentError::spec(format!("invalid YAML: {e}")).into())
+    }
+
+    /// Parses a spec from a string in the given format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Spec`] if the document is not a valid spec.
+    pub fn from_str_with_format(s: &str, format: SpecFormat) -> Result<Self> {
+        match format {
+            SpecFormat::Json => Self::from_json_str(s),
+            SpecFormat::Toml => Self::from_toml_str(s),
+            SpecFormat::Yaml => Self::from_yaml_str(s),
+        }
+    }
+
+    /// Reads a spec from a file, inferring the format from its extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read, or [`AgentError::Spec`]
+    /// if the extension is unsupported or the contents are not a valid spec.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let format = SpecFormat::from_path(path).ok_or_else(|| {
+            AgentError::spec(format!(
+                "cannot infer spec format of '{}' (expected .json, .toml, .yaml or .yml)",
+                path.display()
+            ))
+        })?;
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_str_with_format(&contents, format).map_err(|e| match e {
+            crate::Error::Agent(AgentError::Spec(msg)) => {
+                AgentError::spec(format!("{}: {msg}", path.display())).into()
+            }
+            other => other,
+        })
+    }
+}
+
+/// Declarative output schema, converted into an [`OutputSchema`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputSchemaSpec {
+    /// Schema name (used in the `response_format` API parameter).
+    pub name: String,
+    /// JSON Schema definition.
+    pub schema: Value,
+    /// Whether to enforce strict JSON schema validation.
+    #[serde(default = "default_strict")]
+    pub strict: bool,
+}
+
+const fn default_strict() -> bool {
+    true
+}
+
+impl From<&OutputSchemaSpec> for OutputSchema {
+    fn from(spec: &OutputSchemaSpec) -> Self {
+        Self::with_strict(&spec.name, spec.schema.clone(), spec.strict)
+    }
+}
+
+/// Declarative MCP server connection.
+///
+/// Exactly one of `command` (stdio transport) or `url` (streamable HTTP)
+/// must be set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct McpServerSpec {
+    /// Connection name used in logs and errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Executable to spawn for a stdio server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// Arguments for `command`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+
+    /// Environment variables for `command`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+
+    /// Working directory for `command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+
+    /// Endpoint of a streamable HTTP server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Bearer token sent to `url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+}
+
+impl McpServerSpec {
+    /// Human-readable label for error messages.
+    fn label(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.command.clone())
+            .or_else(|| self.url.clone())
+            .unwrap_or_else(|| "<unnamed>".to_owned())
+    }
+
+    /// Connects to the server and returns its tools.
+    #[cfg(feature = "mcp")]
+    async fn connect_tools(&self) -> Result<Vec<BoxedTool>> {
+        use crate::mcp::McpServer;
+
+        let server = match (&self.command, &self.url) {
+            (Some(command), None) => {
+                let mut builder = McpServer::stdio(command, &self.args).envs(self.env.clone());
+                if let Some(ref dir) = self.working_dir {
+                    builder = builder.working_dir(dir);
+                }
+                if let Some(ref name) = self.name {
+                    builder = builder.name(name);
+                }
+                builder.connect().await?
+            }
+            (None, Some(url)) => {
+                let mut builder = McpServer::http(url);
+                if let Some(ref token) = self.bearer_token {
+                    builder = builder.bearer_auth(token);
+                }
+                if let Some(ref name) = self.name {
+                    builder = builder.name(name);
+                }
+                builder.connect().await?
+            }
+            _ => {
+                return Err(AgentError::spec(format!(
+                    "MCP server '{}' must set exactly one of `command` or `url`",
+                    self.label()
+                ))
+                .into());
+            }
+        };
+        server.tools().await
+    }
+
+    #[cfg(not(feature = "mcp"))]
+    #[allow(clippy::unused_async)]
+    async fn connect_tools(&self) -> Result<Vec<BoxedTool>> {
+        Err(AgentError::spec(format!(
+            "MCP server '{}' requires the `mcp` feature",
+            self.label()
+        ))
+        .into())
+    }
+}
+
+/// Factory producing a fresh tool instance for each agent that references it.
+pub type ToolFactory = Arc<dyn Fn() -> BoxedTool + Send + Sync>;
+
+/// Resolves the named references in an [`AgentSpec`] and builds [`Agent`]s.
+///
+/// Register providers, custom tools and guardrails once, then build any
+/// number of agents from specs:
+///
+/// - **providers** — by name, plus an optional default
+/// - **tools** — custom factories take precedence over built-in tools
+///   created by [`tools::create_tool`](crate::tools::create_tool)
+/// - **guardrails** — keyed by their own [`name`](InputGuardrail::name)
+#[derive(Clone, Default)]
+pub struct SpecRegistry {
+    providers: HashMap<String, SharedChatProvider>,
+    default_provider: Option<SharedChatProvider>,
+    tools: HashMap<String, ToolFactory>,
+    input_guardrails: HashMap<String, InputGuardrail>,
+    output_guardrails: HashMap<String, OutputGuardrail>,
+}
+
+impl fmt::Debug for SpecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpecRegistry")
+            .field("providers", &self.providers.keys().collect::<Vec<_>>())
+            .field("default_provider", &self.default_provider.is_some())
+            .field("tools", &self.tools.keys().collect::<Vec<_>>())
+            .field(
+                "input_guardrails",
+                &self.input_guardrails.keys().collect::<Vec<_>>(),
+            )
+            .field(
+                "output_guardrails",
+                &self.output_guardrails.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl SpecRegistry {
+    /// Creates an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a provider under `name`.
+    #[must_use]
+    pub fn provider(mut self, name: impl Into<String>, provider: SharedChatProvider) -> Self {
+        self.providers.insert(name.into(), provider);
+        self
+    }
+
+    /// Sets the provider used by specs that do not name one.
+    #[must_use]
+    pub fn default_provider(mut self, provider: SharedChatProvider) -> Self {
+        self.default_provider = Some(provider);
+        self
+    }
+
+    /// Registers a custom tool factory under `name`.
+    #[must_use]
+    pub fn tool<F>(mut self, name: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn() -> BoxedTool + Send + Sync + 'static,
+    {
+        self.tools.insert(name.into(), Arc::new(factory));
+        self
+    }
+
+    /// Registers an input guardrail under its own name.
+    #[must_use]
+    pub fn input_guardrail(mut self, guardrail: InputGuardrail) -> Self {
+        self.input_guardrails
+            .insert(guardrail.name().to_owned(), guardrail);
+        self
+    }
+
+    /// Registers an output guardrail under its own name.
+    #[must_use]
+    pub fn output_guardrail(mut self, guardrail: OutputGuardrail) -> Self {
+        self.output_guardrails
+            .insert(guardrail.name().to_owned(), guardrail);
+        self
+    }
+
+    /// Reads a spec file and builds the agent it describes.
+    ///
+    /// # Errors
+    ///
+    /// See [`AgentSpec::from_file`] and [`build`](Self::build).
+    pub async fn load(&self, path: impl AsRef<Path>) -> Result<Agent> {
+        let spec = AgentSpec::from_file(path)?;
+        self.build(&spec).await
+    }
+
+    /// Builds an [`Agent`] (and its managed agents) from a spec.
+    ///
+    /// MCP servers are connected during the build.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Spec`] for unknown providers, tools, tool
+    /// policy targets or guardrails, or propagates MCP connection failures.
+    pub async fn build(&self, spec: &AgentSpec) -> Result<Agent> {
+        self.build_with_parent(spec, None).await
+    }
+
+    fn build_with_parent<'a>(
+        &'a self,
+        spec: &'a AgentSpec,
+        parent_provider: Option<&'a SharedChatProvider>,
+    ) -> Pin<Box<dyn Future<Output = Result<Agent>> + Send + 'a>> {
+        Box::pin(async move {
+            if spec.name.trim().is_empty() {
+                return Err(AgentError::spec("agent `name` must not be empty").into());
+            }
+            let agent_name = &spec.name;
+
+            let provider = match spec.provider {
+                Some(ref name) => Some(self.providers.get(name).cloned().ok_or_else(|| {
+                    AgentError::spec(format!(
+                        "agent '{agent_name}' references unknown provider '{name}'"
+                    ))
+                })?),
+                None => self
+                    .default_provider
+                    .clone()
+                    .or_else(|| parent_provider.cloned()),
+            };
+
+            let mut agent = Agent::new(agent_name);
+            if let Some(ref description) = spec.description {
+                agent = agent.description(description);
+            }
+            if let Some(ref instructions) = spec.instructions {
+                agent = agent.instructions(instructions);
+            }
+            if let Some(ref model) = spec.model {
+                agent = agent.model(model);
+            }
+            if let Some(max_steps) = spec.max_steps {
+                agent = agent.max_steps(max_steps);
+            }
+            if let Some(ref provider) = provider {
+                agent = agent.provider(Arc::clone(provider));
+            }
+
+            for name in &spec.tools {
+                agent = agent.tool(self.resolve_tool(agent_name, name)?);
+            }
+            for server in &spec.mcp_servers {
+                agent.tools.extend(server.connect_tools().await?);
+            }
+
+            for name in &spec.input_guardrails {
+                let guardrail = self.input_guardrails.get(name).cloned().ok_or_else(|| {
+                    AgentError::spec(format!(
+                        "agent '{agent_name}' references unknown input guardrail '{name}'"
+                    ))
+                })?;
+                agent = agent.input_guardrail(guardrail);
+            }
+            for name in &spec.output_guardrails {
+                let guardrail = self.output_guardrails.get(name).cloned().ok_or_else(|| {
+                    AgentError::spec(format!(
+                        "agent '{agent_name}' references unknown output guardrail '{name}'"
+                    ))
+                })?;
+                agent = agent.output_guardrail(guardrail);
+            }
+
+            if let Some(ref schema) = spec.output_schema {
+                agent = agent.output_schema(schema.into());
+            }
+
+            for child in &spec.managed_agents {
+                let sub = self.build_with_parent(child, provider.as_ref()).await?;
+                agent = agent.managed_agent(sub);
+            }
+
+            // Checked last, so policies may name MCP tools and managed agents.
+            for (tool, policy) in &spec.tool_policies {
+                let known = agent.tools.iter().any(|t| t.name() == *tool)
+                    || agent.managed_agents.iter().any(|a| a.name == *tool)
+                    || agent.managed_workflows.iter().any(|w| w.name() == tool);
+                if !known {
+                    return Err(AgentError::spec(format!(
+                        "agent '{agent_name}' sets a policy for unknown tool '{tool}'"
+                    ))
+                    .into());
+                }
+                agent = agent.tool_policy(tool, *policy);
+            }
+
+            Ok(agent)
+        })
+    }
+
+    /// Resolves a tool name against custom factories, then built-in tools.
+    fn resolve_tool(&self, agent_name: &str, name: &str) -> Result<BoxedTool> {
+        if let Some(factory) = self.tools.get(name) {
+            return Ok(factory());
+        }
+        #[cfg(feature = "toolkit")]
+        if let Some(tool) = crate::tools::create_tool(name) {
+            return Ok(tool);
+        }
+        Err(AgentError::spec(format!(
+            "agent '{agent_name}' references unknown tool '{name}'"
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::callback::RunContext;
+    use crate::guardrail::{GuardrailOutput, InputGuardrailCheck};
+    use crate::llms::ReplayProvider;
+    use crate::message::Message;
+    use crate::test_util::EchoTool;
+
+    struct PassCheck;
+
+    #[async_trait]
+    impl InputGuardrailCheck for PassCheck {
+        async fn check(
+            &self,
+            _context: &RunContext,
+            _agent_name: &str,
+            _input: &[Message],
+        ) -> Result<GuardrailOutput> {
+            Ok(GuardrailOutput::pass())
+        }
+    }
+
+    fn registry() -> SpecRegistry {
+        SpecRegistry::new()
+            .provider("mock", Arc::new(ReplayProvider::from_entries(vec![])))
+            .tool("echo", || Box::new(EchoTool))
+            .input_guardrail(InputGuardrail::new("pass", PassCheck))
+    }
+
+    fn spec_error(err: &crate::Error) -> &str {
+        match err {
+            crate::Error::Agent(AgentError::Spec(msg)) => msg,
+            other => panic!("expected spec error, got {other:?}"),
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        const TOML: &str = r#"
+            name = "root"
+            model = "gpt-4o"
+            tools = ["echo"]
+
+            [tool_policies]
+            echo = "require_confirmation"
+
+            [output_schema]
+            name = "answer"
+            schema = { type = "object" }
+
+            [[managed_agents]]
+            name = "child"
+        "#;
+
+        const YAML: &str = "
+name: root
+model: gpt-4o
+tools: [echo]
+tool_policies:
+  echo: require_confirmation
+output_schema:
+  name: answer
+  schema:
+    type: object
+managed_agents:
+  - name: child
+";
+
+        const JSON: &str = r#"{
+            "name": "root",
+            "model": "gpt-4o",
+            "tools": ["echo"],
+            "tool_policies": {"echo": "RequireConfirmation"},
+            "output_schema": {"name": "answer", "schema": {"type": "object"}},
+            "managed_agents": [{"name": "child"}]
+        }"#;
+
+        #[test]
+        fn formats_are_equivalent() {
+            let toml = AgentSpec::from_toml_str(TOML).unwrap();
+            let yaml = AgentSpec::from_yaml_str(YAML).unwrap();
+            let json = AgentSpec::from_json_str(JSON).unwrap();
+            assert_eq!(toml, yaml);
+            assert_eq!(toml, json);
+            assert_eq!(
+                toml.tool_policies["echo"],
+                ToolExecutionPolicy::RequireConfirmation
+            );
+            assert!(toml.output_schema.as_ref().unwrap().strict);
+        }
+
+        #[test]
+        fn unknown_fields_are_rejected() {
+            let err = AgentSpec::from_toml_str("name = \"a\"\nmodle = \"gpt-4o\"").unwrap_err();
+            assert!(spec_error(&err).contains("modle"));
+        }
+
+        #[test]
+        fn format_is_inferred_from_extension() {
+            assert_eq!(
+                SpecFormat::from_path(Path::new("a.TOML")),
+                Some(SpecFormat::Toml)
+            );
+            assert_eq!(
+                SpecFormat::from_path(Path::new("a.yml")),
+                Some(SpecFormat::Yaml)
+            );
+            assert_eq!(SpecFormat::from_path(Path::new("a.txt")), None);
+        }
+
+        #[test]
+        fn from_file_reads_and_reports_path() {
+            let dir = std::env::temp_dir().join("machi_test_spec");
+            std::fs::create_dir_all(&dir).unwrap();
+            let good = dir.join(format!("{}.yaml", uuid::Uuid::new_v4()));
+            std::fs::write(&good, "name: from-file\n").unwrap();
+            assert_eq!(AgentSpec::from_file(&good).unwrap().name, "from-file");
+
+            let bad = dir.join(format!("{}.json", uuid::Uuid::new_v4()));
+            std::fs::write(&bad, "{").unwrap();
+            let err = AgentSpec::from_file(&bad).unwrap_err();
+            assert!(spec_error(&err).contains(&bad.display().to_string()));
+
+            let _ = std::fs::remove_file(&good);
+            let _ = std::fs::remove_file(&bad);
+        }
+    }
+
+    mod building {
+        use super::*;
+
+        #[tokio::test]
+        async fn builds_full_agent_tree() {
+            let spec = AgentSpec {
+                name: "root".into(),
+                description: Some("Root agent".into()),
+                instructions: Some("Be helpful.".into()),
+                model: Some("gpt-4o".into()),
+                provider: Some("mock".into()),
+                max_steps: Some(3),
+                tools: vec!["echo".into()],
+                input_guardrails: vec!["pass".into()],
+                output_schema: Some(OutputSchemaSpec {
+                    name: "answer".into(),
+                    schema: serde_json::json!({"type": "object"}),
+                    strict: false,
+                }),
+                managed_agents: vec![AgentSpec {
+                    name: "child".into(),
+                    ..AgentSpec::default()
+                }],
+                ..AgentSpec::default()
+            };
+
+            let agent = registry().build(&spec).await.unwrap();
+            assert_eq!(agent.name(), "root");
+            assert_eq!(agent.get_description(), "Root agent");
+            assert_eq!(agent.resolve_instructions(), "Be helpful.");
+            assert_eq!(agent.get_max_steps(), 3);
+            assert_eq!(agent.tool_count(), 1);
+            assert_eq!(agent.total_tool_count(), 2);
+            assert_eq!(agent.input_guardrails.len(), 1);
+            assert!(!agent.output_schema.as_ref().unwrap().is_strict());
+
+            // Managed agents inherit the parent's provider.
+            assert!(agent.managed_agents[0].has_provider());
+        }
+
+        #[tokio::test]
+        async fn unknown_provider_is_reported() {
+            let spec = AgentSpec {
+                name: "a".into(),
+                provider: Some("anthropic".into()),
+                ..AgentSpec::default()
+            };
+            let err = registry().build(&spec).await.unwrap_err();
+            assert!(spec_error(&err).contains("unknown provider 'anthropic'"));
+        }
+
+        #[tokio::test]
+        async fn unknown_tool_is_reported() {
+            let spec = AgentSpec {
+                name: "a".into(),
+                tools: vec!["teleport".into()],
+                ..AgentSpec::default()
+            };
+            let err = registry().build(&spec).await.unwrap_err();
+            assert!(spec_error(&err).contains("unknown tool 'teleport'"));
+        }
+
+        #[tokio::test]
+        async fn policies_must_name_a_tool_or_managed_agent() {
+            let mut spec = AgentSpec {
+                name: "a".into(),
+                tools: vec!["echo".into()],
+                tool_policies: HashMap::from([
+                    ("echo".into(), ToolExecutionPolicy::RequireConfirmation),
+                    ("child".into(), ToolExecutionPolicy::Forbidden),
+                ]),
+                managed_agents: vec![AgentSpec {
+                    name: "child".into(),
+                    ..AgentSpec::default()
+                }],
+                ..AgentSpec::default()
+            };
+            registry().build(&spec).await.unwrap();
+
+            spec.tool_policies
+                .insert("ecoh".into(), ToolExecutionPolicy::Forbidden);
+            let err = registry().build(&spec).await.unwrap_err();
+            assert!(spec_error(&err).contains("unknown tool 'ecoh'"));
+        }
+
+        #[tokio::test]
+        async fn unknown_guardrail_in_nested_agent_is_reported() {
+            let spec = AgentSpec {
+                name: "parent".into(),
+                managed_agents: vec![AgentSpec {
+                    name: "child".into(),
+                    output_guardrails: vec!["pii".into()],
+                    ..AgentSpec::default()
+                }],
+                ..AgentSpec::default()
+            };
+            let err = registry().build(&spec).await.unwrap_err();
+            let msg = spec_error(&err);
+            assert!(msg.contains("'child'"), "{msg}");
+            assert!(msg.contains("unknown output guardrail 'pii'"), "{msg}");
+        }
+
+        #[cfg(feature = "toolkit")]
+        #[tokio::test]
+        async fn builtin_tools_resolve_by_name() {
+            let spec = AgentSpec {
+                name: "a".into(),
+                tools: vec!["read_file".into(), "exec".into()],
+                ..AgentSpec::default()
+            };
+            let agent = SpecRegistry::new().build(&spec).await.unwrap();
+            assert_eq!(agent.tool_count(), 2);
+        }
+
+        #[tokio::test]
+        async fn mcp_server_requires_exactly_one_transport() {
+            let spec = AgentSpec {
+                name: "a".into(),
+                mcp_servers: vec![McpServerSpec {
+                    name: Some("broken".into()),
+                    ..McpServerSpec::default()
+                }],
+                ..AgentSpec::default()
+            };
+            let err = registry().build(&spec).await.unwrap_err();
+            assert!(spec_error(&err).contains("'broken'"));
+        }
+    }
+}
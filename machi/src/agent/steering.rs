@@ -0,0 +1,219 @@
+//! Mid-run steering of streamed agent runs.
+//!
+//! [`Runner::run_steered`](super::Runner::run_steered) returns a
+//! [`SteeringHandle`] alongside the event stream. Messages sent through the
+//! handle are appended to the conversation before the next LLM step and
+//! reported as [`RunEvent::MessageInjected`](super::RunEvent::MessageInjected),
+//! so a user can correct an agent mid-tool-loop without restarting the run.
+
+use tokio::sync::mpsc;
+
+use crate::message::Message;
+
+/// Sender half for injecting messages into an active streamed run.
+///
+/// Cheap to clone; every clone feeds the same run. Sending fails once the run
+/// has finished (or its stream was dropped).
+///
+/// Injected messages are picked up at step boundaries. A message sent while
+/// the final LLM call is in flight makes the run take another step to answer
+/// it. Once the run starts its last allowed step, or finishes, sending fails,
+/// so a `true` return means the message will be seen.
+#[derive(Debug, Clone)]
+pub struct SteeringHandle {
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+/// Receiver half drained by the runner before each LLM step.
+pub type SteeringReceiver = mpsc::UnboundedReceiver<Message>;
+
+impl SteeringHandle {
+    /// Create a connected handle/receiver pair.
+    #[must_use]
+    pub fn channel() -> (Self, SteeringReceiver) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    /// Inject a user message. Returns `false` if the run has already ended.
+    #[must_use]
+    pub fn send_user(&self, content: impl Into<String>) -> bool {
+        self.send(Message::user(content))
+    }
+
+    /// Inject a system note. Returns `false` if the run has already ended.
+    #[must_use]
+    pub fn send_system(&self, content: impl Into<String>) -> bool {
+        self.send(Message::system(content))
+    }
+
+    /// Inject an arbitrary message. Returns `false` if the run has already ended.
+    #[must_use]
+    pub fn send(&self, message: Message) -> bool {
+        self.tx.send(message).is_ok()
+    }
+
+    /// Returns `true` once the run has ended and messages can no longer be injected.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// Take every message queued so far without waiting.
+pub fn drain(rx: &mut SteeringReceiver) -> Vec<Message> {
+    std::iter::from_fn(|| rx.try_recv().ok()).collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig, RunEvent, RunResult};
+    use crate::chat::ChatResponse;
+    use crate::memory::{InMemorySession, Session};
+    use crate::message::Role;
+    use crate::test_util::{EchoTool, ScriptedProvider, echo_then_answer, last_user_text};
+
+    fn agent() -> Agent {
+        Agent::new("steered")
+            .provider(echo_then_answer())
+            .tool(Box::new(EchoTool))
+    }
+
+    #[test]
+    fn drain_takes_queued_messages_in_order() {
+        let (handle, mut rx) = SteeringHandle::channel();
+        assert!(handle.send_user("a"));
+        assert!(handle.send_system("b"));
+        let drained = drain(&mut rx);
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].role, Role::User);
+        assert_eq!(drained[1].role, Role::System);
+        assert!(drain(&mut rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn message_sent_mid_run_reaches_next_step() {
+        let agent = agent();
+        let (handle, mut stream) = agent.run_steered("use the US dataset", RunConfig::default());
+
+        let mut injected = Vec::new();
+        let mut result: Option<RunResult> = None;
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                RunEvent::ToolCallCompleted { .. } => {
+                    assert!(handle.send_user("actually use the EU dataset"));
+                }
+                RunEvent::MessageInjected { message } => injected.push(message),
+                RunEvent::RunCompleted { result: r } => result = Some(*r),
+                _ => {}
+            }
+        }
+
+        assert_eq!(injected.len(), 1);
+        assert_eq!(
+            injected[0].text().as_deref(),
+            Some("actually use the EU dataset")
+        );
+        assert_eq!(
+            result.unwrap().text(),
+            Some("final: actually use the EU dataset")
+        );
+
+        drop(stream);
+        assert!(handle.is_closed());
+        assert!(!handle.send_user("too late"));
+    }
+
+    /// Agent whose first LLM call sends `late` through the handle in `slot`,
+    /// then answers; later calls answer the last user message.
+    fn late_steering_agent(
+        slot: &Arc<Mutex<Option<SteeringHandle>>>,
+        accepted: &Arc<Mutex<Vec<bool>>>,
+    ) -> Agent {
+        let slot = Arc::clone(slot);
+        let accepted = Arc::clone(accepted);
+        let provider = ScriptedProvider::new(move |req| {
+            let handle = slot.lock().unwrap().take();
+            if let Some(handle) = handle {
+                accepted
+                    .lock()
+                    .unwrap()
+                    .push(handle.send_user("make it EU"));
+                return ChatResponse::from_text("US answer");
+            }
+            ChatResponse::from_text(format!("final: {}", last_user_text(req)))
+        });
+        Agent::new("steered").provider(Arc::new(provider))
+    }
+
+    #[tokio::test]
+    async fn message_sent_during_final_call_continues_the_run() {
+        let slot = Arc::new(Mutex::new(None));
+        let accepted = Arc::new(Mutex::new(Vec::new()));
+        let agent = late_steering_agent(&slot, &accepted);
+        let session = Arc::new(InMemorySession::new("s"));
+        let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+        let (handle, stream) = agent.run_steered("question", config);
+        *slot.lock().unwrap() = Some(handle);
+
+        let events: Vec<RunEvent> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(*accepted.lock().unwrap(), [true]);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            RunEvent::MessageInjected { message } if message.text().as_deref() == Some("make it EU")
+        )));
+        let Some(RunEvent::RunCompleted { result }) = events.last() else {
+            panic!("expected RunCompleted");
+        };
+        assert_eq!(result.text(), Some("final: make it EU"));
+        assert_eq!(result.steps, 2);
+
+        let saved: Vec<String> = session
+            .get_messages(None)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(Message::text)
+            .collect();
+        assert_eq!(saved, ["question", "make it EU", "final: make it EU"]);
+    }
+
+    #[tokio::test]
+    async fn sending_fails_once_the_last_step_starts() {
+        let slot = Arc::new(Mutex::new(None));
+        let accepted = Arc::new(Mutex::new(Vec::new()));
+        let agent = late_steering_agent(&slot, &accepted).max_steps(1);
+        let (handle, stream) = agent.run_steered("question", RunConfig::default());
+        *slot.lock().unwrap() = Some(handle);
+
+        let events: Vec<RunEvent> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(*accepted.lock().unwrap(), [false]);
+        assert!(matches!(
+            events.last(),
+            Some(RunEvent::RunCompleted { result }) if result.text() == Some("US answer")
+        ));
+    }
+
+    #[tokio::test]
+    async fn message_queued_before_start_is_injected_in_first_step() {
+        let agent = agent();
+        let (handle, stream) = agent.run_steered("hello", RunConfig::default());
+        assert!(handle.send_system("Be brief."));
+
+        let events: Vec<RunEvent> = stream.map(Result::unwrap).collect().await;
+        let first_injection = events
+            .iter()
+            .position(|e| matches!(e, RunEvent::MessageInjected { .. }))
+            .unwrap();
+        assert!(matches!(
+            events[first_injection - 1],
+            RunEvent::StepStarted { step: 1 }
+        ));
+    }
+}
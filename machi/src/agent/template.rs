@@ -0,0 +1,557 @@
+//! Prompt templates for agent instructions.
+//!
+//! [`PromptTemplate`] is a small, logic-light template language so prompts can
+//! live in files and be edited without touching Rust code:
+//!
+//! | Syntax | Meaning |
+//! |--------|---------|
+//! | `{{name}}`, `{{user.name}}` | Substitute a variable (dotted paths index into objects) |
+//! | `{{#if flag}}…{{else}}…{{/if}}` | Render a branch when the value is truthy |
+//! | `{{#each items}}…{{else}}…{{/each}}` | Repeat for each list item; `else` renders for an empty list |
+//! | `{{this}}`, `{{@index}}`, `{{@first}}`, `{{@last}}` | Current item and loop position |
+//! | `{{! comment }}` | Ignored |
+//!
+//! Inside a loop, names are looked up on the current item first, then in the
+//! enclosing scopes. Values that are `null`, `false`, `0`, `""`, `[]` or `{}`
+//! are falsy.
+//!
+//! # Example
+//!
+//! ```rust
+//! use machi::agent::PromptTemplate;
+//! use serde_json::{Map, json};
+//!
+//! let template = PromptTemplate::parse(
+//!     "Help {{user}}.{{#if rules}} Rules:{{#each rules}} {{@index}}. {{this}}{{/each}}{{/if}}",
+//! )?;
+//! let vars: Map<_, _> = json!({"user": "Ada", "rules": ["be brief", "cite sources"]})
+//!     .as_object()
+//!     .cloned()
+//!     .unwrap_or_default();
+//! assert_eq!(
+//!     template.render(&vars)?,
+//!     "Help Ada. Rules: 1. be brief 2. cite sources",
+//! );
+//! # Ok::<(), machi::Error>(())
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use super::error::AgentError;
+use crate::error::Result;
+
+/// A parsed prompt template.
+///
+/// Parsing validates the syntax up front; cloning is cheap.
+#[derive(Clone)]
+pub struct PromptTemplate {
+    source: Arc<str>,
+    nodes: Arc<[Node]>,
+}
+
+/// A parsed template element.
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(VarPath),
+    If {
+        cond: VarPath,
+        then: Vec<Self>,
+        otherwise: Vec<Self>,
+    },
+    Each {
+        list: VarPath,
+        body: Vec<Self>,
+        otherwise: Vec<Self>,
+    },
+}
+
+/// A dotted variable reference such as `user.name`.
+#[derive(Debug)]
+struct VarPath(Vec<String>);
+
+impl VarPath {
+    fn parse(expr: &str, line: usize) -> Result<Self> {
+        let valid = |s: &str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '@')
+        };
+        if !expr.split('.').all(valid) {
+            return Err(syntax_error(line, format!("invalid variable '{expr}'")));
+        }
+        Ok(Self(expr.split('.').map(str::to_owned).collect()))
+    }
+
+    fn root(&self) -> &str {
+        &self.0[0]
+    }
+}
+
+impl fmt::Display for VarPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join("."))
+    }
+}
+
+/// A lexical token: literal text or the trimmed contents of a `{{ }}` tag.
+enum Token<'s> {
+    Text(&'s str),
+    Tag { body: &'s str, line: usize },
+}
+
+fn syntax_error(line: usize, msg: impl fmt::Display) -> crate::Error {
+    AgentError::template(format!("line {line}: {msg}")).into()
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        let line = source[..source.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| syntax_error(line, "unclosed '{{'"))?;
+        tokens.push(Token::Tag {
+            body: after[..end].trim(),
+            line,
+        });
+        rest = &after[end + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    Ok(tokens)
+}
+
+/// Parses a token stream into nodes, tracking the enclosing block.
+struct Parser<'s> {
+    tokens: std::vec::IntoIter<Token<'s>>,
+}
+
+/// What ended a run of nodes.
+enum End {
+    Eof,
+    Else(usize),
+    Close(&'static str, usize),
+}
+
+impl Parser<'_> {
+    fn parse_nodes(&mut self) -> Result<(Vec<Node>, End)> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            let (body, line) = match token {
+                Token::Text(text) => {
+                    nodes.push(Node::Text(text.to_owned()));
+                    continue;
+                }
+                Token::Tag { body, line } => (body, line),
+            };
+            if body.starts_with('!') {
+                continue;
+            }
+            match body.split_once(char::is_whitespace) {
+                Some(("#if", expr)) => {
+                    let cond = VarPath::parse(expr.trim(), line)?;
+                    let (then, otherwise) = self.parse_block("if", line)?;
+                    nodes.push(Node::If {
+                        cond,
+                        then,
+                        otherwise,
+                    });
+                }
+                Some(("#each", expr)) => {
+                    let list = VarPath::parse(expr.trim(), line)?;
+                    let (body, otherwise) = self.parse_block("each", line)?;
+                    nodes.push(Node::Each {
+                        list,
+                        body,
+                        otherwise,
+                    });
+                }
+                _ => match body {
+                    "else" => return Ok((nodes, End::Else(line))),
+                    "/if" => return Ok((nodes, End::Close("if", line))),
+                    "/each" => return Ok((nodes, End::Close("each", line))),
+                    _ if body.starts_with(['#', '/']) => {
+                        return Err(syntax_error(line, format!("unknown tag '{{{{{body}}}}}'")));
+                    }
+                    _ => nodes.push(Node::Var(VarPath::parse(body, line)?)),
+                },
+            }
+        }
+        Ok((nodes, End::Eof))
+    }
+
+    /// Parse the body (and optional `else` branch) of a `#name` block.
+    fn parse_block(
+        &mut self,
+        name: &'static str,
+        open_line: usize,
+    ) -> Result<(Vec<Node>, Vec<Node>)> {
+        let (body, end) = self.parse_nodes()?;
+        let (otherwise, end) = match end {
+            End::Else(_) => self.parse_nodes()?,
+            end => (Vec::new(), end),
+        };
+        match end {
+            End::Close(closed, _) if closed == name => Ok((body, otherwise)),
+            End::Close(closed, line) => Err(syntax_error(
+                line,
+                format!("'{{{{/{closed}}}}}' does not match open '{{{{#{name}}}}}'"),
+            )),
+            End::Else(line) => Err(syntax_error(
+                line,
+                format!("duplicate '{{{{else}}}}' in '#{name}'"),
+            )),
+            End::Eof => Err(syntax_error(
+                open_line,
+                format!("unclosed '{{{{#{name}}}}}'"),
+            )),
+        }
+    }
+}
+
+/// One level of loop scope while rendering.
+struct Scope {
+    item: Value,
+    index: usize,
+    len: usize,
+}
+
+/// Render state: root variables plus the stack of enclosing loops.
+struct Renderer<'v> {
+    vars: &'v Map<String, Value>,
+    scopes: Vec<Scope>,
+}
+
+impl Renderer<'_> {
+    fn lookup(&self, path: &VarPath) -> Option<Value> {
+        let (first, rest) = path.0.split_first()?;
+        let base = match first.as_str() {
+            "this" => self.scopes.last().map_or(Value::Null, |s| s.item.clone()),
+            "@index" => Value::from(self.scopes.last()?.index + 1),
+            "@first" => Value::from(self.scopes.last()?.index == 0),
+            "@last" => {
+                let scope = self.scopes.last()?;
+                Value::from(scope.index + 1 == scope.len)
+            }
+            name => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|s| s.item.get(name))
+                .or_else(|| self.vars.get(name))?
+                .clone(),
+        };
+        rest.iter()
+            .try_fold(base, |value, key| value.get(key).cloned())
+    }
+
+    fn render(&mut self, nodes: &[Node], out: &mut String) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var(path) => {
+                    let value = self.lookup(path).ok_or_else(|| {
+                        AgentError::template(format!("variable '{path}' is not defined"))
+                    })?;
+                    push_value(out, &value);
+                }
+                Node::If {
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if self.lookup(cond).is_some_and(|v| is_truthy(&v)) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    self.render(branch, out)?;
+                }
+                Node::Each {
+                    list,
+                    body,
+                    otherwise,
+                } => self.render_each(list, body, otherwise, out)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn render_each(
+        &mut self,
+        list: &VarPath,
+        body: &[Node],
+        otherwise: &[Node],
+        out: &mut String,
+    ) -> Result<()> {
+        let items = match self.lookup(list) {
+            Some(Value::Array(items)) if !items.is_empty() => items,
+            Some(Value::Array(_) | Value::Null) | None => return self.render(otherwise, out),
+            Some(_) => {
+                return Err(AgentError::template(format!("'{list}' is not a list")).into());
+            }
+        };
+        let len = items.len();
+        for (index, item) in items.into_iter().enumerate() {
+            self.scopes.push(Scope { item, index, len });
+            let result = self.render(body, out);
+            self.scopes.pop();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+fn push_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::String(s) => out.push_str(s),
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// Collect root names substituted outside any block.
+fn collect_required<'n>(nodes: &'n [Node], names: &mut Vec<&'n str>) {
+    for node in nodes {
+        if let Node::Var(path) = node {
+            let root = path.root();
+            if !root.starts_with('@') && root != "this" && !names.contains(&root) {
+                names.push(root);
+            }
+        }
+    }
+}
+
+impl PromptTemplate {
+    /// Parse a template, validating its syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Template`] for unclosed tags or blocks,
+    /// mismatched closing tags, and invalid variable names.
+    pub fn parse(source: impl Into<String>) -> Result<Self> {
+        let source: String = source.into();
+        let mut parser = Parser {
+            tokens: tokenize(&source)?.into_iter(),
+        };
+        let (nodes, end) = parser.parse_nodes()?;
+        match end {
+            End::Eof => {}
+            End::Else(line) => return Err(syntax_error(line, "'{{else}}' outside a block")),
+            End::Close(name, line) => {
+                return Err(syntax_error(line, format!("unexpected '{{{{/{name}}}}}'")));
+            }
+        }
+        Ok(Self {
+            source: source.into(),
+            nodes: nodes.into(),
+        })
+    }
+
+    /// Read and parse a template file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read, or a parse error.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(std::fs::read_to_string(path)?)
+    }
+
+    /// The template source text.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Variables that must be defined for the template to render.
+    ///
+    /// These are the names substituted outside `#if` and `#each` blocks.
+    /// Variables inside blocks are only needed when the block renders, so
+    /// they are reported at render time instead.
+    #[must_use]
+    pub fn required_variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        collect_required(&self.nodes, &mut names);
+        names
+    }
+
+    /// Required variables that are absent from `vars`.
+    #[must_use]
+    pub fn missing_variables(&self, vars: &Map<String, Value>) -> Vec<&str> {
+        self.required_variables()
+            .into_iter()
+            .filter(|name| !vars.contains_key(*name))
+            .collect()
+    }
+
+    /// Render the template with the given variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Template`] if a substituted variable is not
+    /// defined or an `#each` target is not a list.
+    pub fn render(&self, vars: &Map<String, Value>) -> Result<String> {
+        let mut out = String::with_capacity(self.source.len());
+        Renderer {
+            vars,
+            scopes: Vec::new(),
+        }
+        .render(&self.nodes, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl fmt::Debug for PromptTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PromptTemplate").field(&self.source).finish()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn vars(value: Value) -> Map<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn render(source: &str, value: Value) -> Result<String> {
+        PromptTemplate::parse(source)?.render(&vars(value))
+    }
+
+    mod rendering {
+        use super::*;
+
+        #[test]
+        fn substitutes_variables_and_paths() {
+            let out = render(
+                "Hi {{ user.name }}, you have {{count}} items{{missing_ok}}.",
+                json!({"user": {"name": "Ada"}, "count": 3, "missing_ok": null}),
+            )
+            .unwrap();
+            assert_eq!(out, "Hi Ada, you have 3 items.");
+        }
+
+        #[test]
+        fn conditionals_pick_a_branch() {
+            let source = "{{#if vip}}VIP{{else}}regular{{/if}}";
+            assert_eq!(render(source, json!({"vip": true})).unwrap(), "VIP");
+            assert_eq!(render(source, json!({"vip": ""})).unwrap(), "regular");
+            assert_eq!(render(source, json!({})).unwrap(), "regular");
+        }
+
+        #[test]
+        fn loops_expose_item_and_position() {
+            let source = "{{#each tools}}{{@index}}:{{name}}@{{env}}{{#if @last}}.{{else}}, {{/if}}{{/each}}";
+            let out = render(
+                source,
+                json!({"env": "prod", "tools": [{"name": "search"}, {"name": "fetch"}]}),
+            )
+            .unwrap();
+            assert_eq!(out, "1:search@prod, 2:fetch@prod.");
+        }
+
+        #[test]
+        fn nested_loops_and_empty_else() {
+            let source = "{{#each groups}}[{{#each this}}{{this}}{{/each}}]{{else}}none{{/each}}";
+            assert_eq!(
+                render(source, json!({"groups": [[1, 2], [3]]})).unwrap(),
+                "[12][3]"
+            );
+            assert_eq!(render(source, json!({"groups": []})).unwrap(), "none");
+        }
+
+        #[test]
+        fn comments_are_dropped() {
+            assert_eq!(render("a{{! note }}b", json!({})).unwrap(), "ab");
+        }
+
+        #[test]
+        fn undefined_variable_is_an_error() {
+            let err =
+                render("{{#if x}}{{user.name}}{{/if}}", json!({"x": 1, "user": {}})).unwrap_err();
+            assert!(
+                err.to_string().contains("'user.name' is not defined"),
+                "{err}"
+            );
+        }
+
+        #[test]
+        fn each_over_non_list_is_an_error() {
+            let err = render("{{#each n}}{{/each}}", json!({"n": 1})).unwrap_err();
+            assert!(err.to_string().contains("not a list"), "{err}");
+        }
+    }
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn reports_syntax_errors_with_lines() {
+            for (source, expected) in [
+                ("a {{b", "line 1: unclosed '{{'"),
+                ("\n{{#if a}}x", "line 2: unclosed '{{#if}}'"),
+                ("{{#if a}}x{{/each}}", "does not match"),
+                ("{{/if}}", "unexpected '{{/if}}'"),
+                ("{{else}}", "outside a block"),
+                ("{{#unless a}}{{/unless}}", "unknown tag"),
+                ("{{#if a}}1{{else}}2{{else}}3{{/if}}", "duplicate"),
+            ] {
+                let err = PromptTemplate::parse(source).unwrap_err().to_string();
+                assert!(err.contains(expected), "{source:?}: {err}");
+            }
+        }
+
+        #[test]
+        fn required_variables_skip_blocks_and_loop_names() {
+            let template = PromptTemplate::parse(
+                "{{a}} {{b.c}} {{a}} {{#if d}}{{e}}{{/if}}{{#each f}}{{this}}{{/each}}",
+            )
+            .unwrap();
+            assert_eq!(template.required_variables(), ["a", "b"]);
+            assert_eq!(template.missing_variables(&vars(json!({"a": 1}))), ["b"]);
+        }
+
+        #[test]
+        fn loads_from_file() {
+            let path =
+                std::env::temp_dir().join(format!("machi-template-{}.txt", uuid::Uuid::new_v4()));
+            std::fs::write(&path, "Hello {{name}}").unwrap();
+            let template = PromptTemplate::from_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(
+                template.render(&vars(json!({"name": "file"}))).unwrap(),
+                "Hello file"
+            );
+            assert!(PromptTemplate::from_file(&path).is_err());
+        }
+    }
+}
@@ -1,13 +1,16 @@
 //! Hook context types for callback lifecycle events.
 //!
 //! Provides [`RunContext`] which carries shared state across all hook invocations
-//! during an agent run, including cumulative token usage, step tracking, and
-//! user-defined state.
+//! during an agent run, including cumulative token usage, step tracking,
+//! user-defined state, and typed dependencies.
 
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use serde_json::Value;
 
+use super::Dependencies;
 use crate::usage::Usage;
 
 /// Context passed to all hook methods during an agent run.
@@ -22,6 +25,10 @@
 ///   do not modify the execution flow (separation of concerns with guardrails).
 /// - **Cumulative usage**: Tracks token consumption across all LLM calls in the run.
 /// - **User state**: Arbitrary key-value pairs for user-defined data sharing.
+///   With a session in the [`RunConfig`](crate::agent::RunConfig), the state
+///   is restored from it at the start of a run and saved when the run completes.
+/// - **Dependencies**: Live objects (DB pools, clients, credentials) injected
+///   via [`RunConfig::with_dependency`](crate::agent::RunConfig::with_dependency).
 ///
 /// # Example
 ///
@@ -45,6 +52,8 @@ pub struct RunContext {
     agent_name: Option<String>,
     /// User-defined state for sharing data across hooks.
     state: HashMap<String, Value>,
+    /// Typed dependencies for this run.
+    dependencies: Dependencies,
 }
 
 impl RunContext {
@@ -68,6 +77,20 @@ pub const fn with_step(mut self, step: usize) -> Self {
         self
     }
 
+    /// Set the typed dependencies.
+    #[must_use]
+    pub fn with_dependencies(mut self, dependencies: Dependencies) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    /// Set the user-defined state, replacing any existing entries.
+    #[must_use]
+    pub fn with_state(mut self, state: HashMap<String, Value>) -> Self {
+        self.state = state;
+        self
+    }
+
     /// Set the cumulative token usage.
     #[must_use]
     pub const fn with_usage(mut self, usage: Usage) -> Self {
@@ -115,6 +138,18 @@ pub fn remove_state(&mut self, key: &str) -> Option<Value> {
         self.state.remove(key)
     }
 
+    /// Get the run's typed dependencies.
+    #[must_use]
+    pub const fn dependencies(&self) -> &Dependencies {
+        &self.dependencies
+    }
+
+    /// Get the dependency of type `T`, if one was provided.
+    #[must_use]
+    pub fn dependency<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.dependencies.get::<T>()
+    }
+
     /// Update the cumulative token usage by adding new usage.
     pub fn add_usage(&mut self, usage: Usage) {
         self.usage += usage;
@@ -135,7 +170,7 @@ pub fn reset(&mut self) {
         self.usage = Usage::zero();
         self.step = 0;
         self.state.clear();
-        // Preserve agent_name — it is typically set once at construction.
+        // Preserve agent_name and dependencies — they are set once at construction.
     }
 }
 
@@ -183,6 +218,18 @@ fn with_usage_sets_usage() {
             assert_eq!(ctx.usage().output_tokens, 50);
         }
 
+        #[test]
+        fn with_dependencies_exposes_typed_values() {
+            let deps = Dependencies::new().with(Arc::new(String::from("db")));
+            let ctx = RunContext::new().with_dependencies(deps);
+            assert_eq!(
+                ctx.dependency::<String>().as_deref(),
+                Some(&"db".to_owned())
+            );
+            assert!(ctx.dependency::<u8>().is_none());
+            assert_eq!(ctx.dependencies().len(), 1);
+        }
+
         #[test]
         fn builder_chain() {
             let ctx = RunContext::new()
@@ -299,6 +346,14 @@ fn reset_preserves_agent_name() {
             ctx.reset();
             assert_eq!(ctx.agent_name(), Some("preserved"));
         }
+
+        #[test]
+        fn reset_preserves_dependencies() {
+            let deps = Dependencies::new().with(Arc::new(7_u16));
+            let mut ctx = RunContext::new().with_dependencies(deps);
+            ctx.reset();
+            assert_eq!(ctx.dependency::<u16>().as_deref(), Some(&7));
+        }
     }
 
     mod clone {
@@ -0,0 +1,234 @@
+//! Typed dependency container for agent runs.
+//!
+//! [`Dependencies`] is a type map holding live objects — database pools,
+//! HTTP clients, per-user credentials — that cannot be stored as JSON in
+//! [`RunContext`](super::RunContext) state. Values are keyed by their type and
+//! shared via [`Arc`].
+
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A stored dependency together with its type name (for `Debug`).
+type Entry = (&'static str, Arc<dyn Any + Send + Sync>);
+
+/// A type map of shared run dependencies.
+///
+/// Each type can be stored at most once; inserting a second value of the same
+/// type replaces the first. Cloning is cheap.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use machi::callback::Dependencies;
+///
+/// struct TenantId(String);
+///
+/// let deps = Dependencies::new().with(Arc::new(TenantId("acme".into())));
+/// assert_eq!(deps.get::<TenantId>().unwrap().0, "acme");
+/// assert!(deps.get::<String>().is_none());
+/// ```
+#[derive(Clone, Default)]
+pub struct Dependencies {
+    map: Arc<HashMap<TypeId, Entry>>,
+}
+
+impl fmt::Debug for Dependencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.map.values().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl Dependencies {
+    /// Create an empty container.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a dependency, replacing any previous value of the same type.
+    #[must_use]
+    pub fn with<T: Any + Send + Sync>(mut self, value: Arc<T>) -> Self {
+        self.insert(value);
+        self
+    }
+
+    /// Insert a dependency, replacing any previous value of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: Arc<T>) {
+        Arc::make_mut(&mut self.map).insert(TypeId::of::<T>(), (type_name::<T>(), value));
+    }
+
+    /// Get the dependency of type `T`, if present.
+    #[must_use]
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|(_, value)| Arc::clone(value).downcast::<T>().ok())
+    }
+
+    /// Returns `true` if a dependency of type `T` is present.
+    #[must_use]
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the number of stored dependencies.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if no dependencies are stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Tenant(&'static str);
+
+    #[test]
+    fn values_are_keyed_by_type() {
+        let deps = Dependencies::new()
+            .with(Arc::new(Tenant("acme")))
+            .with(Arc::new(42_u32));
+        assert_eq!(deps.len(), 2);
+        assert_eq!(*deps.get::<Tenant>().unwrap(), Tenant("acme"));
+        assert_eq!(*deps.get::<u32>().unwrap(), 42);
+        assert!(!deps.contains::<u64>());
+    }
+
+    #[test]
+    fn insert_replaces_same_type() {
+        let mut deps = Dependencies::new().with(Arc::new(Tenant("a")));
+        deps.insert(Arc::new(Tenant("b")));
+        assert_eq!(deps.len(), 1);
+        assert_eq!(*deps.get::<Tenant>().unwrap(), Tenant("b"));
+    }
+
+    #[test]
+    fn clones_do_not_see_later_inserts() {
+        let base = Dependencies::new().with(Arc::new(1_u8));
+        let mut extended = base.clone();
+        extended.insert(Arc::new(Tenant("x")));
+        assert!(!base.contains::<Tenant>());
+        assert!(extended.contains::<u8>());
+    }
+
+    #[tokio::test]
+    async fn reach_guardrails_tools_and_sub_agents() {
+        use async_trait::async_trait;
+        use serde_json::{Value, json};
+
+        use crate::agent::{Agent, RunConfig};
+        use crate::callback::RunContext;
+        use crate::chat::ChatResponse;
+        use crate::error::Result;
+        use crate::guardrail::{GuardrailOutput, InputGuardrail, InputGuardrailCheck};
+        use crate::message::{Message, Role, ToolCall};
+        use crate::test_util::ScriptedProvider;
+        use crate::tool::{Tool, ToolContext, ToolError};
+
+        struct RequireTenant;
+
+        #[async_trait]
+        impl InputGuardrailCheck for RequireTenant {
+            async fn check(
+                &self,
+                context: &RunContext,
+                _agent_name: &str,
+                _input: &[Message],
+            ) -> Result<GuardrailOutput> {
+                Ok(if context.dependency::<Tenant>().is_some() {
+                    GuardrailOutput::pass()
+                } else {
+                    GuardrailOutput::tripwire("missing tenant")
+                })
+            }
+        }
+
+        struct WhoAmI;
+
+        #[async_trait]
+        impl Tool for WhoAmI {
+            const NAME: &'static str = "whoami";
+            type Args = Value;
+            type Output = String;
+            type Error = ToolError;
+
+            fn description(&self) -> String {
+                "Returns the tenant".to_owned()
+            }
+
+            fn parameters_schema(&self) -> Value {
+                json!({"type": "object"})
+            }
+
+            async fn call(&self, _args: Value) -> std::result::Result<String, ToolError> {
+                Err(ToolError::execution("requires a run context"))
+            }
+
+            async fn call_with_context(
+                &self,
+                _args: Value,
+                ctx: &ToolContext,
+            ) -> std::result::Result<String, ToolError> {
+                ctx.dependency::<Tenant>()
+                    .map(|t| t.0.to_owned())
+                    .ok_or_else(|| ToolError::execution("no tenant"))
+            }
+        }
+
+        /// Calls `tool` once, then answers with the tool result.
+        fn call_then_relay(tool: &'static str, args: &'static str) -> Arc<ScriptedProvider> {
+            Arc::new(ScriptedProvider::new(move |req| {
+                req.messages
+                    .iter()
+                    .find(|m| m.role == Role::Tool)
+                    .map_or_else(
+                        || {
+                            ChatResponse::new(Message::assistant_tool_calls(vec![
+                                ToolCall::function("call_1", tool, args),
+                            ]))
+                        },
+                        |m| ChatResponse::from_text(m.text().unwrap_or_default()),
+                    )
+            }))
+        }
+
+        let child = Agent::new("child")
+            .provider(call_then_relay("whoami", "{}"))
+            .tool(Box::new(WhoAmI));
+        let parent = Agent::new("parent")
+            .provider(call_then_relay("child", r#"{"task": "who?"}"#))
+            .managed_agent(child)
+            .input_guardrail(InputGuardrail::new("tenant", RequireTenant).run_in_parallel(false));
+
+        let err = parent.run("hi", RunConfig::new()).await.unwrap_err();
+        assert!(err.to_string().contains("tenant"), "{err}");
+
+        let config = RunConfig::new().with_dependency(Arc::new(Tenant("acme")));
+        let result = parent.run("hi", config).await.unwrap();
+        assert!(
+            result.text().unwrap().contains("acme"),
+            "{:?}",
+            result.output
+        );
+    }
+
+    #[test]
+    fn debug_lists_type_names() {
+        let deps = Dependencies::new().with(Arc::new(Tenant("a")));
+        assert!(format!("{deps:?}").contains("Tenant"));
+    }
+}
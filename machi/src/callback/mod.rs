@@ -25,7 +25,12 @@
 //! - `on_tool_start` / `on_tool_end`
 //! - `on_error`
 //!
-//! At each lifecycle point, both layers fire in parallel via `tokio::join!`:
+//! **`StepHooks`** (per-run, steers the loop):
+//!
+//! - `on_step_end` → returns a [`StepDecision`] (continue, stop, inject
+//!   messages, or switch model)
+//!
+//! At each lifecycle point, both observer layers fire in parallel via `tokio::join!`:
 //!
 //! > `RunHooks::on_<event>` + `AgentHooks::on_<event>` → concurrent execution
 //!
@@ -65,13 +70,17 @@
 //! ```
 
 mod context;
+mod dependencies;
 mod hooks;
 mod logging;
 mod noop;
+mod step;
 
 pub use context::RunContext;
+pub use dependencies::Dependencies;
 pub use hooks::{
     AgentHooks, BoxedAgentHooks, BoxedRunHooks, RunHooks, SharedAgentHooks, SharedRunHooks,
 };
 pub use logging::{LogLevel, LoggingAgentHooks, LoggingRunHooks};
 pub use noop::{NoopAgentHooks, NoopRunHooks};
+pub use step::{SharedStepHooks, StepDecision, StepHooks};
@@ -0,0 +1,180 @@
+//! Step hooks that can steer the agent loop.
+//!
+//! Unlike [`RunHooks`](super::RunHooks) and [`AgentHooks`](super::AgentHooks),
+//! which only observe, a [`StepHooks`] implementation returns a
+//! [`StepDecision`] after every step that did not already end the run. The
+//! runner honors the decision before the next LLM call, which makes it
+//! possible to plug in custom termination logic, escalate to a stronger model
+//! after repeated failures, or nudge the model with extra messages.
+//!
+//! # Example
+//!
+//! ```rust
+//! use async_trait::async_trait;
+//! use machi::agent::StepInfo;
+//! use machi::callback::{RunContext, StepDecision, StepHooks};
+//!
+//! /// Escalate to a stronger model once a tool has failed twice.
+//! struct Escalate;
+//!
+//! #[async_trait]
+//! impl StepHooks for Escalate {
+//!     async fn on_step_end(&self, _ctx: &RunContext, step: &StepInfo) -> StepDecision {
+//!         let failures = step.tool_calls.iter().filter(|c| !c.success).count();
+//!         if failures >= 2 {
+//!             StepDecision::SwitchModel("gpt-4o".into())
+//!         } else {
+//!             StepDecision::Continue
+//!         }
+//!     }
+//! }
+//! ```
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::context::RunContext;
+use crate::agent::StepInfo;
+use crate::message::Message;
+
+/// A shared, thread-safe [`StepHooks`] trait object.
+pub type SharedStepHooks = std::sync::Arc<dyn StepHooks>;
+
+/// What the runner should do after a step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepDecision {
+    /// Proceed with the next step as usual.
+    Continue,
+    /// End the run now with the given output.
+    ///
+    /// Output guardrails and `on_agent_end` hooks run as for a normal final
+    /// answer.
+    Stop(Value),
+    /// Append messages to the conversation before the next LLM call.
+    InjectMessages(Vec<Message>),
+    /// Use a different model for the remaining steps of this run.
+    SwitchModel(String),
+}
+
+/// Hooks that decide how the agent loop continues after each step.
+///
+/// Called after every step that executed tool calls; a step that produces
+/// the final answer ends the run before the hook fires.
+///
+/// # Object Safety
+///
+/// This trait is object-safe and can be used as `Arc<dyn StepHooks>`.
+#[async_trait]
+pub trait StepHooks: Send + Sync {
+    /// Called after a step completes, with the step's LLM response and tool
+    /// call records.
+    async fn on_step_end(&self, ctx: &RunContext, step: &StepInfo) -> StepDecision;
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig, RunEvent};
+    use crate::chat::ChatResponse;
+    use crate::message::ToolCall;
+    use crate::test_util::{EchoTool, ScriptedProvider, echo_then_answer};
+
+    /// Hook that returns the same decision after every step.
+    struct Always(StepDecision);
+
+    #[async_trait]
+    impl StepHooks for Always {
+        async fn on_step_end(&self, _ctx: &RunContext, _step: &StepInfo) -> StepDecision {
+            self.0.clone()
+        }
+    }
+
+    fn config(decision: StepDecision) -> RunConfig {
+        RunConfig::new().step_hooks(Arc::new(Always(decision)))
+    }
+
+    /// Provider that keeps calling `echo` until the request uses `strong`.
+    fn escalating_provider() -> Arc<ScriptedProvider> {
+        Arc::new(ScriptedProvider::new(|req| {
+            if req.model == "strong" {
+                ChatResponse::from_text(format!("answered by {}", req.model))
+            } else {
+                ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                    "call_1", "echo", "{}",
+                )]))
+            }
+        }))
+    }
+
+    fn agent(provider: Arc<ScriptedProvider>) -> Agent {
+        Agent::new("hooked")
+            .model("weak")
+            .provider(provider)
+            .tool(Box::new(EchoTool))
+    }
+
+    #[tokio::test]
+    async fn stop_ends_the_run_with_given_output() {
+        let agent = agent(escalating_provider());
+        let result = agent
+            .run("go", config(StepDecision::Stop(json!({"halted": true}))))
+            .await
+            .unwrap();
+        assert_eq!(result.output, json!({"halted": true}));
+        assert_eq!(result.steps, 1);
+        assert_eq!(result.step_history.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn switch_model_applies_to_later_steps() {
+        let agent = agent(escalating_provider());
+        let result = agent
+            .run("go", config(StepDecision::SwitchModel("strong".into())))
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("answered by strong"));
+        assert_eq!(result.steps, 2);
+    }
+
+    #[tokio::test]
+    async fn injected_messages_reach_the_next_step() {
+        let agent = agent(echo_then_answer());
+        let nudge = StepDecision::InjectMessages(vec![Message::user("wrap up")]);
+        let result = agent.run("go", config(nudge.clone())).await.unwrap();
+        assert_eq!(result.text(), Some("final: wrap up"));
+
+        let events: Vec<RunEvent> = agent
+            .run_streamed("go", config(nudge))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let injected: Vec<&Message> = events
+            .iter()
+            .filter_map(|e| match e {
+                RunEvent::MessageInjected { message } => Some(message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(injected, [&Message::user("wrap up")]);
+    }
+
+    #[tokio::test]
+    async fn streamed_stop_completes_the_run() {
+        let agent = agent(escalating_provider());
+        let events: Vec<RunEvent> = agent
+            .run_streamed("go", config(StepDecision::Stop(json!("enough"))))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let Some(RunEvent::RunCompleted { result }) = events.last() else {
+            panic!("expected RunCompleted, got {events:?}");
+        };
+        assert_eq!(result.text(), Some("enough"));
+    }
+}
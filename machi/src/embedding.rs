@@ -275,6 +275,9 @@ fn embedding_dimension(&self) -> Option<usize> {
     }
 }
 
+/// Type alias for an Arc-wrapped `EmbeddingProvider`.
+pub type SharedEmbeddingProvider = std::sync::Arc<dyn EmbeddingProvider>;
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used, clippy::float_cmp)]
 mod tests {
@@ -0,0 +1,280 @@
+//! Keyword denylists.
+
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use regex::{Regex, RegexBuilder};
+use serde_json::{Value, json};
+
+use super::latest_user_text;
+use crate::callback::RunContext;
+use crate::error::{AgentError, Result};
+use crate::guardrail::{
+    GuardrailOutput, InputGuardrailCheck, OutputGuardrailCheck, StreamGuardrailCheck,
+};
+use crate::message::Message;
+use crate::workflow::output_text;
+
+/// Guardrail that trips when text contains a denied keyword or phrase.
+///
+/// Matching is case-insensitive and on whole words by default. The
+/// tripwire's `output_info` is `{"matched": [...]}` with each denied keyword
+/// found, as configured.
+///
+/// # Example
+///
+/// ```rust
+/// use machi::guardrail::builtin::KeywordDenylist;
+///
+/// let denylist = KeywordDenylist::new(["airdrop", "seed phrase"])?;
+/// assert_eq!(denylist.matches("Claim your AIRDROP now"), ["airdrop"]);
+/// assert!(denylist.matches("airdrops are over").is_empty());
+/// # Ok::<(), machi::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeywordDenylist {
+    keywords: Vec<String>,
+    case_sensitive: bool,
+    whole_words: bool,
+    regex: Option<Regex>,
+}
+
+impl KeywordDenylist {
+    /// Deny the given keywords. Empty keywords are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keywords are too many to compile into a
+    /// single matcher.
+    pub fn new<I, S>(keywords: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            keywords: keywords
+                .into_iter()
+                .map(Into::into)
+                .filter(|k: &String| !k.trim().is_empty())
+                .collect(),
+            case_sensitive: false,
+            whole_words: true,
+            regex: None,
+        }
+        .compiled()
+    }
+
+    /// Match case exactly (default: `false`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the matcher cannot be rebuilt, as in [`new`](Self::new).
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Result<Self> {
+        self.case_sensitive = case_sensitive;
+        self.compiled()
+    }
+
+    /// Only match whole words (default: `true`). When `false`, keywords also
+    /// match inside longer words.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the matcher cannot be rebuilt, as in [`new`](Self::new).
+    pub fn whole_words(mut self, whole_words: bool) -> Result<Self> {
+        self.whole_words = whole_words;
+        self.compiled()
+    }
+
+    /// Load keywords from text with one keyword per line.
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keywords cannot be compiled, as in
+    /// [`new`](Self::new).
+    pub fn from_lines(text: &str) -> Result<Self> {
+        Self::new(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.starts_with('#')),
+        )
+    }
+
+    /// Load keywords from a file with one keyword per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or its keywords cannot be
+    /// compiled.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            AgentError::guardrail(format!("cannot read denylist {}: {e}", path.display()))
+        })?;
+        Self::from_lines(&text)
+    }
+
+    /// The denied keywords found in `text`, in configured order.
+    #[must_use]
+    pub fn matches(&self, text: &str) -> Vec<&str> {
+        let Some(regex) = &self.regex else {
+            return Vec::new();
+        };
+        let found: BTreeSet<String> = regex
+            .find_iter(text)
+            .map(|m| self.normalize(m.as_str()))
+            .collect();
+        self.keywords
+            .iter()
+            .filter(|k| found.contains(&self.normalize(k)))
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if self.case_sensitive {
+            collapsed
+        } else {
+            collapsed.to_lowercase()
+        }
+    }
+
+    /// Rebuild the matcher after a configuration change.
+    fn compiled(mut self) -> Result<Self> {
+        let alternatives: Vec<String> = self
+            .keywords
+            .iter()
+            .map(|k| {
+                // Any run of whitespace in a phrase matches any other.
+                k.split_whitespace()
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(r"\s+")
+            })
+            .collect();
+        if alternatives.is_empty() {
+            self.regex = None;
+            return Ok(self);
+        }
+        let body = alternatives.join("|");
+        let pattern = if self.whole_words {
+            // Half boundaries so keywords ending in symbols ("c++") still match.
+            format!(r"\b{{start-half}}(?:{body})\b{{end-half}}")
+        } else {
+            format!("(?:{body})")
+        };
+        // Escaped keywords always parse, but a long list can exceed the
+        // compiled size limit.
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map_err(|e| AgentError::guardrail(format!("cannot compile denylist: {e}")))?;
+        self.regex = Some(regex);
+        Ok(self)
+    }
+
+    fn verdict(&self, text: &str) -> GuardrailOutput {
+        let matched = self.matches(text);
+        if matched.is_empty() {
+            GuardrailOutput::pass()
+        } else {
+            GuardrailOutput::tripwire(json!({ "matched": matched }))
+        }
+    }
+}
+
+#[async_trait]
+impl InputGuardrailCheck for KeywordDenylist {
+    async fn check(
+        &self,
+        _context: &RunContext,
+        _agent_name: &str,
+        input: &[Message],
+    ) -> Result<GuardrailOutput> {
+        Ok(self.verdict(&latest_user_text(input)))
+    }
+}
+
+#[async_trait]
+impl OutputGuardrailCheck for KeywordDenylist {
+    async fn check(
+        &self,
+        _context: &RunContext,
+        _agent_name: &str,
+        output: &Value,
+    ) -> Result<GuardrailOutput> {
+        Ok(self.verdict(&output_text(output)))
+    }
+}
+
+#[async_trait]
+impl StreamGuardrailCheck for KeywordDenylist {
+    async fn check(
+        &self,
+        _context: &RunContext,
+        _agent_name: &str,
+        text: &str,
+    ) -> Result<GuardrailOutput> {
+        Ok(self.verdict(text))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_whole_words_ignoring_case_and_spacing() {
+        let denylist = KeywordDenylist::new(["rug pull", "c++", ""]).unwrap();
+        assert_eq!(
+            denylist.matches("a RUG\n  pull, and c++"),
+            ["rug pull", "c++"]
+        );
+        assert!(denylist.matches("rug pulling").is_empty());
+    }
+
+    #[test]
+    fn options_change_matching() {
+        let denylist = KeywordDenylist::new(["Scam"])
+            .unwrap()
+            .case_sensitive(true)
+            .unwrap();
+        assert!(denylist.matches("scam").is_empty());
+        assert_eq!(denylist.matches("Scam"), ["Scam"]);
+        let partial = KeywordDenylist::new(["scam"])
+            .unwrap()
+            .whole_words(false)
+            .unwrap();
+        assert_eq!(partial.matches("scammers"), ["scam"]);
+        assert!(
+            KeywordDenylist::new(Vec::<String>::new())
+                .unwrap()
+                .matches("x")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn loads_from_lines() {
+        let denylist = KeywordDenylist::from_lines("# banned\nfoo\n\n  bar  \n").unwrap();
+        assert_eq!(denylist.matches("bar then foo"), ["foo", "bar"]);
+    }
+
+    #[tokio::test]
+    async fn checks_input_and_output() {
+        let denylist = KeywordDenylist::new(["secret"]).unwrap();
+        let input = [Message::user("tell me the secret")];
+        let output = InputGuardrailCheck::check(&denylist, &RunContext::new(), "a", &input)
+            .await
+            .unwrap();
+        assert_eq!(output.output_info, json!({"matched": ["secret"]}));
+        let output =
+            OutputGuardrailCheck::check(&denylist, &RunContext::new(), "a", &json!("fine"))
+                .await
+                .unwrap();
+        assert!(!output.is_triggered());
+    }
+}
@@ -0,0 +1,167 @@
+//! Limits on the size of the user input.
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::latest_user_text;
+use crate::callback::RunContext;
+use crate::error::Result;
+use crate::guardrail::{GuardrailOutput, InputGuardrailCheck};
+use crate::message::Message;
+
+/// Counts tokens in a piece of text.
+type TokenCounter = Arc<dyn Fn(&str) -> usize + Send + Sync>;
+
+/// Guardrail that trips when the user input is too long.
+///
+/// Lengths are measured in characters or in tokens. Tokens are estimated at
+/// four characters each unless a real tokenizer is supplied with
+/// [`token_counter`](Self::token_counter). The tripwire's `output_info` is
+/// `{"length": n, "limit": max, "unit": "chars" | "tokens"}`.
+///
+/// # Example
+///
+/// ```rust
+/// use machi::guardrail::builtin::MaxInputLength;
+///
+/// let limit = MaxInputLength::tokens(2_000)
+///     .token_counter(|text| text.split_whitespace().count());
+/// assert_eq!(limit.measure("three short words"), 3);
+/// ```
+#[derive(Clone)]
+pub struct MaxInputLength {
+    limit: usize,
+    counter: Option<TokenCounter>,
+    tokens: bool,
+}
+
+impl MaxInputLength {
+    /// Characters per token used by the default token estimate.
+    pub const CHARS_PER_TOKEN: usize = 4;
+
+    /// Limit the input to `max` characters.
+    #[must_use]
+    pub const fn chars(max: usize) -> Self {
+        Self {
+            limit: max,
+            counter: None,
+            tokens: false,
+        }
+    }
+
+    /// Limit the input to `max` tokens.
+    #[must_use]
+    pub const fn tokens(max: usize) -> Self {
+        Self {
+            limit: max,
+            counter: None,
+            tokens: true,
+        }
+    }
+
+    /// Count tokens with `counter` instead of the character estimate.
+    ///
+    /// Switches a character limit to a token limit.
+    #[must_use]
+    pub fn token_counter(
+        mut self,
+        counter: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.counter = Some(Arc::new(counter));
+        self.tokens = true;
+        self
+    }
+
+    /// Returns the configured limit.
+    #[must_use]
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Measure `text` in this guardrail's unit.
+    #[must_use]
+    pub fn measure(&self, text: &str) -> usize {
+        let chars = text.chars().count();
+        match (&self.counter, self.tokens) {
+            (Some(counter), _) => counter(text),
+            (None, true) => chars.div_ceil(Self::CHARS_PER_TOKEN),
+            (None, false) => chars,
+        }
+    }
+
+    const fn unit(&self) -> &'static str {
+        if self.tokens { "tokens" } else { "chars" }
+    }
+}
+
+impl fmt::Debug for MaxInputLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaxInputLength")
+            .field("limit", &self.limit)
+            .field("counter", &self.counter.is_some())
+            .field("tokens", &self.tokens)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl InputGuardrailCheck for MaxInputLength {
+    async fn check(
+        &self,
+        _context: &RunContext,
+        _agent_name: &str,
+        input: &[Message],
+    ) -> Result<GuardrailOutput> {
+        let length = self.measure(&latest_user_text(input));
+        let info = json!({"length": length, "limit": self.limit, "unit": self.unit()});
+        Ok(if length > self.limit {
+            GuardrailOutput::tripwire(info)
+        } else {
+            GuardrailOutput::pass_with_info(info)
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    async fn check(guardrail: &MaxInputLength, text: &str) -> GuardrailOutput {
+        let input = [Message::system("x".repeat(100)), Message::user(text)];
+        guardrail
+            .check(&RunContext::new(), "agent", &input)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn measures_chars_and_estimated_tokens() {
+        assert_eq!(MaxInputLength::chars(10).measure("héllo"), 5);
+        assert_eq!(MaxInputLength::tokens(10).measure("123456789"), 3);
+        assert_eq!(MaxInputLength::tokens(10).measure(""), 0);
+    }
+
+    #[tokio::test]
+    async fn trips_only_above_the_limit() {
+        let limit = MaxInputLength::chars(5);
+        assert!(!check(&limit, "12345").await.is_triggered());
+        let output = check(&limit, "123456").await;
+        assert!(output.is_triggered());
+        assert_eq!(
+            output.output_info,
+            json!({"length": 6, "limit": 5, "unit": "chars"})
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_counter_switches_to_tokens() {
+        let limit = MaxInputLength::chars(2).token_counter(|t| t.split_whitespace().count());
+        let output = check(&limit, "one two three").await;
+        assert!(output.is_triggered());
+        assert_eq!(output.output_info["unit"], "tokens");
+    }
+}
@@ -0,0 +1,58 @@
+//! Ready-made guardrails.
+//!
+//! Each guardrail here implements [`InputGuardrailCheck`](super::InputGuardrailCheck),
+//! [`OutputGuardrailCheck`](super::OutputGuardrailCheck), or both, and is
+//! wrapped in an [`InputGuardrail`](super::InputGuardrail) or
+//! [`OutputGuardrail`](super::OutputGuardrail) like any custom check:
+//!
+//! - [`PiiDetector`] — emails, phone numbers, credit cards, IBANs, private
+//!   keys and BIP-39 seed phrases
+//! - [`MaxInputLength`] — character or token limits on the user input
+//! - [`KeywordDenylist`] — forbidden words or phrases
+//! - [`JsonSchemaGuardrail`] — validates structured output against a schema
+//! - [`LlmClassifier`] — asks a model to judge content against a rubric
+//!
+//! Input checks look at the newest user message; output checks look at the
+//! final output, rendered as text when it is not a string. [`PiiDetector`],
+//! [`KeywordDenylist`] and [`LlmClassifier`] also implement
+//! [`StreamGuardrailCheck`](super::StreamGuardrailCheck) for use in a
+//! [`StreamGuardrail`](super::StreamGuardrail).
+//!
+//! ```rust
+//! use machi::agent::Agent;
+//! use machi::guardrail::builtin::{KeywordDenylist, PiiDetector, PiiKind};
+//! use machi::guardrail::{InputGuardrail, OutputGuardrail};
+//!
+//! let agent = Agent::new("wallet-assistant")
+//!     .input_guardrail(InputGuardrail::new("secrets", PiiDetector::secrets()))
+//!     .input_guardrail(InputGuardrail::new("topics", KeywordDenylist::new(["rug pull"])?))
+//!     .output_guardrail(OutputGuardrail::new(
+//!         "pii",
+//!         PiiDetector::new().without(PiiKind::PrivateKey),
+//!     ));
+//! # Ok::<(), machi::Error>(())
+//! ```
+
+mod classifier;
+mod denylist;
+mod length;
+mod pii;
+mod schema;
+
+pub use classifier::LlmClassifier;
+pub use denylist::KeywordDenylist;
+pub use length::MaxInputLength;
+pub use pii::{PiiDetector, PiiKind};
+pub use schema::JsonSchemaGuardrail;
+
+use crate::message::{Message, Role};
+
+/// Text of the newest user message in a guardrail input.
+fn latest_user_text(input: &[Message]) -> String {
+    input
+        .iter()
+        .rev()
+        .find(|m| m.role == Role::User)
+        .and_then(Message::text)
+        .unwrap_or_default()
+}
@@ -0,0 +1,501 @@
+//! JSON Schema validation of structured output.
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::{Map, Value, json};
+
+use crate::agent::OutputSchema;
+use crate::callback::RunContext;
+use crate::error::Result;
+use crate::guardrail::{GuardrailOutput, OutputGuardrailCheck};
+
+/// How deeply `$ref`s and nested schemas are followed before giving up.
+const MAX_DEPTH: usize = 64;
+
+/// Output guardrail that trips when the output does not match a JSON Schema.
+///
+/// String outputs are parsed as JSON first (a surrounding Markdown code fence
+/// is ignored). Supports the commonly generated subset of JSON Schema: `type`,
+/// `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`,
+/// length, size and range limits, `pattern`, `allOf`/`anyOf`/`oneOf`/`not`
+/// and local `$ref`s. The tripwire's `output_info` is `{"errors": [...]}`
+/// with one `"<path>: <problem>"` entry per violation.
+///
+/// # Example
+///
+/// ```rust
+/// use machi::guardrail::builtin::JsonSchemaGuardrail;
+/// use serde_json::json;
+///
+/// let guardrail = JsonSchemaGuardrail::new(json!({
+///     "type": "object",
+///     "properties": {"score": {"type": "integer", "minimum": 0}},
+///     "required": ["score"]
+/// }));
+/// assert!(guardrail.validate(&json!({"score": 3})).is_empty());
+/// assert_eq!(guardrail.validate(&json!({"score": -1})), ["$.score: must be >= 0"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonSchemaGuardrail {
+    schema: Value,
+}
+
+impl JsonSchemaGuardrail {
+    /// Validate against `schema`.
+    #[must_use]
+    pub const fn new(schema: Value) -> Self {
+        Self { schema }
+    }
+
+    /// Validate against an agent's [`OutputSchema`].
+    #[must_use]
+    pub fn from_output_schema(schema: &OutputSchema) -> Self {
+        Self::new(schema.schema().clone())
+    }
+
+    /// Validate against the JSON Schema generated for `T`.
+    #[cfg(feature = "schema")]
+    #[must_use]
+    pub fn from_type<T: schemars::JsonSchema>() -> Self {
+        Self::from_output_schema(&OutputSchema::from_type::<T>())
+    }
+
+    /// Returns the schema.
+    #[must_use]
+    pub const fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Validate `value`, returning one message per violation.
+    #[must_use]
+    pub fn validate(&self, value: &Value) -> Vec<String> {
+        let mut validator = Validator {
+            root: &self.schema,
+            errors: Vec::new(),
+        };
+        validator.check(&self.schema, value, "$", 0);
+        validator.errors
+    }
+}
+
+#[async_trait]
+impl OutputGuardrailCheck for JsonSchemaGuardrail {
+    async fn check(
+        &self,
+        _context: &RunContext,
+        _agent_name: &str,
+        output: &Value,
+    ) -> Result<GuardrailOutput> {
+        let errors = match output {
+            Value::String(text) => match serde_json::from_str::<Value>(strip_fence(text)) {
+                Ok(parsed) => self.validate(&parsed),
+                Err(e) => vec![format!("$: output is not valid JSON: {e}")],
+            },
+            other => self.validate(other),
+        };
+        Ok(if errors.is_empty() {
+            GuardrailOutput::pass()
+        } else {
+            GuardrailOutput::tripwire(json!({ "errors": errors }))
+        })
+    }
+}
+
+/// Strip a surrounding Markdown code fence, if any.
+fn strip_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map_or(trimmed, |inner| {
+            // Drop the info string ("json") on the opening line.
+            inner
+                .split_once('\n')
+                .map_or(inner, |(_, body)| body)
+                .trim()
+        })
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    errors: Vec<String>,
+}
+
+impl Validator<'_> {
+    /// Whether `value` matches `schema`, without recording errors.
+    fn passes(&self, schema: &Value, value: &Value, depth: usize) -> bool {
+        let mut probe = Validator {
+            root: self.root,
+            errors: Vec::new(),
+        };
+        probe.check(schema, value, "$", depth);
+        probe.errors.is_empty()
+    }
+
+    fn fail(&mut self, path: &str, problem: impl std::fmt::Display) {
+        self.errors.push(format!("{path}: {problem}"));
+    }
+
+    fn check(&mut self, schema: &Value, value: &Value, path: &str, depth: usize) {
+        if depth > MAX_DEPTH {
+            self.fail(path, "schema nests too deeply");
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(false) => return self.fail(path, "no value is allowed here"),
+            Value::Object(schema) => schema,
+            // `true` and non-schema values accept anything.
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match resolve_ref(self.root, reference) {
+                Some(target) => self.check(target, value, path, depth + 1),
+                None => self.fail(path, format!("unresolvable $ref '{reference}'")),
+            }
+        }
+        if let Some(types) = schema.get("type")
+            && !type_matches(types, value)
+        {
+            return self.fail(
+                path,
+                format!("expected {}, got {}", type_names(types), type_of(value)),
+            );
+        }
+        if let Some(options) = schema.get("enum").and_then(Value::as_array)
+            && !options.contains(value)
+        {
+            self.fail(
+                path,
+                format!("must be one of {}", Value::from(options.clone())),
+            );
+        }
+        if let Some(expected) = schema.get("const")
+            && expected != value
+        {
+            self.fail(path, format!("must equal {expected}"));
+        }
+
+        self.check_combinators(schema, value, path, depth);
+        match value {
+            Value::String(s) => self.check_string(schema, s, path),
+            Value::Number(_) => self.check_number(schema, value, path),
+            Value::Array(items) => self.check_array(schema, items, path, depth),
+            Value::Object(object) => self.check_object(schema, object, path, depth),
+            Value::Null | Value::Bool(_) => {}
+        }
+    }
+
+    fn check_combinators(
+        &mut self,
+        schema: &Map<String, Value>,
+        value: &Value,
+        path: &str,
+        depth: usize,
+    ) {
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for sub in all {
+                self.check(sub, value, path, depth + 1);
+            }
+        }
+        if let Some(any) = schema.get("anyOf").and_then(Value::as_array)
+            && !any.iter().any(|sub| self.passes(sub, value, depth + 1))
+        {
+            self.fail(path, "does not match any allowed schema");
+        }
+        if let Some(one) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = one
+                .iter()
+                .filter(|sub| self.passes(sub, value, depth + 1))
+                .count();
+            if matching != 1 {
+                self.fail(
+                    path,
+                    format!("must match exactly one schema, matched {matching}"),
+                );
+            }
+        }
+        if let Some(not) = schema.get("not")
+            && self.passes(not, value, depth + 1)
+        {
+            self.fail(path, "matches a disallowed schema");
+        }
+    }
+
+    fn check_string(&mut self, schema: &Map<String, Value>, s: &str, path: &str) {
+        let len = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+            && len < min
+        {
+            self.fail(path, format!("must be at least {min} characters"));
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+            && len > max
+        {
+            self.fail(path, format!("must be at most {max} characters"));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            match Regex::new(pattern) {
+                Ok(regex) if !regex.is_match(s) => {
+                    self.fail(path, format!("must match pattern '{pattern}'"));
+                }
+                Ok(_) => {}
+                Err(_) => self.fail(path, format!("invalid pattern '{pattern}' in schema")),
+            }
+        }
+    }
+
+    fn check_number(&mut self, schema: &Map<String, Value>, value: &Value, path: &str) {
+        let Some(n) = value.as_f64() else {
+            return;
+        };
+        let bound = |key: &str| schema.get(key).and_then(Value::as_f64);
+        if let Some(min) = bound("minimum")
+            && n < min
+        {
+            self.fail(path, format!("must be >= {}", schema["minimum"]));
+        }
+        if let Some(max) = bound("maximum")
+            && n > max
+        {
+            self.fail(path, format!("must be <= {}", schema["maximum"]));
+        }
+        if let Some(min) = bound("exclusiveMinimum")
+            && n <= min
+        {
+            self.fail(path, format!("must be > {}", schema["exclusiveMinimum"]));
+        }
+        if let Some(max) = bound("exclusiveMaximum")
+            && n >= max
+        {
+            self.fail(path, format!("must be < {}", schema["exclusiveMaximum"]));
+        }
+    }
+
+    fn check_array(
+        &mut self,
+        schema: &Map<String, Value>,
+        items: &[Value],
+        path: &str,
+        depth: usize,
+    ) {
+        let len = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64)
+            && len < min
+        {
+            self.fail(path, format!("must have at least {min} items"));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64)
+            && len > max
+        {
+            self.fail(path, format!("must have at most {max} items"));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true))
+            && items
+                .iter()
+                .enumerate()
+                .any(|(i, item)| items[..i].contains(item))
+        {
+            self.fail(path, "items must be unique");
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                self.check(item_schema, item, &format!("{path}[{i}]"), depth + 1);
+            }
+        }
+    }
+
+    fn check_object(
+        &mut self,
+        schema: &Map<String, Value>,
+        object: &Map<String, Value>,
+        path: &str,
+        depth: usize,
+    ) {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.fail(path, format!("missing required property '{name}'"));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let additional = schema.get("additionalProperties");
+        for (name, value) in object {
+            let child = format!("{path}.{name}");
+            match properties.and_then(|p| p.get(name)) {
+                Some(property) => self.check(property, value, &child, depth + 1),
+                None => match additional {
+                    Some(Value::Bool(false)) => {
+                        self.fail(path, format!("unexpected property '{name}'"));
+                    }
+                    Some(extra) => self.check(extra, value, &child, depth + 1),
+                    None => {}
+                },
+            }
+        }
+    }
+}
+
+/// Resolve a local `$ref` such as `#/$defs/Item`.
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+fn type_matches(types: &Value, value: &Value) -> bool {
+    match types {
+        Value::String(name) => is_type(name, value),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|name| is_type(name, value)),
+        _ => true,
+    }
+}
+
+fn is_type(name: &str, value: &Value) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+fn type_names(types: &Value) -> String {
+    match types {
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        other => other.as_str().unwrap_or("?").to_owned(),
+    }
+}
+
+const fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn person() -> JsonSchemaGuardrail {
+        JsonSchemaGuardrail::new(json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "age": {"type": ["integer", "null"], "maximum": 150},
+                "tags": {"type": "array", "items": {"$ref": "#/$defs/tag"}, "uniqueItems": true},
+                "role": {"enum": ["admin", "user"]}
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+            "$defs": {"tag": {"type": "string", "pattern": "^[a-z]+$"}}
+        }))
+    }
+
+    #[test]
+    fn accepts_valid_values() {
+        let value = json!({"name": "Ana", "age": null, "tags": ["a", "b"], "role": "user"});
+        assert!(person().validate(&value).is_empty());
+        assert!(
+            person()
+                .validate(&json!({"name": "Bo", "age": 30.0}))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn reports_every_violation_with_its_path() {
+        let value = json!({"age": 200, "tags": ["ok", "Bad", "ok"], "role": "root", "x": 1});
+        assert_eq!(
+            person().validate(&value),
+            [
+                "$: missing required property 'name'",
+                "$.age: must be <= 150",
+                "$.role: must be one of [\"admin\",\"user\"]",
+                "$.tags: items must be unique",
+                "$.tags[1]: must match pattern '^[a-z]+$'",
+                "$: unexpected property 'x'",
+            ]
+        );
+        assert_eq!(
+            person().validate(&json!([])),
+            ["$: expected object, got array"]
+        );
+    }
+
+    #[test]
+    fn combinators() {
+        let guardrail = JsonSchemaGuardrail::new(json!({
+            "oneOf": [{"type": "integer"}, {"type": "number", "minimum": 10}],
+            "not": {"const": 42}
+        }));
+        assert!(guardrail.validate(&json!(3)).is_empty());
+        assert_eq!(
+            guardrail.validate(&json!(12)),
+            ["$: must match exactly one schema, matched 2"]
+        );
+        assert_eq!(
+            guardrail.validate(&json!(42)),
+            [
+                "$: must match exactly one schema, matched 2",
+                "$: matches a disallowed schema"
+            ]
+        );
+        let any =
+            JsonSchemaGuardrail::new(json!({"anyOf": [{"type": "string"}, {"type": "null"}]}));
+        assert_eq!(
+            any.validate(&json!(1)),
+            ["$: does not match any allowed schema"]
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_string_output() {
+        let guardrail = person();
+        let check = |output: Value| {
+            let guardrail = guardrail.clone();
+            async move {
+                guardrail
+                    .check(&RunContext::new(), "agent", &output)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert!(
+            !check(json!("```json\n{\"name\": \"Ana\"}\n```"))
+                .await
+                .is_triggered()
+        );
+        let output = check(json!("not json")).await;
+        assert!(
+            output.output_info["errors"][0]
+                .as_str()
+                .unwrap()
+                .starts_with("$: output is not valid JSON")
+        );
+        let output = check(json!({"name": ""})).await;
+        assert_eq!(
+            output.output_info,
+            json!({"errors": ["$.name: must be at least 1 characters"]})
+        );
+    }
+}
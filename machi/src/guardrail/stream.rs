@@ -0,0 +1,397 @@
+//! Guardrails that check text while it streams.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future;
+use serde_json::Value;
+
+use super::GuardrailOutput;
+use crate::callback::RunContext;
+use crate::error::Result;
+
+/// How often a [`StreamGuardrail`] checks the streamed text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamCheckInterval {
+    /// After at least this many new characters.
+    Chars(usize),
+    /// After each sentence boundary: `.`, `!`, `?` or a newline.
+    #[default]
+    Sentences,
+}
+
+impl StreamCheckInterval {
+    /// Whether `unchecked` text warrants another check.
+    fn is_due(self, unchecked: &str) -> bool {
+        match self {
+            Self::Chars(n) => unchecked.chars().count() >= n,
+            Self::Sentences => unchecked.contains(['.', '!', '?', '\n']),
+        }
+    }
+}
+
+/// Trait for implementing streaming guardrail check logic.
+///
+/// The [`check`](StreamGuardrailCheck::check) method receives all text the
+/// model has streamed so far in the current step, not just the newest delta.
+#[async_trait]
+pub trait StreamGuardrailCheck: Send + Sync {
+    /// Check the text streamed so far and return a guardrail output.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` — the current run context (usage, step, state, dependencies)
+    /// * `agent_name` — name of the agent being streamed
+    /// * `text` — the text streamed so far in the current step
+    async fn check(
+        &self,
+        context: &RunContext,
+        agent_name: &str,
+        text: &str,
+    ) -> Result<GuardrailOutput>;
+}
+
+/// A guardrail that checks the model's text while
+/// [`Runner::run_streamed`](crate::agent::Runner::run_streamed) streams it.
+///
+/// Output guardrails only see the final output, after every
+/// [`RunEvent::TextDelta`](crate::agent::RunEvent::TextDelta) has reached the
+/// consumer. A stream guardrail checks the accumulated text at each
+/// [`StreamCheckInterval`] instead. When its tripwire triggers, generation is
+/// aborted, a [`RunEvent::GuardrailTriggered`](crate::agent::RunEvent::GuardrailTriggered)
+/// is emitted, and the stream ends with
+/// [`Error::OutputGuardrailTriggered`](crate::Error).
+///
+/// With [`hold_back`](Self::hold_back), deltas are buffered until this
+/// guardrail has checked them, so text that trips it is never shown. Only
+/// the tripwire is used; repairs are ignored. Stream guardrails do not run
+/// in [`Runner::run`](crate::agent::Runner::run).
+///
+/// # Example
+///
+/// ```rust
+/// use machi::guardrail::StreamGuardrail;
+/// use machi::guardrail::builtin::PiiDetector;
+///
+/// let guardrail = StreamGuardrail::new("pii", PiiDetector::secrets())
+///     .every_chars(200)
+///     .hold_back(true);
+/// assert!(guardrail.holds_back());
+/// ```
+#[derive(Clone)]
+pub struct StreamGuardrail {
+    /// Name of this guardrail (used in tracing and error messages).
+    name: String,
+
+    /// When to check the streamed text.
+    interval: StreamCheckInterval,
+
+    /// Whether deltas wait for this guardrail's check before being shown.
+    hold_back: bool,
+
+    /// The guardrail check implementation.
+    check: Arc<dyn StreamGuardrailCheck>,
+}
+
+impl StreamGuardrail {
+    /// Create a new stream guardrail that checks at sentence boundaries.
+    #[must_use]
+    pub fn new(name: impl Into<String>, check: impl StreamGuardrailCheck + 'static) -> Self {
+        Self {
+            name: name.into(),
+            interval: StreamCheckInterval::default(),
+            hold_back: false,
+            check: Arc::new(check),
+        }
+    }
+
+    /// Check after every `chars` new characters.
+    #[must_use]
+    pub fn every_chars(mut self, chars: usize) -> Self {
+        self.interval = StreamCheckInterval::Chars(chars.max(1));
+        self
+    }
+
+    /// Check at every sentence boundary (the default).
+    #[must_use]
+    pub const fn at_sentences(mut self) -> Self {
+        self.interval = StreamCheckInterval::Sentences;
+        self
+    }
+
+    /// Hold back deltas until this guardrail has checked them
+    /// (default: `false`).
+    #[must_use]
+    pub const fn hold_back(mut self, hold_back: bool) -> Self {
+        self.hold_back = hold_back;
+        self
+    }
+
+    /// Returns the name of this guardrail.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns when this guardrail checks the streamed text.
+    #[must_use]
+    pub const fn interval(&self) -> StreamCheckInterval {
+        self.interval
+    }
+
+    /// Returns whether deltas wait for this guardrail's check.
+    #[must_use]
+    pub const fn holds_back(&self) -> bool {
+        self.hold_back
+    }
+
+    /// Execute this guardrail check on the text streamed so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the guardrail check function fails.
+    pub async fn run(
+        &self,
+        context: &RunContext,
+        agent_name: &str,
+        text: &str,
+    ) -> Result<GuardrailOutput> {
+        self.check.check(context, agent_name, text).await
+    }
+}
+
+impl std::fmt::Debug for StreamGuardrail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamGuardrail")
+            .field("name", &self.name)
+            .field("interval", &self.interval)
+            .field("hold_back", &self.hold_back)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What the runner does with a text delta after the stream guardrails ran.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StreamVerdict {
+    /// Show this text (possibly empty while deltas are held back).
+    Release(String),
+    /// A guardrail tripped; abort the stream.
+    Tripped {
+        /// Name of the guardrail that tripped.
+        name: String,
+        /// Diagnostic information from the guardrail.
+        info: Value,
+    },
+}
+
+/// Stream guardrail state for one step's text.
+pub struct StreamGuard<'a> {
+    /// Each guardrail with the length of text it has checked.
+    guardrails: Vec<(&'a StreamGuardrail, usize)>,
+    text: String,
+    released: usize,
+}
+
+impl<'a> StreamGuard<'a> {
+    pub fn new(guardrails: &[&'a StreamGuardrail]) -> Self {
+        Self {
+            guardrails: guardrails.iter().map(|g| (*g, 0)).collect(),
+            text: String::new(),
+            released: 0,
+        }
+    }
+
+    /// Add a delta, run the guardrails that are due, and release the text
+    /// every holding guardrail has checked.
+    pub async fn push(
+        &mut self,
+        context: &RunContext,
+        agent_name: &str,
+        delta: &str,
+    ) -> Result<StreamVerdict> {
+        self.text.push_str(delta);
+        let text = &self.text;
+        let due: Vec<usize> = (0..self.guardrails.len())
+            .filter(|&i| {
+                let (guardrail, checked) = self.guardrails[i];
+                guardrail.interval.is_due(&text[checked..])
+            })
+            .collect();
+        self.check(context, agent_name, &due).await
+    }
+
+    /// Check any text not yet checked and release the rest.
+    pub async fn finish(
+        &mut self,
+        context: &RunContext,
+        agent_name: &str,
+    ) -> Result<StreamVerdict> {
+        let len = self.text.len();
+        let due: Vec<usize> = (0..self.guardrails.len())
+            .filter(|&i| self.guardrails[i].1 < len)
+            .collect();
+        self.check(context, agent_name, &due).await
+    }
+
+    async fn check(
+        &mut self,
+        context: &RunContext,
+        agent_name: &str,
+        due: &[usize],
+    ) -> Result<StreamVerdict> {
+        let text = &self.text;
+        let outputs = future::join_all(
+            due.iter()
+                .map(|&i| self.guardrails[i].0.run(context, agent_name, text)),
+        )
+        .await;
+        for (&i, output) in due.iter().zip(outputs) {
+            let output = output?;
+            if output.is_triggered() {
+                return Ok(StreamVerdict::Tripped {
+                    name: self.guardrails[i].0.name.clone(),
+                    info: output.output_info,
+                });
+            }
+            self.guardrails[i].1 = self.text.len();
+        }
+
+        let release_to = self
+            .guardrails
+            .iter()
+            .filter(|(g, _)| g.hold_back)
+            .map(|&(_, checked)| checked)
+            .min()
+            .unwrap_or(self.text.len());
+        let released = self.text[self.released..release_to].to_owned();
+        self.released = release_to;
+        Ok(StreamVerdict::Release(released))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::pin::Pin;
+
+    use futures::{Stream, StreamExt};
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig, RunEvent};
+    use crate::chat::{ChatProvider, ChatRequest, ChatResponse};
+    use crate::guardrail::builtin::KeywordDenylist;
+    use crate::stream::StreamChunk;
+
+    /// Streams a fixed reply one delta at a time.
+    struct Deltas(&'static [&'static str]);
+
+    #[async_trait]
+    impl ChatProvider for Deltas {
+        async fn chat(&self, _request: &ChatRequest) -> Result<ChatResponse> {
+            Ok(ChatResponse::from_text(self.0.concat()))
+        }
+
+        async fn chat_stream(
+            &self,
+            _request: &ChatRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+            let chunks = self.0.iter().map(|d| Ok(StreamChunk::text(*d)));
+            let done = std::iter::once(Ok(StreamChunk::done(None)));
+            Ok(Box::pin(futures::stream::iter(chunks.chain(done))))
+        }
+
+        fn provider_name(&self) -> &'static str {
+            "deltas"
+        }
+
+        fn default_model(&self) -> &'static str {
+            "deltas-model"
+        }
+    }
+
+    const REPLY: &[&str] = &["Hello ", "there. ", "The code ", "is SECRET", ". Bye."];
+
+    /// Streams [`REPLY`] with `guardrail`, returning the shown text and
+    /// whether the run ended with the trip event and an error.
+    async fn stream(guardrail: StreamGuardrail) -> (String, Option<String>, bool) {
+        let agent = Agent::new("streamer")
+            .provider(Arc::new(Deltas(REPLY)))
+            .stream_guardrail(guardrail);
+        let mut events = agent.run_streamed("go", RunConfig::new());
+        let (mut shown, mut tripped, mut failed) = (String::new(), None, false);
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(RunEvent::TextDelta(text)) => shown.push_str(&text),
+                Ok(RunEvent::GuardrailTriggered { guardrail_name, .. }) => {
+                    tripped = Some(guardrail_name);
+                }
+                Ok(_) => {}
+                Err(_) => failed = true,
+            }
+        }
+        (shown, tripped, failed)
+    }
+
+    #[test]
+    fn intervals_decide_when_checks_are_due() {
+        assert!(StreamCheckInterval::Sentences.is_due("Done!"));
+        assert!(!StreamCheckInterval::Sentences.is_due("still going"));
+        assert!(StreamCheckInterval::Chars(3).is_due("héy"));
+        assert!(!StreamCheckInterval::Chars(4).is_due("héy"));
+        let guardrail =
+            StreamGuardrail::new("g", KeywordDenylist::new(["x"]).unwrap()).every_chars(0);
+        assert_eq!(guardrail.interval(), StreamCheckInterval::Chars(1));
+    }
+
+    #[tokio::test]
+    async fn held_back_text_is_released_once_checked() {
+        let guardrail = StreamGuardrail::new("words", KeywordDenylist::new(["secret"]).unwrap())
+            .hold_back(true);
+        let mut guard = StreamGuard::new(&[&guardrail]);
+        let context = RunContext::new();
+        let release = |text: &str| StreamVerdict::Release(text.to_owned());
+        assert_eq!(
+            guard.push(&context, "a", "Hello ").await.unwrap(),
+            release("")
+        );
+        assert_eq!(
+            guard.push(&context, "a", "there. Next").await.unwrap(),
+            release("Hello there. Next")
+        );
+        assert_eq!(
+            guard.push(&context, "a", " one").await.unwrap(),
+            release("")
+        );
+        assert_eq!(guard.finish(&context, "a").await.unwrap(), release(" one"));
+    }
+
+    #[tokio::test]
+    async fn tripwire_aborts_the_stream() {
+        let guardrail = StreamGuardrail::new("words", KeywordDenylist::new(["secret"]).unwrap());
+        let (shown, tripped, failed) = stream(guardrail).await;
+        assert_eq!(tripped.as_deref(), Some("words"));
+        assert!(failed);
+        // Deltas pass through until the sentence that trips is complete.
+        assert_eq!(shown, "Hello there. The code is SECRET");
+    }
+
+    #[tokio::test]
+    async fn hold_back_hides_the_offending_text() {
+        let guardrail = StreamGuardrail::new("words", KeywordDenylist::new(["secret"]).unwrap())
+            .hold_back(true);
+        let (shown, tripped, failed) = stream(guardrail).await;
+        assert_eq!(tripped.as_deref(), Some("words"));
+        assert!(failed);
+        assert_eq!(shown, "Hello there. ");
+    }
+
+    #[tokio::test]
+    async fn passing_guardrails_show_everything() {
+        let guardrail = StreamGuardrail::new("words", KeywordDenylist::new(["password"]).unwrap())
+            .every_chars(8)
+            .hold_back(true);
+        let (shown, tripped, failed) = stream(guardrail).await;
+        assert_eq!((tripped, failed), (None, false));
+        assert_eq!(shown, REPLY.concat());
+    }
+}
@@ -9,6 +9,8 @@
 //!   instructions, tools, and optional sub-agents.
 //! - **[`Runner`](agent::Runner)** — A stateless execution engine driving the
 //!   `ReAct` loop (think → act → observe → repeat).
+//! - **[`Workflow`](workflow::Workflow)** — Deterministic composition of agents
+//!   (sequential, parallel, router, loop).
 //! - **[`Tool`](tool::Tool) / [`DynTool`](tool::DynTool)** — Capabilities that
 //!   agents can invoke (filesystem, shell, web search, or custom).
 //! - **[`ChatProvider`](chat::ChatProvider)** — Trait abstracting over LLM backends
@@ -26,7 +28,9 @@
 //! | `a2a` | Agent-to-Agent protocol support |
 //! | `wallet` | EVM wallet for blockchain interactions |
 //! | `memory-sqlite` | SQLite-backed session persistence |
+//! | `memory-encryption` | Encryption at rest for `SQLite` sessions |
 //! | `schema` | Structured output via JSON Schema generation |
+//! | `spec` | Declarative agent definitions (TOML/YAML/JSON) |
 //! | `full` | All of the above (default) |
 //!
 //! # Quick Start
@@ -68,14 +72,18 @@
 pub mod mcp;
 pub mod memory;
 pub mod message;
+pub mod policy;
 pub mod prelude;
 pub mod stream;
+#[cfg(test)]
+mod test_util;
 pub mod tool;
 #[cfg(feature = "toolkit")]
 pub mod tools;
 pub mod usage;
 #[cfg(feature = "wallet")]
 pub mod wallet;
+pub mod workflow;
 
 pub use error::{Error, Result};
 
@@ -0,0 +1,1104 @@
+//! Record-and-replay providers for deterministic tests.
+//!
+//! A *cassette* is a JSON Lines file in which every line is one
+//! [`CassetteEntry`]: a normalized request paired with the response (or
+//! streamed chunks) the provider produced for it.
+//!
+//! - [`RecordingProvider`] wraps any [`ChatProvider`] and appends each
+//!   interaction to a cassette file while passing results through unchanged.
+//! - [`ReplayProvider`] serves responses from a cassette without touching the
+//!   network, matching requests on model, messages and tools. Requests with
+//!   no recorded counterpart fail with an error instead of falling back.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # tokio_test::block_on(async {
+//! use machi::chat::{ChatProvider, ChatRequest, ChatResponse};
+//! use machi::llms::{CassetteEntry, ReplayProvider};
+//!
+//! let request = ChatRequest::new("gpt-4o").user("Hello!");
+//! let replay = ReplayProvider::from_entries(vec![CassetteEntry::chat(
+//!     &request,
+//!     ChatResponse::from_text("Hi there!"),
+//! )]);
+//!
+//! let response = replay.chat(&request).await?;
+//! assert_eq!(response.text().as_deref(), Some("Hi there!"));
+//! assert!(replay.is_exhausted());
+//!
+//! // Requests that were never recorded fail loudly.
+//! assert!(replay.chat(&ChatRequest::new("gpt-4o").user("Bye!")).await.is_err());
+//! # Ok::<(), machi::Error>(())
+//! # }).unwrap();
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use super::error::LlmError;
+use crate::chat::{ChatProvider, ChatRequest, ChatResponse, SharedChatProvider};
+use crate::error::{Error, Result};
+use crate::stream::{StopReason, StreamAggregator, StreamChunk};
+use crate::usage::Usage;
+
+/// Provider name reported by [`ReplayProvider`] and used in its errors.
+const REPLAY_PROVIDER: &str = "replay";
+
+/// The recorded outcome of a single provider call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CassetteResponse {
+    /// A complete response returned by [`ChatProvider::chat`].
+    Chat {
+        /// The recorded response.
+        response: Box<ChatResponse>,
+    },
+    /// The chunks yielded by [`ChatProvider::chat_stream`], in order.
+    Stream {
+        /// The recorded chunks.
+        chunks: Vec<CassetteChunk>,
+        /// The error that ended the stream after `chunks`, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<CassetteError>,
+    },
+    /// The call failed with an error.
+    Error(CassetteError),
+}
+
+/// A recorded provider failure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteError {
+    /// The error message reported by the provider.
+    pub message: String,
+    /// The original error, when the provider failed with an [`LlmError`].
+    ///
+    /// Replaying returns this error unchanged, so callers that match on the
+    /// error kind (e.g. retrying on [`LlmError::RateLimited`]) behave as they
+    /// did when the cassette was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<LlmError>,
+}
+
+impl CassetteError {
+    /// Converts the recorded failure back into an error.
+    ///
+    /// Failures recorded without a kind surface as a provider error carrying
+    /// the original message.
+    #[must_use]
+    pub fn into_error(self) -> Error {
+        self.kind
+            .unwrap_or_else(|| LlmError::provider(REPLAY_PROVIDER, self.message))
+            .into()
+    }
+}
+
+impl From<&Error> for CassetteError {
+    fn from(error: &Error) -> Self {
+        let kind = match error {
+            Error::Llm(e) => Some(e.clone()),
+            _ => None,
+        };
+        Self {
+            message: error.to_string(),
+            kind,
+        }
+    }
+}
+
+impl From<String> for CassetteError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            kind: None,
+        }
+    }
+}
+
+impl From<&str> for CassetteError {
+    fn from(message: &str) -> Self {
+        message.to_owned().into()
+    }
+}
+
+/// Serializable mirror of [`StreamChunk`] used in cassette files.
+///
+/// [`StreamChunk`] is internally tagged, which `serde` cannot apply to its
+/// string-carrying variants; this adjacently tagged copy round-trips every
+/// variant losslessly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum CassetteChunk {
+    /// See [`StreamChunk::Text`].
+    Text(String),
+    /// See [`StreamChunk::ReasoningContent`].
+    ReasoningContent(String),
+    /// See [`StreamChunk::Audio`].
+    Audio {
+        /// Base64-encoded audio data.
+        data: String,
+        /// Audio transcript (if available).
+        transcript: Option<String>,
+    },
+    /// See [`StreamChunk::ToolUseStart`].
+    ToolUseStart {
+        /// Index of this tool call in the response.
+        index: usize,
+        /// Unique identifier for this tool call.
+        id: String,
+        /// Name of the function being called.
+        name: String,
+    },
+    /// See [`StreamChunk::ToolUseDelta`].
+    ToolUseDelta {
+        /// Index of the tool call being updated.
+        index: usize,
+        /// Partial JSON arguments.
+        partial_json: String,
+    },
+    /// See [`StreamChunk::ToolUseComplete`].
+    ToolUseComplete {
+        /// Index of the completed tool call.
+        index: usize,
+    },
+    /// See [`StreamChunk::Usage`].
+    Usage(Usage),
+    /// See [`StreamChunk::Done`].
+    Done {
+        /// Stop reason from the model.
+        stop_reason: Option<StopReason>,
+    },
+    /// See [`StreamChunk::Error`].
+    Error {
+        /// Error message.
+        message: String,
+    },
+}
+
+impl From<StreamChunk> for CassetteChunk {
+    fn from(chunk: StreamChunk) -> Self {
+        match chunk {
+            StreamChunk::Text(text) => Self::Text(text),
+            StreamChunk::ReasoningContent(text) => Self::ReasoningContent(text),
+            StreamChunk::Audio { data, transcript } => Self::Audio { data, transcript },
+            StreamChunk::ToolUseStart { index, id, name } => Self::ToolUseStart { index, id, name },
+            StreamChunk::ToolUseDelta {
+                index,
+                partial_json,
+            } => Self::ToolUseDelta {
+                index,
+                partial_json,
+            },
+            StreamChunk::ToolUseComplete { index } => Self::ToolUseComplete { index },
+            StreamChunk::Usage(usage) => Self::Usage(usage),
+            StreamChunk::Done { stop_reason } => Self::Done { stop_reason },
+            StreamChunk::Error { message } => Self::Error { message },
+        }
+    }
+}
+
+impl From<CassetteChunk> for StreamChunk {
+    fn from(chunk: CassetteChunk) -> Self {
+        match chunk {
+            CassetteChunk::Text(text) => Self::Text(text),
+            CassetteChunk::ReasoningContent(text) => Self::ReasoningContent(text),
+            CassetteChunk::Audio { data, transcript } => Self::Audio { data, transcript },
+            CassetteChunk::ToolUseStart { index, id, name } => {
+                Self::ToolUseStart { index, id, name }
+            }
+            CassetteChunk::ToolUseDelta {
+                index,
+                partial_json,
+            } => Self::ToolUseDelta {
+                index,
+                partial_json,
+            },
+            CassetteChunk::ToolUseComplete { index } => Self::ToolUseComplete { index },
+            CassetteChunk::Usage(usage) => Self::Usage(usage),
+            CassetteChunk::Done { stop_reason } => Self::Done { stop_reason },
+            CassetteChunk::Error { message } => Self::Error { message },
+        }
+    }
+}
+
+/// One recorded request/response pair — a single line of a cassette file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteEntry {
+    /// The normalized request, as produced by [`CassetteEntry::normalize`].
+    pub request: Value,
+    /// What the provider returned for this request.
+    pub response: CassetteResponse,
+}
+
+impl CassetteEntry {
+    /// Creates an entry for a non-streaming response.
+    #[must_use]
+    pub fn chat(request: &ChatRequest, response: ChatResponse) -> Self {
+        Self {
+            request: Self::normalize(request),
+            response: CassetteResponse::Chat {
+                response: Box::new(response),
+            },
+        }
+    }
+
+    /// Creates an entry for a streamed response.
+    #[must_use]
+    pub fn stream(request: &ChatRequest, chunks: Vec<StreamChunk>) -> Self {
+        Self {
+            request: Self::normalize(request),
+            response: CassetteResponse::Stream {
+                chunks: chunks.into_iter().map(CassetteChunk::from).collect(),
+                error: None,
+            },
+        }
+    }
+
+    /// Creates an entry for a stream that failed after yielding `chunks`.
+    #[must_use]
+    pub fn failed_stream(
+        request: &ChatRequest,
+        chunks: Vec<StreamChunk>,
+        error: impl Into<CassetteError>,
+    ) -> Self {
+        Self {
+            request: Self::normalize(request),
+            response: CassetteResponse::Stream {
+                chunks: chunks.into_iter().map(CassetteChunk::from).collect(),
+                error: Some(error.into()),
+            },
+        }
+    }
+
+    /// Creates an entry for a failed call.
+    #[must_use]
+    pub fn error(request: &ChatRequest, error: impl Into<CassetteError>) -> Self {
+        Self {
+            request: Self::normalize(request),
+            response: CassetteResponse::Error(error.into()),
+        }
+    }
+
+    /// Reduces a request to the fields used for replay matching.
+    ///
+    /// Only the model, the messages and the tool definitions participate.
+    /// Tools are sorted by name so registration order does not matter, and
+    /// sampling parameters and the `stream` flag are ignored.
+    #[must_use]
+    pub fn normalize(request: &ChatRequest) -> Value {
+        let messages = serde_json::to_value(&request.messages).unwrap_or(Value::Null);
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .flatten()
+            .filter_map(|t| serde_json::to_value(t).ok())
+            .collect();
+        tools.sort_by(|a, b| tool_name(a).cmp(tool_name(b)));
+
+        serde_json::json!({
+            "model": request.model,
+            "messages": messages,
+            "tools": tools,
+        })
+    }
+}
+
+/// Extracts `function.name` from a serialized tool definition.
+fn tool_name(tool: &Value) -> &str {
+    tool.pointer("/function/name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// Appends entries to a cassette file, one JSON object per line.
+#[derive(Debug)]
+struct CassetteWriter {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl CassetteWriter {
+    fn write(&self, entry: &CassetteEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = self.file.lock().map_err(|e| {
+            LlmError::internal(format!(
+                "cassette '{}' lock poisoned: {e}",
+                self.path.display()
+            ))
+        })?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// A streamed call being recorded.
+///
+/// The entry is written when the stream ends or fails, or with the chunks
+/// read so far if the stream is dropped first.
+struct PendingStream {
+    writer: Arc<CassetteWriter>,
+    request: ChatRequest,
+    /// `None` once the entry has been written.
+    chunks: Option<Vec<StreamChunk>>,
+}
+
+impl PendingStream {
+    fn push(&mut self, chunk: StreamChunk) {
+        if let Some(chunks) = &mut self.chunks {
+            chunks.push(chunk);
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.chunks.take() {
+            Some(chunks) => self
+                .writer
+                .write(&CassetteEntry::stream(&self.request, chunks)),
+            None => Ok(()),
+        }
+    }
+
+    fn fail(&mut self, error: &Error) -> Result<()> {
+        let chunks = self.chunks.take().unwrap_or_default();
+        self.writer
+            .write(&CassetteEntry::failed_stream(&self.request, chunks, error))
+    }
+}
+
+impl Drop for PendingStream {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            warn!(path = %self.writer.path.display(), error = %e, "Failed to record dropped stream");
+        }
+    }
+}
+
+/// A [`ChatProvider`] that records every interaction to a cassette file.
+///
+/// Calls are forwarded to the wrapped provider unchanged. Each completed
+/// call — including errors — is appended to the cassette as a
+/// [`CassetteEntry`]. Streamed calls are written once the stream ends, with
+/// every chunk preserved so replays reproduce the same [`RunEvent`]s. A
+/// stream dropped before its end (e.g. when a stream guardrail trips) is
+/// recorded with the chunks read so far, which a replay stops after in the
+/// same place.
+///
+/// [`RunEvent`]: crate::agent::RunEvent
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use machi::agent::Agent;
+/// use machi::llms::{OpenAI, RecordingProvider};
+///
+/// # fn example() -> machi::Result<()> {
+/// let openai = Arc::new(OpenAI::from_env()?);
+/// let recorder = RecordingProvider::create(openai, "tests/cassettes/weather.jsonl")?;
+///
+/// let agent = Agent::new("assistant")
+///     .model("gpt-4o")
+///     .provider(Arc::new(recorder));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RecordingProvider {
+    inner: SharedChatProvider,
+    writer: Arc<CassetteWriter>,
+}
+
+impl RecordingProvider {
+    /// Wraps `inner`, creating (or truncating) the cassette at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cassette file cannot be created.
+    pub fn create(inner: SharedChatProvider, path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path.as_ref())?;
+        Ok(Self::with_file(inner, path.as_ref(), file))
+    }
+
+    /// Wraps `inner`, appending to the cassette at `path` (created if missing).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cassette file cannot be opened.
+    pub fn append(inner: SharedChatProvider, path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())?;
+        Ok(Self::with_file(inner, path.as_ref(), file))
+    }
+
+    fn with_file(inner: SharedChatProvider, path: &Path, file: File) -> Self {
+        Self {
+            inner,
+            writer: Arc::new(CassetteWriter {
+                path: path.to_path_buf(),
+                file: Mutex::new(file),
+            }),
+        }
+    }
+
+    /// Returns the path of the cassette being written.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.writer.path
+    }
+}
+
+impl std::fmt::Debug for RecordingProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordingProvider")
+            .field("inner", &self.inner.provider_name())
+            .field("path", &self.writer.path)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl ChatProvider for RecordingProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse> {
+        match self.inner.chat(request).await {
+            Ok(response) => {
+                self.writer
+                    .write(&CassetteEntry::chat(request, response.clone()))?;
+                Ok(response)
+            }
+            Err(e) => {
+                self.writer.write(&CassetteEntry::error(request, &e))?;
+                Err(e)
+            }
+        }
+    }
+
+    // `tail_expr_drop_order`: false positive from the `stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let mut inner = match self.inner.chat_stream(request).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.writer.write(&CassetteEntry::error(request, &e))?;
+                return Err(e);
+            }
+        };
+
+        let mut pending = PendingStream {
+            writer: Arc::clone(&self.writer),
+            request: request.clone(),
+            chunks: Some(Vec::new()),
+        };
+
+        Ok(Box::pin(async_stream::stream! {
+            while let Some(item) = inner.next().await {
+                match item {
+                    Ok(chunk) => {
+                        pending.push(chunk.clone());
+                        yield Ok(chunk);
+                    }
+                    Err(e) => {
+                        if let Err(write_err) = pending.fail(&e) {
+                            yield Err(write_err);
+                        }
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            if let Err(e) = pending.finish() {
+                yield Err(e);
+            }
+        }))
+    }
+
+    fn provider_name(&self) -> &'static str {
+        self.inner.provider_name()
+    }
+
+    fn default_model(&self) -> &str {
+        self.inner.default_model()
+    }
+
+    fn supports_streaming(&self) -> bool {
+        self.inner.supports_streaming()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    fn supports_vision(&self) -> bool {
+        self.inner.supports_vision()
+    }
+
+    fn supports_json_mode(&self) -> bool {
+        self.inner.supports_json_mode()
+    }
+}
+
+/// A [`ChatProvider`] that serves responses from a recorded cassette.
+///
+/// Each incoming request is normalized with [`CassetteEntry::normalize`] and
+/// matched against the first unused entry with an identical key; identical
+/// requests recorded several times are replayed in recording order. A request
+/// with no matching entry returns an [`LlmError::Provider`] describing it.
+///
+/// Entries recorded with `chat` can be replayed through `chat_stream` and
+/// vice versa — the response is converted to the requested shape.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    entries: Vec<CassetteEntry>,
+    used: Mutex<Vec<bool>>,
+    default_model: String,
+}
+
+impl ReplayProvider {
+    /// Loads a cassette from a JSON Lines file.
+    ///
+    /// Blank lines are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a line is not a valid
+    /// [`CassetteEntry`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = Vec::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|e| {
+                LlmError::provider(
+                    REPLAY_PROVIDER,
+                    format!(
+                        "{}:{}: invalid cassette entry: {e}",
+                        path.display(),
+                        idx + 1
+                    ),
+                )
+            })?;
+            entries.push(entry);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    /// Creates a replay provider from in-memory entries.
+    #[must_use]
+    pub fn from_entries(entries: Vec<CassetteEntry>) -> Self {
+        let default_model = entries
+            .first()
+            .and_then(|e| e.request.get("model"))
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned();
+        Self {
+            used: Mutex::new(vec![false; entries.len()]),
+            entries,
+            default_model,
+        }
+    }
+
+    /// Returns the number of recorded entries not yet replayed.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.used
+            .lock()
+            .map_or(0, |used| used.iter().filter(|u| !**u).count())
+    }
+
+    /// Returns `true` if every recorded entry has been replayed.
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Finds and consumes the next entry matching `request`.
+    fn take(&self, request: &ChatRequest) -> Result<CassetteResponse> {
+        let key = CassetteEntry::normalize(request);
+        let mut used = self
+            .used
+            .lock()
+            .map_err(|e| LlmError::internal(format!("replay state lock poisoned: {e}")))?;
+
+        let idx = self
+            .entries
+            .iter()
+            .enumerate()
+            .position(|(i, e)| !used[i] && e.request == key)
+            .ok_or_else(|| Self::unmatched(request))?;
+
+        used[idx] = true;
+        Ok(self.entries[idx].response.clone())
+    }
+
+    /// Builds the error returned for a request with no recorded counterpart.
+    fn unmatched(request: &ChatRequest) -> LlmError {
+        let last = request
+            .messages
+            .last()
+            .map(|m| format!("{}: {}", m.role, m.text().unwrap_or_default()))
+            .unwrap_or_default();
+        LlmError::provider(
+            REPLAY_PROVIDER,
+            format!(
+                "no recorded interaction matches request (model '{}', {} messages, last message {last:?})",
+                request.model,
+                request.messages.len(),
+            ),
+        )
+    }
+
+    /// Converts a complete response into the chunk sequence a provider would stream.
+    pub(crate) fn response_to_chunks(response: &ChatResponse) -> Vec<StreamChunk> {
+        let mut chunks = Vec::new();
+        if let Some(reasoning) = response.message.reasoning_content.clone() {
+            chunks.push(StreamChunk::reasoning(reasoning));
+        }
+        if let Some(text) = response.text().filter(|t| !t.is_empty()) {
+            chunks.push(StreamChunk::text(text));
+        }
+        for (index, call) in response.tool_calls().unwrap_or_default().iter().enumerate() {
+            chunks.push(StreamChunk::tool_use_start(index, &call.id, call.name()));
+            chunks.push(StreamChunk::tool_use_delta(index, call.arguments()));
+            chunks.push(StreamChunk::ToolUseComplete { index });
+        }
+        if let Some(usage) = response.usage {
+            chunks.push(StreamChunk::Usage(usage));
+        }
+        chunks.push(StreamChunk::done(Some(response.stop_reason)));
+        chunks
+    }
+}
+
+#[async_trait]
+impl ChatProvider for ReplayProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse> {
+        match self.take(request)? {
+            CassetteResponse::Chat { response } => Ok(*response),
+            CassetteResponse::Stream {
+                error: Some(error), ..
+            }
+            | CassetteResponse::Error(error) => Err(error.into_error()),
+            CassetteResponse::Stream {
+                chunks,
+                error: None,
+            } => {
+                let mut aggregator = StreamAggregator::new();
+                for chunk in chunks {
+                    aggregator.apply(&chunk.into());
+                }
+                Ok(aggregator.into_chat_response())
+            }
+        }
+    }
+
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let (chunks, error) = match self.take(request)? {
+            CassetteResponse::Chat { response } => (Self::response_to_chunks(&response), None),
+            CassetteResponse::Stream { chunks, error } => {
+                (chunks.into_iter().map(Into::into).collect(), error)
+            }
+            CassetteResponse::Error(error) => return Err(error.into_error()),
+        };
+        let items = chunks
+            .into_iter()
+            .map(Ok)
+            .chain(error.map(|e| Err(e.into_error())));
+        Ok(Box::pin(futures::stream::iter(items)))
+    }
+
+    fn provider_name(&self) -> &'static str {
+        REPLAY_PROVIDER
+    }
+
+    fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn supports_vision(&self) -> bool {
+        true
+    }
+
+    fn supports_json_mode(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::message::ToolCall;
+    use crate::tool::ToolDefinition;
+
+    /// Provider that answers every request with a numbered text response.
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChatProvider for CountingProvider {
+        async fn chat(&self, _request: &ChatRequest) -> Result<ChatResponse> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ChatResponse::from_text(format!("answer-{n}")).with_usage(Usage::new(10, 5)))
+        }
+
+        async fn chat_stream(
+            &self,
+            _request: &ChatRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+            let chunks = vec![
+                Ok(StreamChunk::text("Hel")),
+                Ok(StreamChunk::text("lo")),
+                Ok(StreamChunk::done(None)),
+            ];
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+
+        fn provider_name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn default_model(&self) -> &'static str {
+            "mock-model"
+        }
+    }
+
+    fn temp_cassette(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("machi_test_cassette");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(format!("{name}-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    async fn collect(
+        stream: Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>,
+    ) -> Vec<StreamChunk> {
+        stream.map(Result::unwrap).collect().await
+    }
+
+    mod normalize {
+        use super::*;
+
+        #[test]
+        fn ignores_sampling_parameters_and_stream_flag() {
+            let a = ChatRequest::new("m").user("hi").temperature(0.2);
+            let b = ChatRequest::new("m").user("hi").temperature(0.9).stream();
+            assert_eq!(CassetteEntry::normalize(&a), CassetteEntry::normalize(&b));
+        }
+
+        #[test]
+        fn tool_order_is_irrelevant() {
+            let t1 = ToolDefinition::new("alpha", "a", serde_json::json!({}));
+            let t2 = ToolDefinition::new("beta", "b", serde_json::json!({}));
+            let a = ChatRequest::new("m").tools(vec![t1.clone(), t2.clone()]);
+            let b = ChatRequest::new("m").tools(vec![t2, t1]);
+            assert_eq!(CassetteEntry::normalize(&a), CassetteEntry::normalize(&b));
+        }
+
+        #[test]
+        fn model_and_messages_participate() {
+            let base = ChatRequest::new("m").user("hi");
+            let other_model = ChatRequest::new("n").user("hi");
+            let other_text = ChatRequest::new("m").user("hello");
+            assert_ne!(
+                CassetteEntry::normalize(&base),
+                CassetteEntry::normalize(&other_model)
+            );
+            assert_ne!(
+                CassetteEntry::normalize(&base),
+                CassetteEntry::normalize(&other_text)
+            );
+        }
+    }
+
+    mod record_and_replay {
+        use super::*;
+
+        #[tokio::test]
+        async fn chat_round_trips_through_file() {
+            let path = temp_cassette("chat");
+            let inner = Arc::new(CountingProvider::default());
+            let recorder = RecordingProvider::create(inner, &path).unwrap();
+
+            let first = ChatRequest::new("mock-model").user("one");
+            let second = ChatRequest::new("mock-model").user("two");
+            let recorded_1 = recorder.chat(&first).await.unwrap();
+            let recorded_2 = recorder.chat(&second).await.unwrap();
+
+            let replay = ReplayProvider::from_file(&path).unwrap();
+            assert_eq!(replay.remaining(), 2);
+            assert_eq!(replay.default_model(), "mock-model");
+
+            // Out-of-order lookups still match by request.
+            let replayed_2 = replay.chat(&second).await.unwrap();
+            let replayed_1 = replay.chat(&first).await.unwrap();
+            assert_eq!(replayed_1.text(), recorded_1.text());
+            assert_eq!(replayed_2.text(), recorded_2.text());
+            assert_eq!(replayed_1.usage, recorded_1.usage);
+            assert!(replay.is_exhausted());
+
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn stream_chunks_are_preserved() {
+            let path = temp_cassette("stream");
+            let recorder =
+                RecordingProvider::create(Arc::new(CountingProvider::default()), &path).unwrap();
+
+            let request = ChatRequest::new("mock-model").user("stream me").stream();
+            let recorded = collect(recorder.chat_stream(&request).await.unwrap()).await;
+            assert_eq!(recorded.len(), 3);
+
+            let replay = ReplayProvider::from_file(&path).unwrap();
+            let replayed = collect(replay.chat_stream(&request).await.unwrap()).await;
+            let texts: Vec<_> = replayed.iter().filter_map(StreamChunk::as_text).collect();
+            assert_eq!(texts, ["Hel", "lo"]);
+            assert!(replayed.last().unwrap().is_done());
+
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn dropped_streams_are_recorded_up_to_the_drop() {
+            let path = temp_cassette("dropped");
+            let recorder =
+                RecordingProvider::create(Arc::new(CountingProvider::default()), &path).unwrap();
+
+            let request = ChatRequest::new("mock-model").user("stop early").stream();
+            let mut stream = recorder.chat_stream(&request).await.unwrap();
+            assert_eq!(stream.next().await.unwrap().unwrap().as_text(), Some("Hel"));
+            drop(stream);
+
+            let replay = ReplayProvider::from_file(&path).unwrap();
+            let replayed = collect(replay.chat_stream(&request).await.unwrap()).await;
+            let texts: Vec<_> = replayed.iter().filter_map(StreamChunk::as_text).collect();
+            assert_eq!(texts, ["Hel"]);
+            assert!(replay.is_exhausted());
+
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn mid_stream_errors_replay_after_their_chunks() {
+            /// Provider whose stream yields one chunk and then hits a rate limit.
+            struct FlakyStream;
+
+            #[async_trait]
+            impl ChatProvider for FlakyStream {
+                async fn chat(&self, _request: &ChatRequest) -> Result<ChatResponse> {
+                    Err(LlmError::rate_limited("flaky").into())
+                }
+
+                async fn chat_stream(
+                    &self,
+                    _request: &ChatRequest,
+                ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>>
+                {
+                    let items = vec![
+                        Ok(StreamChunk::text("par")),
+                        Err(LlmError::rate_limited("flaky").into()),
+                    ];
+                    Ok(Box::pin(futures::stream::iter(items)))
+                }
+
+                fn provider_name(&self) -> &'static str {
+                    "flaky"
+                }
+
+                fn default_model(&self) -> &'static str {
+                    "m"
+                }
+            }
+
+            let path = temp_cassette("mid-stream-error");
+            let recorder = RecordingProvider::create(Arc::new(FlakyStream), &path).unwrap();
+            let request = ChatRequest::new("m").user("go").stream();
+            let recorded: Vec<_> = recorder
+                .chat_stream(&request)
+                .await
+                .unwrap()
+                .collect()
+                .await;
+            assert_eq!(recorded.len(), 2);
+
+            let replay = ReplayProvider::from_file(&path).unwrap();
+            let mut replayed = replay.chat_stream(&request).await.unwrap();
+            assert_eq!(
+                replayed.next().await.unwrap().unwrap().as_text(),
+                Some("par")
+            );
+            let err = replayed.next().await.unwrap().unwrap_err();
+            assert!(
+                matches!(err, Error::Llm(LlmError::RateLimited { ref provider }) if provider == "flaky"),
+                "{err}"
+            );
+            assert!(replayed.next().await.is_none());
+
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[tokio::test]
+        async fn append_keeps_existing_entries() {
+            let path = temp_cassette("append");
+            let inner: SharedChatProvider = Arc::new(CountingProvider::default());
+
+            let recorder = RecordingProvider::create(Arc::clone(&inner), &path).unwrap();
+            recorder
+                .chat(&ChatRequest::new("m").user("a"))
+                .await
+                .unwrap();
+            drop(recorder);
+
+            let recorder = RecordingProvider::append(inner, &path).unwrap();
+            recorder
+                .chat(&ChatRequest::new("m").user("b"))
+                .await
+                .unwrap();
+
+            let replay = ReplayProvider::from_file(&path).unwrap();
+            assert_eq!(replay.remaining(), 2);
+
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    mod replay {
+        use super::*;
+
+        #[tokio::test]
+        async fn identical_requests_replay_in_order() {
+            let request = ChatRequest::new("m").user("again");
+            let replay = ReplayProvider::from_entries(vec![
+                CassetteEntry::chat(&request, ChatResponse::from_text("first")),
+                CassetteEntry::chat(&request, ChatResponse::from_text("second")),
+            ]);
+
+            assert_eq!(
+                replay.chat(&request).await.unwrap().text().unwrap(),
+                "first"
+            );
+            assert_eq!(
+                replay.chat(&request).await.unwrap().text().unwrap(),
+                "second"
+            );
+            assert!(replay.chat(&request).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn unmatched_request_fails_loudly() {
+            let replay = ReplayProvider::from_entries(vec![CassetteEntry::chat(
+                &ChatRequest::new("m").user("known"),
+                ChatResponse::from_text("ok"),
+            )]);
+
+            let err = replay
+                .chat(&ChatRequest::new("m").user("unknown"))
+                .await
+                .unwrap_err();
+            let msg = err.to_string();
+            assert!(msg.contains("no recorded interaction"), "{msg}");
+            assert!(msg.contains("unknown"), "{msg}");
+            assert_eq!(replay.remaining(), 1);
+        }
+
+        #[tokio::test]
+        async fn recorded_error_is_replayed() {
+            let request = ChatRequest::new("m").user("boom");
+            let replay =
+                ReplayProvider::from_entries(vec![CassetteEntry::error(&request, "rate limited")]);
+
+            let err = replay.chat(&request).await.unwrap_err();
+            assert!(err.to_string().contains("rate limited"));
+        }
+
+        #[tokio::test]
+        async fn recorded_error_kind_is_preserved() {
+            let request = ChatRequest::new("m").user("boom");
+            let error = Error::from(LlmError::http_status(503, "overloaded"));
+            let replay = ReplayProvider::from_entries(vec![CassetteEntry::error(&request, &error)]);
+
+            let err = replay.chat(&request).await.unwrap_err();
+            assert!(
+                matches!(err, Error::Llm(LlmError::HttpStatus { status: 503, .. })),
+                "{err}"
+            );
+        }
+
+        #[test]
+        fn legacy_error_entries_still_parse() {
+            let line = r#"{"request":{},"response":{"type":"error","message":"boom"}}"#;
+            let entry: CassetteEntry = serde_json::from_str(line).unwrap();
+            let CassetteResponse::Error(error) = entry.response else {
+                panic!("expected an error entry");
+            };
+            assert_eq!(error.message, "boom");
+            assert!(error.kind.is_none());
+        }
+
+        #[tokio::test]
+        async fn chat_entry_can_be_streamed() {
+            let request = ChatRequest::new("m").user("call a tool");
+            let response = ChatResponse::new(crate::message::Message::assistant_tool_calls(vec![
+                ToolCall::function("call_1", "lookup", r#"{"q":"rust"}"#),
+            ]))
+            .with_usage(Usage::new(3, 4));
+            let replay =
+                ReplayProvider::from_entries(vec![CassetteEntry::chat(&request, response)]);
+
+            let chunks = collect(replay.chat_stream(&request).await.unwrap()).await;
+            let mut aggregator = StreamAggregator::new();
+            for chunk in &chunks {
+                aggregator.apply(chunk);
+            }
+            let rebuilt = aggregator.into_chat_response();
+            let calls = rebuilt.tool_calls().unwrap();
+            assert_eq!(calls[0].id, "call_1");
+            assert_eq!(calls[0].name(), "lookup");
+            assert_eq!(calls[0].arguments(), r#"{"q":"rust"}"#);
+            assert_eq!(rebuilt.usage, Some(Usage::new(3, 4)));
+        }
+
+        #[tokio::test]
+        async fn stream_entry_can_be_read_as_chat() {
+            let request = ChatRequest::new("m").user("hi");
+            let replay = ReplayProvider::from_entries(vec![CassetteEntry::stream(
+                &request,
+                vec![StreamChunk::text("a"), StreamChunk::text("b")],
+            )]);
+
+            let response = replay.chat(&request).await.unwrap();
+            assert_eq!(response.text().unwrap(), "ab");
+        }
+    }
+}
@@ -4,11 +4,16 @@
 //! backends (authentication, rate limiting, network issues, etc.).
 //! It integrates into the global [`Error`](crate::Error) hierarchy via `Error::Llm`.
 
+use serde::{Deserialize, Serialize};
+
 /// Error type for LLM provider operations.
 ///
 /// Each variant represents a distinct failure mode, enabling callers to
 /// pattern-match on specific cases (e.g., retrying transient errors).
-#[derive(Debug, Clone, thiserror::Error)]
+/// Errors serialize with their kind, so recorded failures can be replayed
+/// faithfully.
+#[derive(Debug, Clone, thiserror::Error, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data", rename_all = "snake_case")]
 #[non_exhaustive]
 pub enum LlmError {
     /// Authentication or authorization failure.
@@ -7,7 +7,13 @@
 //!
 //! - [`openai`] - `OpenAI` API (GPT-4o, GPT-4, etc.)
 //! - [`ollama`] - Ollama local LLM server
+//!
+//! # Testing Utilities
+//!
+//! - [`RecordingProvider`] / [`ReplayProvider`] - record real interactions to
+//!   a cassette file and replay them offline (see [`cassette`])
 
+pub mod cassette;
 pub mod error;
 
 #[cfg(feature = "openai")]
@@ -16,6 +22,10 @@
 #[cfg(feature = "ollama")]
 pub mod ollama;
 
+pub use cassette::{
+    CassetteChunk, CassetteEntry, CassetteError, CassetteResponse, RecordingProvider,
+    ReplayProvider,
+};
 pub use error::LlmError;
 
 #[cfg(feature = "openai")]
@@ -0,0 +1,536 @@
+//! Session wrapper that summarizes old history.
+//!
+//! [`CompactingSession`] keeps a long-lived conversation within a model's
+//! context window. Once the stored history grows past a message or token
+//! threshold, the oldest messages are summarized by a [`ChatProvider`] into
+//! a single summary message, followed by the most recent turns verbatim.
+//!
+//! The summary is written back to the wrapped session, so each compaction
+//! only summarizes the previous summary plus the messages added since.
+//!
+//! [`ChatProvider`]: crate::chat::ChatProvider
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use super::session::Session;
+use crate::chat::{ChatRequest, SharedChatProvider};
+use crate::error::{LlmError, Result};
+use crate::message::{Message, Role};
+
+/// Text that starts every summary message.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+/// Instructions given to the summarizing model by default.
+const DEFAULT_INSTRUCTIONS: &str = "You maintain the running summary of a long conversation \
+    between a user and an AI assistant. Merge the previous summary (if any) with the new \
+    messages into one concise summary. Keep facts, decisions, user preferences, open tasks \
+    and tool results that later turns may rely on. Drop greetings and small talk. Reply with \
+    the summary only.";
+
+/// A [`Session`] that summarizes older messages once history gets too long.
+///
+/// After every [`add_messages`](Session::add_messages) the wrapper checks
+/// the stored history against [`max_messages`](Self::max_messages) and
+/// [`max_tokens`](Self::max_tokens). When either is exceeded, everything
+/// except the last [`keep_recent`](Self::keep_recent) messages is replaced
+/// by one summary message. [`get_messages`](Session::get_messages) then
+/// returns the summary followed by the recent turns.
+///
+/// The split point never separates an assistant message with tool calls
+/// from its tool results; the recent window grows to keep them together.
+/// Writes through the wrapper wait for a running compaction, and the
+/// compacted history replaces the old one with
+/// [`replace_messages`](Session::replace_messages), so no message added in
+/// the meantime is lost.
+/// If summarizing fails, the history is kept as is and the failure is
+/// logged — call [`compact`](Self::compact) to see the error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use machi::llms::OpenAI;
+/// use machi::memory::{CompactingSession, InMemorySession};
+///
+/// # fn example() -> machi::Result<()> {
+/// let provider = Arc::new(OpenAI::from_env()?);
+/// let session = CompactingSession::new(InMemorySession::new("conv-1"), provider)
+///     .max_tokens(50_000)
+///     .keep_recent(10);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CompactingSession<S: Session> {
+    inner: S,
+    provider: SharedChatProvider,
+    model: Option<String>,
+    instructions: String,
+    summary_role: Role,
+    max_messages: Option<usize>,
+    max_tokens: Option<usize>,
+    keep_recent: usize,
+    compacting: Mutex<()>,
+}
+
+impl<S: Session> CompactingSession<S> {
+    /// Message threshold used when no threshold is configured.
+    pub const DEFAULT_MAX_MESSAGES: usize = 100;
+
+    /// Default number of recent messages kept verbatim.
+    pub const DEFAULT_KEEP_RECENT: usize = 20;
+
+    /// Wrap `inner`, summarizing with `provider`.
+    #[must_use]
+    pub fn new(inner: S, provider: SharedChatProvider) -> Self {
+        Self {
+            inner,
+            provider,
+            model: None,
+            instructions: DEFAULT_INSTRUCTIONS.to_owned(),
+            summary_role: Role::System,
+            max_messages: None,
+            max_tokens: None,
+            keep_recent: Self::DEFAULT_KEEP_RECENT,
+            compacting: Mutex::new(()),
+        }
+    }
+
+    /// Summarize with `model` instead of the provider's default model.
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Replace the instructions given to the summarizing model.
+    #[must_use]
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    /// Store the summary as an assistant message instead of a system message.
+    #[must_use]
+    pub const fn summary_as_assistant(mut self) -> Self {
+        self.summary_role = Role::Assistant;
+        self
+    }
+
+    /// Compact once more than `n` messages are stored.
+    ///
+    /// Without this or [`max_tokens`](Self::max_tokens), compaction starts
+    /// after [`DEFAULT_MAX_MESSAGES`](Self::DEFAULT_MAX_MESSAGES).
+    #[must_use]
+    pub const fn max_messages(mut self, n: usize) -> Self {
+        self.max_messages = Some(n);
+        self
+    }
+
+    /// Compact once the stored history exceeds roughly `n` tokens
+    /// (estimated at four characters per token).
+    #[must_use]
+    pub const fn max_tokens(mut self, n: usize) -> Self {
+        self.max_tokens = Some(n);
+        self
+    }
+
+    /// Keep the last `n` messages verbatim when compacting
+    /// (default: [`DEFAULT_KEEP_RECENT`](Self::DEFAULT_KEEP_RECENT)).
+    #[must_use]
+    pub const fn keep_recent(mut self, n: usize) -> Self {
+        self.keep_recent = n;
+        self
+    }
+
+    /// Returns the wrapped session.
+    #[must_use]
+    pub const fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the current summary, if the history has been compacted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wrapped session cannot be read.
+    pub async fn summary(&self) -> Result<Option<String>> {
+        let first = self.inner.get_messages(None).await?.into_iter().next();
+        Ok(first.as_ref().and_then(summary_text))
+    }
+
+    /// Summarize older messages now if a threshold is exceeded.
+    ///
+    /// Returns `true` if the history was compacted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wrapped session fails, the model call fails
+    /// or the model replies without any summary text. The history is left
+    /// untouched in every case.
+    pub async fn compact(&self) -> Result<bool> {
+        let _guard = self.compacting.lock().await;
+        self.compact_locked().await
+    }
+
+    /// [`compact`](Self::compact), with the compaction lock already held.
+    async fn compact_locked(&self) -> Result<bool> {
+        let messages = self.inner.get_messages(None).await?;
+        if !self.over_threshold(&messages) {
+            return Ok(false);
+        }
+
+        let previous = messages.first().and_then(summary_text);
+        let start = usize::from(previous.is_some());
+        let split = split_point(&messages, self.keep_recent);
+        if split <= start {
+            return Ok(false);
+        }
+
+        let summary = self
+            .summarize(previous.as_deref(), &messages[start..split])
+            .await?;
+        let mut compacted = Vec::with_capacity(messages.len() - split + 1);
+        compacted.push(Message::new(
+            self.summary_role,
+            format!("{SUMMARY_PREFIX}{summary}"),
+        ));
+        compacted.extend_from_slice(&messages[split..]);
+
+        self.inner.replace_messages(&compacted).await?;
+        Ok(true)
+    }
+
+    fn over_threshold(&self, messages: &[Message]) -> bool {
+        let max_messages = match (self.max_messages, self.max_tokens) {
+            (None, None) => Some(Self::DEFAULT_MAX_MESSAGES),
+            (n, _) => n,
+        };
+        max_messages.is_some_and(|n| messages.len() > n)
+            || self
+                .max_tokens
+                .is_some_and(|n| messages.iter().map(estimate_tokens).sum::<usize>() > n)
+    }
+
+    async fn summarize(&self, previous: Option<&str>, messages: &[Message]) -> Result<String> {
+        let mut prompt = String::new();
+        if let Some(previous) = previous {
+            let _ = write!(
+                prompt,
+                "<previous_summary>\n{previous}\n</previous_summary>\n\n"
+            );
+        }
+        prompt.push_str("<messages>\n");
+        for message in messages {
+            prompt.push_str(&transcript_line(message));
+            prompt.push('\n');
+        }
+        prompt.push_str("</messages>");
+
+        let model = self
+            .model
+            .as_deref()
+            .unwrap_or_else(|| self.provider.default_model());
+        let request = ChatRequest::new(model)
+            .system(&self.instructions)
+            .user(prompt)
+            .temperature(0.0);
+        let reply = self.provider.chat(&request).await?;
+        let summary = reply.text().unwrap_or_default().trim().to_owned();
+        if summary.is_empty() {
+            return Err(LlmError::response_format("a summary", "a reply without text").into());
+        }
+        Ok(summary)
+    }
+}
+
+/// Returns the text of `message` without its prefix if it is a summary.
+fn summary_text(message: &Message) -> Option<String> {
+    if !matches!(message.role, Role::System | Role::Assistant) {
+        return None;
+    }
+    message
+        .text()
+        .and_then(|text| text.strip_prefix(SUMMARY_PREFIX).map(str::to_owned))
+}
+
+/// Index of the first message to keep verbatim.
+///
+/// Moves the split earlier while it would start on a tool result, so tool
+/// calls and their results stay on the same side.
+fn split_point(messages: &[Message], keep_recent: usize) -> usize {
+    let mut split = messages.len().saturating_sub(keep_recent);
+    while split > 0 && split < messages.len() && messages[split].role == Role::Tool {
+        split -= 1;
+    }
+    split
+}
+
+fn estimate_tokens(message: &Message) -> usize {
+    let text = message.text().map_or(0, |t| t.chars().count());
+    let calls: usize = message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|c| c.name().len() + c.arguments().len())
+        .sum();
+    (text + calls).div_ceil(4)
+}
+
+fn transcript_line(message: &Message) -> String {
+    let text = message.text().unwrap_or_default();
+    match message.role {
+        Role::Tool => format!(
+            "tool result ({}): {text}",
+            message.tool_call_id.as_deref().unwrap_or_default()
+        ),
+        role if message.has_tool_calls() => {
+            let mut line = format!("{role}: {text}");
+            for call in message.tool_calls.iter().flatten() {
+                let _ = write!(line, " [called {call} as {}]", call.id);
+            }
+            line
+        }
+        role => format!("{role}: {text}"),
+    }
+}
+
+impl<S: Session> std::fmt::Debug for CompactingSession<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompactingSession")
+            .field("id", &self.inner.id())
+            .field("provider", &self.provider.provider_name())
+            .field("model", &self.model)
+            .field("max_messages", &self.max_messages)
+            .field("max_tokens", &self.max_tokens)
+            .field("keep_recent", &self.keep_recent)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<S: Session> Session for CompactingSession<S> {
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
+        self.inner.get_messages(limit).await
+    }
+
+    async fn add_messages(&self, messages: &[Message]) -> Result<()> {
+        let _guard = self.compacting.lock().await;
+        self.inner.add_messages(messages).await?;
+        if let Err(e) = self.compact_locked().await {
+            warn!(session = self.inner.id(), error = %e, "session compaction failed");
+        }
+        Ok(())
+    }
+
+    async fn pop_message(&self) -> Result<Option<Message>> {
+        let _guard = self.compacting.lock().await;
+        self.inner.pop_message().await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let _guard = self.compacting.lock().await;
+        self.inner.clear().await
+    }
+
+    async fn replace_messages(&self, messages: &[Message]) -> Result<()> {
+        let _guard = self.compacting.lock().await;
+        self.inner.replace_messages(messages).await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        self.inner.len().await
+    }
+
+    async fn get_state(&self) -> Result<HashMap<String, Value>> {
+        self.inner.get_state().await
+    }
+
+    async fn put_state(&self, state: &HashMap<String, Value>) -> Result<()> {
+        self.inner.put_state(state).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    use super::*;
+    use crate::chat::ChatResponse;
+    use crate::memory::InMemorySession;
+    use crate::message::ToolCall;
+    use crate::test_util::{ScriptedProvider, last_user_text};
+
+    /// Provider that records each summarization prompt and answers
+    /// `summary <n>`.
+    fn summarizer() -> (SharedChatProvider, Arc<StdMutex<Vec<String>>>) {
+        let prompts = Arc::new(StdMutex::new(Vec::new()));
+        let seen = Arc::clone(&prompts);
+        let provider = ScriptedProvider::new(move |req| {
+            let mut prompts = seen.lock().unwrap();
+            prompts.push(last_user_text(req));
+            ChatResponse::from_text(format!("summary {}", prompts.len()))
+        });
+        (Arc::new(provider), prompts)
+    }
+
+    fn turns(range: std::ops::Range<usize>) -> Vec<Message> {
+        range
+            .flat_map(|i| {
+                [
+                    Message::user(format!("q{i}")),
+                    Message::assistant(format!("a{i}")),
+                ]
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn old_messages_are_replaced_by_a_summary() {
+        let (provider, prompts) = summarizer();
+        let session = CompactingSession::new(InMemorySession::new("s"), provider)
+            .max_messages(6)
+            .keep_recent(2);
+
+        session.add_messages(&turns(0..3)).await.unwrap();
+        assert!(prompts.lock().unwrap().is_empty());
+
+        session.add_messages(&turns(3..4)).await.unwrap();
+        let messages = session.get_messages(None).await.unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, Role::System);
+        assert_eq!(
+            messages[0].text().unwrap(),
+            "Summary of the earlier conversation:\nsummary 1"
+        );
+        assert_eq!(messages[1].text().unwrap(), "q3");
+        assert_eq!(session.summary().await.unwrap().unwrap(), "summary 1");
+
+        let prompt = prompts.lock().unwrap()[0].clone();
+        assert!(prompt.contains("user: q0") && prompt.contains("assistant: a2"));
+        assert!(!prompt.contains("q3"));
+    }
+
+    #[tokio::test]
+    async fn compaction_is_incremental() {
+        let (provider, prompts) = summarizer();
+        let session = CompactingSession::new(InMemorySession::new("s"), provider)
+            .max_messages(4)
+            .keep_recent(2)
+            .summary_as_assistant();
+
+        session.add_messages(&turns(0..3)).await.unwrap();
+        session.add_messages(&turns(3..5)).await.unwrap();
+
+        let prompts = prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains("<previous_summary>\nsummary 1\n</previous_summary>"));
+        assert!(prompts[1].contains("q2") && !prompts[1].contains("q0"));
+
+        let messages = session.get_messages(None).await.unwrap();
+        assert_eq!(messages[0].role, Role::Assistant);
+        assert_eq!(session.summary().await.unwrap().unwrap(), "summary 2");
+    }
+
+    #[tokio::test]
+    async fn concurrent_adds_are_not_lost_to_compaction() {
+        let (provider, prompts) = summarizer();
+        let session = Arc::new(
+            CompactingSession::new(InMemorySession::new("s"), provider)
+                .max_messages(4)
+                .keep_recent(2),
+        );
+
+        let writers = (0..8).map(|i| {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move { session.add_messages(&turns(i..i + 1)).await })
+        });
+        for writer in writers {
+            writer.await.unwrap().unwrap();
+        }
+
+        // Every turn is either still verbatim or went into a summary.
+        let messages = session.get_messages(None).await.unwrap();
+        let prompts = prompts.lock().unwrap().join("\n");
+        for i in 0..8 {
+            let question = format!("q{i}");
+            let kept = messages.iter().any(|m| m.text() == Some(question.clone()));
+            assert!(
+                kept || prompts.contains(&format!("user: {question}\n")),
+                "{question} was lost"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_calls_stay_with_their_results() {
+        let (provider, _) = summarizer();
+        let session = CompactingSession::new(InMemorySession::new("s"), provider)
+            .max_messages(4)
+            .keep_recent(2);
+
+        let mut history = turns(0..1);
+        history.push(Message::user("weather?"));
+        history.push(Message::assistant_tool_calls(vec![
+            ToolCall::function("c1", "weather", "{}"),
+            ToolCall::function("c2", "time", "{}"),
+        ]));
+        history.push(Message::tool("c1", "sunny"));
+        history.push(Message::tool("c2", "noon"));
+        session.add_messages(&history).await.unwrap();
+
+        let messages = session.get_messages(None).await.unwrap();
+        assert_eq!(messages.len(), 4);
+        assert!(messages[1].has_tool_calls());
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("c2"));
+    }
+
+    #[tokio::test]
+    async fn token_threshold_triggers_compaction() {
+        let (provider, prompts) = summarizer();
+        let session = CompactingSession::new(InMemorySession::new("s"), provider)
+            .max_tokens(10)
+            .keep_recent(1);
+
+        session
+            .add_messages(&[Message::user("short")])
+            .await
+            .unwrap();
+        assert!(!session.compact().await.unwrap());
+
+        session
+            .add_messages(&[Message::assistant("x".repeat(40))])
+            .await
+            .unwrap();
+        assert_eq!(prompts.lock().unwrap().len(), 1);
+        assert_eq!(session.len().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn replies_without_text_keep_the_history() {
+        let provider = ScriptedProvider::new(|_| {
+            ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                "c1", "noop", "{}",
+            )]))
+        });
+        let session = CompactingSession::new(InMemorySession::new("s"), Arc::new(provider))
+            .max_messages(2)
+            .keep_recent(1);
+
+        session.add_messages(&turns(0..2)).await.unwrap();
+        assert!(session.compact().await.is_err());
+        assert_eq!(session.get_messages(None).await.unwrap(), turns(0..2));
+        assert!(session.summary().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,431 @@
+//! Encryption at rest for `SQLite` sessions.
+//!
+//! Messages and saved run state are sealed with XChaCha20-Poly1305 into a
+//! JSON envelope that keeps only the role readable. See [`Keyring`] for the
+//! format and key rotation.
+
+use std::sync::Arc;
+
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+
+use super::error::MemoryError;
+use crate::message::{Message, Role};
+
+/// Value of the envelope's `enc` field.
+const ALGORITHM: &str = "xchacha20poly1305";
+
+/// A 256-bit key with an identifier recorded next to every message it seals.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    id: String,
+    cipher: XChaCha20Poly1305,
+}
+
+impl EncryptionKey {
+    /// Uses `key` as is, e.g. from a secrets manager or your own KDF.
+    #[must_use]
+    pub fn new(id: impl Into<String>, key: [u8; 32]) -> Self {
+        Self {
+            id: id.into(),
+            cipher: XChaCha20Poly1305::new(&key.into()),
+        }
+    }
+
+    /// Derives a key from `passphrase` with Argon2id.
+    ///
+    /// Store `salt` (at least 8 random bytes) alongside the database; the
+    /// same passphrase and salt always give the same key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MemoryError::Encryption`] if the salt is too short.
+    pub fn from_passphrase(
+        id: impl Into<String>,
+        passphrase: &str,
+        salt: &[u8],
+    ) -> Result<Self, MemoryError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| MemoryError::Encryption(format!("key derivation failed: {e}")))?;
+        Ok(Self::new(id, key))
+    }
+
+    /// Returns the key identifier.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The keys a session encrypts and decrypts with.
+///
+/// With a keyring, [`SqliteSession`](super::SqliteSession) stores each
+/// message as an envelope instead of plaintext JSON:
+///
+/// ```json
+/// {"enc":"xchacha20poly1305","kid":"2025-06","role":"user","nonce":"…","data":"…"}
+/// ```
+///
+/// Only the role stays readable, so retention can keep tool calls with
+/// their results; it is authenticated along with the key id and the
+/// session id, so a row copied into another session does not decrypt there.
+/// `SessionStore::fork_at` re-seals the rows it copies for the new session.
+/// Encrypted messages are left out of the full-text index. Run state saved
+/// with [`Session::put_state`](super::Session::put_state) is sealed the same
+/// way, without a role.
+///
+/// Rows are sealed individually, so the keyring cannot tell whether a row
+/// was deleted from or replayed within the same session.
+///
+/// # Key Rotation
+///
+/// New messages are sealed with the primary key. Keys added with
+/// [`previous`](Self::previous) still decrypt older messages, and
+/// `reencrypt` on a session or `SessionStore` rewrites every message and
+/// saved state under the primary key, after which the old keys can be dropped.
+/// It also turns on `secure_delete`, optimizes the full-text index and
+/// checkpoints the WAL, so the replaced rows do not linger in the file.
+///
+/// # Plaintext
+///
+/// Unencrypted rows are rejected by default, so a row swapped in by someone
+/// with write access to the file cannot pass for one of yours. To migrate a
+/// database written before encryption was enabled, open it once with
+/// [`allow_plaintext`](Self::allow_plaintext) and run `reencrypt`.
+///
+/// # Example
+///
+/// ```rust
+/// use machi::memory::{EncryptionKey, Keyring, SqliteSession};
+///
+/// let keyring = Keyring::new(EncryptionKey::new("2025-06", [7; 32]))
+///     .previous(EncryptionKey::new("2025-01", [3; 32]));
+/// let session = SqliteSession::in_memory("wallet-chat")?.with_encryption(keyring);
+/// # Ok::<(), machi::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keyring {
+    primary: EncryptionKey,
+    previous: Vec<EncryptionKey>,
+    allow_plaintext: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    enc: String,
+    kid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<Role>,
+    nonce: String,
+    data: String,
+}
+
+impl Keyring {
+    /// Encrypt new messages with `primary`.
+    #[must_use]
+    pub const fn new(primary: EncryptionKey) -> Self {
+        Self {
+            primary,
+            previous: Vec::new(),
+            allow_plaintext: false,
+        }
+    }
+
+    /// Also accept `key` when decrypting older messages.
+    #[must_use]
+    pub fn previous(mut self, key: EncryptionKey) -> Self {
+        self.previous.push(key);
+        self
+    }
+
+    /// Read unencrypted rows as is instead of rejecting them (default: off).
+    ///
+    /// Meant for migrating data written before encryption was enabled; turn
+    /// it off again once `reencrypt` has run.
+    #[must_use]
+    pub const fn allow_plaintext(mut self, allow: bool) -> Self {
+        self.allow_plaintext = allow;
+        self
+    }
+
+    /// Returns the id of the key new messages are sealed with.
+    #[must_use]
+    pub fn primary_id(&self) -> &str {
+        &self.primary.id
+    }
+
+    /// Seals a serialized message of `session_id`, or its saved run state
+    /// when `role` is `None`.
+    pub(super) fn seal(
+        &self,
+        session_id: &str,
+        role: Option<Role>,
+        json: &str,
+    ) -> Result<String, MemoryError> {
+        let key = &self.primary;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(&key.id, session_id, role);
+        let data = key
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: json.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| MemoryError::Encryption("failed to encrypt data".to_owned()))?;
+        Ok(serde_json::to_string(&Envelope {
+            enc: ALGORITHM.to_owned(),
+            kid: key.id.clone(),
+            role,
+            nonce: STANDARD.encode(nonce),
+            data: STANDARD.encode(data),
+        })?)
+    }
+
+    /// Returns the serialized message or state of `session_id` in `stored`,
+    /// decrypting it if sealed.
+    pub(super) fn open(&self, session_id: &str, stored: &str) -> Result<String, MemoryError> {
+        if !is_sealed(stored) {
+            if self.allow_plaintext {
+                return Ok(stored.to_owned());
+            }
+            return Err(MemoryError::Encryption(
+                "found unencrypted data; use `Keyring::allow_plaintext` to migrate it".to_owned(),
+            ));
+        }
+        let envelope: Envelope = serde_json::from_str(stored)?;
+        if envelope.enc != ALGORITHM {
+            return Err(MemoryError::Encryption(format!(
+                "unsupported cipher `{}`",
+                envelope.enc
+            )));
+        }
+        let key = std::iter::once(&self.primary)
+            .chain(&self.previous)
+            .find(|k| k.id == envelope.kid)
+            .ok_or_else(|| {
+                MemoryError::Encryption(format!(
+                    "message was encrypted with key `{}`, which is not in the keyring",
+                    envelope.kid
+                ))
+            })?;
+
+        let corrupt = || {
+            MemoryError::Encryption(format!(
+                "cannot decrypt message with key `{}`: wrong key or corrupted data",
+                envelope.kid
+            ))
+        };
+        let nonce: [u8; 24] = STANDARD
+            .decode(&envelope.nonce)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(corrupt)?;
+        let data = STANDARD.decode(&envelope.data).map_err(|_| corrupt())?;
+        let aad = associated_data(&envelope.kid, session_id, envelope.role);
+        let plain = key
+            .cipher
+            .decrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: &data,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| corrupt())?;
+        String::from_utf8(plain).map_err(|_| corrupt())
+    }
+
+    /// Re-seals a row of session `from` for session `to`. Plaintext rows
+    /// are returned as is when allowed.
+    pub(super) fn rebind(&self, from: &str, to: &str, stored: &str) -> Result<String, MemoryError> {
+        let json = self.open(from, stored)?;
+        if !is_sealed(stored) {
+            return Ok(json);
+        }
+        let role = serde_json::from_str::<Envelope>(stored)?.role;
+        self.seal(to, role, &json)
+    }
+
+    /// Returns `true` if `stored` is already sealed with the primary key.
+    fn is_current(&self, stored: &str) -> bool {
+        serde_json::from_str::<Envelope>(stored)
+            .is_ok_and(|e| e.enc == ALGORITHM && e.kid == self.primary.id)
+    }
+}
+
+/// Binds the key id, the session id and the role (or the `state` marker)
+/// to the ciphertext, so a row cannot be moved to another session and a
+/// sealed state can never be read back as a message or vice versa.
+///
+/// Encoded as a JSON array so ids containing separators stay unambiguous.
+fn associated_data(kid: &str, session_id: &str, role: Option<Role>) -> String {
+    let label = role.map_or_else(|| "state".to_owned(), |role| role.to_string());
+    serde_json::json!([ALGORITHM, kid, session_id, label]).to_string()
+}
+
+/// Returns `true` if `stored` is an encryption envelope rather than a
+/// message or state map.
+pub(super) fn is_sealed(stored: &str) -> bool {
+    serde_json::from_str::<Envelope>(stored).is_ok()
+}
+
+/// Rewrites every message and saved state (of one session, or all) not yet
+/// sealed with the primary key. Returns the number of rows rewritten.
+///
+/// `secure_delete` stays on for the connection; after rewriting, the
+/// full-text index is optimized and the WAL truncated so no replaced
+/// content remains in the index or the log.
+pub(super) fn reencrypt(
+    conn: &Connection,
+    keyring: &Arc<Keyring>,
+    session_id: Option<&str>,
+) -> Result<usize, MemoryError> {
+    conn.execute_batch("PRAGMA secure_delete = ON;")?;
+    let tx = conn.unchecked_transaction()?;
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT id, session_id, message_data FROM messages \
+             WHERE ?1 IS NULL OR session_id = ?1 ORDER BY id",
+        )?;
+        stmt.query_map(params![session_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mut rewritten = 0;
+    for (id, owner, stored) in rows {
+        if keyring.is_current(&stored) {
+            continue;
+        }
+        let json = keyring.open(&owner, &stored)?;
+        let role = serde_json::from_str::<Message>(&json)?.role;
+        tx.execute(
+            "UPDATE messages SET message_data = ?1 WHERE id = ?2",
+            params![keyring.seal(&owner, Some(role), &json)?, id],
+        )?;
+        tx.execute("DELETE FROM messages_fts WHERE rowid = ?1", params![id])?;
+        rewritten += 1;
+    }
+
+    let states = {
+        let mut stmt = tx.prepare(
+            "SELECT session_id, data FROM session_state WHERE ?1 IS NULL OR session_id = ?1",
+        )?;
+        stmt.query_map(params![session_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    for (id, stored) in states {
+        if keyring.is_current(&stored) {
+            continue;
+        }
+        let json = keyring.open(&id, &stored)?;
+        tx.execute(
+            "UPDATE session_state SET data = ?1 WHERE session_id = ?2",
+            params![keyring.seal(&id, None, &json)?, id],
+        )?;
+        rewritten += 1;
+    }
+    tx.commit()?;
+
+    if rewritten > 0 {
+        conn.execute_batch("INSERT INTO messages_fts (messages_fts) VALUES ('optimize');")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn keyring() -> Keyring {
+        Keyring::new(EncryptionKey::new("k1", [1; 32]))
+    }
+
+    #[test]
+    fn sealed_messages_round_trip_and_hide_content() {
+        let keyring = keyring();
+        let json = serde_json::to_string(&Message::user("send 5 ETH to 0xabc")).unwrap();
+        let sealed = keyring.seal("s", Some(Role::User), &json).unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("0xabc"));
+        assert_eq!(keyring.open("s", &sealed).unwrap(), json);
+    }
+
+    #[test]
+    fn rows_are_bound_to_their_session() {
+        let keyring = keyring();
+        let sealed = keyring.seal("a", Some(Role::User), "{}").unwrap();
+        let err = keyring.open("b", &sealed).unwrap_err();
+        assert!(err.to_string().contains("wrong key"), "{err}");
+
+        let moved = keyring.rebind("a", "b", &sealed).unwrap();
+        assert_eq!(keyring.open("b", &moved).unwrap(), "{}");
+        assert!(keyring.open("a", &moved).is_err());
+        assert!(keyring.rebind("b", "c", &sealed).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_rejected_unless_allowed() {
+        let json = serde_json::to_string(&Message::user("hi")).unwrap();
+        let err = keyring().open("s", &json).unwrap_err();
+        assert!(err.to_string().contains("allow_plaintext"), "{err}");
+        assert_eq!(
+            keyring().allow_plaintext(true).open("s", &json).unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn wrong_or_missing_keys_are_reported() {
+        let sealed = keyring().seal("s", Some(Role::User), "{}").unwrap();
+
+        let wrong = Keyring::new(EncryptionKey::new("k1", [2; 32]));
+        let err = wrong.open("s", &sealed).unwrap_err();
+        assert!(err.to_string().contains("wrong key"), "{err}");
+
+        let other = Keyring::new(EncryptionKey::new("k2", [1; 32]));
+        let err = other.open("s", &sealed).unwrap_err();
+        assert!(err.to_string().contains("`k1`"), "{err}");
+
+        let tampered = sealed.replace("\"role\":\"user\"", "\"role\":\"system\"");
+        assert!(keyring().open("s", &tampered).is_err());
+        let as_state = sealed.replace(",\"role\":\"user\"", "");
+        assert!(keyring().open("s", &as_state).is_err());
+    }
+
+    #[test]
+    fn passphrase_keys_are_deterministic() {
+        let a = EncryptionKey::from_passphrase("p", "correct horse", b"saltsalt").unwrap();
+        let b = EncryptionKey::from_passphrase("p", "correct horse", b"saltsalt").unwrap();
+        let sealed = Keyring::new(a).seal("s", Some(Role::User), "{}").unwrap();
+        assert_eq!(Keyring::new(b).open("s", &sealed).unwrap(), "{}");
+        assert!(EncryptionKey::from_passphrase("p", "x", b"short").is_err());
+    }
+}
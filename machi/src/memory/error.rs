@@ -27,6 +27,11 @@ pub enum MemoryError {
     #[error("lock error: {0}")]
     Lock(String),
 
+    /// Encrypting or decrypting stored messages failed, e.g. because of a
+    /// wrong or missing key.
+    #[error("encryption error: {0}")]
+    Encryption(String),
+
     /// An async task failed to join (`spawn_blocking` panicked or was cancelled).
     #[error("task error: {0}")]
     Task(String),
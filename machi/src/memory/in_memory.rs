@@ -5,31 +5,58 @@
 //!
 //! Best suited for single-run agents, testing, and short-lived conversations.
 
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
 use async_trait::async_trait;
+use serde_json::Value;
 use tokio::sync::RwLock;
 
+use super::retention::{RetentionPolicy, keep_from};
 use super::session::Session;
 use crate::error::Result;
-use crate::message::Message;
+use crate::message::{Message, Role};
 
 /// In-memory session backed by `tokio::sync::RwLock<Vec<Message>>`.
 ///
 /// Concurrent readers may retrieve history simultaneously; writes acquire
 /// exclusive access. All data is ephemeral — lost when the value is dropped.
+///
+/// Bound the history with [`with_retention`](Self::with_retention).
 #[derive(Debug)]
 pub struct InMemorySession {
     id: String,
     messages: RwLock<Vec<Message>>,
+    /// When each message was added, and when the session was last written.
+    /// Only touched while holding the `messages` write lock.
+    stamps: Mutex<Stamps>,
+    retention: Option<RetentionPolicy>,
+    /// Run state saved by the agent runner; not subject to retention.
+    state: Mutex<HashMap<String, Value>>,
+}
+
+#[derive(Debug)]
+struct Stamps {
+    added: Vec<Instant>,
+    updated: Instant,
+}
+
+impl Stamps {
+    fn new(len: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            added: vec![now; len],
+            updated: now,
+        }
+    }
 }
 
 impl InMemorySession {
     /// Creates an empty session.
     #[must_use]
     pub fn new(id: impl Into<String>) -> Self {
-        Self {
-            id: id.into(),
-            messages: RwLock::new(Vec::new()),
-        }
+        Self::with_messages(id, Vec::new())
     }
 
     /// Creates a session pre-populated with `messages`.
@@ -37,20 +64,92 @@ pub fn new(id: impl Into<String>) -> Self {
     pub fn with_messages(id: impl Into<String>, messages: Vec<Message>) -> Self {
         Self {
             id: id.into(),
+            stamps: Mutex::new(Stamps::new(messages.len())),
             messages: RwLock::new(messages),
+            retention: None,
+            state: Mutex::new(HashMap::new()),
         }
     }
 
     /// Creates an empty session with pre-allocated capacity.
+    ///
+    /// This does not bound the history; use [`with_retention`](Self::with_retention).
     #[must_use]
     pub fn with_capacity(id: impl Into<String>, capacity: usize) -> Self {
-        Self {
-            id: id.into(),
-            messages: RwLock::new(Vec::with_capacity(capacity)),
+        Self::with_messages(id, Vec::with_capacity(capacity))
+    }
+
+    /// Applies `policy` on every write and before every read.
+    #[must_use]
+    pub const fn with_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Applies the retention policy now, returning the number of messages
+    /// removed. Does nothing without a policy.
+    ///
+    /// # Errors
+    ///
+    /// Never fails; returns `Result` for parity with other backends.
+    pub async fn prune(&self) -> Result<usize> {
+        let Some(policy) = self.retention else {
+            return Ok(0);
+        };
+        let mut messages = self.messages.write().await;
+        let mut stamps = self.stamps();
+        Ok(expire(&policy, &mut messages, &mut stamps) + trim(&policy, &mut messages, &mut stamps))
+    }
+
+    fn stamps(&self) -> std::sync::MutexGuard<'_, Stamps> {
+        self.stamps.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn prune_expired(&self) {
+        if let Some(policy) = self.retention.filter(RetentionPolicy::expires) {
+            let mut messages = self.messages.write().await;
+            expire(&policy, &mut messages, &mut self.stamps());
         }
     }
 }
 
+/// Drops the session if idle too long, then messages past their TTL.
+fn expire(policy: &RetentionPolicy, messages: &mut Vec<Message>, stamps: &mut Stamps) -> usize {
+    if policy
+        .get_session_ttl()
+        .is_some_and(|ttl| stamps.updated.elapsed() > ttl)
+    {
+        let removed = messages.len();
+        messages.clear();
+        stamps.added.clear();
+        return removed;
+    }
+    let Some(ttl) = policy.get_message_ttl() else {
+        return 0;
+    };
+    let expired = stamps
+        .added
+        .iter()
+        .take_while(|t| t.elapsed() > ttl)
+        .count();
+    drop_front(messages, stamps, expired)
+}
+
+/// Drops the oldest messages beyond the message limit.
+fn trim(policy: &RetentionPolicy, messages: &mut Vec<Message>, stamps: &mut Stamps) -> usize {
+    let Some(max) = policy.get_max_messages() else {
+        return 0;
+    };
+    drop_front(messages, stamps, messages.len().saturating_sub(max))
+}
+
+fn drop_front(messages: &mut Vec<Message>, stamps: &mut Stamps, n: usize) -> usize {
+    let n = keep_from(messages.len(), n, |i| messages[i].role == Role::Tool);
+    messages.drain(..n);
+    stamps.added.drain(..n);
+    n
+}
+
 #[async_trait]
 impl Session for InMemorySession {
     fn id(&self) -> &str {
@@ -58,6 +157,7 @@ fn id(&self) -> &str {
     }
 
     async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
+        self.prune_expired().await;
         let guard = self.messages.read().await;
         match limit {
             Some(n) if n < guard.len() => Ok(guard[guard.len() - n..].to_vec()),
@@ -69,22 +169,63 @@ async fn add_messages(&self, messages: &[Message]) -> Result<()> {
         if messages.is_empty() {
             return Ok(());
         }
-        self.messages.write().await.extend(messages.iter().cloned());
+        let mut stored = self.messages.write().await;
+        let mut stamps = self.stamps();
+        if let Some(policy) = &self.retention {
+            expire(policy, &mut stored, &mut stamps);
+        }
+        stored.extend(messages.iter().cloned());
+        let now = Instant::now();
+        stamps.added.resize(stored.len(), now);
+        stamps.updated = now;
+        if let Some(policy) = &self.retention {
+            trim(policy, &mut stored, &mut stamps);
+        }
         Ok(())
     }
 
     async fn pop_message(&self) -> Result<Option<Message>> {
-        Ok(self.messages.write().await.pop())
+        self.prune_expired().await;
+        let mut messages = self.messages.write().await;
+        self.stamps().added.pop();
+        Ok(messages.pop())
     }
 
     async fn clear(&self) -> Result<()> {
-        self.messages.write().await.clear();
+        let mut messages = self.messages.write().await;
+        self.stamps().added.clear();
+        messages.clear();
+        Ok(())
+    }
+
+    async fn replace_messages(&self, messages: &[Message]) -> Result<()> {
+        let mut stored = self.messages.write().await;
+        let mut stamps = self.stamps();
+        *stored = messages.to_vec();
+        *stamps = Stamps::new(stored.len());
+        if let Some(policy) = &self.retention {
+            trim(policy, &mut stored, &mut stamps);
+        }
         Ok(())
     }
 
     async fn len(&self) -> Result<usize> {
+        self.prune_expired().await;
         Ok(self.messages.read().await.len())
     }
+
+    async fn get_state(&self) -> Result<HashMap<String, Value>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    async fn put_state(&self, state: &HashMap<String, Value>) -> Result<()> {
+        state.clone_into(&mut self.state.lock().unwrap_or_else(PoisonError::into_inner));
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -318,6 +459,32 @@ async fn clear_on_empty_session_is_idempotent() {
         }
     }
 
+    mod replace_messages {
+        use super::*;
+        use crate::memory::RetentionPolicy;
+
+        #[tokio::test]
+        async fn swaps_history_and_keeps_state() {
+            let session = InMemorySession::with_messages("rp1", sample_messages(5));
+            let state = HashMap::from([("k".to_owned(), Value::from(1))]);
+            session.put_state(&state).await.unwrap();
+
+            let replacement = sample_messages(2);
+            session.replace_messages(&replacement).await.unwrap();
+            assert_eq!(session.get_messages(None).await.unwrap(), replacement);
+            assert_eq!(session.get_state().await.unwrap(), state);
+        }
+
+        #[tokio::test]
+        async fn applies_the_message_limit() {
+            let session =
+                InMemorySession::new("rp2").with_retention(RetentionPolicy::new().max_messages(2));
+            let msgs = sample_messages(4);
+            session.replace_messages(&msgs).await.unwrap();
+            assert_eq!(session.get_messages(None).await.unwrap(), msgs[2..]);
+        }
+    }
+
     mod len_and_is_empty {
         use super::*;
 
@@ -509,4 +676,156 @@ async fn unicode_session_id() {
             assert_eq!(session.id(), "会话-αβγ-🦀");
         }
     }
+
+    mod retention {
+        use std::time::Duration;
+
+        use super::*;
+        use crate::memory::RetentionPolicy;
+        use crate::message::ToolCall;
+
+        #[tokio::test]
+        async fn max_messages_keeps_the_latest() {
+            let session =
+                InMemorySession::new("r1").with_retention(RetentionPolicy::new().max_messages(3));
+            let msgs = sample_messages(5);
+            session.add_messages(&msgs).await.unwrap();
+            assert_eq!(session.get_messages(None).await.unwrap(), msgs[2..]);
+        }
+
+        #[tokio::test]
+        async fn max_messages_never_orphans_tool_results() {
+            let session =
+                InMemorySession::new("r2").with_retention(RetentionPolicy::new().max_messages(2));
+            session
+                .add_messages(&[
+                    Message::user("weather?"),
+                    Message::assistant_tool_calls(vec![ToolCall::function("c1", "weather", "{}")]),
+                    Message::tool("c1", "sunny"),
+                    Message::assistant("It is sunny."),
+                ])
+                .await
+                .unwrap();
+
+            let stored = session.get_messages(None).await.unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].text().unwrap(), "It is sunny.");
+        }
+
+        #[tokio::test]
+        async fn expired_messages_are_never_returned() {
+            let session = InMemorySession::new("r3")
+                .with_retention(RetentionPolicy::new().message_ttl(Duration::from_millis(50)));
+            session.add_messages(&[Message::user("old")]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(80)).await;
+            session.add_messages(&[Message::user("new")]).await.unwrap();
+
+            let stored = session.get_messages(None).await.unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].text().unwrap(), "new");
+
+            tokio::time::sleep(Duration::from_millis(80)).await;
+            assert_eq!(session.len().await.unwrap(), 0);
+        }
+
+        #[tokio::test]
+        async fn idle_sessions_are_cleared() {
+            let session = InMemorySession::with_messages("r4", sample_messages(3))
+                .with_retention(RetentionPolicy::new().session_ttl(Duration::from_millis(50)));
+            assert_eq!(session.prune().await.unwrap(), 0);
+            tokio::time::sleep(Duration::from_millis(80)).await;
+            assert_eq!(session.prune().await.unwrap(), 3);
+            assert!(session.is_empty().await.unwrap());
+        }
+    }
+
+    mod state {
+        use async_trait::async_trait;
+        use serde_json::json;
+
+        use super::*;
+        use crate::agent::{Agent, RunConfig};
+        use crate::chat::ChatResponse;
+        use crate::memory::SharedSession;
+        use crate::message::ToolCall;
+        use crate::test_util::ScriptedProvider;
+        use crate::tool::{Tool, ToolContext, ToolError};
+
+        /// Advances `checkout_step` in the run state.
+        struct Advance;
+
+        #[async_trait]
+        impl Tool for Advance {
+            const NAME: &'static str = "advance";
+            type Args = Value;
+            type Output = String;
+            type Error = ToolError;
+
+            fn description(&self) -> String {
+                "Moves the checkout to the next step".to_owned()
+            }
+
+            fn parameters_schema(&self) -> Value {
+                json!({"type": "object"})
+            }
+
+            async fn call(
+                &self,
+                _args: Self::Args,
+            ) -> std::result::Result<Self::Output, Self::Error> {
+                Err(ToolError::execution("requires a run context"))
+            }
+
+            async fn call_with_context(
+                &self,
+                _args: Self::Args,
+                ctx: &ToolContext,
+            ) -> std::result::Result<Self::Output, Self::Error> {
+                let step = ctx
+                    .get_state("checkout_step")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0);
+                ctx.set_state("checkout_step", (step + 1).into());
+                Ok(format!("step {}", step + 1))
+            }
+        }
+
+        #[tokio::test]
+        async fn state_is_kept_apart_from_messages() {
+            let session = InMemorySession::with_messages("st1", sample_messages(2));
+            assert!(session.get_state().await.unwrap().is_empty());
+
+            let state = HashMap::from([("plan".to_owned(), json!("pro"))]);
+            session.put_state(&state).await.unwrap();
+            session.clear().await.unwrap();
+            assert_eq!(session.get_state().await.unwrap(), state);
+        }
+
+        #[tokio::test]
+        async fn runner_restores_and_saves_state() {
+            // Call the tool once per run, then answer with its output.
+            let provider = ScriptedProvider::new(|req| match req.messages.last() {
+                Some(m) if m.role == Role::Tool => {
+                    ChatResponse::from_text(m.text().unwrap_or_default())
+                }
+                _ => ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                    "c1", "advance", "{}",
+                )])),
+            });
+            let agent = Agent::new("checkout")
+                .provider(Arc::new(provider))
+                .tool(Box::new(Advance));
+            let session = Arc::new(InMemorySession::new("st2"));
+            let shared: SharedSession = Arc::<InMemorySession>::clone(&session);
+
+            for expected in ["\"step 1\"", "\"step 2\""] {
+                let config = RunConfig::new().session(Arc::clone(&shared));
+                let result = agent.run("next", config).await.unwrap();
+                assert_eq!(result.text(), Some(expected));
+            }
+            let state = session.get_state().await.unwrap();
+            assert_eq!(state.get("checkout_step"), Some(&json!(2)));
+            assert_eq!(session.len().await.unwrap(), 4);
+        }
+    }
 }
@@ -0,0 +1,527 @@
+//! JSON Lines file session implementation.
+//!
+//! [`JsonlSession`] stores one session per file, one serialized
+//! [`Message`] per line, appended in order. The files are plain text, so
+//! they are easy to inspect, diff, commit as test fixtures or feed to other
+//! tools.
+//!
+//! # Concurrency
+//!
+//! Every operation holds an OS file lock on the session file (shared for
+//! reads, exclusive for writes), so several processes may use the same
+//! session safely. Session files are only ever appended to or truncated,
+//! never replaced, which keeps the locks meaningful.
+//!
+//! Run state saved with [`Session::put_state`] lives in a sidecar file next
+//! to the session file (`conv-1.state.json` for `conv-1.jsonl`). It is read
+//! and written under the session file's lock.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::error::MemoryError;
+use super::session::Session;
+use crate::error::Result;
+use crate::message::Message;
+
+/// File extension of session files.
+const EXTENSION: &str = "jsonl";
+
+/// File extension of run state sidecar files.
+const STATE_EXTENSION: &str = "state.json";
+
+/// Session stored as an append-only JSON Lines file.
+///
+/// Reads stream the file line by line; with a `limit`, only the last
+/// `limit` lines are parsed. [`pop_message`](Session::pop_message)
+/// truncates the file at the start of its last line,
+/// [`clear`](Session::clear) truncates it to zero bytes, and
+/// [`replace_messages`](Session::replace_messages) truncates and rewrites it
+/// under one lock.
+///
+/// All blocking I/O is offloaded to the tokio blocking thread pool.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # tokio_test::block_on(async {
+/// use machi::memory::{JsonlSession, Session};
+/// use machi::message::Message;
+///
+/// // Stored in `.sessions/conv-1.jsonl`.
+/// let session = JsonlSession::open(".sessions", "conv-1")?;
+/// session.add_messages(&[Message::user("Hello")]).await?;
+/// # Ok::<(), machi::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonlSession {
+    id: String,
+    path: Arc<PathBuf>,
+}
+
+impl JsonlSession {
+    /// Opens the session `session_id` in `dir`, creating the directory if
+    /// needed.
+    ///
+    /// The file name is the session ID with characters outside
+    /// `[A-Za-z0-9._-]` percent-encoded, plus `.jsonl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn open(dir: impl AsRef<Path>, session_id: impl Into<String>) -> Result<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(io)?;
+        let id = session_id.into();
+        let path = dir.join(format!("{}.{EXTENSION}", file_stem(&id)));
+        Ok(Self {
+            id,
+            path: Arc::new(path),
+        })
+    }
+
+    /// Uses the file at `path` directly, e.g. a committed test fixture.
+    ///
+    /// The session ID is the file name without its extension.
+    #[must_use]
+    pub fn from_file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            id,
+            path: Arc::new(path),
+        }
+    }
+
+    /// Returns the path of the session file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the run state file, which exists once non-empty
+    /// state has been saved.
+    #[must_use]
+    pub fn state_path(&self) -> PathBuf {
+        self.path.with_extension(STATE_EXTENSION)
+    }
+
+    /// Runs `f` on the locked session file in the tokio blocking thread pool.
+    ///
+    /// The file is created if missing and locked exclusively when `write`
+    /// is set, shared otherwise. The lock is released when the file closes.
+    async fn locked<F, T>(&self, write: bool, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut File) -> std::result::Result<T, MemoryError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let path = Arc::clone(&self.path);
+        Ok(tokio::task::spawn_blocking(move || {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(path.as_path())
+                .map_err(io)?;
+            if write {
+                file.lock().map_err(io)?;
+            } else {
+                file.lock_shared().map_err(io)?;
+            }
+            f(&mut file)
+        })
+        .await
+        .map_err(|e| MemoryError::Task(e.to_string()))??)
+    }
+}
+
+/// Percent-encodes `id` into a portable file name.
+fn file_stem(id: &str) -> String {
+    let mut stem = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'_' | b'-') {
+            stem.push(char::from(byte));
+        } else {
+            let _ = write!(stem, "%{byte:02X}");
+        }
+    }
+    stem
+}
+
+#[allow(clippy::needless_pass_by_value)] // used as `map_err(io)`
+fn io(e: std::io::Error) -> MemoryError {
+    MemoryError::storage("jsonl", e.to_string())
+}
+
+/// Serializes `messages` one per line.
+fn to_lines(messages: &[Message]) -> std::result::Result<String, MemoryError> {
+    let mut lines = String::new();
+    for message in messages {
+        lines.push_str(&serde_json::to_string(message)?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Calls `f` with the byte offset and contents of each non-empty line.
+fn for_each_line(
+    file: &mut File,
+    mut f: impl FnMut(u64, &str) -> std::result::Result<(), MemoryError>,
+) -> std::result::Result<(), MemoryError> {
+    file.seek(SeekFrom::Start(0)).map_err(io)?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(io)?;
+        if read == 0 {
+            return Ok(());
+        }
+        let trimmed = line.trim();
+        if !trimmed.is_empty() {
+            f(offset, trimmed)?;
+        }
+        offset += read as u64;
+    }
+}
+
+/// The last `n` non-empty lines with their byte offsets, oldest first.
+///
+/// Reads backwards from the end of the file in blocks, so the cost depends
+/// on the length of the tail rather than of the whole history.
+fn last_lines(file: &mut File, n: usize) -> std::result::Result<Vec<(u64, String)>, MemoryError> {
+    const BLOCK: u64 = 8 * 1024;
+
+    let mut lines = Vec::new();
+    // Bytes from `pos` up to the last line split off so far.
+    let mut partial = Vec::new();
+    let mut pos = file.seek(SeekFrom::End(0)).map_err(io)?;
+    while pos > 0 && lines.len() < n {
+        let start = pos.saturating_sub(BLOCK);
+        // A block is at most `BLOCK` bytes, so it fits in `usize`.
+        #[allow(clippy::cast_possible_truncation)]
+        let mut block = vec![0; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start)).map_err(io)?;
+        file.read_exact(&mut block).map_err(io)?;
+        block.append(&mut partial);
+        partial = block;
+        pos = start;
+
+        while lines.len() < n {
+            let Some(newline) = partial.iter().rposition(|b| *b == b'\n') else {
+                break;
+            };
+            let line = partial.split_off(newline + 1);
+            partial.truncate(newline);
+            push_line(&mut lines, start + newline as u64 + 1, line)?;
+        }
+    }
+    if pos == 0 && lines.len() < n {
+        push_line(&mut lines, 0, partial)?;
+    }
+    lines.reverse();
+    Ok(lines)
+}
+
+/// Appends `bytes` as a line starting at `offset`, unless it is blank.
+fn push_line(
+    lines: &mut Vec<(u64, String)>,
+    offset: u64,
+    bytes: Vec<u8>,
+) -> std::result::Result<(), MemoryError> {
+    let line = String::from_utf8(bytes)
+        .map_err(|e| io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+    let trimmed = line.trim();
+    if !trimmed.is_empty() {
+        lines.push((offset, trimmed.to_owned()));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Session for JsonlSession {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
+        self.locked(false, move |file| {
+            let Some(n) = limit else {
+                let mut messages = Vec::new();
+                for_each_line(file, |_, line| {
+                    messages.push(serde_json::from_str(line)?);
+                    Ok(())
+                })?;
+                return Ok(messages);
+            };
+
+            last_lines(file, n)?
+                .iter()
+                .map(|(_, line)| Ok(serde_json::from_str(line)?))
+                .collect()
+        })
+        .await
+    }
+
+    async fn add_messages(&self, messages: &[Message]) -> Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let batch = to_lines(messages)?;
+        self.locked(true, move |file| {
+            // One write per batch, so readers never see half of it.
+            file.write_all(batch.as_bytes()).map_err(io)?;
+            file.flush().map_err(io)
+        })
+        .await
+    }
+
+    async fn pop_message(&self) -> Result<Option<Message>> {
+        self.locked(true, |file| {
+            let Some((offset, line)) = last_lines(file, 1)?.pop() else {
+                return Ok(None);
+            };
+            let message = serde_json::from_str(&line)?;
+            file.set_len(offset).map_err(io)?;
+            Ok(Some(message))
+        })
+        .await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.locked(true, |file| file.set_len(0).map_err(io)).await
+    }
+
+    async fn replace_messages(&self, messages: &[Message]) -> Result<()> {
+        let lines = to_lines(messages)?;
+        self.locked(true, move |file| {
+            file.set_len(0).map_err(io)?;
+            file.write_all(lines.as_bytes()).map_err(io)?;
+            file.flush().map_err(io)
+        })
+        .await
+    }
+
+    async fn get_state(&self) -> Result<HashMap<String, Value>> {
+        let path = self.state_path();
+        self.locked(false, move |_| match std::fs::read(&path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(io(e)),
+        })
+        .await
+    }
+
+    async fn put_state(&self, state: &HashMap<String, Value>) -> Result<()> {
+        let path = self.state_path();
+        let json = (!state.is_empty())
+            .then(|| serde_json::to_vec(state))
+            .transpose()
+            .map_err(MemoryError::from)?;
+        self.locked(true, move |_| {
+            let Some(json) = json else {
+                return match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io(e)),
+                    _ => Ok(()),
+                };
+            };
+            // Write a temporary file and rename it, so a crash never leaves
+            // half a state file behind.
+            let tmp = path.with_extension(format!("{STATE_EXTENSION}.tmp"));
+            std::fs::write(&tmp, json).map_err(io)?;
+            std::fs::rename(&tmp, &path).map_err(io)
+        })
+        .await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        self.locked(false, |file| {
+            let mut count = 0;
+            for_each_line(file, |_, _| {
+                count += 1;
+                Ok(())
+            })?;
+            Ok(count)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::message::{Role, ToolCall};
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("machi-jsonl-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn sample_messages(n: usize) -> Vec<Message> {
+        (0..n)
+            .map(|i| {
+                if i % 2 == 0 {
+                    Message::user(format!("user-{i}"))
+                } else {
+                    Message::assistant(format!("assistant-{i}"))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn last_lines_reads_across_blocks() {
+        let path = temp_dir().with_extension("jsonl");
+        let long = "x".repeat(10_000);
+        std::fs::write(&path, format!("a\n\n{long}\n  b  \n\nc")).unwrap();
+        let mut file = File::open(&path).unwrap();
+
+        let lines = last_lines(&mut file, 3).unwrap();
+        let texts: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(texts, [long.as_str(), "b", "c"]);
+        assert_eq!(lines[0].0, 3);
+        let all = last_lines(&mut file, 10).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0], (0, "a".to_owned()));
+        assert!(last_lines(&mut file, 0).unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn stores_one_message_per_line() {
+        let dir = temp_dir();
+        let session = JsonlSession::open(&dir, "conv-1").unwrap();
+        assert!(session.is_empty().await.unwrap());
+
+        let msgs = vec![
+            Message::user("line\nbreak"),
+            Message::assistant_tool_calls(vec![ToolCall::function("c1", "echo", "{}")]),
+            Message::tool("c1", "ok"),
+        ];
+        session.add_messages(&msgs).await.unwrap();
+        assert_eq!(session.get_messages(None).await.unwrap(), msgs);
+        assert_eq!(session.get_messages(Some(2)).await.unwrap(), msgs[1..]);
+        assert!(session.get_messages(Some(0)).await.unwrap().is_empty());
+
+        let text = std::fs::read_to_string(dir.join("conv-1.jsonl")).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn pop_and_clear_truncate_the_file() {
+        let dir = temp_dir();
+        let session = JsonlSession::open(&dir, "s").unwrap();
+        let msgs = sample_messages(3);
+        session.add_messages(&msgs).await.unwrap();
+
+        assert_eq!(session.pop_message().await.unwrap(), Some(msgs[2].clone()));
+        session.add_messages(&msgs[..1]).await.unwrap();
+        let stored = session.get_messages(None).await.unwrap();
+        assert_eq!(stored, [msgs[0].clone(), msgs[1].clone(), msgs[0].clone()]);
+
+        session.clear().await.unwrap();
+        assert_eq!(std::fs::metadata(session.path()).unwrap().len(), 0);
+        assert_eq!(session.pop_message().await.unwrap(), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn replace_rewrites_the_file() {
+        let dir = temp_dir();
+        let session = JsonlSession::open(&dir, "s").unwrap();
+        session.add_messages(&sample_messages(5)).await.unwrap();
+
+        let replacement = vec![Message::system("summary"), Message::user("q")];
+        session.replace_messages(&replacement).await.unwrap();
+        assert_eq!(session.get_messages(None).await.unwrap(), replacement);
+        let text = std::fs::read_to_string(session.path()).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn state_lives_in_a_sidecar_file() {
+        let dir = temp_dir();
+        let session = JsonlSession::open(&dir, "conv-1").unwrap();
+        assert!(session.get_state().await.unwrap().is_empty());
+
+        let state = HashMap::from([("step".to_owned(), Value::from(3))]);
+        session.put_state(&state).await.unwrap();
+        assert_eq!(session.state_path(), dir.join("conv-1.state.json"));
+        let reopened = JsonlSession::open(&dir, "conv-1").unwrap();
+        assert_eq!(reopened.get_state().await.unwrap(), state);
+
+        session.add_messages(&sample_messages(2)).await.unwrap();
+        session.clear().await.unwrap();
+        assert_eq!(session.get_state().await.unwrap(), state);
+        assert_eq!(session.len().await.unwrap(), 0);
+
+        session.put_state(&HashMap::new()).await.unwrap();
+        assert!(!session.state_path().exists());
+        assert!(session.get_state().await.unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn history_survives_reopening() {
+        let dir = temp_dir();
+        JsonlSession::open(&dir, "s")
+            .unwrap()
+            .add_messages(&sample_messages(4))
+            .await
+            .unwrap();
+
+        let reopened = JsonlSession::open(&dir, "s").unwrap();
+        assert_eq!(reopened.len().await.unwrap(), 4);
+        let fixture = JsonlSession::from_file(reopened.path());
+        assert_eq!(fixture.id(), "s");
+        assert_eq!(
+            fixture.get_messages(Some(1)).await.unwrap()[0].role,
+            Role::Assistant
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn concurrent_writers_do_not_interleave() {
+        let dir = temp_dir();
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let session = JsonlSession::open(&dir, "shared").unwrap();
+                tokio::spawn(async move {
+                    for _ in 0..10 {
+                        session.add_messages(&sample_messages(3)).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let session = JsonlSession::open(&dir, "shared").unwrap();
+        assert_eq!(session.get_messages(None).await.unwrap().len(), 240);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn session_ids_become_safe_file_names() {
+        assert_eq!(file_stem("conv-1.v2_a"), "conv-1.v2_a");
+        assert_eq!(file_stem("../etc/passwd"), "..%2Fetc%2Fpasswd");
+        assert_eq!(file_stem("会"), "%E4%BC%9A");
+    }
+}
@@ -1,16 +1,39 @@
-//! Session-based memory for AI agents.
+//! Session-based and long-term memory for AI agents.
 //!
 //! The [`Session`] trait abstracts over storage backends, treating the
 //! **message list as the single source of truth** for conversation history.
 //! Agents stay stateless — all context lives in the session.
 //!
+//! The [`MemoryStore`] trait keeps long-term facts that outlive a session,
+//! searched by embedding similarity. Agents use it through [`RememberTool`]
+//! and [`RecallTool`], or have relevant memories injected into every run
+//! with [`AutoRecall`].
+//!
 //! # Available Backends
 //!
 //! | Type | Persistence | Feature |
 //! |------|-------------|---------|
 //! | [`InMemorySession`] | None (ephemeral) | always |
+//! | [`JsonlSession`] | One JSON Lines file per session | always |
 //! | [`SqliteSession`] | File or `:memory:` | `memory-sqlite` |
 //!
+//! [`SessionStore`] lists, annotates, forks, searches and deletes the
+//! sessions in a `SQLite` database.
+//!
+//! With the `memory-encryption` feature, `SQLite` sessions can encrypt
+//! messages at rest under a `Keyring`.
+//!
+//! Both backends accept a [`RetentionPolicy`] that caps the number of
+//! messages and deletes history past a time to live.
+//!
+//! Wrap any backend in [`CompactingSession`] to summarize old messages once
+//! the history outgrows a model's context window.
+//!
+//! | Memory store | Persistence | Feature |
+//! |------|-------------|---------|
+//! | [`InMemoryStore`] | None (ephemeral) | always |
+//! | [`SqliteMemoryStore`] | File or `:memory:` | `memory-sqlite` |
+//!
 //! # Quick Start
 //!
 //! ```rust
@@ -34,16 +57,41 @@
 //! # }).unwrap();
 //! ```
 
+mod compacting;
+#[cfg(feature = "memory-encryption")]
+mod encryption;
 mod error;
 mod in_memory;
+mod jsonl;
+mod retention;
 mod session;
+mod store;
+mod tools;
 
+#[cfg(feature = "memory-sqlite")]
+mod session_store;
 #[cfg(feature = "memory-sqlite")]
 mod sqlite;
+#[cfg(feature = "memory-sqlite")]
+mod sqlite_store;
 
+pub use compacting::CompactingSession;
 pub use error::MemoryError;
 pub use in_memory::InMemorySession;
+pub use jsonl::JsonlSession;
+pub use retention::RetentionPolicy;
 pub use session::{BoxedSession, Session, SharedSession};
+pub use store::{
+    AutoRecall, InMemoryStore, MemoryFilter, MemoryMatch, MemoryRecord, MemoryStore,
+    SharedMemoryStore,
+};
+pub use tools::{RecallArgs, RecallTool, RememberArgs, RememberTool};
 
+#[cfg(feature = "memory-encryption")]
+pub use encryption::{EncryptionKey, Keyring};
+#[cfg(feature = "memory-sqlite")]
+pub use session_store::{SearchHit, SessionInfo, SessionMetadata, SessionQuery, SessionStore};
 #[cfg(feature = "memory-sqlite")]
 pub use sqlite::SqliteSession;
+#[cfg(feature = "memory-sqlite")]
+pub use sqlite_store::SqliteMemoryStore;
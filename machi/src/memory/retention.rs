@@ -0,0 +1,119 @@
+//! Retention rules for stored conversation history.
+//!
+//! A [`RetentionPolicy`] bounds how much history a session keeps and for
+//! how long. Sessions apply it on every write and before every read, so
+//! expired messages are never returned; call `prune` on a session (or
+//! `SessionStore::prune` for a whole `SQLite` database) to apply it eagerly.
+
+use std::time::Duration;
+
+/// Limits on the size and age of a session's history.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use machi::memory::{InMemorySession, RetentionPolicy};
+///
+/// // Keep the last 200 messages, and forget anything older than 30 days.
+/// let policy = RetentionPolicy::new()
+///     .max_messages(200)
+///     .message_ttl(Duration::from_hours(30 * 24));
+/// let session = InMemorySession::new("conv-1").with_retention(policy);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    max_messages: Option<usize>,
+    message_ttl: Option<Duration>,
+    session_ttl: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Creates a policy that keeps everything.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_messages: None,
+            message_ttl: None,
+            session_ttl: None,
+        }
+    }
+
+    /// Keep at most `n` messages, dropping the oldest first.
+    ///
+    /// Tool results are never kept without the assistant message that
+    /// called them, so slightly fewer than `n` messages may remain.
+    #[must_use]
+    pub const fn max_messages(mut self, n: usize) -> Self {
+        self.max_messages = Some(n);
+        self
+    }
+
+    /// Delete each message once it is older than `ttl`.
+    #[must_use]
+    pub const fn message_ttl(mut self, ttl: Duration) -> Self {
+        self.message_ttl = Some(ttl);
+        self
+    }
+
+    /// Delete the whole session once nothing has been added for `ttl`.
+    #[must_use]
+    pub const fn session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the message limit, if any.
+    #[must_use]
+    pub const fn get_max_messages(&self) -> Option<usize> {
+        self.max_messages
+    }
+
+    /// Returns the per-message time to live, if any.
+    #[must_use]
+    pub const fn get_message_ttl(&self) -> Option<Duration> {
+        self.message_ttl
+    }
+
+    /// Returns the per-session time to live, if any.
+    #[must_use]
+    pub const fn get_session_ttl(&self) -> Option<Duration> {
+        self.session_ttl
+    }
+
+    /// Returns `true` if the policy expires history by age.
+    #[must_use]
+    pub const fn expires(&self) -> bool {
+        self.message_ttl.is_some() || self.session_ttl.is_some()
+    }
+}
+
+/// Index of the first message to keep when dropping the front of a history
+/// of `len` messages.
+///
+/// Starts at `start` and moves past tool results, whose calls would
+/// otherwise be dropped without them.
+pub(super) fn keep_from(len: usize, mut start: usize, is_tool: impl Fn(usize) -> bool) -> usize {
+    if start == 0 {
+        return 0;
+    }
+    while start < len && is_tool(start) {
+        start += 1;
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_from_skips_orphaned_tool_results() {
+        let tool = [false, false, true, true, false];
+        assert_eq!(keep_from(5, 0, |i| tool[i]), 0);
+        assert_eq!(keep_from(5, 1, |i| tool[i]), 1);
+        assert_eq!(keep_from(5, 2, |i| tool[i]), 4);
+        assert_eq!(keep_from(4, 3, |i| tool[i]), 4);
+    }
+}
@@ -6,10 +6,14 @@
 //! # Design
 //!
 //! - **Stateless agents** — history lives in the session, not the agent.
-//! - **Messages as ground truth** — no separate metadata layer.
+//! - **Messages as ground truth** — no separate metadata layer; the only
+//!   extra data is the run state the agent runner restores between runs.
 //! - **Backend-agnostic** — implement [`Session`] for any storage engine.
 
+use std::collections::HashMap;
+
 use async_trait::async_trait;
+use serde_json::Value;
 
 use crate::error::Result;
 use crate::message::Message;
@@ -48,6 +52,18 @@ pub trait Session: Send + Sync {
     /// Removes all messages from this session.
     async fn clear(&self) -> Result<()>;
 
+    /// Replaces the whole history with `messages`, keeping the saved state.
+    ///
+    /// Readers see either the old or the new history, never a mix, and
+    /// retention treats every message as just added. The default
+    /// implementation calls [`clear`](Self::clear) and then
+    /// [`add_messages`](Self::add_messages), which is not atomic; backends
+    /// should override it.
+    async fn replace_messages(&self, messages: &[Message]) -> Result<()> {
+        self.clear().await?;
+        self.add_messages(messages).await
+    }
+
     /// Returns the number of stored messages.
     async fn len(&self) -> Result<usize>;
 
@@ -55,6 +71,27 @@ pub trait Session: Send + Sync {
     async fn is_empty(&self) -> Result<bool> {
         Ok(self.len().await? == 0)
     }
+
+    /// Returns the run state saved by [`put_state`](Self::put_state).
+    ///
+    /// The agent runner loads it into the
+    /// [`RunContext`](crate::callback::RunContext) state at the start of
+    /// each run. The default implementation persists nothing and returns an
+    /// empty map.
+    async fn get_state(&self) -> Result<HashMap<String, Value>> {
+        Ok(HashMap::new())
+    }
+
+    /// Replaces the saved run state.
+    ///
+    /// The agent runner calls this with the final
+    /// [`RunContext`](crate::callback::RunContext) state when a run
+    /// completes. State is kept apart from the history: [`clear`](Self::clear)
+    /// leaves it in place. The default implementation discards it.
+    async fn put_state(&self, state: &HashMap<String, Value>) -> Result<()> {
+        let _ = state;
+        Ok(())
+    }
 }
 
 /// A boxed session for dynamic dispatch.
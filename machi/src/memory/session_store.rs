@@ -0,0 +1,1108 @@
+//! Management APIs over a `SQLite` session database.
+//!
+//! [`SessionStore`] works on the database that [`SqliteSession`] handles
+//! write to: it lists sessions with their metadata, forks them, deletes
+//! them (one at a time or in bulk by age) and searches message contents
+//! through the `messages_fts` full-text index.
+//!
+//! # Example
+//!
+//! ```rust
+//! # tokio_test::block_on(async {
+//! use machi::memory::{Session, SessionMetadata, SessionQuery, SessionStore};
+//! use machi::message::Message;
+//!
+//! let store = SessionStore::in_memory()?;
+//! let session = store.session("conv-1");
+//! session.add_messages(&[Message::user("Plan a trip to Kyoto")]).await?;
+//! store
+//!     .set_metadata("conv-1", &SessionMetadata::new().title("Kyoto").user_id("alice"))
+//!     .await?;
+//!
+//! let sidebar = store.list(&SessionQuery::new().user_id("alice")).await?;
+//! assert_eq!(sidebar[0].metadata.title.as_deref(), Some("Kyoto"));
+//!
+//! let hits = store.search("kyoto", &SessionQuery::new().user_id("alice")).await?;
+//! assert_eq!(hits[0].session_id, "conv-1");
+//! # Ok::<(), machi::Error>(())
+//! # }).unwrap();
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+#[cfg(feature = "memory-encryption")]
+use super::encryption::{Keyring, reencrypt};
+use super::error::MemoryError;
+use super::retention::RetentionPolicy;
+use super::sqlite::{Codec, SqliteSession, blocking, init_schema, prune};
+use crate::error::Result;
+use crate::message::Message;
+
+/// Selects [`SessionInfo`] columns; filters refer to metadata as `m.data`.
+const SELECT_INFO: &str = "SELECT s.session_id, s.created_at, s.updated_at, \
+     COALESCE(m.data, '{}'), \
+     (SELECT COUNT(*) FROM messages WHERE session_id = s.session_id) \
+     FROM sessions s LEFT JOIN session_metadata m ON m.session_id = s.session_id";
+
+/// Application metadata attached to a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    /// Display title, e.g. for a conversation sidebar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The user who owns the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Free-form labels.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Tokens used by the session so far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_count: Option<u64>,
+    /// Any other application data.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl SessionMetadata {
+    /// Creates empty metadata.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the title.
+    #[must_use]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Sets the owning user.
+    #[must_use]
+    pub fn user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Adds a tag.
+    #[must_use]
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Sets the token count.
+    #[must_use]
+    pub const fn token_count(mut self, tokens: u64) -> Self {
+        self.token_count = Some(tokens);
+        self
+    }
+
+    /// Sets an application-defined `key: value` entry.
+    #[must_use]
+    pub fn extra(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// A session as listed by [`SessionStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Session identifier.
+    pub id: String,
+    /// When the session was created (`YYYY-MM-DD HH:MM:SS`, UTC).
+    pub created_at: String,
+    /// When messages were last added (`YYYY-MM-DD HH:MM:SS`, UTC).
+    pub updated_at: String,
+    /// Number of stored messages.
+    pub message_count: usize,
+    /// Attached metadata (empty if none was set).
+    pub metadata: SessionMetadata,
+}
+
+/// Filter and page for [`SessionStore::list`] and [`SessionStore::search`].
+///
+/// Results are ordered most recently updated first (best match first for
+/// search) and paged with [`limit`](Self::limit) and [`offset`](Self::offset).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionQuery {
+    user_id: Option<String>,
+    tags: Vec<String>,
+    limit: usize,
+    offset: usize,
+}
+
+impl Default for SessionQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionQuery {
+    /// Default page size.
+    pub const DEFAULT_LIMIT: usize = 50;
+
+    /// Matches every session, first page.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            user_id: None,
+            tags: Vec::new(),
+            limit: Self::DEFAULT_LIMIT,
+            offset: 0,
+        }
+    }
+
+    /// Only sessions owned by `user_id`.
+    #[must_use]
+    pub fn user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Only sessions tagged `tag`. Repeat to require several tags.
+    #[must_use]
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Return at most `n` results (default: [`DEFAULT_LIMIT`](Self::DEFAULT_LIMIT)).
+    #[must_use]
+    pub const fn limit(mut self, n: usize) -> Self {
+        self.limit = n;
+        self
+    }
+
+    /// Skip the first `n` results.
+    #[must_use]
+    pub const fn offset(mut self, n: usize) -> Self {
+        self.offset = n;
+        self
+    }
+
+    /// SQL conditions on `m.data` and their parameters.
+    fn conditions(&self) -> (Vec<&'static str>, Vec<SqlValue>) {
+        let mut sql = Vec::new();
+        let mut values = Vec::new();
+        if let Some(user_id) = &self.user_id {
+            sql.push("json_extract(m.data, '$.user_id') = ?");
+            values.push(SqlValue::Text(user_id.clone()));
+        }
+        for tag in &self.tags {
+            sql.push("EXISTS (SELECT 1 FROM json_each(m.data, '$.tags') WHERE value = ?)");
+            values.push(SqlValue::Text(tag.clone()));
+        }
+        (sql, values)
+    }
+
+    fn page(&self) -> [SqlValue; 2] {
+        [
+            SqlValue::Integer(i64::try_from(self.limit).unwrap_or(i64::MAX)),
+            SqlValue::Integer(i64::try_from(self.offset).unwrap_or(i64::MAX)),
+        ]
+    }
+}
+
+/// A message matched by [`SessionStore::search`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// Session containing the message.
+    pub session_id: String,
+    /// Position of the message within its session, usable with
+    /// [`SessionStore::fork_at`].
+    pub index: usize,
+    /// The matched message.
+    pub message: Message,
+    /// Excerpt around the match, with matched terms in `[brackets]`.
+    pub snippet: String,
+    /// Relevance; higher is better.
+    pub score: f64,
+}
+
+/// Lists, annotates, forks, searches and deletes sessions in a `SQLite`
+/// database.
+///
+/// Shares the schema of [`SqliteSession`]; open session handles on the same
+/// connection with [`session`](Self::session). Cloneable via
+/// `Arc<Mutex<Connection>>`, with all blocking I/O on the tokio blocking pool.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    conn: Arc<Mutex<Connection>>,
+    codec: Codec,
+}
+
+impl SessionStore {
+    /// Shortest period accepted by [`spawn_pruner`](Self::spawn_pruner).
+    pub const MIN_PRUNE_PERIOD: Duration = Duration::from_secs(1);
+
+    /// Opens (or creates) a database at `path` and initializes the schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or schema initialization fails.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open(path.as_ref()).map_err(MemoryError::from)?;
+        Self::from_connection(conn)
+    }
+
+    /// Opens an ephemeral in-memory database (data lost on drop).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the in-memory database cannot be created.
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().map_err(MemoryError::from)?;
+        Self::from_connection(conn)
+    }
+
+    /// Wraps an existing [`Connection`], applying pragmas and schema setup.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pragma execution or schema setup fails.
+    pub fn from_connection(conn: Connection) -> Result<Self> {
+        init_schema(&conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            codec: Codec::default(),
+        })
+    }
+
+    /// Encrypts messages of sessions opened with [`session`](Self::session)
+    /// and decrypts search results with `keyring`.
+    ///
+    /// Encrypted messages are not full-text indexed, so search only finds
+    /// messages stored in plaintext.
+    #[cfg(feature = "memory-encryption")]
+    #[must_use]
+    pub fn with_encryption(mut self, keyring: Keyring) -> Self {
+        self.codec = Codec::encrypted(keyring);
+        self
+    }
+
+    /// Re-encrypts every message and saved run state in the database under
+    /// the primary key, returning how many were rewritten. Does nothing without a keyring.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MemoryError::Encryption`] if a message cannot be decrypted
+    /// with any key in the keyring.
+    #[cfg(feature = "memory-encryption")]
+    pub async fn reencrypt(&self) -> Result<usize> {
+        let Some(keyring) = self.codec.keyring() else {
+            return Ok(0);
+        };
+        blocking(&self.conn, move |conn| reencrypt(conn, &keyring, None)).await
+    }
+
+    /// Returns a [`SqliteSession`] for `session_id` on this database.
+    #[must_use]
+    pub fn session(&self, session_id: impl Into<String>) -> SqliteSession {
+        SqliteSession::shared(
+            Arc::clone(&self.conn),
+            session_id.into(),
+            self.codec.clone(),
+        )
+    }
+
+    /// Returns one session, or `None` if it has no messages or metadata row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub async fn get(&self, session_id: &str) -> Result<Option<SessionInfo>> {
+        let session_id = session_id.to_owned();
+        blocking(&self.conn, move |conn| {
+            conn.query_row(
+                &format!("{SELECT_INFO} WHERE s.session_id = ?1"),
+                params![session_id],
+                info_row,
+            )
+            .optional()?
+            .map(into_info)
+            .transpose()
+        })
+        .await
+    }
+
+    /// Replaces the metadata of `session_id`, creating the session if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization or the write fails.
+    pub async fn set_metadata(&self, session_id: &str, metadata: &SessionMetadata) -> Result<()> {
+        let session_id = session_id.to_owned();
+        let data = serde_json::to_string(metadata).map_err(MemoryError::from)?;
+        blocking(&self.conn, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT OR IGNORE INTO sessions (session_id) VALUES (?1)",
+                params![session_id],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO session_metadata (session_id, data) VALUES (?1, ?2)",
+                params![session_id, data],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Lists sessions matching `query`, most recently updated first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub async fn list(&self, query: &SessionQuery) -> Result<Vec<SessionInfo>> {
+        let (conditions, mut values) = query.conditions();
+        values.extend(query.page());
+        let sql = format!(
+            "{SELECT_INFO}{} ORDER BY s.updated_at DESC, s.rowid DESC LIMIT ? OFFSET ?",
+            where_clause(&conditions, " WHERE ")
+        );
+        blocking(&self.conn, move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(values), info_row)?;
+            rows.map(|row| into_info(row?)).collect()
+        })
+        .await
+    }
+
+    /// Counts sessions matching `query`, ignoring its page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub async fn count(&self, query: &SessionQuery) -> Result<usize> {
+        let (conditions, values) = query.conditions();
+        let sql = format!(
+            "SELECT COUNT(*) FROM sessions s \
+             LEFT JOIN session_metadata m ON m.session_id = s.session_id{}",
+            where_clause(&conditions, " WHERE ")
+        );
+        blocking(&self.conn, move |conn| {
+            let count: i64 = conn.query_row(&sql, params_from_iter(values), |row| row.get(0))?;
+            Ok(usize::try_from(count).unwrap_or_default())
+        })
+        .await
+    }
+
+    /// Copies `source` with all its messages, metadata and run state to a new session
+    /// `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` does not exist or `target` already does.
+    pub async fn fork(&self, source: &str, target: &str) -> Result<SqliteSession> {
+        self.fork_at(source, target, usize::MAX).await
+    }
+
+    /// Like [`fork`](Self::fork), but copies only the messages before
+    /// position `index`.
+    ///
+    /// With a keyring, copied messages and run state are re-sealed for
+    /// `target`, since sealed rows are bound to their session.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` does not exist or `target` already does.
+    pub async fn fork_at(&self, source: &str, target: &str, index: usize) -> Result<SqliteSession> {
+        let source = source.to_owned();
+        let target_id = target.to_owned();
+        let limit = i64::try_from(index).unwrap_or(i64::MAX);
+        let codec = self.codec.clone();
+        blocking(&self.conn, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let exists = |id: &str| -> rusqlite::Result<bool> {
+                tx.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM sessions WHERE session_id = ?1)",
+                    params![id],
+                    |row| row.get(0),
+                )
+            };
+            if !exists(&source)? {
+                return Err(MemoryError::storage(
+                    "sqlite",
+                    format!("session `{source}` not found"),
+                ));
+            }
+            if exists(&target_id)? {
+                return Err(MemoryError::storage(
+                    "sqlite",
+                    format!("session `{target_id}` already exists"),
+                ));
+            }
+
+            tx.execute(
+                "INSERT INTO sessions (session_id) VALUES (?1)",
+                params![target_id],
+            )?;
+            let messages = tx
+                .prepare(
+                    "SELECT message_data, created_at FROM messages \
+                     WHERE session_id = ?1 ORDER BY id LIMIT ?2",
+                )?
+                .query_map(params![source, limit], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let mut insert = tx.prepare(
+                "INSERT INTO messages (session_id, message_data, created_at) VALUES (?1, ?2, ?3)",
+            )?;
+            for (stored, created_at) in messages {
+                let stored = codec.rebind(&source, &target_id, stored)?;
+                insert.execute(params![target_id, stored, created_at])?;
+            }
+            drop(insert);
+
+            tx.execute(
+                "INSERT OR REPLACE INTO session_metadata (session_id, data) \
+                 SELECT ?2, data FROM session_metadata WHERE session_id = ?1",
+                params![source, target_id],
+            )?;
+            let state: Option<String> = tx
+                .query_row(
+                    "SELECT data FROM session_state WHERE session_id = ?1",
+                    params![source],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(stored) = state {
+                tx.execute(
+                    "INSERT OR REPLACE INTO session_state (session_id, data) VALUES (?1, ?2)",
+                    params![target_id, codec.rebind(&source, &target_id, stored)?],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(self.session(target))
+    }
+
+    /// Deletes a session with its messages, metadata and run state.
+    ///
+    /// Returns `true` if anything was deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the delete fails.
+    pub async fn delete(&self, session_id: &str) -> Result<bool> {
+        let session_id = session_id.to_owned();
+        blocking(&self.conn, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let mut deleted = 0;
+            for table in ["messages", "session_metadata", "session_state", "sessions"] {
+                deleted += tx.execute(
+                    &format!("DELETE FROM {table} WHERE session_id = ?1"),
+                    params![session_id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    /// Deletes every session not updated within `age`.
+    ///
+    /// Returns the number of sessions deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the delete fails.
+    pub async fn delete_older_than(&self, age: Duration) -> Result<usize> {
+        let modifier = format!("-{} seconds", age.as_secs());
+        blocking(&self.conn, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            for table in ["messages", "session_metadata", "session_state"] {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE session_id IN ( \
+                             SELECT session_id FROM sessions \
+                             WHERE updated_at < datetime('now', ?1))"
+                    ),
+                    params![modifier],
+                )?;
+            }
+            let deleted = tx.execute(
+                "DELETE FROM sessions WHERE updated_at < datetime('now', ?1)",
+                params![modifier],
+            )?;
+            tx.commit()?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// Applies `policy` to every session, returning the number of messages
+    /// removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a query or delete fails.
+    pub async fn prune(&self, policy: &RetentionPolicy) -> Result<usize> {
+        let policy = *policy;
+        blocking(&self.conn, move |conn| {
+            let ids = conn
+                .prepare("SELECT session_id FROM sessions")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let mut removed = 0;
+            for id in ids {
+                removed += prune(conn, &id, &policy)?;
+            }
+            Ok(removed)
+        })
+        .await
+    }
+
+    /// Runs [`prune`](Self::prune) every `period` on a background task until
+    /// the returned handle is aborted. Failures are logged and retried on
+    /// the next tick.
+    ///
+    /// `period` is raised to at least [`MIN_PRUNE_PERIOD`](Self::MIN_PRUNE_PERIOD),
+    /// so a zero period does not spin. Must be called from within a tokio
+    /// runtime.
+    #[must_use = "dropping the handle does not stop the task; abort it to stop pruning"]
+    pub fn spawn_pruner(&self, policy: RetentionPolicy, period: Duration) -> JoinHandle<()> {
+        let store = self.clone();
+        let period = period.max(Self::MIN_PRUNE_PERIOD);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match store.prune(&policy).await {
+                    Ok(0) => {}
+                    Ok(removed) => debug!(removed, "pruned expired session messages"),
+                    Err(e) => warn!(error = %e, "session pruning failed"),
+                }
+            }
+        })
+    }
+
+    /// Full-text search over message contents in sessions matching `query`.
+    ///
+    /// Every whitespace-separated word of `text` must appear in a message
+    /// (case-insensitive); FTS5 query syntax is treated as literal text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub async fn search(&self, text: &str, query: &SessionQuery) -> Result<Vec<SearchHit>> {
+        let terms: Vec<String> = text
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (conditions, filters) = query.conditions();
+        let mut values = vec![SqlValue::Text(terms.join(" "))];
+        values.extend(filters);
+        values.extend(query.page());
+        let sql = format!(
+            "SELECT msg.session_id, \
+                 (SELECT COUNT(*) FROM messages p \
+                  WHERE p.session_id = msg.session_id AND p.id < msg.id), \
+                 msg.message_data, \
+                 snippet(messages_fts, 0, '[', ']', '…', 16), \
+                 bm25(messages_fts) \
+             FROM messages_fts \
+             JOIN messages msg ON msg.id = messages_fts.rowid \
+             LEFT JOIN session_metadata m ON m.session_id = msg.session_id \
+             WHERE messages_fts MATCH ?{} \
+             ORDER BY bm25(messages_fts) LIMIT ? OFFSET ?",
+            where_clause(&conditions, " AND ")
+        );
+        let codec = self.codec.clone();
+        blocking(&self.conn, move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(values), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })?;
+            rows.map(|row| {
+                let (session_id, index, message, snippet, rank) = row?;
+                let message = codec.decode(&session_id, &message)?;
+                Ok(SearchHit {
+                    session_id,
+                    index: usize::try_from(index).unwrap_or_default(),
+                    message,
+                    snippet,
+                    score: -rank,
+                })
+            })
+            .collect()
+        })
+        .await
+    }
+}
+
+type InfoRow = (String, String, String, String, i64);
+
+fn info_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<InfoRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+    ))
+}
+
+fn into_info(
+    (id, created_at, updated_at, metadata, count): InfoRow,
+) -> std::result::Result<SessionInfo, MemoryError> {
+    Ok(SessionInfo {
+        id,
+        created_at,
+        updated_at,
+        message_count: usize::try_from(count).unwrap_or_default(),
+        metadata: serde_json::from_str(&metadata)?,
+    })
+}
+
+fn where_clause(conditions: &[&str], prefix: &str) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}{}", conditions.join(" AND "))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::memory::Session;
+    use crate::message::{Content, ContentPart, Role};
+
+    async fn seeded() -> SessionStore {
+        let store = SessionStore::in_memory().unwrap();
+        for (id, user, tag) in [
+            ("a", "alice", "work"),
+            ("b", "alice", "home"),
+            ("c", "bob", "work"),
+        ] {
+            store
+                .session(id)
+                .add_messages(&[
+                    Message::user(format!("hello from {id}")),
+                    Message::assistant("hi"),
+                ])
+                .await
+                .unwrap();
+            let metadata = SessionMetadata::new()
+                .title(format!("Chat {id}"))
+                .user_id(user)
+                .tag(tag)
+                .token_count(42);
+            store.set_metadata(id, &metadata).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn lists_sessions_with_filters_and_pages() {
+        let store = seeded().await;
+
+        let all = store.list(&SessionQuery::new()).await.unwrap();
+        let ids: Vec<&str> = all.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["c", "b", "a"]);
+        assert_eq!(all[0].message_count, 2);
+        assert_eq!(all[0].metadata.token_count, Some(42));
+
+        let alice = SessionQuery::new().user_id("alice");
+        assert_eq!(store.count(&alice).await.unwrap(), 2);
+        let page = store.list(&alice.clone().limit(1).offset(1)).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, "a");
+
+        let work = store.list(&SessionQuery::new().tag("work")).await.unwrap();
+        assert_eq!(work.len(), 2);
+        assert_eq!(store.count(&alice.tag("work")).await.unwrap(), 1);
+
+        let info = store.get("b").await.unwrap().unwrap();
+        assert_eq!(info.metadata.title.as_deref(), Some("Chat b"));
+        assert!(store.get("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn fork_copies_a_prefix_and_metadata() {
+        let store = seeded().await;
+        store
+            .session("a")
+            .add_messages(&[Message::user("third"), Message::assistant("fourth")])
+            .await
+            .unwrap();
+
+        let fork = store.fork_at("a", "a2", 2).await.unwrap();
+        assert_eq!(fork.len().await.unwrap(), 2);
+        assert_eq!(store.session("a").len().await.unwrap(), 4);
+        let info = store.get("a2").await.unwrap().unwrap();
+        assert_eq!(info.metadata.user_id.as_deref(), Some("alice"));
+        assert!(fork.get_state().await.unwrap().is_empty());
+
+        let state = HashMap::from([("step".to_owned(), Value::from("payment"))]);
+        store.session("a").put_state(&state).await.unwrap();
+
+        let full = store.fork("a", "a3").await.unwrap();
+        assert_eq!(full.get_messages(None).await.unwrap().len(), 4);
+        assert_eq!(full.get_state().await.unwrap(), state);
+        assert!(store.delete("a3").await.unwrap());
+        assert!(full.get_state().await.unwrap().is_empty());
+
+        assert!(store.fork("a", "b").await.is_err());
+        assert!(store.fork("missing", "x").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn search_matches_message_text_within_a_query() {
+        let store = seeded().await;
+        let parts = Message::new(
+            Role::User,
+            Content::Parts(vec![ContentPart::text("Booking the Kyoto ryokan")]),
+        );
+        store.session("b").add_messages(&[parts]).await.unwrap();
+        store
+            .session("c")
+            .add_messages(&[Message::user("kyoto trip")])
+            .await
+            .unwrap();
+
+        let hits = store.search("KYOTO", &SessionQuery::new()).await.unwrap();
+        assert_eq!(hits.len(), 2);
+
+        let alice = SessionQuery::new().user_id("alice");
+        let hits = store.search("kyoto", &alice).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].session_id, "b");
+        assert_eq!(hits[0].index, 2);
+        assert_eq!(hits[0].snippet, "Booking the [Kyoto] ryokan");
+
+        assert_eq!(store.search("\"ryokan", &alice).await.unwrap().len(), 1);
+        store.session("b").pop_message().await.unwrap();
+        assert!(store.search("kyoto", &alice).await.unwrap().is_empty());
+        assert!(store.search("  ", &alice).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deletes_single_and_stale_sessions() {
+        let store = seeded().await;
+        assert!(store.delete("c").await.unwrap());
+        assert!(!store.delete("c").await.unwrap());
+        assert!(
+            store
+                .search("from c", &SessionQuery::new())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        {
+            let conn = store.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE sessions SET updated_at = datetime('now', '-10 days') WHERE session_id = 'a'",
+                [],
+            )
+            .unwrap();
+        }
+        let deleted = store
+            .delete_older_than(Duration::from_hours(24))
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+        let left = store.list(&SessionQuery::new()).await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].id, "b");
+    }
+
+    #[tokio::test]
+    async fn replacing_messages_keeps_the_session_row() {
+        let store = seeded().await;
+        {
+            let conn = store.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE sessions SET created_at = '2020-01-01 00:00:00' WHERE session_id = 'a'",
+                [],
+            )
+            .unwrap();
+        }
+        let session = store.session("a");
+        let state = HashMap::from([("step".to_owned(), serde_json::json!(3))]);
+        session.put_state(&state).await.unwrap();
+
+        session
+            .replace_messages(&[Message::system("summary")])
+            .await
+            .unwrap();
+        let info = store.get("a").await.unwrap().unwrap();
+        assert_eq!(info.created_at, "2020-01-01 00:00:00");
+        assert_eq!(info.message_count, 1);
+        assert_eq!(info.metadata.title.as_deref(), Some("Chat a"));
+        assert_eq!(session.get_state().await.unwrap(), state);
+        assert_eq!(
+            session.get_messages(None).await.unwrap(),
+            [Message::system("summary")]
+        );
+    }
+
+    #[tokio::test]
+    async fn prune_applies_a_policy_to_every_session() {
+        let store = seeded().await;
+        {
+            let conn = store.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE messages SET created_at = datetime('now', '-40 days') \
+                 WHERE session_id IN ('a', 'b')",
+                [],
+            )
+            .unwrap();
+        }
+        let policy = RetentionPolicy::new().message_ttl(Duration::from_hours(30 * 24));
+        assert_eq!(store.prune(&policy).await.unwrap(), 4);
+        assert_eq!(store.session("a").len().await.unwrap(), 0);
+        assert_eq!(store.session("c").len().await.unwrap(), 2);
+
+        let pruner = store.spawn_pruner(
+            RetentionPolicy::new().max_messages(1),
+            Duration::from_mins(1),
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        pruner.abort();
+        assert_eq!(store.session("c").len().await.unwrap(), 1);
+
+        // A zero period is clamped instead of panicking the task.
+        store
+            .session("c")
+            .add_messages(&[Message::user("again")])
+            .await
+            .unwrap();
+        let pruner = store.spawn_pruner(RetentionPolicy::new().max_messages(1), Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pruner.is_finished());
+        pruner.abort();
+        assert_eq!(store.session("c").len().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn existing_messages_are_indexed_on_open() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                session_id TEXT PRIMARY KEY,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            CREATE TABLE messages (
+                id           INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id   TEXT    NOT NULL,
+                message_data TEXT    NOT NULL,
+                created_at   TEXT    NOT NULL DEFAULT (datetime('now'))
+            );
+            INSERT INTO sessions (session_id) VALUES ('old');",
+        )
+        .unwrap();
+        let data = serde_json::to_string(&Message::user("legacy message")).unwrap();
+        conn.execute(
+            "INSERT INTO messages (session_id, message_data) VALUES ('old', ?1)",
+            params![data],
+        )
+        .unwrap();
+
+        let store = SessionStore::from_connection(conn).unwrap();
+        let hits = store.search("legacy", &SessionQuery::new()).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.text().unwrap(), "legacy message");
+    }
+
+    #[cfg(feature = "memory-encryption")]
+    mod encryption {
+        use super::*;
+        use crate::memory::{EncryptionKey, Keyring};
+
+        fn keyring() -> Keyring {
+            Keyring::new(EncryptionKey::new("k1", [1; 32]))
+        }
+
+        fn raw_rows(store: &SessionStore) -> Vec<String> {
+            let conn = store.conn.lock().unwrap();
+            conn.prepare("SELECT message_data FROM messages ORDER BY id")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn messages_are_encrypted_at_rest() {
+            let store = SessionStore::in_memory()
+                .unwrap()
+                .with_encryption(keyring());
+            let session = store.session("s");
+            let msgs = [
+                Message::user("my seed is abandon"),
+                Message::assistant("noted"),
+            ];
+            session.add_messages(&msgs).await.unwrap();
+
+            assert!(raw_rows(&store).iter().all(|row| !row.contains("abandon")));
+            assert_eq!(session.get_messages(None).await.unwrap(), msgs);
+            assert!(
+                store
+                    .search("abandon", &SessionQuery::new())
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+
+            let wrong = SessionStore {
+                codec: Codec::encrypted(Keyring::new(EncryptionKey::new("k1", [9; 32]))),
+                ..store.clone()
+            };
+            let err = wrong.session("s").get_messages(None).await.unwrap_err();
+            assert!(err.to_string().contains("wrong key"), "{err}");
+            let unkeyed = SessionStore {
+                codec: Codec::default(),
+                ..store
+            };
+            let err = unkeyed.session("s").pop_message().await.unwrap_err();
+            assert!(err.to_string().contains("keyring"), "{err}");
+            assert_eq!(unkeyed.session("s").len().await.unwrap(), 2);
+        }
+
+        #[tokio::test]
+        async fn forks_are_resealed_and_rows_stay_bound() {
+            let store = SessionStore::in_memory()
+                .unwrap()
+                .with_encryption(keyring());
+            let msgs = [Message::user("send 1 ETH"), Message::assistant("done")];
+            store.session("a").add_messages(&msgs).await.unwrap();
+            let state = HashMap::from([("step".to_owned(), Value::from(2))]);
+            store.session("a").put_state(&state).await.unwrap();
+
+            let fork = store.fork("a", "b").await.unwrap();
+            assert_eq!(fork.get_messages(None).await.unwrap(), msgs);
+            assert_eq!(fork.get_state().await.unwrap(), state);
+
+            // A sealed row copied into another session by hand does not decrypt.
+            {
+                let conn = store.conn.lock().unwrap();
+                conn.execute_batch(
+                    "INSERT INTO sessions (session_id) VALUES ('c');
+                     INSERT INTO messages (session_id, message_data)
+                     SELECT 'c', message_data FROM messages WHERE session_id = 'a';",
+                )
+                .unwrap();
+            }
+            let err = store.session("c").get_messages(None).await.unwrap_err();
+            assert!(err.to_string().contains("wrong key"), "{err}");
+        }
+
+        #[tokio::test]
+        async fn run_state_is_encrypted_and_rotated() {
+            let store = SessionStore::in_memory().unwrap();
+            let state = HashMap::from([("card_last4".to_owned(), Value::from("4242"))]);
+            let old = store.clone().with_encryption(keyring());
+            old.session("s").put_state(&state).await.unwrap();
+
+            let raw = || -> String {
+                let conn = store.conn.lock().unwrap();
+                conn.query_row("SELECT data FROM session_state", [], |row| row.get(0))
+                    .unwrap()
+            };
+            assert!(!raw().contains("4242"));
+            assert_eq!(old.session("s").get_state().await.unwrap(), state);
+            let err = store.session("s").get_state().await.unwrap_err();
+            assert!(err.to_string().contains("keyring"), "{err}");
+
+            let rotated = Keyring::new(EncryptionKey::new("k2", [2; 32]))
+                .previous(EncryptionKey::new("k1", [1; 32]));
+            let new = store.clone().with_encryption(rotated);
+            assert_eq!(new.session("s").reencrypt().await.unwrap(), 1);
+            assert!(raw().contains("\"kid\":\"k2\""));
+            assert_eq!(new.session("s").get_state().await.unwrap(), state);
+        }
+
+        #[tokio::test]
+        async fn keys_rotate_and_plaintext_is_migrated() {
+            let store = SessionStore::in_memory().unwrap();
+            store
+                .session("s")
+                .add_messages(&[Message::user("plaintext")])
+                .await
+                .unwrap();
+
+            let old = store.clone().with_encryption(keyring());
+            old.session("s")
+                .add_messages(&[Message::user("under k1")])
+                .await
+                .unwrap();
+
+            let rotated = Keyring::new(EncryptionKey::new("k2", [2; 32]))
+                .previous(EncryptionKey::new("k1", [1; 32]));
+            let strict = store.clone().with_encryption(rotated.clone());
+            let err = strict.session("s").get_messages(None).await.unwrap_err();
+            assert!(err.to_string().contains("allow_plaintext"), "{err}");
+            assert!(strict.reencrypt().await.is_err());
+
+            let new = store.clone().with_encryption(rotated.allow_plaintext(true));
+            assert_eq!(new.session("s").len().await.unwrap(), 2);
+            assert_eq!(new.reencrypt().await.unwrap(), 2);
+            assert_eq!(new.session("s").reencrypt().await.unwrap(), 0);
+            assert!(
+                raw_rows(&store)
+                    .iter()
+                    .all(|row| row.contains("\"kid\":\"k2\""))
+            );
+            assert!(
+                store
+                    .search("plaintext", &SessionQuery::new())
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+            let secure_delete: i64 = store
+                .conn
+                .lock()
+                .unwrap()
+                .query_row("PRAGMA secure_delete", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(secure_delete, 1);
+
+            let only_k2 = store.with_encryption(Keyring::new(EncryptionKey::new("k2", [2; 32])));
+            let texts: Vec<String> = only_k2
+                .session("s")
+                .get_messages(None)
+                .await
+                .unwrap()
+                .iter()
+                .filter_map(Message::text)
+                .collect();
+            assert_eq!(texts, ["plaintext", "under k1"]);
+        }
+    }
+}
@@ -8,15 +8,21 @@
 //!
 //! Messages are stored as JSON rows in the `messages` table, ordered by
 //! auto-incrementing `id`. WAL journal mode and a composite index on
-//! `(session_id, id)` ensure efficient concurrent reads.
+//! `(session_id, id)` ensure efficient concurrent reads. Run state saved by
+//! [`Session::put_state`] is one JSON row per session in `session_state`.
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
 
+#[cfg(feature = "memory-encryption")]
+use super::encryption::{Keyring, reencrypt};
 use super::error::MemoryError;
+use super::retention::{RetentionPolicy, keep_from};
 use super::session::Session;
 use crate::error::Result;
 use crate::message::Message;
@@ -27,11 +33,131 @@
 /// handles (even with different session IDs) may share a single database.
 ///
 /// Schema is auto-created on construction. All blocking I/O is offloaded
-/// to the tokio blocking thread pool.
+/// to the tokio blocking thread pool. Bound the history with
+/// [`with_retention`](Self::with_retention).
 #[derive(Debug, Clone)]
 pub struct SqliteSession {
     id: String,
     conn: Arc<Mutex<Connection>>,
+    retention: Option<RetentionPolicy>,
+    codec: Codec,
+}
+
+/// Converts messages and run state to and from their stored JSON, sealing
+/// them when a keyring is configured.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Codec {
+    #[cfg(feature = "memory-encryption")]
+    keyring: Option<Arc<Keyring>>,
+}
+
+// Without encryption the methods only serialize, so `self` is unused.
+#[cfg_attr(not(feature = "memory-encryption"), allow(clippy::unused_self))]
+impl Codec {
+    #[cfg(feature = "memory-encryption")]
+    pub(super) fn encrypted(keyring: Keyring) -> Self {
+        Self {
+            keyring: Some(Arc::new(keyring)),
+        }
+    }
+
+    #[cfg(feature = "memory-encryption")]
+    pub(super) fn keyring(&self) -> Option<Arc<Keyring>> {
+        self.keyring.clone()
+    }
+
+    pub(super) fn encode(
+        &self,
+        session_id: &str,
+        message: &Message,
+    ) -> std::result::Result<String, MemoryError> {
+        let json = serde_json::to_string(message)?;
+        #[cfg(feature = "memory-encryption")]
+        if let Some(keyring) = &self.keyring {
+            return keyring.seal(session_id, Some(message.role), &json);
+        }
+        #[cfg(not(feature = "memory-encryption"))]
+        let _ = session_id;
+        Ok(json)
+    }
+
+    pub(super) fn decode(
+        &self,
+        session_id: &str,
+        stored: &str,
+    ) -> std::result::Result<Message, MemoryError> {
+        #[cfg(feature = "memory-encryption")]
+        if let Some(keyring) = &self.keyring {
+            return Ok(serde_json::from_str(&keyring.open(session_id, stored)?)?);
+        }
+        #[cfg(not(feature = "memory-encryption"))]
+        let _ = session_id;
+        if stored.starts_with("{\"enc\"") {
+            return Err(MemoryError::Encryption(
+                "message is encrypted; open the session with its keyring".to_owned(),
+            ));
+        }
+        Ok(serde_json::from_str(stored)?)
+    }
+
+    pub(super) fn encode_state(
+        &self,
+        session_id: &str,
+        state: &HashMap<String, Value>,
+    ) -> std::result::Result<String, MemoryError> {
+        let json = serde_json::to_string(state)?;
+        #[cfg(feature = "memory-encryption")]
+        if let Some(keyring) = &self.keyring {
+            return keyring.seal(session_id, None, &json);
+        }
+        #[cfg(not(feature = "memory-encryption"))]
+        let _ = session_id;
+        Ok(json)
+    }
+
+    pub(super) fn decode_state(
+        &self,
+        session_id: &str,
+        stored: &str,
+    ) -> std::result::Result<HashMap<String, Value>, MemoryError> {
+        #[cfg(feature = "memory-encryption")]
+        if let Some(keyring) = &self.keyring {
+            return Ok(serde_json::from_str(&keyring.open(session_id, stored)?)?);
+        }
+        #[cfg(not(feature = "memory-encryption"))]
+        let _ = session_id;
+        let state: HashMap<String, Value> = serde_json::from_str(stored)?;
+        if ["enc", "kid", "nonce", "data"]
+            .iter()
+            .all(|key| state.contains_key(*key))
+        {
+            return Err(MemoryError::Encryption(
+                "run state is encrypted; open the session with its keyring".to_owned(),
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Re-seals a stored message or state of session `from` for session
+    /// `to`; without a keyring it is copied unchanged.
+    #[cfg_attr(
+        not(feature = "memory-encryption"),
+        allow(clippy::missing_const_for_fn, clippy::unnecessary_wraps)
+    )]
+    pub(super) fn rebind(
+        &self,
+        from: &str,
+        to: &str,
+        stored: String,
+    ) -> std::result::Result<String, MemoryError> {
+        #[cfg(feature = "memory-encryption")]
+        if let Some(keyring) = &self.keyring {
+            return keyring.rebind(from, to, &stored);
+        }
+        #[cfg(not(feature = "memory-encryption"))]
+        let _ = (from, to);
+        Ok(stored)
+    }
 }
 
 impl SqliteSession {
@@ -65,36 +191,79 @@ pub fn in_memory(session_id: impl Into<String>) -> Result<Self> {
     ///
     /// Returns an error if pragma execution or schema setup fails.
     pub fn from_connection(conn: Connection, session_id: impl Into<String>) -> Result<Self> {
-        conn.execute_batch(
-            "PRAGMA journal_mode = WAL;\
-             PRAGMA foreign_keys = ON;\
-             PRAGMA busy_timeout = 5000;",
-        )
-        .map_err(MemoryError::from)?;
-
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                session_id TEXT PRIMARY KEY,
-                created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-            );
-
-            CREATE TABLE IF NOT EXISTS messages (
-                id           INTEGER PRIMARY KEY AUTOINCREMENT,
-                session_id   TEXT    NOT NULL REFERENCES sessions(session_id) ON DELETE CASCADE,
-                message_data TEXT    NOT NULL,
-                created_at   TEXT    NOT NULL DEFAULT (datetime('now'))
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_messages_session
-            ON messages (session_id, id);",
-        )
-        .map_err(MemoryError::from)?;
-
-        Ok(Self {
-            id: session_id.into(),
-            conn: Arc::new(Mutex::new(conn)),
-        })
+        init_schema(&conn)?;
+        Ok(Self::shared(
+            Arc::new(Mutex::new(conn)),
+            session_id.into(),
+            Codec::default(),
+        ))
+    }
+
+    /// Encrypts messages at rest with `keyring`.
+    ///
+    /// See [`Keyring`] for the stored format and key rotation.
+    #[cfg(feature = "memory-encryption")]
+    #[must_use]
+    pub fn with_encryption(mut self, keyring: Keyring) -> Self {
+        self.codec = Codec::encrypted(keyring);
+        self
+    }
+
+    /// Re-encrypts this session's messages and run state under the primary
+    /// key, returning how many were rewritten. Does nothing without a keyring.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MemoryError::Encryption`] if a message cannot be decrypted
+    /// with any key in the keyring.
+    #[cfg(feature = "memory-encryption")]
+    pub async fn reencrypt(&self) -> Result<usize> {
+        let Some(keyring) = self.codec.keyring() else {
+            return Ok(0);
+        };
+        let session_id = self.id.clone();
+        self.blocking(move |conn| reencrypt(conn, &keyring, Some(&session_id)))
+            .await
+    }
+
+    /// Applies `policy` on every write and before every read.
+    ///
+    /// Message ages come from the `created_at` column, so they survive
+    /// restarts. To prune sessions that are no longer opened, use
+    /// [`SessionStore::prune`](super::SessionStore::prune).
+    #[must_use]
+    pub const fn with_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Applies the retention policy now, returning the number of messages
+    /// removed. Does nothing without a policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the delete fails.
+    pub async fn prune(&self) -> Result<usize> {
+        let Some(policy) = self.retention else {
+            return Ok(0);
+        };
+        let session_id = self.id.clone();
+        self.blocking(move |conn| prune(conn, &session_id, &policy))
+            .await
+    }
+
+    /// Creates a handle for `session_id` on an already initialized connection.
+    pub(super) const fn shared(
+        conn: Arc<Mutex<Connection>>,
+        session_id: String,
+        codec: Codec,
+    ) -> Self {
+        Self {
+            id: session_id,
+            conn,
+            retention: None,
+            codec,
+        }
     }
 
     /// Bridges a synchronous closure onto the tokio blocking thread pool.
@@ -107,14 +276,218 @@ async fn blocking<F, T>(&self, f: F) -> Result<T>
         F: FnOnce(&Connection) -> std::result::Result<T, MemoryError> + Send + 'static,
         T: Send + 'static,
     {
-        let conn = Arc::clone(&self.conn);
-        Ok(tokio::task::spawn_blocking(move || {
-            let guard = conn.lock().map_err(|e| MemoryError::Lock(e.to_string()))?;
-            f(&guard)
-        })
-        .await
-        .map_err(|e| MemoryError::Task(e.to_string()))??)
+        blocking(&self.conn, f).await
+    }
+}
+
+/// Applies pragmas and creates the session schema if missing.
+///
+/// Besides `sessions` and `messages`, this creates `session_metadata`,
+/// `session_state` and the `messages_fts` full-text index, kept in sync by triggers. Messages
+/// stored before the index existed are indexed once, on first open.
+pub(super) fn init_schema(conn: &Connection) -> std::result::Result<(), MemoryError> {
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;\
+         PRAGMA foreign_keys = ON;\
+         PRAGMA busy_timeout = 5000;",
+    )?;
+
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            session_id TEXT PRIMARY KEY,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS messages (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id   TEXT    NOT NULL REFERENCES sessions(session_id) ON DELETE CASCADE,
+            message_data TEXT    NOT NULL,
+            created_at   TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_messages_session
+        ON messages (session_id, id);
+
+        CREATE TABLE IF NOT EXISTS session_metadata (
+            session_id TEXT PRIMARY KEY,
+            data       TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS session_state (
+            session_id TEXT PRIMARY KEY,
+            data       TEXT NOT NULL
+        );",
+    )?;
+
+    let indexed: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'messages_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !indexed {
+        let text = message_text_sql("message_data");
+        let new_text = message_text_sql("new.message_data");
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE messages_fts USING fts5(text);
+
+            CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+                INSERT INTO messages_fts (rowid, text) VALUES (new.id, {new_text});
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+                DELETE FROM messages_fts WHERE rowid = old.id;
+            END;
+
+            INSERT INTO messages_fts (rowid, text) SELECT id, {text} FROM messages;"
+        ))?;
+    }
+    Ok(())
+}
+
+/// SQL expression extracting the searchable text of a stored message:
+/// plain string content, or the text parts of multipart content.
+fn message_text_sql(column: &str) -> String {
+    format!(
+        "CASE json_type({column}, '$.content') \
+             WHEN 'text' THEN json_extract({column}, '$.content') \
+             WHEN 'array' THEN ( \
+                 SELECT group_concat(json_extract(value, '$.text'), ' ') \
+                 FROM json_each({column}, '$.content') \
+                 WHERE json_extract(value, '$.type') = 'text') \
+             ELSE '' END"
+    )
+}
+
+/// Applies `policy` to one session, returning the number of messages removed.
+pub(super) fn prune(
+    conn: &Connection,
+    session_id: &str,
+    policy: &RetentionPolicy,
+) -> std::result::Result<usize, MemoryError> {
+    let tx = conn.unchecked_transaction()?;
+    let removed = expire(&tx, session_id, policy)? + trim(&tx, session_id, policy)?;
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// `datetime('now', ..)` modifier for `ttl` in the past.
+fn ago(ttl: std::time::Duration) -> String {
+    format!("-{} seconds", ttl.as_secs())
+}
+
+/// Deletes the session if idle past its TTL, then messages past theirs.
+fn expire(
+    conn: &Connection,
+    session_id: &str,
+    policy: &RetentionPolicy,
+) -> std::result::Result<usize, MemoryError> {
+    if let Some(ttl) = policy.get_session_ttl() {
+        let idle: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sessions \
+             WHERE session_id = ?1 AND updated_at < datetime('now', ?2))",
+            params![session_id, ago(ttl)],
+            |row| row.get(0),
+        )?;
+        if idle {
+            let removed = conn.execute(
+                "DELETE FROM messages WHERE session_id = ?1",
+                params![session_id],
+            )?;
+            for table in ["session_metadata", "session_state", "sessions"] {
+                conn.execute(
+                    &format!("DELETE FROM {table} WHERE session_id = ?1"),
+                    params![session_id],
+                )?;
+            }
+            return Ok(removed);
+        }
     }
+    let Some(ttl) = policy.get_message_ttl() else {
+        return Ok(0);
+    };
+    // Also drop tool results left at the front without their call.
+    Ok(conn.execute(
+        "DELETE FROM messages WHERE session_id = ?1 AND ( \
+             created_at < datetime('now', ?2) \
+             OR id < COALESCE(( \
+                 SELECT MIN(id) FROM messages \
+                 WHERE session_id = ?1 AND created_at >= datetime('now', ?2) \
+                 AND json_extract(message_data, '$.role') <> 'tool'), 0))",
+        params![session_id, ago(ttl)],
+    )?)
+}
+
+/// Deletes the oldest messages beyond the policy's message limit.
+fn trim(
+    conn: &Connection,
+    session_id: &str,
+    policy: &RetentionPolicy,
+) -> std::result::Result<usize, MemoryError> {
+    let Some(max) = policy.get_max_messages() else {
+        return Ok(0);
+    };
+    let mut stmt = conn.prepare(
+        "SELECT id, json_extract(message_data, '$.role') = 'tool' FROM messages \
+         WHERE session_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map(params![session_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let start = keep_from(rows.len(), rows.len().saturating_sub(max), |i| rows[i].1);
+    if start == 0 {
+        return Ok(0);
+    }
+    let first_kept = rows.get(start).map_or(i64::MAX, |row| row.0);
+    Ok(conn.execute(
+        "DELETE FROM messages WHERE session_id = ?1 AND id < ?2",
+        params![session_id, first_kept],
+    )?)
+}
+
+/// Appends encoded messages and touches the session row, creating it if
+/// needed.
+fn insert_messages(
+    conn: &Connection,
+    session_id: &str,
+    serialized: &[String],
+) -> std::result::Result<(), MemoryError> {
+    conn.execute(
+        "INSERT OR IGNORE INTO sessions (session_id) VALUES (?1)",
+        params![session_id],
+    )?;
+
+    let mut stmt =
+        conn.prepare("INSERT INTO messages (session_id, message_data) VALUES (?1, ?2)")?;
+    for json in serialized {
+        stmt.execute(params![session_id, json])?;
+    }
+
+    conn.execute(
+        "UPDATE sessions SET updated_at = CURRENT_TIMESTAMP \
+         WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    Ok(())
+}
+
+/// Runs `f` on the locked connection in the tokio blocking thread pool.
+///
+/// Shared by every SQLite-backed store in this module.
+pub(super) async fn blocking<F, T>(conn: &Arc<Mutex<Connection>>, f: F) -> Result<T>
+where
+    F: FnOnce(&Connection) -> std::result::Result<T, MemoryError> + Send + 'static,
+    T: Send + 'static,
+{
+    let conn = Arc::clone(conn);
+    Ok(tokio::task::spawn_blocking(move || {
+        let guard = conn.lock().map_err(|e| MemoryError::Lock(e.to_string()))?;
+        f(&guard)
+    })
+    .await
+    .map_err(|e| MemoryError::Task(e.to_string()))??)
 }
 
 #[async_trait]
@@ -125,7 +498,12 @@ fn id(&self) -> &str {
 
     async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
         let session_id = self.id.clone();
+        let retention = self.retention;
+        let codec = self.codec.clone();
         self.blocking(move |conn| {
+            if let Some(policy) = &retention {
+                expire(conn, &session_id, policy)?;
+            }
             let mut messages = if let Some(n) = limit {
                 let mut stmt = conn.prepare(
                     "SELECT message_data FROM messages \
@@ -136,7 +514,7 @@ async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
                 #[allow(clippy::cast_possible_wrap)]
                 let limit = n as i64;
                 stmt.query_map(params![session_id, limit], |row| row.get::<_, String>(0))?
-                    .map(|r| Ok(serde_json::from_str::<Message>(&r?)?))
+                    .map(|r| codec.decode(&session_id, &r?))
                     .collect::<std::result::Result<Vec<_>, MemoryError>>()?
             } else {
                 let mut stmt = conn.prepare(
@@ -146,7 +524,7 @@ async fn get_messages(&self, limit: Option<usize>) -> Result<Vec<Message>> {
                 )?;
 
                 stmt.query_map(params![session_id], |row| row.get::<_, String>(0))?
-                    .map(|r| Ok(serde_json::from_str::<Message>(&r?)?))
+                    .map(|r| codec.decode(&session_id, &r?))
                     .collect::<std::result::Result<Vec<_>, MemoryError>>()?
             };
 
@@ -168,31 +546,48 @@ async fn add_messages(&self, messages: &[Message]) -> Result<()> {
 
         let serialized = messages
             .iter()
-            .map(|m| serde_json::to_string(m).map_err(MemoryError::from))
+            .map(|m| self.codec.encode(&self.id, m))
             .collect::<std::result::Result<Vec<String>, MemoryError>>()?;
 
+        let retention = self.retention;
         self.blocking(move |conn| {
             let tx = conn.unchecked_transaction()?;
+            if let Some(policy) = &retention {
+                expire(&tx, &session_id, policy)?;
+            }
 
-            tx.execute(
-                "INSERT OR IGNORE INTO sessions (session_id) VALUES (?1)",
-                params![session_id],
-            )?;
+            insert_messages(&tx, &session_id, &serialized)?;
+            if let Some(policy) = &retention {
+                trim(&tx, &session_id, policy)?;
+            }
 
-            {
-                let mut stmt =
-                    tx.prepare("INSERT INTO messages (session_id, message_data) VALUES (?1, ?2)")?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
 
-                for json in &serialized {
-                    stmt.execute(params![session_id, json])?;
-                }
-            }
+    /// Swaps the history in one transaction; the session row, and with it
+    /// `created_at`, is kept.
+    async fn replace_messages(&self, messages: &[Message]) -> Result<()> {
+        let session_id = self.id.clone();
+
+        let serialized = messages
+            .iter()
+            .map(|m| self.codec.encode(&self.id, m))
+            .collect::<std::result::Result<Vec<String>, MemoryError>>()?;
 
+        let retention = self.retention;
+        self.blocking(move |conn| {
+            let tx = conn.unchecked_transaction()?;
             tx.execute(
-                "UPDATE sessions SET updated_at = CURRENT_TIMESTAMP \
-                 WHERE session_id = ?1",
+                "DELETE FROM messages WHERE session_id = ?1",
                 params![session_id],
             )?;
+            insert_messages(&tx, &session_id, &serialized)?;
+            if let Some(policy) = &retention {
+                trim(&tx, &session_id, policy)?;
+            }
 
             tx.commit()?;
             Ok(())
@@ -202,8 +597,15 @@ async fn add_messages(&self, messages: &[Message]) -> Result<()> {
 
     async fn pop_message(&self) -> Result<Option<Message>> {
         let session_id = self.id.clone();
+        let retention = self.retention;
+        let codec = self.codec.clone();
         self.blocking(move |conn| {
-            let json: Option<String> = conn
+            if let Some(policy) = &retention {
+                expire(conn, &session_id, policy)?;
+            }
+            // Undo the delete if the message cannot be decoded.
+            let tx = conn.unchecked_transaction()?;
+            let json: Option<String> = tx
                 .query_row(
                     "DELETE FROM messages \
                      WHERE id = ( \
@@ -216,10 +618,9 @@ async fn pop_message(&self) -> Result<Option<Message>> {
                 )
                 .ok();
 
-            match json {
-                Some(j) => Ok(Some(serde_json::from_str(&j)?)),
-                None => Ok(None),
-            }
+            let message = json.map(|j| codec.decode(&session_id, &j)).transpose()?;
+            tx.commit()?;
+            Ok(message)
         })
         .await
     }
@@ -244,7 +645,11 @@ async fn clear(&self) -> Result<()> {
 
     async fn len(&self) -> Result<usize> {
         let session_id = self.id.clone();
+        let retention = self.retention;
         self.blocking(move |conn| {
+            if let Some(policy) = &retention {
+                expire(conn, &session_id, policy)?;
+            }
             let count: i64 = conn.query_row(
                 "SELECT COUNT(*) FROM messages WHERE session_id = ?1",
                 params![session_id],
@@ -256,6 +661,60 @@ async fn len(&self) -> Result<usize> {
         })
         .await
     }
+
+    async fn get_state(&self) -> Result<HashMap<String, Value>> {
+        let session_id = self.id.clone();
+        let retention = self.retention;
+        let codec = self.codec.clone();
+        self.blocking(move |conn| {
+            if let Some(policy) = &retention {
+                expire(conn, &session_id, policy)?;
+            }
+            let stored: Option<String> = conn
+                .query_row(
+                    "SELECT data FROM session_state WHERE session_id = ?1",
+                    params![session_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            stored.map_or_else(
+                || Ok(HashMap::new()),
+                |s| codec.decode_state(&session_id, &s),
+            )
+        })
+        .await
+    }
+
+    async fn put_state(&self, state: &HashMap<String, Value>) -> Result<()> {
+        let session_id = self.id.clone();
+        let stored = (!state.is_empty())
+            .then(|| self.codec.encode_state(&self.id, state))
+            .transpose()?;
+        self.blocking(move |conn| {
+            match stored {
+                Some(data) => {
+                    let tx = conn.unchecked_transaction()?;
+                    tx.execute(
+                        "INSERT OR IGNORE INTO sessions (session_id) VALUES (?1)",
+                        params![session_id],
+                    )?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO session_state (session_id, data) VALUES (?1, ?2)",
+                        params![session_id, data],
+                    )?;
+                    tx.commit()?;
+                }
+                None => {
+                    conn.execute(
+                        "DELETE FROM session_state WHERE session_id = ?1",
+                        params![session_id],
+                    )?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
 }
 
 #[cfg(test)]
@@ -601,10 +1060,7 @@ async fn sessions_on_shared_db_are_isolated() {
             // Two sessions sharing one in-memory database.
             let conn = Connection::open_in_memory().unwrap();
             let s1 = SqliteSession::from_connection(conn, "iso-1").unwrap();
-            let s2 = SqliteSession {
-                id: "iso-2".into(),
-                conn: Arc::clone(&s1.conn),
-            };
+            let s2 = SqliteSession::shared(Arc::clone(&s1.conn), "iso-2".into(), Codec::default());
 
             s1.add_messages(&[Message::user("from-s1")]).await.unwrap();
             s2.add_messages(&[Message::user("from-s2"), Message::user("from-s2-b")])
@@ -626,10 +1082,7 @@ async fn sessions_on_shared_db_are_isolated() {
         async fn clear_one_session_does_not_affect_other() {
             let conn = Connection::open_in_memory().unwrap();
             let s1 = SqliteSession::from_connection(conn, "iso-a").unwrap();
-            let s2 = SqliteSession {
-                id: "iso-b".into(),
-                conn: Arc::clone(&s1.conn),
-            };
+            let s2 = SqliteSession::shared(Arc::clone(&s1.conn), "iso-b".into(), Codec::default());
 
             s1.add_messages(&[Message::user("keep-me")]).await.unwrap();
             s2.add_messages(&[Message::user("delete-me")])
@@ -757,4 +1210,124 @@ async fn unicode_message_content() {
             assert_eq!(stored[0], msg);
         }
     }
+
+    mod retention {
+        use std::time::Duration;
+
+        use super::*;
+        use crate::message::ToolCall;
+
+        const DAY: Duration = Duration::from_hours(24);
+
+        /// Moves every message and the session's `updated_at` `days` into the past.
+        fn age(session: &SqliteSession, days: u32) {
+            let modifier = format!("-{days} days");
+            let guard = session.conn.lock().unwrap();
+            guard
+                .execute(
+                    "UPDATE messages SET created_at = datetime('now', ?1)",
+                    params![modifier],
+                )
+                .unwrap();
+            guard
+                .execute(
+                    "UPDATE sessions SET updated_at = datetime('now', ?1)",
+                    params![modifier],
+                )
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn max_messages_respects_tool_pairs() {
+            let session = new_session("rt1").with_retention(RetentionPolicy::new().max_messages(2));
+            session
+                .add_messages(&[
+                    Message::user("weather?"),
+                    Message::assistant_tool_calls(vec![ToolCall::function("c1", "weather", "{}")]),
+                    Message::tool("c1", "sunny"),
+                    Message::assistant("It is sunny."),
+                ])
+                .await
+                .unwrap();
+            let stored = session.get_messages(None).await.unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].text().unwrap(), "It is sunny.");
+
+            session.add_messages(&sample_messages(5)).await.unwrap();
+            assert_eq!(session.len().await.unwrap(), 2);
+        }
+
+        #[tokio::test]
+        async fn expired_messages_are_deleted() {
+            let session =
+                new_session("rt2").with_retention(RetentionPolicy::new().message_ttl(30 * DAY));
+            session.add_messages(&[Message::user("old")]).await.unwrap();
+            age(&session, 31);
+            session.add_messages(&[Message::user("new")]).await.unwrap();
+
+            let stored = session.get_messages(None).await.unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].text().unwrap(), "new");
+        }
+
+        #[tokio::test]
+        async fn idle_sessions_are_deleted() {
+            let session =
+                new_session("rt3").with_retention(RetentionPolicy::new().session_ttl(30 * DAY));
+            session.add_messages(&sample_messages(3)).await.unwrap();
+            session
+                .put_state(&HashMap::from([("step".to_owned(), Value::from(2))]))
+                .await
+                .unwrap();
+            age(&session, 29);
+            assert_eq!(session.prune().await.unwrap(), 0);
+
+            age(&session, 31);
+            assert_eq!(session.prune().await.unwrap(), 3);
+            assert!(session.is_empty().await.unwrap());
+            assert!(session.get_state().await.unwrap().is_empty());
+        }
+    }
+
+    mod state {
+        use super::*;
+
+        fn checkout(step: &str) -> HashMap<String, Value> {
+            HashMap::from([
+                ("checkout_step".to_owned(), Value::from(step)),
+                ("cart".to_owned(), serde_json::json!({"items": 2})),
+            ])
+        }
+
+        #[tokio::test]
+        async fn state_round_trips_per_session() {
+            let a = new_session("a");
+            let b = SqliteSession::shared(Arc::clone(&a.conn), "b".to_owned(), Codec::default());
+            assert!(a.get_state().await.unwrap().is_empty());
+
+            a.put_state(&checkout("shipping")).await.unwrap();
+            b.put_state(&checkout("payment")).await.unwrap();
+            a.put_state(&checkout("review")).await.unwrap();
+            assert_eq!(a.get_state().await.unwrap(), checkout("review"));
+            assert_eq!(b.get_state().await.unwrap(), checkout("payment"));
+        }
+
+        #[tokio::test]
+        async fn clear_keeps_state_and_empty_state_deletes_the_row() {
+            let session = new_session("s");
+            session.add_messages(&sample_messages(2)).await.unwrap();
+            session.put_state(&checkout("payment")).await.unwrap();
+            session.clear().await.unwrap();
+            assert_eq!(session.get_state().await.unwrap(), checkout("payment"));
+
+            session.put_state(&HashMap::new()).await.unwrap();
+            let rows: i64 = session
+                .conn
+                .lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM session_state", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(rows, 0);
+        }
+    }
 }
@@ -0,0 +1,223 @@
+//! SQLite-backed long-term memory.
+//!
+//! [`SqliteMemoryStore`] keeps memories and their embeddings in the
+//! `memories` table. Embeddings are stored as little-endian `f32` blobs and
+//! compared in process, so no vector extension is needed.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{Connection, params};
+use serde_json::{Map, Value};
+
+use super::error::MemoryError;
+use super::sqlite::blocking;
+use super::store::{Embedder, MemoryFilter, MemoryMatch, MemoryRecord, MemoryStore, rank};
+use crate::embedding::{Embedding, SharedEmbeddingProvider};
+use crate::error::Result;
+
+/// SQLite-backed [`MemoryStore`] that survives process restarts.
+///
+/// Cloneable via `Arc<Mutex<Connection>>`. Schema is auto-created on
+/// construction, and all blocking I/O runs on the tokio blocking pool.
+#[derive(Debug, Clone)]
+pub struct SqliteMemoryStore {
+    conn: Arc<Mutex<Connection>>,
+    embedder: Embedder,
+}
+
+impl SqliteMemoryStore {
+    /// Opens (or creates) a database at `path` and initializes the schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or schema initialization fails.
+    pub fn open(path: impl AsRef<Path>, provider: SharedEmbeddingProvider) -> Result<Self> {
+        let conn = Connection::open(path.as_ref()).map_err(MemoryError::from)?;
+        Self::from_connection(conn, provider)
+    }
+
+    /// Opens an ephemeral in-memory database (data lost on drop).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the in-memory database cannot be created.
+    pub fn in_memory(provider: SharedEmbeddingProvider) -> Result<Self> {
+        let conn = Connection::open_in_memory().map_err(MemoryError::from)?;
+        Self::from_connection(conn, provider)
+    }
+
+    /// Wraps an existing [`Connection`] and creates the schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if schema setup fails.
+    pub fn from_connection(conn: Connection, provider: SharedEmbeddingProvider) -> Result<Self> {
+        conn.execute_batch(
+            "PRAGMA busy_timeout = 5000;
+
+            CREATE TABLE IF NOT EXISTS memories (
+                id         TEXT    PRIMARY KEY,
+                text       TEXT    NOT NULL,
+                metadata   TEXT    NOT NULL,
+                embedding  BLOB    NOT NULL,
+                created_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(MemoryError::from)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            embedder: Embedder::new(provider),
+        })
+    }
+
+    /// Embed with `model` instead of the provider's default model.
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.embedder.set_model(model);
+        self
+    }
+}
+
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[async_trait]
+impl MemoryStore for SqliteMemoryStore {
+    async fn add(&self, text: &str, metadata: Map<String, Value>) -> Result<String> {
+        let embedding = self.embedder.embed(text).await?;
+        let record = MemoryRecord::new(text, metadata);
+        let id = record.id.clone();
+        let metadata = serde_json::to_string(&record.metadata).map_err(MemoryError::from)?;
+        blocking(&self.conn, move |conn| {
+            #[allow(clippy::cast_possible_wrap)]
+            let created_at = record.created_at as i64;
+            conn.execute(
+                "INSERT INTO memories (id, text, metadata, embedding, created_at) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    record.id,
+                    record.text,
+                    metadata,
+                    to_blob(&embedding.vector),
+                    created_at
+                ],
+            )?;
+            Ok(())
+        })
+        .await?;
+        Ok(id)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        k: usize,
+        filter: Option<&MemoryFilter>,
+    ) -> Result<Vec<MemoryMatch>> {
+        let query = self.embedder.embed(query).await?;
+        let filter = filter.cloned();
+        let candidates = blocking(&self.conn, move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, text, metadata, embedding, created_at FROM memories ORDER BY rowid",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?;
+            let mut candidates = Vec::new();
+            for row in rows {
+                let (id, text, metadata, blob, created_at) = row?;
+                let metadata: Map<String, Value> = serde_json::from_str(&metadata)?;
+                if filter.as_ref().is_some_and(|f| !f.matches(&metadata)) {
+                    continue;
+                }
+                let record = MemoryRecord {
+                    id,
+                    text,
+                    metadata,
+                    created_at: u64::try_from(created_at).unwrap_or_default(),
+                };
+                candidates.push((record, Embedding::new(from_blob(&blob), 0)));
+            }
+            Ok(candidates)
+        })
+        .await?;
+        Ok(rank(&query, candidates, k))
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let id = id.to_owned();
+        blocking(&self.conn, move |conn| {
+            Ok(conn.execute("DELETE FROM memories WHERE id = ?1", params![id])? > 0)
+        })
+        .await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        blocking(&self.conn, |conn| {
+            let count: i64 =
+                conn.query_row("SELECT COUNT(*) FROM memories", [], |row| row.get(0))?;
+            Ok(usize::try_from(count).unwrap_or_default())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::WordEmbedder;
+
+    #[test]
+    fn embeddings_round_trip_through_blobs() {
+        let vector = vec![0.5, -1.25, 3.0];
+        assert_eq!(from_blob(&to_blob(&vector)), vector);
+    }
+
+    #[tokio::test]
+    async fn memories_persist_across_connections() {
+        let path = std::env::temp_dir().join(format!("machi-memory-{}.db", uuid::Uuid::new_v4()));
+        let metadata = json!({"user": "alice"}).as_object().cloned().unwrap();
+        {
+            let store = SqliteMemoryStore::open(&path, Arc::new(WordEmbedder)).unwrap();
+            store
+                .add("alice drinks tea", metadata.clone())
+                .await
+                .unwrap();
+            store.add("bob drinks coffee", Map::new()).await.unwrap();
+        }
+
+        let store = SqliteMemoryStore::open(&path, Arc::new(WordEmbedder)).unwrap();
+        assert_eq!(store.len().await.unwrap(), 2);
+        let hits = store.search("coffee", 2, None).await.unwrap();
+        assert_eq!(hits[0].record.text, "bob drinks coffee");
+
+        let alice = MemoryFilter::new().eq("user", "alice");
+        let hits = store.search("coffee", 2, Some(&alice)).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].record.metadata, metadata);
+
+        assert!(store.delete(&hits[0].record.id).await.unwrap());
+        assert_eq!(store.len().await.unwrap(), 1);
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,501 @@
+//! Long-term semantic memory.
+//!
+//! A [`MemoryStore`] keeps standalone facts ("the user prefers dark mode")
+//! rather than a conversation. Facts are embedded with any
+//! [`EmbeddingProvider`](crate::embedding::EmbeddingProvider) and retrieved
+//! by cosine similarity to a query, optionally filtered by metadata.
+
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
+
+use crate::embedding::{Embedding, SharedEmbeddingProvider};
+use crate::error::Result;
+use crate::message::Message;
+
+/// A fact stored in a [`MemoryStore`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryRecord {
+    /// Unique identifier assigned by the store.
+    pub id: String,
+    /// The remembered text.
+    pub text: String,
+    /// Arbitrary metadata used for filtering (e.g. `{"user": "alice"}`).
+    pub metadata: Map<String, Value>,
+    /// When the memory was added, in seconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+impl MemoryRecord {
+    /// Create a record with a fresh id and the current time.
+    #[must_use]
+    pub fn new(text: impl Into<String>, metadata: Map<String, Value>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            text: text.into(),
+            metadata,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+}
+
+/// A [`MemoryRecord`] returned by [`MemoryStore::search`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryMatch {
+    /// The matching memory.
+    pub record: MemoryRecord,
+    /// Cosine similarity between the memory and the query.
+    pub score: f32,
+}
+
+/// Restricts a search to memories whose metadata has the given values.
+///
+/// # Example
+///
+/// ```rust
+/// use machi::memory::MemoryFilter;
+/// use serde_json::json;
+///
+/// let filter = MemoryFilter::new().eq("user", "alice");
+/// let metadata = json!({"user": "alice", "topic": "ui"});
+/// assert!(filter.matches(metadata.as_object().unwrap()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryFilter {
+    equals: Map<String, Value>,
+}
+
+impl MemoryFilter {
+    /// Create a filter that matches every memory.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require metadata `key` to equal `value`.
+    #[must_use]
+    pub fn eq(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.equals.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns `true` if `metadata` satisfies every condition.
+    #[must_use]
+    pub fn matches(&self, metadata: &Map<String, Value>) -> bool {
+        self.equals
+            .iter()
+            .all(|(key, value)| metadata.get(key) == Some(value))
+    }
+}
+
+/// Async trait for long-term, searchable memory.
+///
+/// All implementations must be `Send + Sync` for use across async tasks.
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Store `text` with `metadata`, returning the new memory's id.
+    async fn add(&self, text: &str, metadata: Map<String, Value>) -> Result<String>;
+
+    /// Return up to `k` memories most similar to `query`, best first.
+    ///
+    /// Only memories matching `filter` are considered.
+    async fn search(
+        &self,
+        query: &str,
+        k: usize,
+        filter: Option<&MemoryFilter>,
+    ) -> Result<Vec<MemoryMatch>>;
+
+    /// Delete the memory with `id`. Returns `false` if it did not exist.
+    async fn delete(&self, id: &str) -> Result<bool>;
+
+    /// Returns the number of stored memories.
+    async fn len(&self) -> Result<usize>;
+
+    /// Returns `true` if the store contains no memories.
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+/// A shared, reference-counted memory store for use across tasks.
+pub type SharedMemoryStore = Arc<dyn MemoryStore>;
+
+/// Embeds text with a provider and an optional model override.
+#[derive(Clone)]
+pub(super) struct Embedder {
+    provider: SharedEmbeddingProvider,
+    model: Option<String>,
+}
+
+impl Embedder {
+    pub(super) const fn new(provider: SharedEmbeddingProvider) -> Self {
+        Self {
+            provider,
+            model: None,
+        }
+    }
+
+    pub(super) fn set_model(&mut self, model: impl Into<String>) {
+        self.model = Some(model.into());
+    }
+
+    pub(super) async fn embed(&self, text: &str) -> Result<Embedding> {
+        let model = self
+            .model
+            .as_deref()
+            .unwrap_or_else(|| self.provider.default_embedding_model());
+        self.provider.embed_single(model, text).await
+    }
+}
+
+impl fmt::Debug for Embedder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Embedder")
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The `k` candidates most similar to `query`, best first.
+pub(super) fn rank(
+    query: &Embedding,
+    candidates: impl IntoIterator<Item = (MemoryRecord, Embedding)>,
+    k: usize,
+) -> Vec<MemoryMatch> {
+    let mut matches: Vec<MemoryMatch> = candidates
+        .into_iter()
+        .map(|(record, embedding)| MemoryMatch {
+            score: query.cosine_similarity(&embedding),
+            record,
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(k);
+    matches
+}
+
+/// In-memory [`MemoryStore`]; memories are lost when it is dropped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # tokio_test::block_on(async {
+/// use std::sync::Arc;
+///
+/// use machi::llms::OpenAI;
+/// use machi::memory::{InMemoryStore, MemoryFilter, MemoryStore};
+/// use serde_json::json;
+///
+/// let store = InMemoryStore::new(Arc::new(OpenAI::from_env()?));
+/// let metadata = json!({"user": "alice"}).as_object().cloned().unwrap();
+/// store.add("Prefers dark mode", metadata).await?;
+///
+/// let filter = MemoryFilter::new().eq("user", "alice");
+/// let hits = store.search("which theme?", 3, Some(&filter)).await?;
+/// # Ok::<(), machi::Error>(())
+/// # }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct InMemoryStore {
+    embedder: Embedder,
+    memories: RwLock<Vec<(MemoryRecord, Embedding)>>,
+}
+
+impl InMemoryStore {
+    /// Create an empty store that embeds with `provider`'s default model.
+    #[must_use]
+    pub fn new(provider: SharedEmbeddingProvider) -> Self {
+        Self {
+            embedder: Embedder::new(provider),
+            memories: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Embed with `model` instead of the provider's default model.
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.embedder.set_model(model);
+        self
+    }
+
+    /// Returns every stored memory, oldest first.
+    pub async fn records(&self) -> Vec<MemoryRecord> {
+        self.memories
+            .read()
+            .await
+            .iter()
+            .map(|(record, _)| record.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn add(&self, text: &str, metadata: Map<String, Value>) -> Result<String> {
+        let embedding = self.embedder.embed(text).await?;
+        let record = MemoryRecord::new(text, metadata);
+        let id = record.id.clone();
+        self.memories.write().await.push((record, embedding));
+        Ok(id)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        k: usize,
+        filter: Option<&MemoryFilter>,
+    ) -> Result<Vec<MemoryMatch>> {
+        let query = self.embedder.embed(query).await?;
+        let memories = self.memories.read().await;
+        let candidates = memories
+            .iter()
+            .filter(|(record, _)| filter.is_none_or(|f| f.matches(&record.metadata)))
+            .cloned();
+        Ok(rank(&query, candidates, k))
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let mut memories = self.memories.write().await;
+        let before = memories.len();
+        memories.retain(|(record, _)| record.id != id);
+        Ok(memories.len() < before)
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self.memories.read().await.len())
+    }
+}
+
+/// Injects the memories relevant to each run's input into its messages.
+///
+/// Set with [`RunConfig::auto_recall`](crate::agent::RunConfig::auto_recall).
+/// Before the first step, the store is searched with the user's input and
+/// the matches are added as a system message just before it. The injected
+/// message is not saved to the run's session.
+#[derive(Clone)]
+pub struct AutoRecall {
+    store: SharedMemoryStore,
+    top_k: usize,
+    min_score: f32,
+    filter: Option<MemoryFilter>,
+}
+
+impl AutoRecall {
+    /// Default number of memories injected.
+    pub const DEFAULT_TOP_K: usize = 5;
+
+    /// Recall from `store`.
+    #[must_use]
+    pub fn new(store: SharedMemoryStore) -> Self {
+        Self {
+            store,
+            top_k: Self::DEFAULT_TOP_K,
+            min_score: 0.0,
+            filter: None,
+        }
+    }
+
+    /// Inject at most `k` memories (default: [`DEFAULT_TOP_K`](Self::DEFAULT_TOP_K)).
+    #[must_use]
+    pub const fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    /// Skip memories less similar to the input than `score` (default: `0.0`).
+    #[must_use]
+    pub const fn min_score(mut self, score: f32) -> Self {
+        self.min_score = score;
+        self
+    }
+
+    /// Only recall memories matching `filter`.
+    #[must_use]
+    pub fn filter(mut self, filter: MemoryFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// The memories to inject for `input`, best first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store search fails.
+    pub async fn recall(&self, input: &str) -> Result<Vec<MemoryMatch>> {
+        let mut matches = self
+            .store
+            .search(input, self.top_k, self.filter.as_ref())
+            .await?;
+        matches.retain(|m| m.score >= self.min_score);
+        Ok(matches)
+    }
+
+    /// The system message to inject for `input`, or `None` if nothing
+    /// relevant is remembered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store search fails.
+    pub async fn message(&self, input: &str) -> Result<Option<Message>> {
+        let matches = self.recall(input).await?;
+        if matches.is_empty() {
+            return Ok(None);
+        }
+        let mut text = String::from("Relevant memories from earlier conversations:");
+        for m in &matches {
+            let _ = write!(text, "\n- {}", m.record.text);
+        }
+        Ok(Some(Message::system(text)))
+    }
+}
+
+impl fmt::Debug for AutoRecall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoRecall")
+            .field("top_k", &self.top_k)
+            .field("min_score", &self.min_score)
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::agent::{Agent, RunConfig};
+    use crate::chat::ChatResponse;
+    use crate::test_util::{ScriptedProvider, WordEmbedder};
+
+    fn meta(value: &Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    async fn store() -> Arc<InMemoryStore> {
+        let store = Arc::new(InMemoryStore::new(Arc::new(WordEmbedder)));
+        store
+            .add("alice likes dark theme", meta(&json!({"user": "alice"})))
+            .await
+            .unwrap();
+        store
+            .add("alice drinks tea", meta(&json!({"user": "alice"})))
+            .await
+            .unwrap();
+        store
+            .add("bob drinks coffee", meta(&json!({"user": "bob"})))
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn search_ranks_by_similarity_and_filters() {
+        let store = store().await;
+        let hits = store.search("coffee or tea", 3, None).await.unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits[0].score >= hits[1].score && hits[1].score > hits[2].score);
+        assert_eq!(hits[2].record.text, "alice likes dark theme");
+
+        let alice = MemoryFilter::new().eq("user", "alice");
+        let hits = store.search("coffee", 5, Some(&alice)).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|h| h.record.metadata["user"] == "alice"));
+    }
+
+    #[tokio::test]
+    async fn delete_removes_a_memory() {
+        let store = store().await;
+        let id = store.records().await[0].id.clone();
+        assert!(store.delete(&id).await.unwrap());
+        assert!(!store.delete(&id).await.unwrap());
+        assert_eq!(store.len().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn auto_recall_injects_relevant_memories() {
+        let provider = ScriptedProvider::new(|req| {
+            let system: Vec<String> = req
+                .messages
+                .iter()
+                .filter(|m| m.role == crate::message::Role::System)
+                .filter_map(Message::text)
+                .collect();
+            ChatResponse::from_text(system.join("|"))
+        });
+        let recall = AutoRecall::new(store().await)
+            .top_k(1)
+            .filter(MemoryFilter::new().eq("user", "alice"));
+        let result = Agent::new("assistant")
+            .provider(Arc::new(provider))
+            .run("which theme?", RunConfig::new().auto_recall(recall))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.text(),
+            Some("Relevant memories from earlier conversations:\n- alice likes dark theme")
+        );
+
+        let unrelated = AutoRecall::new(store().await).min_score(0.5);
+        assert!(unrelated.message("rust").await.unwrap().is_none());
+    }
+
+    /// Embedder that counts its calls and always fails.
+    #[derive(Default)]
+    struct BrokenEmbedder {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl crate::embedding::EmbeddingProvider for BrokenEmbedder {
+        async fn embed(
+            &self,
+            _request: &crate::embedding::EmbeddingRequest,
+        ) -> Result<crate::embedding::EmbeddingResponse> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(crate::error::LlmError::network("embedding service down").into())
+        }
+
+        fn default_embedding_model(&self) -> &'static str {
+            "broken"
+        }
+    }
+
+    #[tokio::test]
+    async fn auto_recall_runs_after_guardrails_and_never_aborts() {
+        use crate::guardrail::InputGuardrail;
+        use crate::guardrail::builtin::KeywordDenylist;
+
+        let embedder = Arc::new(BrokenEmbedder::default());
+        let shared: SharedEmbeddingProvider = Arc::<BrokenEmbedder>::clone(&embedder);
+        let recall = AutoRecall::new(Arc::new(InMemoryStore::new(shared)));
+        let calls = || embedder.calls.load(std::sync::atomic::Ordering::SeqCst);
+        let agent = Agent::new("assistant")
+            .provider(ScriptedProvider::reply(|_| "ok".to_owned()))
+            .input_guardrail(
+                InputGuardrail::new("denylist", KeywordDenylist::new(["secret"]).unwrap())
+                    .run_in_parallel(false),
+            );
+        let config = || RunConfig::new().auto_recall(recall.clone());
+
+        assert!(agent.run("tell me the secret", config()).await.is_err());
+        assert_eq!(calls(), 0);
+
+        let result = agent.run("   ", config()).await.unwrap();
+        assert_eq!(result.text(), Some("ok"));
+        assert_eq!(calls(), 0);
+
+        let result = agent.run("which theme?", config()).await.unwrap();
+        assert_eq!(result.text(), Some("ok"));
+        assert_eq!(calls(), 1);
+    }
+}
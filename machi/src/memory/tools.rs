@@ -0,0 +1,250 @@
+//! Tools that let an agent manage its own long-term memory.
+//!
+//! [`RememberTool`] stores a fact in a [`MemoryStore`]; [`RecallTool`]
+//! searches it. Scope both to one user with fixed metadata and a matching
+//! [`MemoryFilter`].
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use super::store::{MemoryFilter, SharedMemoryStore};
+use crate::tool::{Tool, ToolError};
+
+/// Arguments for [`RememberTool`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct RememberArgs {
+    /// The fact to remember.
+    pub text: String,
+}
+
+/// Tool that stores a fact in long-term memory.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use machi::agent::Agent;
+/// use machi::llms::OpenAI;
+/// use machi::memory::{InMemoryStore, MemoryFilter, RecallTool, RememberTool, SharedMemoryStore};
+///
+/// # fn example() -> machi::Result<()> {
+/// let store: SharedMemoryStore = Arc::new(InMemoryStore::new(Arc::new(OpenAI::from_env()?)));
+/// let agent = Agent::new("assistant")
+///     .tool(Box::new(RememberTool::new(Arc::clone(&store)).metadata("user", "alice")))
+///     .tool(Box::new(
+///         RecallTool::new(store).filter(MemoryFilter::new().eq("user", "alice")),
+///     ));
+/// # Ok(())
+/// # }
+/// ```
+pub struct RememberTool {
+    store: SharedMemoryStore,
+    metadata: Map<String, Value>,
+}
+
+impl RememberTool {
+    /// Remember facts in `store`.
+    #[must_use]
+    pub fn new(store: SharedMemoryStore) -> Self {
+        Self {
+            store,
+            metadata: Map::new(),
+        }
+    }
+
+    /// Attach `key: value` metadata to every remembered fact.
+    #[must_use]
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl std::fmt::Debug for RememberTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RememberTool")
+            .field("metadata", &self.metadata)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Tool for RememberTool {
+    const NAME: &'static str = "remember";
+    type Args = RememberArgs;
+    type Output = Value;
+    type Error = ToolError;
+
+    fn description(&self) -> String {
+        "Save a lasting fact about the user or task to long-term memory, such as \
+         a preference or a decision. Write it as a short, self-contained sentence."
+            .to_owned()
+    }
+
+    fn parameters_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "The fact to remember"
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    async fn call(&self, args: RememberArgs) -> Result<Value, ToolError> {
+        let id = self
+            .store
+            .add(&args.text, self.metadata.clone())
+            .await
+            .map_err(|e| ToolError::execution(e.to_string()))?;
+        Ok(json!({ "id": id }))
+    }
+}
+
+/// Arguments for [`RecallTool`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecallArgs {
+    /// What to look for.
+    pub query: String,
+    /// Maximum number of memories to return.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Tool that searches long-term memory.
+///
+/// Returns a list of `{"id", "text", "score"}` objects, best match first.
+pub struct RecallTool {
+    store: SharedMemoryStore,
+    top_k: usize,
+    filter: Option<MemoryFilter>,
+}
+
+impl RecallTool {
+    /// Default number of memories returned.
+    pub const DEFAULT_TOP_K: usize = 5;
+
+    /// Search `store`.
+    #[must_use]
+    pub fn new(store: SharedMemoryStore) -> Self {
+        Self {
+            store,
+            top_k: Self::DEFAULT_TOP_K,
+            filter: None,
+        }
+    }
+
+    /// Return at most `k` memories unless the model asks for fewer
+    /// (default: [`DEFAULT_TOP_K`](Self::DEFAULT_TOP_K)).
+    #[must_use]
+    pub const fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    /// Only search memories matching `filter`.
+    #[must_use]
+    pub fn filter(mut self, filter: MemoryFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+impl std::fmt::Debug for RecallTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecallTool")
+            .field("top_k", &self.top_k)
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Tool for RecallTool {
+    const NAME: &'static str = "recall";
+    type Args = RecallArgs;
+    type Output = Value;
+    type Error = ToolError;
+
+    fn description(&self) -> String {
+        "Search long-term memory for facts saved in earlier conversations, such as \
+         the user's preferences. Returns the most relevant memories first."
+            .to_owned()
+    }
+
+    fn parameters_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "What to look for"
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of memories to return"
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn call(&self, args: RecallArgs) -> Result<Value, ToolError> {
+        let k = args.limit.map_or(self.top_k, |n| n.min(self.top_k));
+        let matches = self
+            .store
+            .search(&args.query, k, self.filter.as_ref())
+            .await
+            .map_err(|e| ToolError::execution(e.to_string()))?;
+        Ok(matches
+            .into_iter()
+            .map(|m| json!({"id": m.record.id, "text": m.record.text, "score": m.score}))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::InMemoryStore;
+    use crate::test_util::WordEmbedder;
+
+    #[tokio::test]
+    async fn remembered_facts_can_be_recalled_per_user() {
+        let store: SharedMemoryStore = Arc::new(InMemoryStore::new(Arc::new(WordEmbedder)));
+        let alice = RememberTool::new(Arc::clone(&store)).metadata("user", "alice");
+        let bob = RememberTool::new(Arc::clone(&store)).metadata("user", "bob");
+        alice
+            .call(RememberArgs {
+                text: "Prefers tea".into(),
+            })
+            .await
+            .unwrap();
+        bob.call(RememberArgs {
+            text: "Prefers coffee".into(),
+        })
+        .await
+        .unwrap();
+        assert_eq!(store.len().await.unwrap(), 2);
+
+        let recall = RecallTool::new(store).filter(MemoryFilter::new().eq("user", "alice"));
+        let found = recall
+            .call(RecallArgs {
+                query: "coffee or tea?".into(),
+                limit: Some(10),
+            })
+            .await
+            .unwrap();
+        let found = found.as_array().unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0]["text"], "Prefers tea");
+    }
+}
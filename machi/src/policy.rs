@@ -0,0 +1,1092 @@
+//! Policy module — argument-aware tool execution rules.
+//!
+//! [`ToolExecutionPolicy`] alone is fixed per tool name. [`ToolRules`] decides
+//! per call instead, from the tool's arguments and the [`RunContext`]:
+//!
+//! - auto-approve `write_file` under `./out/`
+//! - confirm `transfer` above 0.1 ETH
+//! - forbid `exec` containing `rm -rf`
+//!
+//! Rules are checked in order and the first match wins. Calls that match no
+//! rule fall back to the agent's per-tool policy. Every decision is recorded in
+//! [`StepInfo::policy_decisions`](crate::agent::StepInfo::policy_decisions)
+//! together with the rule that made it.
+//!
+//! # Rules in Code
+//!
+//! ```rust
+//! use machi::callback::RunContext;
+//! use machi::policy::{ArgCondition, PolicyRule, ToolRules};
+//! use machi::tool::ToolExecutionPolicy;
+//! use serde_json::json;
+//!
+//! let rules = ToolRules::new()
+//!     .rule(
+//!         PolicyRule::new("out-dir", "write_file", ToolExecutionPolicy::Auto)
+//!             .when_arg(ArgCondition::path_under("path", "./out")),
+//!     )
+//!     .rule(
+//!         // `transfer` takes `amount` in wei; the limit is 0.1 ETH.
+//!         PolicyRule::new("large-transfer", "transfer", ToolExecutionPolicy::RequireConfirmation)
+//!             .when_arg(ArgCondition::greater_than_units("amount", "0.1", 18)),
+//!     );
+//!
+//! let ctx = RunContext::new();
+//! let rule = rules
+//!     .evaluate("transfer", &json!({"amount": "200000000000000000"}), &ctx)
+//!     .unwrap();
+//! assert_eq!(rule.name(), "large-transfer");
+//! assert!(rules.evaluate("transfer", &json!({"amount": "10000000000000000"}), &ctx).is_none());
+//! assert!(rules.evaluate("write_file", &json!({"path": "./out/../.env"}), &ctx).is_none());
+//! ```
+//!
+//! # Rules from a File
+//!
+//! The same rules as TOML (JSON and YAML work too):
+//!
+//! ```toml
+//! [[rules]]
+//! name = "out-dir"
+//! tool = "write_file"
+//! action = "auto"
+//! when = [{ arg = "path", path_under = "./out" }]
+//!
+//! [[rules]]
+//! name = "large-transfer"
+//! tool = "transfer"
+//! action = "require_confirmation"
+//! when = [{ arg = "amount", greater_than_units = { value = "0.1", decimals = 18 } }]
+//!
+//! [[rules]]
+//! name = "no-rm-rf"
+//! tool = "exec"
+//! action = "forbidden"
+//! when = [{ arg = "command", contains = "rm -rf" }]
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::agent::AgentError;
+use crate::callback::RunContext;
+use crate::error::Result;
+use crate::tool::{
+    ConfirmationHandler, SharedConfirmationHandler, ToolConfirmationRequest,
+    ToolConfirmationResponse, ToolExecutionPolicy,
+};
+
+/// Custom rule predicate over the call arguments and run context.
+type Predicate = Arc<dyn Fn(&Value, &RunContext) -> bool + Send + Sync>;
+
+/// A test applied to one argument of a tool call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgTest {
+    /// The argument equals this value.
+    Equals(Value),
+    /// The argument is a string containing this substring.
+    Contains(String),
+    /// The argument is a string starting with this prefix.
+    ///
+    /// This is a plain string test; use [`PathUnder`](Self::PathUnder) for
+    /// paths.
+    StartsWith(String),
+    /// The argument is a path inside this directory.
+    ///
+    /// Both paths are compared component-wise after dropping `.`; arguments
+    /// containing `..` never match, and absolute arguments only match an
+    /// absolute directory.
+    PathUnder(String),
+    /// The argument is a string matching this regular expression.
+    Matches(String),
+    /// The argument is a number (or numeric string) greater than this.
+    GreaterThan(f64),
+    /// The argument is a number (or numeric string) less than this.
+    LessThan(f64),
+    /// The argument is an integer amount in base units (or a string of
+    /// digits, e.g. wei) greater than `value` whole units with `decimals`
+    /// decimal places. Compared exactly, without floating point.
+    GreaterThanUnits {
+        /// Limit in whole units, e.g. `"0.1"`.
+        value: String,
+        /// Decimal places of one whole unit, e.g. `18` for ETH.
+        decimals: u8,
+    },
+    /// The argument is present (`true`) or absent (`false`).
+    Exists(bool),
+}
+
+/// A condition on one argument, addressed by a dotted path such as
+/// `"to.address"` (array elements by index, e.g. `"files.0"`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArgCondition {
+    /// Dotted path to the argument.
+    pub arg: String,
+    /// The test to apply.
+    #[serde(flatten)]
+    pub test: ArgTest,
+}
+
+impl ArgCondition {
+    /// Create a condition from a path and test.
+    #[must_use]
+    pub fn new(arg: impl Into<String>, test: ArgTest) -> Self {
+        Self {
+            arg: arg.into(),
+            test,
+        }
+    }
+
+    /// The argument equals `value`.
+    #[must_use]
+    pub fn equals(arg: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::new(arg, ArgTest::Equals(value.into()))
+    }
+
+    /// The argument contains `needle`.
+    #[must_use]
+    pub fn contains(arg: impl Into<String>, needle: impl Into<String>) -> Self {
+        Self::new(arg, ArgTest::Contains(needle.into()))
+    }
+
+    /// The argument starts with `prefix`.
+    #[must_use]
+    pub fn starts_with(arg: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self::new(arg, ArgTest::StartsWith(prefix.into()))
+    }
+
+    /// The argument is a path inside `dir`.
+    #[must_use]
+    pub fn path_under(arg: impl Into<String>, dir: impl Into<String>) -> Self {
+        Self::new(arg, ArgTest::PathUnder(dir.into()))
+    }
+
+    /// The argument matches the regular expression `pattern`.
+    #[must_use]
+    pub fn matches(arg: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self::new(arg, ArgTest::Matches(pattern.into()))
+    }
+
+    /// The argument is greater than `limit`.
+    #[must_use]
+    pub fn greater_than(arg: impl Into<String>, limit: f64) -> Self {
+        Self::new(arg, ArgTest::GreaterThan(limit))
+    }
+
+    /// The argument is less than `limit`.
+    #[must_use]
+    pub fn less_than(arg: impl Into<String>, limit: f64) -> Self {
+        Self::new(arg, ArgTest::LessThan(limit))
+    }
+
+    /// The argument, in base units, is greater than `value` whole units with
+    /// `decimals` decimal places.
+    #[must_use]
+    pub fn greater_than_units(
+        arg: impl Into<String>,
+        value: impl Into<String>,
+        decimals: u8,
+    ) -> Self {
+        Self::new(
+            arg,
+            ArgTest::GreaterThanUnits {
+                value: value.into(),
+                decimals,
+            },
+        )
+    }
+
+    /// Returns `true` if the condition holds for `args`.
+    ///
+    /// An invalid `Matches` pattern, `PathUnder` directory or
+    /// `GreaterThanUnits` limit never matches; rules loaded through
+    /// [`ToolRules`] have these validated up front.
+    #[must_use]
+    pub fn is_met(&self, args: &Value) -> bool {
+        let value = lookup(args, &self.arg);
+        match &self.test {
+            ArgTest::Exists(expected) => value.is_some() == *expected,
+            ArgTest::Equals(expected) => value == Some(expected),
+            ArgTest::Contains(needle) => as_str(value).is_some_and(|s| s.contains(needle.as_str())),
+            ArgTest::StartsWith(prefix) => {
+                as_str(value).is_some_and(|s| s.starts_with(prefix.as_str()))
+            }
+            ArgTest::PathUnder(dir) => as_str(value)
+                .and_then(normalize_path)
+                .zip(normalize_path(dir))
+                .is_some_and(|(path, dir)| path.starts_with(dir)),
+            ArgTest::Matches(pattern) => {
+                as_str(value).is_some_and(|s| Regex::new(pattern).is_ok_and(|re| re.is_match(s)))
+            }
+            ArgTest::GreaterThan(limit) => as_number(value).is_some_and(|n| n > *limit),
+            ArgTest::LessThan(limit) => as_number(value).is_some_and(|n| n < *limit),
+            ArgTest::GreaterThanUnits {
+                value: limit,
+                decimals,
+            } => as_base_units(value)
+                .zip(to_base_units(limit, *decimals))
+                .is_some_and(|(n, limit)| cmp_digits(&n, &limit) == Ordering::Greater),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match &self.test {
+            ArgTest::Matches(pattern) => {
+                Regex::new(pattern).map_err(|e| {
+                    AgentError::policy(format!("invalid pattern for '{}': {e}", self.arg))
+                })?;
+            }
+            ArgTest::PathUnder(dir) if normalize_path(dir).is_none() => {
+                return Err(AgentError::policy(format!(
+                    "invalid directory for '{}': '{dir}' must not contain '..'",
+                    self.arg
+                ))
+                .into());
+            }
+            ArgTest::GreaterThanUnits { value, decimals }
+                if to_base_units(value, *decimals).is_none() =>
+            {
+                return Err(AgentError::policy(format!(
+                    "invalid amount for '{}': '{value}' is not a decimal with at most \
+                     {decimals} decimal places",
+                    self.arg
+                ))
+                .into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn lookup<'v>(args: &'v Value, path: &str) -> Option<&'v Value> {
+    path.split('.').try_fold(args, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        other => other.get(key),
+    })
+}
+
+fn as_str(value: Option<&Value>) -> Option<&str> {
+    value.and_then(Value::as_str)
+}
+
+fn as_number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Drops `.` components; `None` if the path contains `..`.
+fn normalize_path(path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => return None,
+            other => normalized.push(other),
+        }
+    }
+    Some(normalized)
+}
+
+/// Digits of an integer argument without leading zeros.
+fn as_base_units(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::Number(n) => n.as_u64().map(|n| n.to_string()),
+        Value::String(s) => {
+            let s = s.trim();
+            (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| s.trim_start_matches('0').to_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Scales a decimal such as `"0.1"` to base-unit digits without leading
+/// zeros; `None` if it has more than `decimals` decimal places.
+fn to_base_units(amount: &str, decimals: u8) -> Option<String> {
+    let amount = amount.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() && fraction.is_empty()
+        || !digits(whole)
+        || !digits(fraction)
+        || fraction.len() > usize::from(decimals)
+    {
+        return None;
+    }
+    let scaled = format!("{whole}{fraction:0<width$}", width = usize::from(decimals));
+    Some(scaled.trim_start_matches('0').to_owned())
+}
+
+/// Compares digit strings without leading zeros.
+fn cmp_digits(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// A named rule deciding the policy for matching tool calls.
+#[derive(Clone)]
+pub struct PolicyRule {
+    name: String,
+    tool: String,
+    action: ToolExecutionPolicy,
+    conditions: Vec<ArgCondition>,
+    predicate: Option<Predicate>,
+}
+
+impl PolicyRule {
+    /// Create a rule applying `action` to every call of `tool` (`"*"` for any
+    /// tool). Narrow it with [`when_arg`](Self::when_arg) or
+    /// [`when`](Self::when).
+    #[must_use]
+    pub fn new(
+        name: impl Into<String>,
+        tool: impl Into<String>,
+        action: ToolExecutionPolicy,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            tool: tool.into(),
+            action,
+            conditions: Vec::new(),
+            predicate: None,
+        }
+    }
+
+    /// Require an argument condition (all conditions must hold).
+    #[must_use]
+    pub fn when_arg(mut self, condition: ArgCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Require a custom predicate over the arguments and run context.
+    #[must_use]
+    pub fn when<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Value, &RunContext) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Returns the rule name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the tool name this rule applies to (`"*"` for any).
+    #[must_use]
+    pub fn tool(&self) -> &str {
+        &self.tool
+    }
+
+    /// Returns the policy applied when this rule matches.
+    #[must_use]
+    pub const fn action(&self) -> ToolExecutionPolicy {
+        self.action
+    }
+
+    /// Returns `true` if this rule applies to the call.
+    #[must_use]
+    pub fn matches(&self, tool: &str, args: &Value, context: &RunContext) -> bool {
+        (self.tool == "*" || self.tool == tool)
+            && self.conditions.iter().all(|c| c.is_met(args))
+            && self.predicate.as_ref().is_none_or(|p| p(args, context))
+    }
+}
+
+impl fmt::Debug for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolicyRule")
+            .field("name", &self.name)
+            .field("tool", &self.tool)
+            .field("action", &self.action)
+            .field("conditions", &self.conditions)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+/// Serializable form of a [`PolicyRule`] (without custom predicates).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRuleSpec {
+    /// Rule name, recorded when the rule matches.
+    pub name: String,
+    /// Tool name, or `"*"` for any tool.
+    pub tool: String,
+    /// Policy applied on match: `auto`, `require_confirmation` or `forbidden`.
+    pub action: ToolExecutionPolicy,
+    /// Argument conditions; all must hold.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<ArgCondition>,
+}
+
+/// Top-level layout of a rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<PolicyRuleSpec>,
+}
+
+/// An ordered set of [`PolicyRule`]s; the first matching rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct ToolRules {
+    rules: Vec<PolicyRule>,
+}
+
+impl ToolRules {
+    /// Create an empty rule set.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a rule.
+    #[must_use]
+    pub fn rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Build rules from their serializable form.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Policy`] if a `matches` pattern, `path_under`
+    /// directory or `greater_than_units` limit is invalid.
+    pub fn from_specs(specs: impl IntoIterator<Item = PolicyRuleSpec>) -> Result<Self> {
+        let mut rules = Self::new();
+        for spec in specs {
+            for condition in &spec.when {
+                condition.validate()?;
+            }
+            rules.rules.push(PolicyRule {
+                name: spec.name,
+                tool: spec.tool,
+                action: spec.action,
+                conditions: spec.when,
+                predicate: None,
+            });
+        }
+        Ok(rules)
+    }
+
+    /// Parse rules from JSON (`{"rules": [...]}`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Policy`] if the document is not a valid rule set.
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        let file: RulesFile =
+            serde_json::from_str(s).map_err(|e| AgentError::policy(e.to_string()))?;
+        Self::from_specs(file.rules)
+    }
+
+    /// Parse rules from TOML (`[[rules]]` tables).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Policy`] if the document is not a valid rule set.
+    #[cfg(feature = "spec")]
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(s).map_err(|e| AgentError::policy(e.to_string()))?;
+        Self::from_specs(file.rules)
+    }
+
+    /// Parse rules from YAML (a `rules:` list).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Policy`] if the document is not a valid rule set.
+    #[cfg(feature = "spec")]
+    pub fn from_yaml_str(s: &str) -> Result<Self> {
+        let file: RulesFile =
+            serde_norway::from_str(s).map_err(|e| AgentError::policy(e.to_string()))?;
+        Self::from_specs(file.rules)
+    }
+
+    /// Read rules from a file, inferring the format from its extension.
+    ///
+    /// `.json` is always supported; `.toml`, `.yaml` and `.yml` need the
+    /// `spec` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read, or
+    /// [`AgentError::Policy`] if the format is unsupported or the contents
+    /// are not a valid rule set.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        let contents = std::fs::read_to_string(path)?;
+        match ext.as_str() {
+            "json" => Self::from_json_str(&contents),
+            #[cfg(feature = "spec")]
+            "toml" => Self::from_toml_str(&contents),
+            #[cfg(feature = "spec")]
+            "yaml" | "yml" => Self::from_yaml_str(&contents),
+            _ => Err(AgentError::policy(format!(
+                "unsupported rules file format: '{}'",
+                path.display()
+            ))
+            .into()),
+        }
+    }
+
+    /// Returns the first rule matching the call, if any.
+    #[must_use]
+    pub fn evaluate(&self, tool: &str, args: &Value, context: &RunContext) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(tool, args, context))
+    }
+
+    /// Returns the number of rules.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns `true` if there are no rules.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// The policy applied to one tool call, and the rule that chose it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDecision {
+    /// The tool call ID.
+    pub call_id: String,
+    /// The tool name.
+    pub tool: String,
+    /// The policy applied.
+    pub policy: ToolExecutionPolicy,
+    /// Name of the matching rule, or `None` for the per-tool policy.
+    pub rule: Option<String>,
+}
+
+/// [`ConfirmationHandler`] that answers from [`ToolRules`].
+///
+/// Matching `auto` rules approve and `forbidden` rules deny; calls matching a
+/// `require_confirmation` rule or no rule go to the fallback handler, or are
+/// denied when there is none. Rules see an empty [`RunContext`] here; attach
+/// them with [`Agent::tool_rules`](crate::agent::Agent::tool_rules) to use
+/// the run's context.
+#[derive(Clone)]
+pub struct RuleConfirmationHandler {
+    rules: ToolRules,
+    fallback: Option<SharedConfirmationHandler>,
+}
+
+impl RuleConfirmationHandler {
+    /// Create a handler with no fallback.
+    #[must_use]
+    pub const fn new(rules: ToolRules) -> Self {
+        Self {
+            rules,
+            fallback: None,
+        }
+    }
+
+    /// Ask `handler` when the rules do not settle a request.
+    #[must_use]
+    pub fn with_fallback(mut self, handler: SharedConfirmationHandler) -> Self {
+        self.fallback = Some(handler);
+        self
+    }
+}
+
+impl fmt::Debug for RuleConfirmationHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuleConfirmationHandler")
+            .field("rules", &self.rules)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl ConfirmationHandler for RuleConfirmationHandler {
+    async fn confirm(&self, request: &ToolConfirmationRequest) -> ToolConfirmationResponse {
+        let context = RunContext::new();
+        let action = self
+            .rules
+            .evaluate(&request.name, &request.arguments, &context)
+            .map(PolicyRule::action);
+        match action {
+            Some(ToolExecutionPolicy::Auto) => ToolConfirmationResponse::Approved,
+            Some(ToolExecutionPolicy::Forbidden) => ToolConfirmationResponse::Denied,
+            _ => match &self.fallback {
+                Some(handler) => handler.confirm(request).await,
+                None => ToolConfirmationResponse::Denied,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn wallet_rules() -> ToolRules {
+        ToolRules::new()
+            .rule(
+                PolicyRule::new("out-dir", "write_file", ToolExecutionPolicy::Auto)
+                    .when_arg(ArgCondition::path_under("path", "./out")),
+            )
+            .rule(
+                PolicyRule::new(
+                    "large-transfer",
+                    "transfer",
+                    ToolExecutionPolicy::RequireConfirmation,
+                )
+                .when_arg(ArgCondition::greater_than_units("amount", "0.1", 18)),
+            )
+            .rule(
+                PolicyRule::new("no-rm-rf", "exec", ToolExecutionPolicy::Forbidden)
+                    .when_arg(ArgCondition::contains("command", "rm -rf")),
+            )
+    }
+
+    mod conditions {
+        use super::*;
+
+        #[test]
+        fn paths_reach_nested_values_and_array_items() {
+            let args = json!({"to": {"address": "0xabc"}, "files": ["a.txt", "b.txt"]});
+            assert!(ArgCondition::equals("to.address", "0xabc").is_met(&args));
+            assert!(ArgCondition::equals("files.1", "b.txt").is_met(&args));
+            assert!(ArgCondition::new("files.2", ArgTest::Exists(false)).is_met(&args));
+        }
+
+        #[test]
+        fn numbers_compare_from_strings_too() {
+            let cond = ArgCondition::greater_than("amount", 0.1);
+            assert!(cond.is_met(&json!({"amount": 0.2})));
+            assert!(cond.is_met(&json!({"amount": "0.5"})));
+            assert!(!cond.is_met(&json!({"amount": 0.1})));
+            assert!(!cond.is_met(&json!({"amount": "lots"})));
+            assert!(ArgCondition::less_than("amount", 1.0).is_met(&json!({"amount": 0})));
+        }
+
+        #[test]
+        fn units_compare_exactly_in_base_units() {
+            let cond = ArgCondition::greater_than_units("amount", "0.1", 18);
+            assert!(cond.is_met(&json!({"amount": "200000000000000000"})));
+            assert!(cond.is_met(&json!({"amount": "100000000000000001"})));
+            assert!(!cond.is_met(&json!({"amount": "100000000000000000"})));
+            assert!(!cond.is_met(&json!({"amount": "000099999999999999999"})));
+            assert!(cond.is_met(&json!({"amount": u64::MAX})));
+            // Far beyond f64 precision, and beyond u128.
+            assert!(cond.is_met(&json!({"amount": "1".repeat(60)})));
+            assert!(!cond.is_met(&json!({"amount": "0.2"})));
+            assert!(!cond.is_met(&json!({"amount": "-1"})));
+            assert!(!cond.is_met(&json!({"amount": 1e30})));
+
+            let whole = ArgCondition::greater_than_units("amount", "5", 6);
+            assert!(whole.is_met(&json!({"amount": 5_000_001})));
+            assert!(!whole.is_met(&json!({"amount": 5_000_000})));
+            assert!(
+                !ArgCondition::greater_than_units("amount", "0.1234567", 6)
+                    .is_met(&json!({"amount": "9999999"}))
+            );
+        }
+
+        #[test]
+        fn paths_must_stay_under_the_directory() {
+            let cond = ArgCondition::path_under("path", "./out");
+            assert!(cond.is_met(&json!({"path": "./out/a.md"})));
+            assert!(cond.is_met(&json!({"path": "out/./nested/b.md"})));
+            assert!(!cond.is_met(&json!({"path": "./out/../.env"})));
+            assert!(!cond.is_met(&json!({"path": "./out/sub/../../secret"})));
+            assert!(!cond.is_met(&json!({"path": "/out/a.md"})));
+            assert!(!cond.is_met(&json!({"path": "./outside/a.md"})));
+            assert!(!cond.is_met(&json!({"path": 3})));
+
+            let absolute = ArgCondition::path_under("path", "/srv/out/");
+            assert!(absolute.is_met(&json!({"path": "/srv/out/a"})));
+            assert!(!absolute.is_met(&json!({"path": "srv/out/a"})));
+        }
+
+        #[test]
+        fn regex_matches_strings() {
+            let cond = ArgCondition::matches("url", r"^https://([a-z]+\.)?example\.com/");
+            assert!(cond.is_met(&json!({"url": "https://api.example.com/v1"})));
+            assert!(!cond.is_met(&json!({"url": "https://evil.com/example.com/"})));
+        }
+    }
+
+    mod rules {
+        use super::*;
+
+        #[test]
+        fn first_matching_rule_wins() {
+            let ctx = RunContext::new();
+            let rules = wallet_rules().rule(PolicyRule::new(
+                "everything-else",
+                "*",
+                ToolExecutionPolicy::RequireConfirmation,
+            ));
+
+            let check = |tool: &str, args: Value| {
+                rules
+                    .evaluate(tool, &args, &ctx)
+                    .map(|r| (r.name().to_owned(), r.action()))
+            };
+            assert_eq!(
+                check("write_file", json!({"path": "./out/a.md"})),
+                Some(("out-dir".into(), ToolExecutionPolicy::Auto))
+            );
+            assert_eq!(
+                check("write_file", json!({"path": "/etc/passwd"}))
+                    .unwrap()
+                    .0,
+                "everything-else"
+            );
+            assert_eq!(
+                check("write_file", json!({"path": "./out/../../etc/passwd"}))
+                    .unwrap()
+                    .0,
+                "everything-else"
+            );
+            assert_eq!(
+                check("exec", json!({"command": "sudo rm -rf /"})),
+                Some(("no-rm-rf".into(), ToolExecutionPolicy::Forbidden))
+            );
+        }
+
+        #[test]
+        fn predicates_see_the_run_context() {
+            let rules = ToolRules::new().rule(
+                PolicyRule::new("late", "*", ToolExecutionPolicy::Forbidden)
+                    .when(|_, ctx| ctx.step() > 3),
+            );
+            assert!(
+                rules
+                    .evaluate("x", &json!({}), &RunContext::new())
+                    .is_none()
+            );
+            assert!(
+                rules
+                    .evaluate("x", &json!({}), &RunContext::new().with_step(4))
+                    .is_some()
+            );
+        }
+
+        #[test]
+        fn load_from_json_and_reject_bad_patterns() {
+            let rules = ToolRules::from_json_str(
+                r#"{"rules": [
+                    {"name": "out-dir", "tool": "write_file", "action": "Auto",
+                     "when": [{"arg": "path", "starts_with": "./out/"}]},
+                    {"name": "no-rm", "tool": "exec", "action": "forbidden",
+                     "when": [{"arg": "command", "matches": "rm\\s+-rf"}]}
+                ]}"#,
+            )
+            .unwrap();
+            assert_eq!(rules.len(), 2);
+            let rule = rules
+                .evaluate("exec", &json!({"command": "rm  -rf x"}), &RunContext::new())
+                .unwrap();
+            assert_eq!(rule.action(), ToolExecutionPolicy::Forbidden);
+
+            let err = ToolRules::from_json_str(
+                r#"{"rules": [{"name": "bad", "tool": "t", "action": "auto",
+                    "when": [{"arg": "a", "matches": "("}]}]}"#,
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("invalid pattern for 'a'"), "{err}");
+        }
+
+        #[test]
+        fn reject_bad_directories_and_amounts() {
+            let load = |when: &str| {
+                ToolRules::from_json_str(&format!(
+                    r#"{{"rules": [{{"name": "r", "tool": "t", "action": "auto", "when": [{when}]}}]}}"#
+                ))
+            };
+            let err = load(r#"{"arg": "p", "path_under": "../out"}"#).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid directory for 'p'"),
+                "{err}"
+            );
+            let err =
+                load(r#"{"arg": "a", "greater_than_units": {"value": "0.001", "decimals": 2}}"#)
+                    .unwrap_err();
+            assert!(err.to_string().contains("invalid amount for 'a'"), "{err}");
+            assert!(
+                load(r#"{"arg": "a", "greater_than_units": {"value": "1e3", "decimals": 18}}"#)
+                    .is_err()
+            );
+            assert!(
+                load(r#"{"arg": "a", "greater_than_units": {"value": "0.1", "decimals": 18}}"#)
+                    .is_ok()
+            );
+        }
+
+        #[cfg(feature = "spec")]
+        #[test]
+        fn load_from_toml_file() {
+            let path =
+                std::env::temp_dir().join(format!("machi-rules-{}.toml", uuid::Uuid::new_v4()));
+            std::fs::write(
+                &path,
+                r#"
+                [[rules]]
+                name = "big"
+                tool = "transfer"
+                action = "require_confirmation"
+                when = [{ arg = "amount", greater_than_units = { value = "0.1", decimals = 18 } }]
+
+                [[rules]]
+                name = "out-dir"
+                tool = "write_file"
+                action = "auto"
+                when = [{ arg = "path", path_under = "./out" }]
+                "#,
+            )
+            .unwrap();
+            let rules = ToolRules::from_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let ctx = RunContext::new();
+            assert_eq!(
+                rules
+                    .evaluate("transfer", &json!({"amount": "200000000000000000"}), &ctx)
+                    .map(PolicyRule::name),
+                Some("big")
+            );
+            assert!(
+                rules
+                    .evaluate("write_file", &json!({"path": "out/../x"}), &ctx)
+                    .is_none()
+            );
+        }
+    }
+
+    mod runner {
+        use super::*;
+        use crate::agent::{Agent, RunConfig};
+        use crate::chat::ChatResponse;
+        use crate::message::{Message, Role, ToolCall};
+        use crate::test_util::{EchoTool, ScriptedProvider};
+
+        /// Calls `echo` with two commands, then relays the tool messages.
+        fn two_calls() -> Arc<ScriptedProvider> {
+            Arc::new(ScriptedProvider::new(|req| {
+                let results: Vec<_> = req
+                    .messages
+                    .iter()
+                    .filter(|m| m.role == Role::Tool)
+                    .filter_map(Message::text)
+                    .collect();
+                if results.is_empty() {
+                    ChatResponse::new(Message::assistant_tool_calls(vec![
+                        ToolCall::function("safe", "echo", r#"{"command": "ls"}"#),
+                        ToolCall::function("bad", "echo", r#"{"command": "rm -rf /"}"#),
+                    ]))
+                } else {
+                    ChatResponse::from_text(results.join(" | "))
+                }
+            }))
+        }
+
+        #[tokio::test]
+        async fn rules_decide_per_call_and_are_audited() {
+            let agent = Agent::new("ops")
+                .provider(two_calls())
+                .tool(Box::new(EchoTool))
+                .tool_policy("echo", ToolExecutionPolicy::RequireConfirmation)
+                .tool_rules(
+                    ToolRules::new()
+                        .rule(
+                            PolicyRule::new("no-rm-rf", "echo", ToolExecutionPolicy::Forbidden)
+                                .when_arg(ArgCondition::contains("command", "rm -rf")),
+                        )
+                        .rule(
+                            PolicyRule::new("read-only", "echo", ToolExecutionPolicy::Auto)
+                                .when_arg(ArgCondition::equals("command", "ls")),
+                        ),
+                );
+
+            // No confirmation handler needed: the rules settle both calls.
+            let result = agent.run("tidy up", RunConfig::new()).await.unwrap();
+            let text = result.text().unwrap();
+            assert!(text.contains("ls"), "{text}");
+            assert!(text.contains("forbidden by execution policy"), "{text}");
+
+            let decisions = &result.step_history[0].policy_decisions;
+            assert_eq!(
+                decisions
+                    .iter()
+                    .map(|d| (d.call_id.as_str(), d.policy, d.rule.as_deref()))
+                    .collect::<Vec<_>>(),
+                [
+                    ("safe", ToolExecutionPolicy::Auto, Some("read-only")),
+                    ("bad", ToolExecutionPolicy::Forbidden, Some("no-rm-rf")),
+                ]
+            );
+        }
+
+        /// `transfer` with the wallet tool's schema: `amount` in wei, as a
+        /// string.
+        struct TransferStub;
+
+        #[async_trait]
+        impl crate::tool::Tool for TransferStub {
+            const NAME: &'static str = "transfer";
+            type Args = Value;
+            type Output = String;
+            type Error = crate::tool::ToolError;
+
+            fn description(&self) -> String {
+                "Transfer native token; amount is in wei".to_owned()
+            }
+
+            fn parameters_schema(&self) -> Value {
+                json!({
+                    "type": "object",
+                    "properties": {"to": {"type": "string"}, "amount": {"type": "string"}},
+                    "required": ["to", "amount"]
+                })
+            }
+
+            async fn call(&self, args: Value) -> std::result::Result<String, Self::Error> {
+                Ok(format!(
+                    "sent {}",
+                    args["amount"].as_str().unwrap_or_default()
+                ))
+            }
+        }
+
+        #[tokio::test]
+        async fn transfers_above_the_limit_need_confirmation() {
+            let provider = Arc::new(ScriptedProvider::new(|req| {
+                let results: Vec<_> = req
+                    .messages
+                    .iter()
+                    .filter(|m| m.role == Role::Tool)
+                    .filter_map(Message::text)
+                    .collect();
+                if results.is_empty() {
+                    ChatResponse::new(Message::assistant_tool_calls(vec![
+                        ToolCall::function(
+                            "big",
+                            "transfer",
+                            r#"{"to": "0xabc", "amount": "200000000000000000"}"#,
+                        ),
+                        ToolCall::function(
+                            "small",
+                            "transfer",
+                            r#"{"to": "0xabc", "amount": "10000000000000000"}"#,
+                        ),
+                    ]))
+                } else {
+                    ChatResponse::from_text(results.join(" | "))
+                }
+            }));
+            let agent = Agent::new("wallet")
+                .provider(provider)
+                .tool(Box::new(TransferStub))
+                .tool_rules(wallet_rules());
+
+            let config = RunConfig::new()
+                .confirmation_handler(Arc::new(RuleConfirmationHandler::new(ToolRules::new())));
+            let result = agent.run("pay", config).await.unwrap();
+            let text = result.text().unwrap();
+            assert!(text.contains("sent 10000000000000000"), "{text}");
+            assert!(!text.contains("sent 200000000000000000"), "{text}");
+
+            assert_eq!(
+                result.step_history[0]
+                    .policy_decisions
+                    .iter()
+                    .map(|d| (d.call_id.as_str(), d.policy, d.rule.as_deref()))
+                    .collect::<Vec<_>>(),
+                [
+                    (
+                        "big",
+                        ToolExecutionPolicy::RequireConfirmation,
+                        Some("large-transfer")
+                    ),
+                    ("small", ToolExecutionPolicy::Auto, None),
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn unmatched_calls_fall_back_to_tool_policy() {
+            let agent = Agent::new("ops")
+                .provider(two_calls())
+                .tool(Box::new(EchoTool))
+                .tool_policy("echo", ToolExecutionPolicy::Forbidden);
+            let result = agent.run("tidy up", RunConfig::new()).await.unwrap();
+            assert!(
+                result.step_history[0]
+                    .policy_decisions
+                    .iter()
+                    .all(|d| { d.rule.is_none() && d.policy == ToolExecutionPolicy::Forbidden })
+            );
+        }
+    }
+
+    mod confirmation {
+        use super::*;
+        use crate::tool::AutoApproveHandler;
+
+        #[tokio::test]
+        async fn rules_settle_or_defer_to_fallback() {
+            let handler = RuleConfirmationHandler::new(wallet_rules());
+            let confirm = |name: &str, args: Value| {
+                let request = ToolConfirmationRequest::new("1", name, args);
+                let handler = handler.clone();
+                async move { handler.confirm(&request).await }
+            };
+            assert_eq!(
+                confirm("write_file", json!({"path": "./out/x"})).await,
+                ToolConfirmationResponse::Approved
+            );
+            assert_eq!(
+                confirm("transfer", json!({"amount": "5000000000000000000"})).await,
+                ToolConfirmationResponse::Denied
+            );
+
+            let handler = handler.with_fallback(Arc::new(AutoApproveHandler));
+            let request = ToolConfirmationRequest::new(
+                "1",
+                "transfer",
+                json!({"amount": "5000000000000000000"}),
+            );
+            assert_eq!(
+                handler.confirm(&request).await,
+                ToolConfirmationResponse::Approved
+            );
+            let request = ToolConfirmationRequest::new("1", "exec", json!({"command": "rm -rf /"}));
+            assert_eq!(
+                handler.confirm(&request).await,
+                ToolConfirmationResponse::Denied
+            );
+        }
+    }
+}
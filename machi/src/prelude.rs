@@ -18,17 +18,22 @@
 #[cfg(feature = "a2a")]
 pub use crate::a2a::{A2aAgent, A2aAgentBuilder};
 pub use crate::agent::{
-    Agent, AgentError, Instructions, OutputSchema, RunConfig, RunEvent, RunResult, Runner,
-    StepInfo, ToolCallRecord, UserInput,
+    Agent, AgentError, AskUserTool, HumanInputHandler, HumanInputRequest, InputResponder,
+    InstructionContext, InstructionProvider, Instructions, OutputSchema, PromptTemplate, RunConfig,
+    RunEvent, RunResult, Runner, SharedHumanInputHandler, SteeringHandle, StepInfo,
+    TerminalInputHandler, ToolCallRecord, UserInput,
 };
+#[cfg(feature = "spec")]
+pub use crate::agent::{AgentSpec, SpecRegistry};
 pub use crate::audio::{
     AudioFormat, SpeechRequest, SpeechResponse, SpeechToTextProvider, TextToSpeechProvider,
     TimestampGranularity, TranscriptionRequest, TranscriptionResponse, TranscriptionResponseFormat,
     TranscriptionSegment, TranscriptionWord, Voice,
 };
 pub use crate::callback::{
-    AgentHooks, BoxedAgentHooks, BoxedRunHooks, LogLevel, LoggingAgentHooks, LoggingRunHooks,
-    NoopAgentHooks, NoopRunHooks, RunContext, RunHooks, SharedAgentHooks, SharedRunHooks,
+    AgentHooks, BoxedAgentHooks, BoxedRunHooks, Dependencies, LogLevel, LoggingAgentHooks,
+    LoggingRunHooks, NoopAgentHooks, NoopRunHooks, RunContext, RunHooks, SharedAgentHooks,
+    SharedRunHooks, SharedStepHooks, StepDecision, StepHooks,
 };
 pub use crate::chat::{
     ChatProvider, ChatProviderExt, ChatRequest, ChatResponse, ResponseFormat, SharedChatProvider,
@@ -36,38 +41,50 @@
 };
 pub use crate::embedding::{
     Embedding, EmbeddingProvider, EmbeddingRequest, EmbeddingResponse, EmbeddingUsage,
-    EncodingFormat,
+    EncodingFormat, SharedEmbeddingProvider,
 };
 pub use crate::error::{Error, Result};
 pub use crate::guardrail::{
-    GuardrailOutput, InputGuardrail, InputGuardrailCheck, InputGuardrailResult, OutputGuardrail,
-    OutputGuardrailCheck, OutputGuardrailResult,
+    GuardrailOutput, GuardrailRepair, InputGuardrail, InputGuardrailCheck, InputGuardrailResult,
+    OutputGuardrail, OutputGuardrailCheck, OutputGuardrailResult, StreamCheckInterval,
+    StreamGuardrail, StreamGuardrailCheck, ToolGuardrailOutput, ToolGuardrailResult,
+    ToolInputGuardrail, ToolInputGuardrailCheck, ToolOutputGuardrail, ToolOutputGuardrailCheck,
 };
-pub use crate::llms::LlmError;
+pub use crate::llms::{LlmError, RecordingProvider, ReplayProvider};
 #[cfg(feature = "ollama")]
 pub use crate::llms::{Ollama, OllamaConfig};
 #[cfg(feature = "openai")]
 pub use crate::llms::{OpenAI, OpenAIConfig};
 #[cfg(feature = "mcp")]
 pub use crate::mcp::{HttpBuilder, McpServer, StdioBuilder};
+pub use crate::memory::{
+    AutoRecall, BoxedSession, CompactingSession, InMemorySession, InMemoryStore, JsonlSession,
+    MemoryError, MemoryFilter, MemoryStore, RecallTool, RememberTool, RetentionPolicy, Session,
+    SharedMemoryStore, SharedSession,
+};
+#[cfg(feature = "memory-encryption")]
+pub use crate::memory::{EncryptionKey, Keyring};
 #[cfg(feature = "memory-sqlite")]
-pub use crate::memory::SqliteSession;
-pub use crate::memory::{BoxedSession, InMemorySession, MemoryError, Session, SharedSession};
+pub use crate::memory::{SessionStore, SqliteMemoryStore, SqliteSession};
 pub use crate::message::{
     Annotation, Content, ContentPart, FunctionCall, ImageDetail, ImageMime, InputAudio, Message,
     Role, ThinkingBlock, ToolCall,
 };
+pub use crate::policy::{
+    ArgCondition, PolicyDecision, PolicyRule, RuleConfirmationHandler, ToolRules,
+};
 pub use crate::stream::{StopReason, StreamAggregator, StreamChunk};
 pub use crate::tool::{
     AlwaysDenyHandler, AutoApproveHandler, BoxedConfirmationHandler, BoxedTool,
     ConfirmationHandler, DynTool, SharedConfirmationHandler, Tool, ToolCallResult,
-    ToolConfirmationRequest, ToolConfirmationResponse, ToolDefinition, ToolError,
+    ToolConfirmationRequest, ToolConfirmationResponse, ToolContext, ToolDefinition, ToolError,
     ToolExecutionPolicy, ToolResult,
 };
 #[cfg(feature = "toolkit")]
 pub use crate::tools::{
     BingProvider, BraveProvider, DuckDuckGoProvider, EditFileTool, ExecTool, ListDirTool,
-    ReadFileTool, SearchProvider, SearxngProvider, TavilyProvider, WebSearchTool, WriteFileTool,
+    ReadFileTool, SearchProvider, SearxngProvider, TavilyProvider, TerminalConfirmationHandler,
+    WebSearchTool, WriteFileTool,
 };
 pub use crate::usage::Usage;
 #[cfg(feature = "x402")]
@@ -78,5 +95,9 @@
 };
 #[cfg(feature = "erc8004")]
 pub use crate::wallet::{Erc8004Network, RegistrationFile};
+pub use crate::workflow::{
+    ChatTurn, Evaluation, EvaluatorOptimizer, GroupChat, LoopWorkflow, ParallelWorkflow,
+    RouterWorkflow, SequentialWorkflow, SharedWorkflow, Workflow,
+};
 #[cfg(feature = "derive")]
 pub use machi_derive::tool;
@@ -0,0 +1,154 @@
+//! Shared helpers for unit tests.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::Stream;
+
+use crate::chat::{ChatProvider, ChatRequest, ChatResponse};
+use crate::error::Result;
+use crate::llms::ReplayProvider;
+use crate::message::Role;
+use crate::stream::StreamChunk;
+use crate::usage::Usage;
+
+type Responder = dyn Fn(&ChatRequest) -> ChatResponse + Send + Sync;
+
+/// Provider whose responses are computed from the request by a closure.
+///
+/// Every response carries `Usage::new(10, 5)` unless the closure sets one,
+/// and streaming replays the response as chunks.
+pub struct ScriptedProvider {
+    respond: Box<Responder>,
+}
+
+impl ScriptedProvider {
+    pub fn new(respond: impl Fn(&ChatRequest) -> ChatResponse + Send + Sync + 'static) -> Self {
+        Self {
+            respond: Box::new(respond),
+        }
+    }
+
+    /// Answers every request with `f(last user message text)`.
+    pub fn reply(f: impl Fn(&str) -> String + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self::new(move |req| {
+            ChatResponse::from_text(f(&last_user_text(req)))
+        }))
+    }
+}
+
+/// Text of the last user message in a request.
+pub fn last_user_text(request: &ChatRequest) -> String {
+    request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == Role::User)
+        .and_then(crate::message::Message::text)
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl ChatProvider for ScriptedProvider {
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse> {
+        let mut response = (self.respond)(request);
+        if response.usage.is_none() {
+            response.usage = Some(Usage::new(10, 5));
+        }
+        Ok(response)
+    }
+
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        let response = self.chat(request).await?;
+        let chunks = ReplayProvider::response_to_chunks(&response);
+        Ok(Box::pin(futures::stream::iter(chunks.into_iter().map(Ok))))
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn default_model(&self) -> &'static str {
+        "scripted-model"
+    }
+}
+
+/// Tool named `echo` that returns its arguments unchanged.
+pub struct EchoTool;
+
+#[async_trait]
+impl crate::tool::Tool for EchoTool {
+    const NAME: &'static str = "echo";
+    type Args = serde_json::Value;
+    type Output = serde_json::Value;
+    type Error = crate::tool::ToolError;
+
+    fn description(&self) -> String {
+        "Echo the arguments back".to_owned()
+    }
+
+    fn parameters_schema(&self) -> serde_json::Value {
+        serde_json::json!({"type": "object"})
+    }
+
+    async fn call(&self, args: Self::Args) -> std::result::Result<Self::Output, Self::Error> {
+        Ok(args)
+    }
+}
+
+/// Provider that calls `echo` once, then answers with the last user message.
+pub fn echo_then_answer() -> Arc<ScriptedProvider> {
+    use crate::message::{Message, ToolCall};
+
+    Arc::new(ScriptedProvider::new(|req| {
+        if req.messages.iter().any(|m| m.role == Role::Tool) {
+            ChatResponse::from_text(format!("final: {}", last_user_text(req)))
+        } else {
+            ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                "call_1", "echo", "{}",
+            )]))
+        }
+    }))
+}
+
+/// Embeds text as counts of a few known words, so similarity is predictable.
+pub struct WordEmbedder;
+
+impl WordEmbedder {
+    const VOCABULARY: [&'static str; 5] = ["coffee", "tea", "dark", "theme", "rust"];
+
+    fn vector(text: &str) -> Vec<f32> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .map(str::to_lowercase)
+            .collect();
+        Self::VOCABULARY
+            .iter()
+            .map(|v| f32::from(u8::from(words.iter().any(|w| w == v))))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl crate::embedding::EmbeddingProvider for WordEmbedder {
+    async fn embed(
+        &self,
+        request: &crate::embedding::EmbeddingRequest,
+    ) -> Result<crate::embedding::EmbeddingResponse> {
+        let embeddings = request
+            .input
+            .iter()
+            .enumerate()
+            .map(|(i, text)| crate::embedding::Embedding::new(Self::vector(text), i))
+            .collect();
+        Ok(crate::embedding::EmbeddingResponse::new(embeddings))
+    }
+
+    fn default_embedding_model(&self) -> &'static str {
+        "words"
+    }
+}
@@ -11,9 +11,13 @@
 //! - Compatible with both Chat Completions and Responses APIs
 
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::Value;
+use std::any::Any;
 use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::callback::{Dependencies, RunContext};
 
 /// Error type for tool execution failures.
 #[derive(Debug, Clone, thiserror::Error)]
@@ -233,6 +237,177 @@ fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     }
 }
 
+/// Per-call context handed to [`Tool::call_with_context`].
+///
+/// Exposes a snapshot of the [`RunContext`] taken when the call started (agent
+/// name, step, usage, state, typed [`Dependencies`]) and the ID of the tool
+/// call being executed.
+///
+/// State written with [`set_state`](Self::set_state) is visible to later reads
+/// through this context and is merged back into the run's [`RunContext`] when
+/// the call returns. Tool calls that run concurrently in the same step do not
+/// see each other's writes; they are applied in call order.
+///
+/// # Example
+///
+/// With the `#[tool]` macro, a `&ToolContext` parameter is filled in by the
+/// runner and hidden from the model:
+///
+/// ```rust
+/// # #[cfg(feature = "derive")]
+/// # {
+/// use std::sync::Arc;
+/// use machi::prelude::*;
+///
+/// struct TenantId(String);
+///
+/// /// Count lookups per tenant.
+/// #[tool]
+/// async fn lookup(ctx: &ToolContext, key: String) -> ToolResult<String> {
+///     let tenant = ctx
+///         .dependency::<TenantId>()
+///         .ok_or_else(|| ToolError::execution("no tenant configured"))?;
+///     let count = ctx.get_state("lookups").and_then(|v| v.as_u64()).unwrap_or(0);
+///     ctx.set_state("lookups", (count + 1).into());
+///     Ok(format!("{}:{key}", tenant.0))
+/// }
+///
+/// # tokio_test::block_on(async {
+/// let ctx = ToolContext::new("call_1")
+///     .with_dependencies(Dependencies::new().with(Arc::new(TenantId("acme".into()))));
+/// let out = LOOKUP
+///     .call_with_context(LookupArgs { key: "k".into() }, &ctx)
+///     .await
+///     .unwrap();
+/// assert_eq!(out, "acme:k");
+/// assert_eq!(ctx.get_state("lookups"), Some(1.into()));
+/// # });
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ToolContext {
+    run: RunContext,
+    call_id: String,
+    changes: Mutex<Vec<(String, Option<Value>)>>,
+}
+
+impl ToolContext {
+    /// Create a context for the tool call with the given ID.
+    #[must_use]
+    pub fn new(call_id: impl Into<String>) -> Self {
+        Self {
+            call_id: call_id.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the run context snapshot.
+    #[must_use]
+    pub fn with_run_context(mut self, run: RunContext) -> Self {
+        self.run = run;
+        self
+    }
+
+    /// Replace the dependencies of the run context snapshot.
+    #[must_use]
+    pub fn with_dependencies(mut self, dependencies: Dependencies) -> Self {
+        self.run = self.run.with_dependencies(dependencies);
+        self
+    }
+
+    /// The run context as it was when the call started.
+    #[must_use]
+    pub const fn run_context(&self) -> &RunContext {
+        &self.run
+    }
+
+    /// Name of the agent executing the tool, if known.
+    #[must_use]
+    pub fn agent_name(&self) -> Option<&str> {
+        self.run.agent_name()
+    }
+
+    /// Current step number.
+    #[must_use]
+    pub const fn step(&self) -> usize {
+        self.run.step()
+    }
+
+    /// ID of the tool call being executed.
+    #[must_use]
+    pub fn call_id(&self) -> &str {
+        &self.call_id
+    }
+
+    /// The run's typed dependencies.
+    #[must_use]
+    pub const fn dependencies(&self) -> &Dependencies {
+        self.run.dependencies()
+    }
+
+    /// Get the dependency of type `T`, if one was provided.
+    #[must_use]
+    pub fn dependency<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.run.dependency::<T>()
+    }
+
+    /// Get a value from the run state, including writes made by this call.
+    #[must_use]
+    pub fn get_state(&self, key: &str) -> Option<Value> {
+        let changes = self.changes.lock().unwrap_or_else(PoisonError::into_inner);
+        changes
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map_or_else(|| self.run.get_state(key).cloned(), |(_, v)| v.clone())
+    }
+
+    /// Insert a value into the run state.
+    pub fn set_state(&self, key: impl Into<String>, value: Value) {
+        self.record(key.into(), Some(value));
+    }
+
+    /// Remove a value from the run state, returning its current value.
+    pub fn remove_state(&self, key: &str) -> Option<Value> {
+        let previous = self.get_state(key);
+        self.record(key.to_owned(), None);
+        previous
+    }
+
+    /// Apply the state writes made during the call to `run`.
+    pub fn apply_to(self, run: &mut RunContext) {
+        let changes = self
+            .changes
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (key, value) in changes {
+            match value {
+                Some(value) => run.set_state(key, value),
+                None => {
+                    run.remove_state(&key);
+                }
+            }
+        }
+    }
+
+    fn record(&self, key: String, value: Option<Value>) {
+        self.changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((key, value));
+    }
+}
+
+/// Parse tool arguments given either as a JSON object or a JSON-encoded string.
+fn parse_args<A: DeserializeOwned>(args: Value) -> Result<A, ToolError> {
+    match &args {
+        Value::String(s) => {
+            serde_json::from_str(s).map_err(|e| ToolError::InvalidArguments(e.to_string()))
+        }
+        _ => serde_json::from_value(args).map_err(|e| ToolError::InvalidArguments(e.to_string())),
+    }
+}
+
 /// The core trait for all tools that agents can use.
 #[async_trait]
 pub trait Tool: Send + Sync {
@@ -262,6 +437,20 @@ fn name(&self) -> &'static str {
     /// Execute the tool with the given arguments.
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error>;
 
+    /// Execute the tool with access to the run context.
+    ///
+    /// The runner always invokes tools through this method. The default
+    /// implementation ignores the context and delegates to
+    /// [`call`](Self::call); override it when the tool needs run state,
+    /// dependencies, or its call ID.
+    async fn call_with_context(
+        &self,
+        args: Self::Args,
+        _ctx: &ToolContext,
+    ) -> Result<Self::Output, Self::Error> {
+        self.call(args).await
+    }
+
     /// Get the tool definition for LLM function calling.
     fn definition(&self) -> ToolDefinition {
         ToolDefinition {
@@ -277,16 +466,23 @@ async fn call_json(&self, args: Value) -> Result<Value, ToolError>
     where
         Self::Output: 'static,
     {
-        // Handle both string and object arguments
-        let typed_args: Self::Args = match &args {
-            Value::String(s) => {
-                serde_json::from_str(s).map_err(|e| ToolError::InvalidArguments(e.to_string()))?
-            }
-            _ => serde_json::from_value(args)
-                .map_err(|e| ToolError::InvalidArguments(e.to_string()))?,
-        };
+        let result = self.call(parse_args(args)?).await.map_err(Into::into)?;
+        serde_json::to_value(result).map_err(|e| ToolError::Execution(e.to_string()))
+    }
 
-        let result = self.call(typed_args).await.map_err(Into::into)?;
+    /// Call the tool with JSON arguments and a [`ToolContext`].
+    async fn call_json_with_context(
+        &self,
+        args: Value,
+        ctx: &ToolContext,
+    ) -> Result<Value, ToolError>
+    where
+        Self::Output: 'static,
+    {
+        let result = self
+            .call_with_context(parse_args(args)?, ctx)
+            .await
+            .map_err(Into::into)?;
         serde_json::to_value(result).map_err(|e| ToolError::Execution(e.to_string()))
     }
 }
@@ -308,6 +504,17 @@ pub trait DynTool: Send + Sync {
 
     /// Call the tool with JSON arguments.
     async fn call_json(&self, args: Value) -> Result<Value, ToolError>;
+
+    /// Call the tool with JSON arguments and a [`ToolContext`].
+    ///
+    /// Defaults to [`call_json`](Self::call_json), ignoring the context.
+    async fn call_json_with_context(
+        &self,
+        args: Value,
+        _ctx: &ToolContext,
+    ) -> Result<Value, ToolError> {
+        self.call_json(args).await
+    }
 }
 
 #[async_trait]
@@ -330,6 +537,14 @@ fn definition(&self) -> ToolDefinition {
     async fn call_json(&self, args: Value) -> Result<Value, ToolError> {
         Tool::call_json(self, args).await
     }
+
+    async fn call_json_with_context(
+        &self,
+        args: Value,
+        ctx: &ToolContext,
+    ) -> Result<Value, ToolError> {
+        Tool::call_json_with_context(self, args, ctx).await
+    }
 }
 
 /// Execution policy for a tool.
@@ -338,10 +553,13 @@ async fn call_json(&self, args: Value) -> Result<Value, ToolError> {
 pub enum ToolExecutionPolicy {
     /// Agent can execute the tool autonomously without confirmation.
     #[default]
+    #[serde(alias = "auto")]
     Auto,
     /// Requires human confirmation before execution.
+    #[serde(alias = "require_confirmation")]
     RequireConfirmation,
     /// Tool execution is forbidden.
+    #[serde(alias = "forbidden")]
     Forbidden,
 }
 
@@ -437,7 +655,7 @@ pub trait ConfirmationHandler: Send + Sync {
 pub type BoxedConfirmationHandler = Box<dyn ConfirmationHandler>;
 
 /// A shared confirmation handler for use across cloneable contexts.
-pub type SharedConfirmationHandler = std::sync::Arc<dyn ConfirmationHandler>;
+pub type SharedConfirmationHandler = Arc<dyn ConfirmationHandler>;
 
 /// Default confirmation handler that auto-approves all requests.
 #[derive(Debug, Clone, Copy, Default)]
@@ -931,4 +1149,209 @@ fn returns_error() -> ToolResult<()> {
             assert!(returns_error().is_err());
         }
     }
+
+    mod tool_context {
+        use super::*;
+
+        struct Plain;
+
+        #[async_trait]
+        impl Tool for Plain {
+            const NAME: &'static str = "plain";
+            type Args = Value;
+            type Output = Value;
+            type Error = ToolError;
+
+            fn description(&self) -> String {
+                "Returns its arguments".to_owned()
+            }
+
+            fn parameters_schema(&self) -> Value {
+                serde_json::json!({"type": "object"})
+            }
+
+            async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+                Ok(args)
+            }
+        }
+
+        struct Counter;
+
+        #[async_trait]
+        impl Tool for Counter {
+            const NAME: &'static str = "counter";
+            type Args = Value;
+            type Output = String;
+            type Error = ToolError;
+
+            fn description(&self) -> String {
+                "Counts its calls in run state".to_owned()
+            }
+
+            fn parameters_schema(&self) -> Value {
+                serde_json::json!({"type": "object"})
+            }
+
+            async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+                Err(ToolError::execution("requires a run context"))
+            }
+
+            async fn call_with_context(
+                &self,
+                _args: Self::Args,
+                ctx: &ToolContext,
+            ) -> Result<Self::Output, Self::Error> {
+                let count = ctx.get_state("count").and_then(|v| v.as_u64()).unwrap_or(0);
+                ctx.set_state("count", (count + 1).into());
+                Ok(format!("{}@{}", ctx.call_id(), ctx.step()))
+            }
+        }
+
+        #[test]
+        fn exposes_run_metadata() {
+            let run = RunContext::new().with_agent_name("agent").with_step(2);
+            let ctx = ToolContext::new("call_9").with_run_context(run);
+            assert_eq!(ctx.agent_name(), Some("agent"));
+            assert_eq!(ctx.step(), 2);
+            assert_eq!(ctx.call_id(), "call_9");
+            assert!(ctx.dependencies().is_empty());
+        }
+
+        #[test]
+        fn state_reads_see_own_writes_over_snapshot() {
+            let mut run = RunContext::new();
+            run.set_state("a", Value::from(1));
+            run.set_state("b", Value::from(2));
+            let ctx = ToolContext::new("id").with_run_context(run.clone());
+
+            ctx.set_state("a", Value::from(10));
+            assert_eq!(ctx.get_state("a"), Some(Value::from(10)));
+            assert_eq!(ctx.remove_state("b"), Some(Value::from(2)));
+            assert_eq!(ctx.get_state("b"), None);
+            // The snapshot itself is untouched until applied.
+            assert_eq!(ctx.run_context().get_state("b"), Some(&Value::from(2)));
+
+            ctx.apply_to(&mut run);
+            assert_eq!(run.get_state("a"), Some(&Value::from(10)));
+            assert!(run.get_state("b").is_none());
+        }
+
+        #[test]
+        fn dependencies_are_typed() {
+            let deps = Dependencies::new().with(Arc::new(String::from("tenant")));
+            let ctx = ToolContext::new("id").with_dependencies(deps);
+            assert_eq!(
+                ctx.dependency::<String>().as_deref().map(String::as_str),
+                Some("tenant")
+            );
+            assert!(ctx.dependency::<u32>().is_none());
+        }
+
+        #[tokio::test]
+        async fn default_call_with_context_delegates_to_call() {
+            let tool: BoxedTool = Box::new(Plain);
+            let out = tool
+                .call_json_with_context(serde_json::json!({"x": 1}), &ToolContext::new("id"))
+                .await
+                .unwrap();
+            assert_eq!(out, serde_json::json!({"x": 1}));
+        }
+
+        #[tokio::test]
+        async fn dyn_tool_routes_context_to_override() {
+            let tool: BoxedTool = Box::new(Counter);
+            let ctx = ToolContext::new("call_1").with_run_context(RunContext::new().with_step(3));
+            let out = tool
+                .call_json_with_context(Value::String("{}".to_owned()), &ctx)
+                .await
+                .unwrap();
+            assert_eq!(out, Value::from("call_1@3"));
+            assert_eq!(ctx.get_state("count"), Some(Value::from(1)));
+            assert!(tool.call_json(serde_json::json!({})).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn runner_merges_state_and_passes_dependencies() {
+            use crate::agent::{Agent, RunConfig};
+            use crate::callback::RunHooks;
+            use crate::chat::ChatResponse;
+            use crate::message::{Message, Role, ToolCall};
+            use crate::test_util::ScriptedProvider;
+
+            /// Tool that reads a dependency and records the call ID in state.
+            struct Tenant;
+
+            #[async_trait]
+            impl Tool for Tenant {
+                const NAME: &'static str = "tenant";
+                type Args = Value;
+                type Output = String;
+                type Error = ToolError;
+
+                fn description(&self) -> String {
+                    "Returns the tenant".to_owned()
+                }
+
+                fn parameters_schema(&self) -> Value {
+                    serde_json::json!({"type": "object"})
+                }
+
+                async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+                    Err(ToolError::execution("requires a run context"))
+                }
+
+                async fn call_with_context(
+                    &self,
+                    _args: Self::Args,
+                    ctx: &ToolContext,
+                ) -> Result<Self::Output, Self::Error> {
+                    ctx.set_state("last_call", ctx.call_id().into());
+                    let tenant = ctx
+                        .dependency::<String>()
+                        .ok_or_else(|| ToolError::execution("no tenant"))?;
+                    Ok(format!(
+                        "{tenant} for {}",
+                        ctx.agent_name().unwrap_or_default()
+                    ))
+                }
+            }
+
+            /// Captures the run state seen by `on_agent_end`.
+            #[derive(Default)]
+            struct Capture(Mutex<Option<Value>>);
+
+            #[async_trait]
+            impl RunHooks for Capture {
+                async fn on_agent_end(&self, ctx: &RunContext, _agent: &str, _output: &Value) {
+                    *self.0.lock().unwrap() = ctx.get_state("last_call").cloned();
+                }
+            }
+
+            let provider = ScriptedProvider::new(|req| {
+                req.messages
+                    .iter()
+                    .find(|m| m.role == Role::Tool)
+                    .map_or_else(
+                        || {
+                            ChatResponse::new(Message::assistant_tool_calls(vec![
+                                ToolCall::function("call_7", "tenant", "{}"),
+                            ]))
+                        },
+                        |m| ChatResponse::from_text(m.text().unwrap_or_default()),
+                    )
+            });
+            let agent = Agent::new("support")
+                .provider(Arc::new(provider))
+                .tool(Box::new(Tenant));
+            let capture = Arc::new(Capture::default());
+            let hooks: Arc<dyn RunHooks> = Arc::<Capture>::clone(&capture);
+            let config = RunConfig::new()
+                .hooks(hooks)
+                .with_dependency(Arc::new(String::from("acme")));
+
+            let result = agent.run("who?", config).await.unwrap();
+            assert_eq!(result.text(), Some("\"acme for support\""));
+            assert_eq!(*capture.0.lock().unwrap(), Some(Value::from("call_7")));
+        }
+    }
 }
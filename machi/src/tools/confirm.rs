@@ -0,0 +1,411 @@
+//! Interactive confirmation for tools that require approval.
+//!
+//! [`TerminalConfirmationHandler`] prints a preview of each pending tool call
+//! and asks on stdin whether to run it:
+//!
+//! - `write_file` / `edit_file` — a unified diff against the current file
+//! - `transfer` — the amount in ETH and the recipient (with its ENS name when
+//!   a wallet is attached)
+//! - anything else — the pretty-printed JSON arguments
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead as _, Write as _};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::fs;
+
+#[cfg(feature = "wallet")]
+use std::sync::Arc;
+
+use crate::tool::{ConfirmationHandler, ToolConfirmationRequest, ToolConfirmationResponse};
+#[cfg(feature = "wallet")]
+use crate::wallet::EvmWallet;
+
+/// Lines of unchanged context around each diff hunk.
+const DIFF_CONTEXT: usize = 3;
+
+/// Diffs above this many line comparisons are summarized instead.
+const DIFF_MAX_CELLS: usize = 4_000_000;
+
+/// Confirmation handler that previews tool calls and prompts on the terminal.
+///
+/// Answers: `y`/`yes` approves, `a`/`all` approves this and all future calls
+/// to the tool, `n`/`no` denies. End of input denies.
+///
+/// Stdin is read on Tokio's blocking pool. If the run is cancelled while a
+/// prompt is open, the read stays pending until the next line, which is
+/// discarded.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use machi::agent::RunConfig;
+/// use machi::tools::TerminalConfirmationHandler;
+///
+/// let config = RunConfig::new().confirmation_handler(Arc::new(TerminalConfirmationHandler::new()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TerminalConfirmationHandler {
+    #[cfg(feature = "wallet")]
+    wallet: Option<Arc<EvmWallet>>,
+}
+
+impl TerminalConfirmationHandler {
+    /// Create a handler.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up ENS names for transfer recipients through `wallet`.
+    #[cfg(feature = "wallet")]
+    #[must_use]
+    pub fn with_wallet(mut self, wallet: Arc<EvmWallet>) -> Self {
+        self.wallet = Some(wallet);
+        self
+    }
+
+    /// Build the preview shown for a request.
+    pub async fn preview(&self, request: &ToolConfirmationRequest) -> String {
+        let mut out = format!("Tool '{}' wants to run:\n", request.name);
+        let args = &request.arguments;
+        let detail = match request.name.as_str() {
+            "write_file" | "edit_file" => file_change_preview(&request.name, args).await,
+            "transfer" => self.transfer_preview(args).await,
+            _ => None,
+        };
+        if let Some(detail) = detail {
+            out.push_str(&detail);
+        } else {
+            let pretty = serde_json::to_string_pretty(args).unwrap_or_else(|_| args.to_string());
+            let _ = writeln!(out, "{pretty}");
+        }
+        out
+    }
+
+    async fn transfer_preview(&self, args: &Value) -> Option<String> {
+        let to = args.get("to")?.as_str()?;
+        let eth = format_units(args.get("amount")?.as_str()?, 18)?;
+        let name = self.ens_name(to).await;
+        let recipient = name.map_or_else(|| to.to_owned(), |name| format!("{name} ({to})"));
+        Some(format!("  Send {eth} ETH to {recipient}\n"))
+    }
+
+    #[cfg(feature = "wallet")]
+    async fn ens_name(&self, address: &str) -> Option<String> {
+        let wallet = self.wallet.as_ref()?;
+        let address = address.parse().ok()?;
+        wallet.reverse_ens(address).await.ok().flatten()
+    }
+
+    #[cfg(not(feature = "wallet"))]
+    #[allow(clippy::unused_async)]
+    async fn ens_name(&self, _address: &str) -> Option<String> {
+        None
+    }
+}
+
+#[async_trait]
+impl ConfirmationHandler for TerminalConfirmationHandler {
+    async fn confirm(&self, request: &ToolConfirmationRequest) -> ToolConfirmationResponse {
+        let preview = self.preview(request).await;
+        let name = request.name.clone();
+
+        // Stdin blocks, so prompt on the blocking pool.
+        tokio::task::spawn_blocking(move || {
+            let mut stdout = io::stdout().lock();
+            let _ = write!(stdout, "\n{preview}");
+            let mut stdin = io::stdin().lock();
+            loop {
+                let _ = write!(stdout, "Run it? [y]es / [n]o / [a]lways for '{name}': ");
+                let _ = stdout.flush();
+                let mut line = String::new();
+                match stdin.read_line(&mut line) {
+                    Ok(0) | Err(_) => break ToolConfirmationResponse::Denied,
+                    Ok(_) => {
+                        if let Some(response) = parse_answer(&line) {
+                            break response;
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap_or(ToolConfirmationResponse::Denied)
+    }
+}
+
+/// Map a typed answer onto a response; `None` means ask again.
+fn parse_answer(line: &str) -> Option<ToolConfirmationResponse> {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Some(ToolConfirmationResponse::Approved),
+        "n" | "no" => Some(ToolConfirmationResponse::Denied),
+        "a" | "all" | "always" => Some(ToolConfirmationResponse::ApproveAll),
+        _ => None,
+    }
+}
+
+/// Diff of the change a `write_file` or `edit_file` call would make.
+async fn file_change_preview(tool: &str, args: &Value) -> Option<String> {
+    let path = args.get("path")?.as_str()?;
+    let current = fs::read_to_string(path).await.unwrap_or_default();
+    let proposed = if tool == "write_file" {
+        let content = args.get("content")?.as_str()?;
+        if args.get("append").and_then(Value::as_bool) == Some(true) {
+            format!("{current}{content}")
+        } else {
+            content.to_owned()
+        }
+    } else {
+        let old = args.get("old_text")?.as_str()?;
+        let new = args.get("new_text")?.as_str()?;
+        if old.is_empty() || !current.contains(old) {
+            return Some(format!(
+                "  {path}: old_text not found; the edit will fail\n"
+            ));
+        }
+        if args.get("replace_all").and_then(Value::as_bool) == Some(true) {
+            current.replace(old, new)
+        } else {
+            current.replacen(old, new, 1)
+        }
+    };
+    Some(unified_diff(path, &current, &proposed))
+}
+
+/// Render a wei-style integer string with `decimals` decimal places.
+fn format_units(raw: &str, decimals: usize) -> Option<String> {
+    let raw = raw.trim();
+    if raw.is_empty() || !raw.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits = raw.trim_start_matches('0');
+    let padded = format!("{digits:0>width$}", width = decimals + 1);
+    let (whole, frac) = padded.split_at(padded.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    Some(if frac.is_empty() {
+        whole.to_owned()
+    } else {
+        format!("{whole}.{frac}")
+    })
+}
+
+/// A line-level edit operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Line operations turning `old` into `new`, from a longest common subsequence.
+fn diff_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j] = LCS length of old[i..] and new[j..].
+    let mut lcs = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::with_capacity(n + m);
+    while i < n || j < m {
+        let same = old.get(i).is_some_and(|line| new.get(j) == Some(line));
+        if same {
+            ops.push(Op::Equal);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Unified diff of `old` against `new` for display.
+fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let mut out = format!("--- {path}\n+++ {path}\n");
+    if old == new {
+        out.push_str("(no changes)\n");
+        return out;
+    }
+    if old_lines.len().saturating_mul(new_lines.len()) > DIFF_MAX_CELLS {
+        let _ = writeln!(
+            out,
+            "(too large to diff: {} lines -> {} lines)",
+            old_lines.len(),
+            new_lines.len()
+        );
+        return out;
+    }
+
+    let ops = diff_ops(&old_lines, &new_lines);
+    // Positions (in old and new) at the start of each op.
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        positions.push((i, j));
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+
+    let changed: Vec<usize> = (0..ops.len()).filter(|&k| ops[k] != Op::Equal).collect();
+    let mut k = 0;
+    while k < changed.len() {
+        // Grow the hunk while the next change is within two contexts.
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut last = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] - last <= 2 * DIFF_CONTEXT {
+            k += 1;
+            last = changed[k];
+        }
+        let end = (last + DIFF_CONTEXT + 1).min(ops.len());
+        k += 1;
+
+        let (old_start, new_start) = positions[start];
+        let old_len = ops[start..end]
+            .iter()
+            .filter(|op| **op != Op::Insert)
+            .count();
+        let new_len = ops[start..end]
+            .iter()
+            .filter(|op| **op != Op::Delete)
+            .count();
+        let _ = writeln!(
+            out,
+            "@@ -{},{old_len} +{},{new_len} @@",
+            old_start + usize::from(old_len > 0),
+            new_start + usize::from(new_len > 0),
+        );
+        for idx in start..end {
+            let (oi, nj) = positions[idx];
+            let _ = match ops[idx] {
+                Op::Equal => writeln!(out, " {}", old_lines[oi]),
+                Op::Delete => writeln!(out, "-{}", old_lines[oi]),
+                Op::Insert => writeln!(out, "+{}", new_lines[nj]),
+            };
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn answers_map_to_responses() {
+        assert_eq!(
+            parse_answer("Y\n"),
+            Some(ToolConfirmationResponse::Approved)
+        );
+        assert_eq!(parse_answer("no"), Some(ToolConfirmationResponse::Denied));
+        assert_eq!(
+            parse_answer(" a "),
+            Some(ToolConfirmationResponse::ApproveAll)
+        );
+        assert_eq!(parse_answer("maybe"), None);
+    }
+
+    #[test]
+    fn units_are_scaled() {
+        assert_eq!(format_units("1000000000000000000", 18).unwrap(), "1");
+        assert_eq!(format_units("150000000000000000", 18).unwrap(), "0.15");
+        assert_eq!(format_units("0", 18).unwrap(), "0");
+        assert_eq!(format_units("12345", 2).unwrap(), "123.45");
+        assert!(format_units("-1", 18).is_none());
+    }
+
+    #[test]
+    fn diff_shows_hunks_with_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            unified_diff("x.txt", old, new),
+            "--- x.txt\n+++ x.txt\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+    }
+
+    #[test]
+    fn diff_of_new_file_adds_every_line() {
+        assert_eq!(
+            unified_diff("n.txt", "", "one\ntwo\n"),
+            "--- n.txt\n+++ n.txt\n@@ -0,0 +1,2 @@\n+one\n+two\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn previews_file_edits_transfers_and_other_tools() {
+        let path = std::env::temp_dir().join(format!("machi-confirm-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "hello\nworld\n").unwrap();
+        let path_str = path.to_str().unwrap();
+        let handler = TerminalConfirmationHandler::new();
+
+        let edit = ToolConfirmationRequest::new(
+            "1",
+            "edit_file",
+            json!({"path": path_str, "old_text": "world", "new_text": "there"}),
+        );
+        let preview = handler.preview(&edit).await;
+        assert!(preview.contains("-world\n+there\n"), "{preview}");
+
+        let missing = ToolConfirmationRequest::new(
+            "1",
+            "edit_file",
+            json!({"path": path_str, "old_text": "absent", "new_text": "x"}),
+        );
+        assert!(
+            handler
+                .preview(&missing)
+                .await
+                .contains("old_text not found")
+        );
+
+        let append = ToolConfirmationRequest::new(
+            "1",
+            "write_file",
+            json!({"path": path_str, "content": "again\n", "append": true}),
+        );
+        assert!(handler.preview(&append).await.contains(" world\n+again\n"));
+        std::fs::remove_file(&path).unwrap();
+
+        let transfer = ToolConfirmationRequest::new(
+            "1",
+            "transfer",
+            json!({"to": "0xabc", "amount": "250000000000000000"}),
+        );
+        assert!(
+            handler
+                .preview(&transfer)
+                .await
+                .contains("Send 0.25 ETH to 0xabc")
+        );
+
+        let other = ToolConfirmationRequest::new("1", "search", json!({"q": "rust"}));
+        assert!(handler.preview(&other).await.contains("\"q\": \"rust\""));
+    }
+}
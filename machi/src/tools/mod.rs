@@ -13,6 +13,9 @@
 //!   ([`TavilyProvider`], [`SearxngProvider`], [`BraveProvider`],
 //!   [`DuckDuckGoProvider`], [`BingProvider`])
 //!
+//! [`TerminalConfirmationHandler`] prompts on the terminal before running
+//! tools that require confirmation, previewing file changes as diffs.
+//!
 //! # Examples
 //!
 //! ```rust
@@ -31,10 +34,12 @@
 //! assert_eq!(tools.len(), 4);
 //! ```
 
+mod confirm;
 mod fs;
 mod shell;
 mod web_search;
 
+pub use confirm::TerminalConfirmationHandler;
 pub use fs::{
     EditFileArgs, EditFileTool, ListDirArgs, ListDirTool, ReadFileArgs, ReadFileTool,
     WriteFileArgs, WriteFileTool,
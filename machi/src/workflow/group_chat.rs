@@ -0,0 +1,662 @@
+//! Multi-agent group conversations.
+
+use std::fmt::{self, Write as _};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Combined, Forwarder, SharedWorkflow, Workflow, detach_session, input_text, match_name,
+    output_text, save_exchange, stream_detached,
+};
+use crate::agent::{AgentError, RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+/// Speaker name used for the opening message of a group chat.
+pub const USER_SPEAKER: &str = "user";
+
+/// One message in a group-chat transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatTurn {
+    /// Who spoke: a participant name, or [`USER_SPEAKER`] for the opening message.
+    pub speaker: String,
+    /// What they said.
+    pub content: String,
+}
+
+impl ChatTurn {
+    /// Create a transcript entry.
+    #[must_use]
+    pub fn new(speaker: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            speaker: speaker.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Why a group chat ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// A message contained the termination keyword.
+    Keyword,
+    /// The termination predicate returned `true`.
+    Predicate,
+    /// The turn limit was reached.
+    MaxTurns,
+}
+
+/// Outcome of a [`GroupChat`] run.
+#[derive(Debug)]
+pub struct GroupChatResult {
+    /// Combined result: the last message as output, the last speaker as
+    /// agent name, and the usage of every turn and speaker selection.
+    pub result: RunResult,
+    /// The full conversation, starting with the opening message.
+    pub transcript: Vec<ChatTurn>,
+    /// Why the conversation ended.
+    pub reason: TerminationReason,
+}
+
+type SelectFn = dyn Fn(&[ChatTurn], &[&str]) -> Option<String> + Send + Sync;
+type TerminateFn = dyn Fn(&[ChatTurn]) -> bool + Send + Sync;
+
+/// How the next speaker is chosen.
+#[derive(Clone)]
+enum SpeakerSelector {
+    /// Participants take turns in registration order.
+    RoundRobin,
+    /// A selector agent picks from participant names and descriptions.
+    Llm(SharedWorkflow),
+    /// A function of the transcript and participant names.
+    Function(Arc<SelectFn>),
+}
+
+/// Several agents sharing one conversation, AutoGen-style.
+///
+/// Each turn, a selector picks the next speaker — round-robin by default,
+/// an LLM choosing from participant descriptions
+/// ([`llm_selector`](Self::llm_selector)), or a custom function
+/// ([`select_with`](Self::select_with)). The speaker is run as a regular
+/// agent (its own provider, tools and hooks) with the transcript so far as
+/// input, and its reply is appended to the transcript.
+///
+/// The conversation ends when a reply contains the
+/// [`terminate_on`](Self::terminate_on) keyword, the
+/// [`terminate_when`](Self::terminate_when) predicate holds, or
+/// [`max_turns`](Self::max_turns) replies have been made.
+///
+/// Streamed runs emit [`RunEvent::TurnStarted`] and
+/// [`RunEvent::TurnCompleted`] around each speaker's forwarded events.
+///
+/// # Examples
+///
+/// ```rust
+/// use machi::agent::Agent;
+/// use machi::workflow::{GroupChat, Workflow};
+///
+/// let debate = GroupChat::new("debate")
+///     .participant(Agent::new("optimist").description("Argues for the proposal."))
+///     .participant(Agent::new("skeptic").description("Argues against the proposal."))
+///     .participant(Agent::new("moderator").description("Summarizes and ends the debate."))
+///     .llm_selector(Agent::new("host"))
+///     .terminate_on("TERMINATE")
+///     .max_turns(8);
+///
+/// assert_eq!(debate.name(), "debate");
+/// assert_eq!(debate.len(), 3);
+/// ```
+#[derive(Clone)]
+pub struct GroupChat {
+    name: String,
+    description: String,
+    participants: Vec<SharedWorkflow>,
+    selector: SpeakerSelector,
+    keyword: Option<String>,
+    predicate: Option<Arc<TerminateFn>>,
+    max_turns: usize,
+}
+
+impl fmt::Debug for GroupChat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let selector = match &self.selector {
+            SpeakerSelector::RoundRobin => "round_robin".to_owned(),
+            SpeakerSelector::Llm(w) => w.name().to_owned(),
+            SpeakerSelector::Function(_) => "<function>".to_owned(),
+        };
+        f.debug_struct("GroupChat")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("participants", &self.participant_names())
+            .field("selector", &selector)
+            .field("keyword", &self.keyword)
+            .field("predicate", &self.predicate.is_some())
+            .field("max_turns", &self.max_turns)
+            .finish()
+    }
+}
+
+impl GroupChat {
+    /// Default turn limit.
+    pub const DEFAULT_MAX_TURNS: usize = 10;
+
+    /// Create a group chat with no participants.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            participants: Vec::new(),
+            selector: SpeakerSelector::RoundRobin,
+            keyword: None,
+            predicate: None,
+            max_turns: Self::DEFAULT_MAX_TURNS,
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Add a participant.
+    #[must_use]
+    pub fn participant(mut self, participant: impl Workflow + 'static) -> Self {
+        self.participants.push(Arc::new(participant));
+        self
+    }
+
+    /// Add an already shared participant.
+    #[must_use]
+    pub fn shared_participant(mut self, participant: SharedWorkflow) -> Self {
+        self.participants.push(participant);
+        self
+    }
+
+    /// Let participants speak in registration order (the default).
+    #[must_use]
+    pub fn round_robin(mut self) -> Self {
+        self.selector = SpeakerSelector::RoundRobin;
+        self
+    }
+
+    /// Let a selector agent choose the next speaker.
+    ///
+    /// The selector sees each participant's name and description plus the
+    /// transcript, and must reply with a participant name.
+    #[must_use]
+    pub fn llm_selector(mut self, selector: impl Workflow + 'static) -> Self {
+        self.selector = SpeakerSelector::Llm(Arc::new(selector));
+        self
+    }
+
+    /// Choose the next speaker with a function of the transcript and the
+    /// participant names. Returning `None` falls back to round-robin.
+    #[must_use]
+    pub fn select_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&[ChatTurn], &[&str]) -> Option<String> + Send + Sync + 'static,
+    {
+        self.selector = SpeakerSelector::Function(Arc::new(f));
+        self
+    }
+
+    /// End the conversation when a reply contains `keyword`.
+    #[must_use]
+    pub fn terminate_on(mut self, keyword: impl Into<String>) -> Self {
+        self.keyword = Some(keyword.into());
+        self
+    }
+
+    /// End the conversation when `predicate(transcript)` returns `true`.
+    #[must_use]
+    pub fn terminate_when<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&[ChatTurn]) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Set the maximum number of participant replies.
+    #[must_use]
+    pub const fn max_turns(mut self, max: usize) -> Self {
+        self.max_turns = max;
+        self
+    }
+
+    /// Returns the number of participants.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.participants.len()
+    }
+
+    /// Returns `true` if the chat has no participants.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.participants.is_empty()
+    }
+
+    /// Run the conversation, returning the transcript alongside the result.
+    ///
+    /// A session in `config` receives only the opening message and the
+    /// final output; participant turns and speaker selection stay out of it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there are no participants, a selector names an unknown
+    /// participant, or a participant run fails.
+    pub async fn chat(
+        &self,
+        input: impl Into<UserInput>,
+        config: RunConfig,
+    ) -> Result<GroupChatResult> {
+        let input = input.into();
+        let (config, session) = detach_session(config);
+        let done = self.chat_detached(&input, &config).await?;
+        save_exchange(session.as_ref(), input, &done.result.output).await;
+        Ok(done)
+    }
+
+    async fn chat_detached(
+        &self,
+        input: &UserInput,
+        config: &RunConfig,
+    ) -> Result<GroupChatResult> {
+        let mut state = self.start(input)?;
+        loop {
+            let speaker = self.next_speaker(&mut state, config).await?;
+            let participant = &self.participants[speaker];
+            let result = participant
+                .run(
+                    self.turn_input(participant.as_ref(), &state.transcript),
+                    config.clone(),
+                )
+                .await?;
+            if let Some(done) = self.record(&mut state, speaker, result) {
+                return Ok(done);
+            }
+        }
+    }
+
+    fn participant_names(&self) -> Vec<&str> {
+        self.participants.iter().map(|p| p.name()).collect()
+    }
+
+    fn start(&self, input: &UserInput) -> Result<ChatState> {
+        if self.participants.is_empty() {
+            return Err(AgentError::runtime(format!(
+                "group chat '{}' has no participants",
+                self.name
+            ))
+            .into());
+        }
+        Ok(ChatState {
+            transcript: vec![ChatTurn::new(USER_SPEAKER, input_text(input))],
+            combined: Combined::default(),
+            turns: 0,
+            last_speaker: None,
+        })
+    }
+
+    /// Index of the participant who speaks next.
+    async fn next_speaker(&self, state: &mut ChatState, config: &RunConfig) -> Result<usize> {
+        let round_robin = state
+            .last_speaker
+            .map_or(0, |last| (last + 1) % self.participants.len());
+        let choice = match &self.selector {
+            SpeakerSelector::RoundRobin => return Ok(round_robin),
+            SpeakerSelector::Function(f) => match f(&state.transcript, &self.participant_names()) {
+                Some(choice) => choice,
+                None => return Ok(round_robin),
+            },
+            SpeakerSelector::Llm(selector) => {
+                let result = selector
+                    .run(self.selector_input(&state.transcript), config.clone())
+                    .await?;
+                output_text(&state.combined.absorb(result).0)
+            }
+        };
+        match_name(&choice, &self.participant_names()).ok_or_else(|| {
+            AgentError::runtime(format!(
+                "group chat '{}': no participant matches {choice:?}",
+                self.name
+            ))
+            .into()
+        })
+    }
+
+    /// Append a reply; `Some` once the conversation is over.
+    fn record(
+        &self,
+        state: &mut ChatState,
+        speaker: usize,
+        result: RunResult,
+    ) -> Option<GroupChatResult> {
+        let (output, agent_name) = state.combined.absorb(result);
+        let content = output_text(&output);
+        state.turns += 1;
+        state.last_speaker = Some(speaker);
+        state
+            .transcript
+            .push(ChatTurn::new(self.participants[speaker].name(), &content));
+
+        let reason = if self
+            .keyword
+            .as_ref()
+            .is_some_and(|k| content.contains(k.as_str()))
+        {
+            TerminationReason::Keyword
+        } else if self
+            .predicate
+            .as_ref()
+            .is_some_and(|p| p(&state.transcript))
+        {
+            TerminationReason::Predicate
+        } else if state.turns >= self.max_turns {
+            TerminationReason::MaxTurns
+        } else {
+            return None;
+        };
+
+        let state = std::mem::take(state);
+        Some(GroupChatResult {
+            result: state.combined.finish(output, agent_name),
+            transcript: state.transcript,
+            reason,
+        })
+    }
+
+    /// Input for a participant's turn.
+    fn turn_input(&self, participant: &dyn Workflow, transcript: &[ChatTurn]) -> UserInput {
+        UserInput::text(format!(
+            "You are {}, taking part in the group conversation \"{}\".\n\n\
+             Conversation so far:\n{}\n\
+             Reply with your next message only.",
+            participant.name(),
+            self.name,
+            render_transcript(transcript)
+        ))
+    }
+
+    /// Input for the LLM speaker selector.
+    fn selector_input(&self, transcript: &[ChatTurn]) -> UserInput {
+        let mut prompt = String::from("Participants:\n");
+        for p in &self.participants {
+            let _ = writeln!(prompt, "- {}: {}", p.name(), p.description());
+        }
+        let _ = write!(
+            prompt,
+            "\nConversation so far:\n{}\n\
+             Who should speak next? Reply with the participant name only.",
+            render_transcript(transcript)
+        );
+        UserInput::text(prompt)
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            let mut state = self.start(&input)?;
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            let done = loop {
+                let speaker = self.next_speaker(&mut state, &config).await?;
+                let participant = &self.participants[speaker];
+                let turn = state.turns + 1;
+                yield RunEvent::TurnStarted {
+                    speaker: participant.name().to_owned(),
+                    turn,
+                };
+
+                let turn_input = self.turn_input(participant.as_ref(), &state.transcript);
+                let mut inner = Forwarder::new(participant.run_streamed(turn_input, config.clone()));
+                while let Some(event) = inner.next().await? {
+                    yield event;
+                }
+                let result = inner.finish(&self.name)?;
+                let outcome = self.record(&mut state, speaker, result);
+
+                let content = outcome
+                    .as_ref()
+                    .map_or(&state.transcript, |done| &done.transcript)
+                    .last()
+                    .map(|t| t.content.clone())
+                    .unwrap_or_default();
+                yield RunEvent::TurnCompleted {
+                    speaker: participant.name().to_owned(),
+                    turn,
+                    content,
+                };
+
+                if let Some(done) = outcome {
+                    break done;
+                }
+            };
+
+            yield RunEvent::RunCompleted { result: Box::new(done.result) };
+        }
+    }
+}
+
+/// Mutable conversation state shared by the blocking and streaming paths.
+#[derive(Default)]
+struct ChatState {
+    transcript: Vec<ChatTurn>,
+    combined: Combined,
+    turns: usize,
+    last_speaker: Option<usize>,
+}
+
+/// Render a transcript as `[speaker]: content` lines.
+fn render_transcript(transcript: &[ChatTurn]) -> String {
+    let mut out = String::new();
+    for turn in transcript {
+        let _ = writeln!(out, "[{}]: {}", turn.speaker, turn.content);
+    }
+    out
+}
+
+impl Workflow for GroupChat {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(async move { Ok(self.chat(input, config).await?.result) })
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::collect;
+    use super::*;
+    use crate::agent::Agent;
+    use crate::test_util::ScriptedProvider;
+
+    /// Participant that replies with its name and the number of prior turns.
+    fn speaker(name: &'static str) -> Agent {
+        Agent::new(name)
+            .description(format!("{name} speaks"))
+            .provider(ScriptedProvider::reply(move |input| {
+                let prior = input.matches("\n[").count().saturating_sub(1);
+                format!("{name}#{prior}")
+            }))
+    }
+
+    fn speakers(transcript: &[ChatTurn]) -> Vec<&str> {
+        transcript.iter().map(|t| t.speaker.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn round_robin_until_max_turns() {
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(speaker("b"))
+            .max_turns(3);
+        let outcome = chat.chat("topic", RunConfig::default()).await.unwrap();
+        assert_eq!(speakers(&outcome.transcript), ["user", "a", "b", "a"]);
+        assert_eq!(outcome.transcript[3].content, "a#2");
+        assert_eq!(outcome.reason, TerminationReason::MaxTurns);
+        assert_eq!(outcome.result.agent_name, "a");
+        assert_eq!(outcome.result.usage.total_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn speakers_see_the_shared_transcript() {
+        let listener = Agent::new("listener").provider(ScriptedProvider::reply(|input| {
+            assert!(input.starts_with("You are listener"));
+            assert!(input.contains("[user]: topic\n[a]: a#0\n"));
+            "heard".to_owned()
+        }));
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(listener)
+            .max_turns(2);
+        let outcome = chat.chat("topic", RunConfig::default()).await.unwrap();
+        assert_eq!(outcome.result.text(), Some("heard"));
+    }
+
+    #[tokio::test]
+    async fn keyword_and_predicate_terminate() {
+        let closer = Agent::new("closer")
+            .provider(ScriptedProvider::reply(|_| "done. TERMINATE".to_owned()));
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(closer)
+            .terminate_on("TERMINATE");
+        let outcome = chat.chat("x", RunConfig::default()).await.unwrap();
+        assert_eq!(outcome.reason, TerminationReason::Keyword);
+        assert_eq!(outcome.transcript.len(), 3);
+
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .terminate_when(|t| t.len() > 2);
+        let outcome = chat.chat("x", RunConfig::default()).await.unwrap();
+        assert_eq!(outcome.reason, TerminationReason::Predicate);
+        assert_eq!(outcome.transcript.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn function_selector_picks_speaker() {
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(speaker("b"))
+            .select_with(|_, names| {
+                assert_eq!(names, ["a", "b"]);
+                Some("b".to_owned())
+            })
+            .max_turns(2);
+        let outcome = chat.chat("x", RunConfig::default()).await.unwrap();
+        assert_eq!(speakers(&outcome.transcript), ["user", "b", "b"]);
+    }
+
+    #[tokio::test]
+    async fn llm_selector_uses_descriptions() {
+        let host = Agent::new("host").provider(ScriptedProvider::reply(|prompt| {
+            assert!(prompt.contains("- b: b speaks"));
+            "B".to_owned()
+        }));
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(speaker("b"))
+            .llm_selector(host)
+            .max_turns(1);
+        let outcome = chat.chat("x", RunConfig::default()).await.unwrap();
+        assert_eq!(speakers(&outcome.transcript), ["user", "b"]);
+        // Selector usage counts towards the total.
+        assert_eq!(outcome.result.usage.total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn session_gets_only_the_opening_and_final_output() {
+        use crate::memory::{InMemorySession, Session};
+
+        let host = Agent::new("host").provider(ScriptedProvider::reply(|prompt| {
+            if prompt.contains("[a]:") { "b" } else { "a" }.to_owned()
+        }));
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(speaker("b"))
+            .llm_selector(host)
+            .max_turns(2);
+        let session = Arc::new(InMemorySession::new("s"));
+        let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+        chat.chat("x", config).await.unwrap();
+
+        let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+        collect(chat.run_streamed("x".into(), config)).await;
+
+        let texts: Vec<String> = session
+            .get_messages(None)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(crate::message::Message::text)
+            .collect();
+        assert_eq!(texts, ["x", "b#1", "x", "b#1"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_speaker_is_an_error() {
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .select_with(|_, _| Some("z".to_owned()));
+        let err = chat.chat("x", RunConfig::default()).await.unwrap_err();
+        assert!(err.to_string().contains("no participant matches"));
+    }
+
+    #[tokio::test]
+    async fn streamed_turns_are_tagged_by_speaker() {
+        let chat = GroupChat::new("chat")
+            .participant(speaker("a"))
+            .participant(speaker("b"))
+            .max_turns(2);
+        let (events, result) = collect(chat.run_streamed("x".into(), RunConfig::default())).await;
+        let turns: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                RunEvent::TurnCompleted {
+                    speaker,
+                    turn,
+                    content,
+                } => Some((speaker.as_str(), *turn, content.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(turns, [("a", 1, "a#0"), ("b", 2, "b#1")]);
+        assert!(matches!(
+            &events[1],
+            RunEvent::TurnStarted { speaker, turn: 1 } if speaker == "a"
+        ));
+        assert_eq!(result.text(), Some("b#1"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Repetition until a condition holds.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+use serde_json::Value;
+
+use super::{
+    Combined, Forwarder, SharedWorkflow, Workflow, input_text, output_text, run_detached,
+    stream_detached,
+};
+use crate::agent::{RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+type PredicateFn = dyn Fn(&RunResult, usize) -> bool + Send + Sync;
+
+/// When the loop stops.
+#[derive(Clone)]
+enum StopCondition {
+    /// Only the iteration limit.
+    Never,
+    /// A predicate on the latest result and 1-based iteration number.
+    Predicate(Arc<PredicateFn>),
+    /// An evaluator workflow that replies `APPROVED` or gives feedback.
+    Evaluator(SharedWorkflow),
+}
+
+/// Outcome of checking the stop condition after one iteration.
+enum Verdict {
+    Stop,
+    Continue { feedback: Option<String> },
+}
+
+/// Repeats a body workflow until a condition holds or the iteration limit is hit.
+///
+/// - With a **predicate** ([`until`](Self::until)), each iteration receives the
+///   previous iteration's output as input — useful for iterative refinement.
+/// - With an **evaluator** ([`until_approved_by`](Self::until_approved_by)),
+///   the evaluator sees the original task and the latest output. A reply
+///   starting with `APPROVED` ends the loop; anything else is treated as
+///   feedback, and the next iteration receives the task, the previous attempt
+///   and that feedback.
+///
+/// When the limit is reached the last result is returned as-is.
+#[derive(Clone)]
+pub struct LoopWorkflow {
+    name: String,
+    description: String,
+    body: SharedWorkflow,
+    max_iterations: usize,
+    stop: StopCondition,
+}
+
+impl fmt::Debug for LoopWorkflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stop = match &self.stop {
+            StopCondition::Never => None,
+            StopCondition::Predicate(_) => Some("<predicate>".to_owned()),
+            StopCondition::Evaluator(w) => Some(w.name().to_owned()),
+        };
+        f.debug_struct("LoopWorkflow")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("body", &self.body.name())
+            .field("max_iterations", &self.max_iterations)
+            .field("stop", &stop)
+            .finish()
+    }
+}
+
+impl LoopWorkflow {
+    /// Default iteration limit.
+    pub const DEFAULT_MAX_ITERATIONS: usize = 5;
+
+    /// Create a loop around `body`.
+    #[must_use]
+    pub fn new(name: impl Into<String>, body: impl Workflow + 'static) -> Self {
+        Self::shared(name, Arc::new(body))
+    }
+
+    /// Create a loop around an already shared body.
+    #[must_use]
+    pub fn shared(name: impl Into<String>, body: SharedWorkflow) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            body,
+            max_iterations: Self::DEFAULT_MAX_ITERATIONS,
+            stop: StopCondition::Never,
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the iteration limit (at least one iteration always runs).
+    #[must_use]
+    pub const fn max_iterations(mut self, max: usize) -> Self {
+        self.max_iterations = max;
+        self
+    }
+
+    /// Stop once `predicate(result, iteration)` returns `true`.
+    #[must_use]
+    pub fn until<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&RunResult, usize) -> bool + Send + Sync + 'static,
+    {
+        self.stop = StopCondition::Predicate(Arc::new(predicate));
+        self
+    }
+
+    /// Stop once `evaluator` replies `APPROVED`.
+    #[must_use]
+    pub fn until_approved_by(mut self, evaluator: impl Workflow + 'static) -> Self {
+        self.stop = StopCondition::Evaluator(Arc::new(evaluator));
+        self
+    }
+
+    /// Check the stop condition, folding any evaluator run into `combined`.
+    async fn check(
+        &self,
+        task: &str,
+        result: &RunResult,
+        iteration: usize,
+        config: &RunConfig,
+        combined: &mut Combined,
+    ) -> Result<Verdict> {
+        match &self.stop {
+            StopCondition::Never => Ok(Verdict::Continue { feedback: None }),
+            StopCondition::Predicate(p) => Ok(if p(result, iteration) {
+                Verdict::Stop
+            } else {
+                Verdict::Continue { feedback: None }
+            }),
+            StopCondition::Evaluator(evaluator) => {
+                let prompt = format!(
+                    "Evaluate whether the output below fully accomplishes the task. \
+                     Reply with APPROVED if it does; otherwise explain what must change.\n\n\
+                     Task:\n{task}\n\nOutput:\n{}",
+                    output_text(&result.output)
+                );
+                let review = evaluator
+                    .run(UserInput::text(prompt), config.clone())
+                    .await?;
+                let (verdict, _) = combined.absorb(review);
+                let verdict = output_text(&verdict);
+                Ok(if is_approval(&verdict) {
+                    Verdict::Stop
+                } else {
+                    Verdict::Continue {
+                        feedback: Some(verdict),
+                    }
+                })
+            }
+        }
+    }
+
+    /// Input for the next iteration.
+    fn next_input(task: &str, output: &Value, feedback: Option<String>) -> UserInput {
+        let output = output_text(output);
+        UserInput::text(feedback.map_or_else(
+            || output.clone(),
+            |feedback| format!("{task}\n\nPrevious attempt:\n{output}\n\nFeedback:\n{feedback}"),
+        ))
+    }
+
+    async fn run_inner(&self, input: UserInput, config: RunConfig) -> Result<RunResult> {
+        let task = input_text(&input);
+        let mut combined = Combined::default();
+        let mut input = input;
+        let mut iteration = 0;
+        loop {
+            iteration += 1;
+            let result = self.body.run(input, config.clone()).await?;
+            let verdict = self
+                .check(&task, &result, iteration, &config, &mut combined)
+                .await?;
+            let (output, agent_name) = combined.absorb(result);
+            match verdict {
+                Verdict::Continue { feedback } if iteration < self.max_iterations => {
+                    input = Self::next_input(&task, &output, feedback);
+                }
+                _ => return Ok(combined.finish(output, agent_name)),
+            }
+        }
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            let task = input_text(&input);
+            let mut combined = Combined::default();
+            let mut input = input;
+            let mut iteration = 0;
+            let result = loop {
+                iteration += 1;
+                let mut inner = Forwarder::new(self.body.run_streamed(input, config.clone()));
+                while let Some(event) = inner.next().await? {
+                    yield event;
+                }
+                let result = inner.finish(&self.name)?;
+                let verdict = self
+                    .check(&task, &result, iteration, &config, &mut combined)
+                    .await?;
+                let (output, agent_name) = combined.absorb(result);
+                match verdict {
+                    Verdict::Continue { feedback } if iteration < self.max_iterations => {
+                        input = Self::next_input(&task, &output, feedback);
+                    }
+                    _ => break combined.finish(output, agent_name),
+                }
+            };
+
+            yield RunEvent::RunCompleted { result: Box::new(result) };
+        }
+    }
+}
+
+/// Returns `true` if an evaluator reply approves the output.
+fn is_approval(reply: &str) -> bool {
+    reply
+        .trim_start()
+        .get(..8)
+        .is_some_and(|head| head.eq_ignore_ascii_case("approved"))
+}
+
+impl Workflow for LoopWorkflow {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(run_detached(input, config, async |input, config| {
+            self.run_inner(input, config).await
+        }))
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::{collect, echo_agent, started};
+    use super::*;
+    use crate::agent::Agent;
+    use crate::test_util::ScriptedProvider;
+
+    #[test]
+    fn approval_is_case_insensitive_prefix() {
+        assert!(is_approval("APPROVED"));
+        assert!(is_approval("  approved, looks good"));
+        assert!(!is_approval("Not approved"));
+        assert!(!is_approval("ok"));
+    }
+
+    #[tokio::test]
+    async fn runs_until_iteration_limit_without_condition() {
+        let workflow = LoopWorkflow::new("loop", echo_agent("a")).max_iterations(3);
+        let result = workflow
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("a(a(a(x)))"));
+        assert_eq!(result.steps, 3);
+    }
+
+    #[tokio::test]
+    async fn predicate_stops_the_loop() {
+        let workflow = LoopWorkflow::new("loop", echo_agent("a"))
+            .max_iterations(10)
+            .until(|_, iteration| iteration == 2);
+        let result = workflow
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("a(a(x))"));
+        assert_eq!(result.usage.total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn evaluator_feedback_is_passed_to_next_iteration() {
+        let writer = Agent::new("writer").provider(ScriptedProvider::reply(|input| {
+            if input.contains("Feedback:\nadd detail") {
+                "detailed draft".to_owned()
+            } else {
+                "draft".to_owned()
+            }
+        }));
+        let reviewer = Agent::new("reviewer").provider(ScriptedProvider::reply(|prompt| {
+            if prompt.ends_with("Output:\ndetailed draft") {
+                "APPROVED".to_owned()
+            } else {
+                "add detail".to_owned()
+            }
+        }));
+        let workflow = LoopWorkflow::new("refine", writer).until_approved_by(reviewer);
+
+        let result = workflow
+            .run("write".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("detailed draft"));
+        assert_eq!(result.agent_name, "writer");
+        // Two writer runs and two reviews.
+        assert_eq!(result.usage.total_tokens, 60);
+    }
+
+    #[tokio::test]
+    async fn streamed_run_forwards_each_iteration() {
+        let workflow = LoopWorkflow::new("loop", echo_agent("a")).max_iterations(2);
+        let (events, result) =
+            collect(workflow.run_streamed("x".into(), RunConfig::default())).await;
+        assert_eq!(started(&events), ["loop", "a", "a"]);
+        assert_eq!(result.text(), Some("a(a(x))"));
+    }
+}
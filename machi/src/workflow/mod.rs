@@ -0,0 +1,513 @@
+//! Deterministic workflow composition on top of agents.
+//!
+//! Where the [`Runner`] lets the LLM decide which tools
+//! and managed agents to call, workflows fix the control flow in code:
+//!
+//! - **[`SequentialWorkflow`]** — the output of each step becomes the input of the next
+//! - **[`ParallelWorkflow`]** — fan out to several branches, then merge the results
+//! - **[`RouterWorkflow`]** — a function or classifier agent picks one route
+//! - **[`LoopWorkflow`]** — repeat a step until a predicate or evaluator approves
+//! - **[`EvaluatorOptimizer`]** — re-run a generator with structured evaluator feedback
+//! - **[`GroupChat`]** — several agents share one conversation, with speaker selection
+//!
+//! Every workflow — and every [`Agent`] — implements the [`Workflow`] trait,
+//! so workflows nest freely, run through the same `run` / `run_streamed`
+//! interface, produce a [`RunResult`] with combined usage, and can be handed to
+//! a parent agent via [`Agent::managed_workflow`].
+//!
+//! A session in the [`RunConfig`] belongs to the workflow as a whole: inner
+//! runs get neither the session nor auto-recall, and the workflow records
+//! just its input and final output as one exchange.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use machi::agent::Agent;
+//! use machi::workflow::{ParallelWorkflow, SequentialWorkflow, Workflow};
+//!
+//! let research = ParallelWorkflow::new("research")
+//!     .branch(Agent::new("web").instructions("Search the web."))
+//!     .branch(Agent::new("papers").instructions("Search academic papers."))
+//!     .aggregator(Agent::new("synthesizer").instructions("Merge the findings."));
+//!
+//! let pipeline = SequentialWorkflow::new("report")
+//!     .description("Researches a topic and writes a report.")
+//!     .step(research)
+//!     .step(Agent::new("writer").instructions("Write a concise report."));
+//!
+//! assert_eq!(pipeline.name(), "report");
+//! assert_eq!(pipeline.len(), 2);
+//! ```
+
+mod group_chat;
+mod looping;
+mod parallel;
+mod reflection;
+mod router;
+mod sequential;
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::agent::{Agent, AgentError, RunConfig, RunEvent, RunResult, Runner, UserInput};
+use crate::error::Result;
+use crate::memory::SharedSession;
+use crate::message::{ContentPart, Message};
+use crate::tool::ToolDefinition;
+use crate::usage::Usage;
+
+pub use group_chat::{ChatTurn, GroupChat, GroupChatResult, TerminationReason, USER_SPEAKER};
+pub use looping::LoopWorkflow;
+pub use parallel::{BranchOutput, ParallelWorkflow};
+pub use reflection::{Evaluation, EvaluatorOptimizer, Iteration, OptimizationResult};
+pub use router::RouterWorkflow;
+pub use sequential::SequentialWorkflow;
+
+/// A unit of work that turns a [`UserInput`] into a [`RunResult`].
+///
+/// Implemented by [`Agent`] and by all workflow types in this module, which
+/// accept any `Workflow` as a step, branch, route or loop body.
+pub trait Workflow: Send + Sync {
+    /// Unique name of this workflow (the tool name when managed).
+    fn name(&self) -> &str;
+
+    /// Human-readable description (the tool description when managed).
+    fn description(&self) -> &str;
+
+    /// Execute the workflow to completion.
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>>;
+
+    /// Execute the workflow, streaming [`RunEvent`]s.
+    ///
+    /// Events from inner agents are forwarded as they happen — their
+    /// `RunStarted` events identify which agent is active — and a single
+    /// `RunCompleted` carrying the combined result ends the stream.
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>>;
+
+    /// Build a [`ToolDefinition`] for this workflow when used as a managed agent.
+    fn tool_definition(&self) -> ToolDefinition {
+        ToolDefinition::new(self.name(), self.description(), task_parameters())
+    }
+}
+
+/// A shared, type-erased [`Workflow`].
+pub type SharedWorkflow = Arc<dyn Workflow>;
+
+impl Workflow for Agent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Runner::run(self, input, config)
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        Runner::run_streamed(self, input, config)
+    }
+
+    fn tool_definition(&self) -> ToolDefinition {
+        Self::tool_definition(self)
+    }
+}
+
+/// JSON Schema of the single `task` parameter exposed by managed agents.
+pub(crate) fn task_parameters() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "task": {
+                "type": "string",
+                "description": "The task to delegate to this agent."
+            }
+        },
+        "required": ["task"],
+        "additionalProperties": false
+    })
+}
+
+/// Render a run output as text: strings verbatim, anything else as JSON.
+pub(crate) fn output_text(output: &Value) -> String {
+    match output {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Extract the textual part of a user input, joining multimodal text parts.
+pub(crate) fn input_text(input: &UserInput) -> String {
+    match input {
+        UserInput::Text(text) => text.clone(),
+        UserInput::Parts(parts) => parts
+            .iter()
+            .filter_map(ContentPart::as_text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Find which of `names` an LLM reply refers to.
+///
+/// Matches case-insensitively after trimming quotes and punctuation, then
+/// falls back to the single name that appears in the reply.
+pub(crate) fn match_name(reply: &str, names: &[&str]) -> Option<usize> {
+    let cleaned = reply
+        .trim()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c == '`' || c == '.')
+        .trim();
+    if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(cleaned)) {
+        return Some(i);
+    }
+    let lower = cleaned.to_lowercase();
+    let mut mentioned = names
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| lower.contains(&n.to_lowercase()));
+    match (mentioned.next(), mentioned.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    }
+}
+
+/// Takes the session and auto-recall out of a workflow's config.
+///
+/// Inner runs must not read or write the caller's session, or every step,
+/// branch and classifier prompt would land in it. The workflow records a
+/// single exchange itself with [`save_exchange`].
+pub(crate) fn detach_session(mut config: RunConfig) -> (RunConfig, Option<SharedSession>) {
+    config.auto_recall = None;
+    let session = config.session.take();
+    (config, session)
+}
+
+/// Appends a workflow's input and final output to `session`.
+pub(crate) async fn save_exchange(
+    session: Option<&SharedSession>,
+    input: UserInput,
+    output: &Value,
+) {
+    let Some(session) = session else {
+        return;
+    };
+    let exchange = [
+        input.into_message(),
+        Message::assistant(output_text(output)),
+    ];
+    if let Err(e) = session.add_messages(&exchange).await {
+        warn!(session = session.id(), error = %e, "Failed to save workflow exchange");
+    }
+}
+
+/// Runs `run` on a config without the session, then saves one exchange.
+pub(crate) async fn run_detached<F>(
+    input: UserInput,
+    config: RunConfig,
+    run: F,
+) -> Result<RunResult>
+where
+    F: AsyncFnOnce(UserInput, RunConfig) -> Result<RunResult>,
+{
+    let (config, session) = detach_session(config);
+    let result = run(input.clone(), config).await?;
+    save_exchange(session.as_ref(), input, &result.output).await;
+    Ok(result)
+}
+
+/// Streaming counterpart of [`run_detached`]: the exchange is saved just
+/// before the final `RunCompleted` is forwarded.
+// `tail_expr_drop_order`: false positive from the `stream!` macro.
+#[allow(tail_expr_drop_order)]
+pub(crate) fn stream_detached<'a>(
+    input: UserInput,
+    config: RunConfig,
+    run: impl FnOnce(UserInput, RunConfig) -> EventStream<'a>,
+) -> EventStream<'a> {
+    let (config, session) = detach_session(config);
+    let Some(session) = session else {
+        return run(input, config);
+    };
+    let mut inner = run(input.clone(), config);
+    Box::pin(async_stream::stream! {
+        while let Some(event) = inner.next().await {
+            if let Ok(RunEvent::RunCompleted { result }) = &event {
+                save_exchange(Some(&session), input.clone(), &result.output).await;
+            }
+            yield event;
+        }
+    })
+}
+
+/// Accumulated bookkeeping for a workflow that runs several inner results.
+#[derive(Debug, Default)]
+pub(crate) struct Combined {
+    usage: Usage,
+    steps: usize,
+    step_history: Vec<crate::agent::StepInfo>,
+    input_guardrail_results: Vec<crate::guardrail::InputGuardrailResult>,
+    output_guardrail_results: Vec<crate::guardrail::OutputGuardrailResult>,
+}
+
+impl Combined {
+    /// Fold an inner result into the totals, returning its output and agent name.
+    pub(crate) fn absorb(&mut self, result: RunResult) -> (Value, String) {
+        self.usage += result.usage;
+        self.steps += result.steps;
+        self.step_history.extend(result.step_history);
+        self.input_guardrail_results
+            .extend(result.input_guardrail_results);
+        self.output_guardrail_results
+            .extend(result.output_guardrail_results);
+        (result.output, result.agent_name)
+    }
+
+    /// Produce the combined result with the given final output.
+    pub(crate) fn finish(self, output: Value, agent_name: String) -> RunResult {
+        RunResult {
+            output,
+            usage: self.usage,
+            steps: self.steps,
+            step_history: self.step_history,
+            agent_name,
+            input_guardrail_results: self.input_guardrail_results,
+            output_guardrail_results: self.output_guardrail_results,
+        }
+    }
+}
+
+/// Boxed stream of run events, as returned by [`Workflow::run_streamed`].
+pub(crate) type EventStream<'a> = Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>>;
+
+/// Forwards an inner run's events while capturing its final result.
+///
+/// Everything except the inner `RunCompleted` is handed back to the caller
+/// for re-emission; the captured result is returned by [`finish`](Self::finish).
+pub(crate) struct Forwarder<'a> {
+    inner: EventStream<'a>,
+    completed: Option<RunResult>,
+}
+
+impl<'a> Forwarder<'a> {
+    pub(crate) fn new(inner: EventStream<'a>) -> Self {
+        Self {
+            inner,
+            completed: None,
+        }
+    }
+
+    /// Next event to forward, or `None` once the inner run has ended.
+    pub(crate) async fn next(&mut self) -> Result<Option<RunEvent>> {
+        while let Some(event) = self.inner.next().await {
+            match event? {
+                RunEvent::RunCompleted { result } => self.completed = Some(*result),
+                other => return Ok(Some(other)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The inner run's result; an error if it never completed.
+    pub(crate) fn finish(self, owner: &str) -> Result<RunResult> {
+        self.completed.ok_or_else(|| {
+            AgentError::runtime(format!(
+                "workflow '{owner}': inner run ended without a result"
+            ))
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScriptedProvider;
+
+    /// Agent that answers with `"{name}({input})"`.
+    pub(super) fn echo_agent(name: &str) -> Agent {
+        let tag = name.to_owned();
+        Agent::new(name)
+            .description(format!("The {name} agent"))
+            .provider(ScriptedProvider::reply(move |input| {
+                format!("{tag}({input})")
+            }))
+    }
+
+    /// Collect a streamed run into its events and final result.
+    pub(super) async fn collect(
+        stream: Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + '_>>,
+    ) -> (Vec<RunEvent>, RunResult) {
+        let mut events: Vec<RunEvent> = stream.map(Result::unwrap).collect().await;
+        match events.pop() {
+            Some(RunEvent::RunCompleted { result }) => (events, *result),
+            other => panic!("expected RunCompleted, got {other:?}"),
+        }
+    }
+
+    /// Names of the agents started in a streamed run, in order.
+    pub(super) fn started(events: &[RunEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                RunEvent::RunStarted { agent_name } => Some(agent_name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    mod helpers {
+        use super::*;
+
+        #[test]
+        fn output_text_renders_strings_verbatim() {
+            assert_eq!(output_text(&Value::from("hi")), "hi");
+            assert_eq!(output_text(&serde_json::json!({"a": 1})), r#"{"a":1}"#);
+        }
+
+        #[test]
+        fn match_name_is_lenient_but_unambiguous() {
+            let names = ["billing", "support"];
+            assert_eq!(match_name(" `Support`. ", &names), Some(1));
+            assert_eq!(match_name("I'd pick billing", &names), Some(0));
+            assert_eq!(match_name("billing or support", &names), None);
+            assert_eq!(match_name("sales", &names), None);
+        }
+
+        #[test]
+        fn input_text_joins_text_parts() {
+            let input = UserInput::parts(vec![
+                ContentPart::text("a"),
+                ContentPart::image_url("https://example.com/x.png"),
+                ContentPart::text("b"),
+            ]);
+            assert_eq!(input_text(&input), "a\nb");
+        }
+
+        #[test]
+        fn agent_tool_definition_matches_trait() {
+            let agent = echo_agent("helper");
+            let via_trait = Workflow::tool_definition(&agent);
+            let inherent = Agent::tool_definition(&agent);
+            assert_eq!(via_trait.name(), inherent.name());
+            assert_eq!(via_trait.description(), inherent.description());
+            assert_eq!(via_trait.parameters, inherent.parameters);
+        }
+    }
+
+    mod session {
+        use super::*;
+        use crate::memory::{InMemorySession, Session};
+
+        /// One workflow of each kind, all with internal runs besides the output.
+        fn workflows() -> Vec<Box<dyn Workflow>> {
+            let approve = Agent::new("evaluator")
+                .provider(ScriptedProvider::reply(|_| "APPROVED".to_owned()));
+            let classifier =
+                Agent::new("classifier").provider(ScriptedProvider::reply(|_| "b".to_owned()));
+            vec![
+                Box::new(
+                    SequentialWorkflow::new("seq")
+                        .step(echo_agent("a"))
+                        .step(echo_agent("b")),
+                ),
+                Box::new(
+                    ParallelWorkflow::new("par")
+                        .branch(echo_agent("a"))
+                        .branch(echo_agent("b")),
+                ),
+                Box::new(
+                    RouterWorkflow::new("router")
+                        .route(echo_agent("a"))
+                        .route(echo_agent("b"))
+                        .classifier(classifier),
+                ),
+                Box::new(LoopWorkflow::new("loop", echo_agent("a")).until_approved_by(approve)),
+            ]
+        }
+
+        async fn assert_one_exchange(session: &InMemorySession, result: &RunResult) {
+            let stored = session.get_messages(None).await.unwrap();
+            assert_eq!(stored.len(), 2, "{stored:?}");
+            assert_eq!(stored[0].text().as_deref(), Some("x"));
+            assert_eq!(stored[1].text(), Some(output_text(&result.output)));
+        }
+
+        #[tokio::test]
+        async fn workflows_save_one_exchange() {
+            for workflow in workflows() {
+                let session = Arc::new(InMemorySession::new("s"));
+                let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+                let result = workflow.run("x".into(), config).await.unwrap();
+                assert_one_exchange(&session, &result).await;
+
+                let session = Arc::new(InMemorySession::new("s"));
+                let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+                let (_, result) = collect(workflow.run_streamed("x".into(), config)).await;
+                assert_one_exchange(&session, &result).await;
+            }
+        }
+    }
+
+    mod managed {
+        use super::*;
+        use crate::chat::ChatResponse;
+        use crate::message::{Message, ToolCall};
+
+        #[tokio::test]
+        async fn workflow_runs_as_managed_agent() {
+            let pipeline = SequentialWorkflow::new("pipeline")
+                .description("Two-stage pipeline")
+                .step(echo_agent("a"))
+                .step(echo_agent("b"));
+
+            // The parent delegates once, then answers with the tool result.
+            let parent_provider =
+                Arc::new(ScriptedProvider::new(|req| match req.messages.last() {
+                    Some(m) if m.role == crate::message::Role::Tool => {
+                        ChatResponse::from_text(format!("done: {}", m.text().unwrap_or_default()))
+                    }
+                    _ => {
+                        ChatResponse::new(Message::assistant_tool_calls(vec![ToolCall::function(
+                            "call_1",
+                            "pipeline",
+                            r#"{"task":"x"}"#,
+                        )]))
+                    }
+                }));
+            let parent = Agent::new("parent")
+                .provider(parent_provider)
+                .managed_workflow(pipeline);
+
+            assert!(parent.has_managed_agents());
+            assert_eq!(parent.total_tool_count(), 1);
+
+            let result = parent.run("go", RunConfig::default()).await.unwrap();
+            assert_eq!(result.text(), Some(r#"done: "b(a(x))""#));
+            // Parent: 2 LLM calls; pipeline: 2 LLM calls.
+            assert_eq!(result.usage.total_tokens, 60);
+        }
+    }
+}
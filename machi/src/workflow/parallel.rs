@@ -0,0 +1,379 @@
+//! Fan-out / fan-in execution.
+
+use std::fmt::{self, Write as _};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+
+use super::{
+    Combined, Forwarder, SharedWorkflow, Workflow, input_text, output_text, run_detached,
+    stream_detached,
+};
+use crate::agent::{AgentError, RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+/// The output of one branch of a [`ParallelWorkflow`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchOutput {
+    /// Name of the branch workflow.
+    pub name: String,
+    /// The branch's final output.
+    pub output: Value,
+}
+
+type MergeFn = dyn Fn(&[BranchOutput]) -> Value + Send + Sync;
+
+/// How branch outputs are combined into the final output.
+#[derive(Clone)]
+enum Merge {
+    /// A JSON object mapping branch names to outputs.
+    Object,
+    /// A user-supplied function.
+    Function(Arc<MergeFn>),
+    /// An aggregator workflow that receives all branch outputs as its input.
+    Aggregator(SharedWorkflow),
+}
+
+/// Runs all branches concurrently on the same input, then merges the results.
+///
+/// By default the final output is a JSON object keyed by branch name. Use
+/// [`merge`](Self::merge) for a custom function or
+/// [`aggregator`](Self::aggregator) to let an agent synthesize the results.
+#[derive(Clone)]
+pub struct ParallelWorkflow {
+    name: String,
+    description: String,
+    branches: Vec<SharedWorkflow>,
+    merge: Merge,
+}
+
+impl fmt::Debug for ParallelWorkflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let merge = match &self.merge {
+            Merge::Object => "object".to_owned(),
+            Merge::Function(_) => "<function>".to_owned(),
+            Merge::Aggregator(w) => w.name().to_owned(),
+        };
+        f.debug_struct("ParallelWorkflow")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field(
+                "branches",
+                &self.branches.iter().map(|b| b.name()).collect::<Vec<_>>(),
+            )
+            .field("merge", &merge)
+            .finish()
+    }
+}
+
+impl ParallelWorkflow {
+    /// Create a workflow with no branches.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            branches: Vec::new(),
+            merge: Merge::Object,
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Add a branch.
+    #[must_use]
+    pub fn branch(mut self, branch: impl Workflow + 'static) -> Self {
+        self.branches.push(Arc::new(branch));
+        self
+    }
+
+    /// Add an already shared branch.
+    #[must_use]
+    pub fn shared_branch(mut self, branch: SharedWorkflow) -> Self {
+        self.branches.push(branch);
+        self
+    }
+
+    /// Merge branch outputs with a function.
+    #[must_use]
+    pub fn merge<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&[BranchOutput]) -> Value + Send + Sync + 'static,
+    {
+        self.merge = Merge::Function(Arc::new(f));
+        self
+    }
+
+    /// Merge branch outputs by running an aggregator workflow.
+    ///
+    /// The aggregator receives the original task followed by each branch's
+    /// output under a `## <branch name>` heading.
+    #[must_use]
+    pub fn aggregator(mut self, aggregator: impl Workflow + 'static) -> Self {
+        self.merge = Merge::Aggregator(Arc::new(aggregator));
+        self
+    }
+
+    /// Returns the number of branches.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Returns `true` if the workflow has no branches.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    fn ensure_branches(&self) -> Result<()> {
+        if self.branches.is_empty() {
+            return Err(
+                AgentError::runtime(format!("workflow '{}' has no branches", self.name)).into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Fold branch results into `combined`, preserving branch order.
+    fn collect_outputs(
+        &self,
+        combined: &mut Combined,
+        results: Vec<RunResult>,
+    ) -> Vec<BranchOutput> {
+        self.branches
+            .iter()
+            .zip(results)
+            .map(|(branch, result)| BranchOutput {
+                name: branch.name().to_owned(),
+                output: combined.absorb(result).0,
+            })
+            .collect()
+    }
+
+    /// Apply a non-aggregator merge.
+    fn merge_outputs(&self, outputs: &[BranchOutput]) -> Value {
+        match &self.merge {
+            Merge::Function(f) => f(outputs),
+            Merge::Object | Merge::Aggregator(_) => Value::Object(
+                outputs
+                    .iter()
+                    .map(|o| (o.name.clone(), o.output.clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Render the aggregator's input.
+    fn aggregator_input(task: &UserInput, outputs: &[BranchOutput]) -> UserInput {
+        let mut prompt = format!(
+            "Task:\n{}\n\nResults from parallel agents:\n",
+            input_text(task)
+        );
+        for o in outputs {
+            let _ = write!(prompt, "\n## {}\n{}\n", o.name, output_text(&o.output));
+        }
+        UserInput::text(prompt)
+    }
+
+    async fn run_inner(&self, input: UserInput, config: RunConfig) -> Result<RunResult> {
+        self.ensure_branches()?;
+        let results = futures::future::try_join_all(
+            self.branches
+                .iter()
+                .map(|b| b.run(input.clone(), config.clone())),
+        )
+        .await?;
+
+        let mut combined = Combined::default();
+        let outputs = self.collect_outputs(&mut combined, results);
+
+        if let Merge::Aggregator(ref aggregator) = self.merge {
+            let result = aggregator
+                .run(Self::aggregator_input(&input, &outputs), config)
+                .await?;
+            let (output, agent_name) = combined.absorb(result);
+            return Ok(combined.finish(output, agent_name));
+        }
+        let output = self.merge_outputs(&outputs);
+        Ok(combined.finish(output, self.name.clone()))
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            self.ensure_branches()?;
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            // Interleave branch events as they arrive, tagged by branch index.
+            let mut merged = futures::stream::select_all(
+                self.branches.iter().enumerate().map(|(i, b)| {
+                    b.run_streamed(input.clone(), config.clone())
+                        .map(move |event| (i, event))
+                        .boxed()
+                }),
+            );
+            let mut slots: Vec<Option<RunResult>> =
+                std::iter::repeat_with(|| None).take(self.branches.len()).collect();
+            while let Some((i, event)) = merged.next().await {
+                match event? {
+                    RunEvent::RunCompleted { result } => slots[i] = Some(*result),
+                    other => yield other,
+                }
+            }
+            drop(merged);
+            let results = slots
+                .into_iter()
+                .zip(&self.branches)
+                .map(|(slot, branch)| {
+                    slot.ok_or_else(|| {
+                        AgentError::runtime(format!(
+                            "workflow '{}': branch '{}' ended without a result",
+                            self.name,
+                            branch.name()
+                        ))
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            let mut combined = Combined::default();
+            let outputs = self.collect_outputs(&mut combined, results);
+
+            let result = if let Merge::Aggregator(ref aggregator) = self.merge {
+                let agg_input = Self::aggregator_input(&input, &outputs);
+                let mut inner = Forwarder::new(aggregator.run_streamed(agg_input, config.clone()));
+                while let Some(event) = inner.next().await? {
+                    yield event;
+                }
+                let (output, agent_name) = combined.absorb(inner.finish(&self.name)?);
+                combined.finish(output, agent_name)
+            } else {
+                let output = self.merge_outputs(&outputs);
+                combined.finish(output, self.name.clone())
+            };
+
+            yield RunEvent::RunCompleted { result: Box::new(result) };
+        }
+    }
+}
+
+impl Workflow for ParallelWorkflow {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(run_detached(input, config, async |input, config| {
+            self.run_inner(input, config).await
+        }))
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::{collect, echo_agent, started};
+    use super::*;
+
+    fn fan_out() -> ParallelWorkflow {
+        ParallelWorkflow::new("fan")
+            .branch(echo_agent("a"))
+            .branch(echo_agent("b"))
+    }
+
+    #[tokio::test]
+    async fn default_merge_is_object_keyed_by_branch() {
+        let result = fan_out()
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.output, serde_json::json!({"a": "a(x)", "b": "b(x)"}));
+        assert_eq!(result.agent_name, "fan");
+        assert_eq!(result.usage.total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn merge_function_sees_branches_in_order() {
+        let workflow = fan_out().merge(|outputs| {
+            Value::from(
+                outputs
+                    .iter()
+                    .map(|o| output_text(&o.output))
+                    .collect::<Vec<_>>()
+                    .join("+"),
+            )
+        });
+        let result = workflow
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("a(x)+b(x)"));
+    }
+
+    #[tokio::test]
+    async fn aggregator_receives_all_outputs() {
+        let workflow = fan_out().aggregator(echo_agent("agg"));
+        let result = workflow
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        let text = result.text().unwrap();
+        assert!(text.starts_with("agg(Task:\nx"), "{text}");
+        assert!(text.contains("## a\na(x)"), "{text}");
+        assert!(text.contains("## b\nb(x)"), "{text}");
+        assert_eq!(result.agent_name, "agg");
+        assert_eq!(result.usage.total_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn streamed_run_covers_every_branch() {
+        let workflow = fan_out().aggregator(echo_agent("agg"));
+        let (events, result) =
+            collect(workflow.run_streamed("x".into(), RunConfig::default())).await;
+        let mut names = started(&events);
+        assert_eq!(names.first().map(String::as_str), Some("fan"));
+        assert_eq!(names.last().map(String::as_str), Some("agg"));
+        names.sort();
+        assert_eq!(names, ["a", "agg", "b", "fan"]);
+        assert_eq!(result.usage.total_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn empty_workflow_is_an_error() {
+        let err = ParallelWorkflow::new("none")
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no branches"));
+    }
+}
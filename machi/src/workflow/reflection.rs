@@ -0,0 +1,497 @@
+//! Evaluator–optimizer (self-critique) loop.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    Combined, Forwarder, Workflow, detach_session, input_text, output_text, save_exchange,
+    stream_detached,
+};
+use crate::agent::{Agent, AgentError, OutputSchema, RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+/// An evaluator's verdict on one candidate output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Whether the candidate is acceptable.
+    pub passed: bool,
+    /// Critique and concrete suggestions for improvement.
+    #[serde(default)]
+    pub feedback: String,
+}
+
+impl Evaluation {
+    /// The structured output schema imposed on evaluator agents.
+    #[must_use]
+    pub fn output_schema() -> OutputSchema {
+        OutputSchema::new(
+            "evaluation",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "passed": {
+                        "type": "boolean",
+                        "description": "Whether the output fully satisfies the task."
+                    },
+                    "feedback": {
+                        "type": "string",
+                        "description": "Critique and concrete suggestions for improvement."
+                    }
+                },
+                "required": ["passed", "feedback"],
+                "additionalProperties": false
+            }),
+        )
+    }
+
+    /// Parse an evaluator's output, accepting JSON objects or JSON text.
+    fn from_output(evaluator: &str, output: &Value) -> Result<Self> {
+        let parsed = match output {
+            Value::String(text) => serde_json::from_str(text),
+            other => serde_json::from_value(other.clone()),
+        };
+        parsed.map_err(|e| {
+            AgentError::runtime(format!(
+                "evaluator '{evaluator}' returned an invalid evaluation: {e}"
+            ))
+            .into()
+        })
+    }
+}
+
+/// One generate-and-evaluate round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iteration {
+    /// Iteration number (1-indexed).
+    pub iteration: usize,
+    /// The generator's candidate output.
+    pub output: Value,
+    /// The evaluator's verdict on it.
+    pub evaluation: Evaluation,
+}
+
+/// Outcome of an [`EvaluatorOptimizer`] run.
+#[derive(Debug)]
+pub struct OptimizationResult {
+    /// Combined result: the last candidate as output, with the usage and
+    /// step history of every generator and evaluator run.
+    pub result: RunResult,
+    /// Every round, in order.
+    pub iterations: Vec<Iteration>,
+    /// Whether the last candidate passed evaluation (`false` if the
+    /// iteration limit was reached first).
+    pub passed: bool,
+}
+
+/// Re-runs a generator with evaluator feedback until the output passes.
+///
+/// The generator answers the task; the evaluator — constrained to the
+/// [`Evaluation`] schema unless it already has an output schema — judges the
+/// candidate against the task. On failure the generator is re-run with its
+/// previous attempt and the feedback, up to
+/// [`max_iterations`](Self::max_iterations) rounds.
+///
+/// Where an output guardrail can only trip, this turns the critique into a
+/// feedback loop. Use [`optimize`](Self::optimize) to inspect every round, or
+/// [`Workflow::run`] to get just the combined [`RunResult`].
+///
+/// # Examples
+///
+/// ```rust
+/// use machi::agent::Agent;
+/// use machi::workflow::{EvaluatorOptimizer, Workflow};
+///
+/// let drafting = EvaluatorOptimizer::new(
+///     "drafting",
+///     Agent::new("writer").instructions("Write a product announcement."),
+///     Agent::new("editor").instructions("You are a demanding editor."),
+/// )
+/// .criteria("Under 100 words, mentions the release date.")
+/// .max_iterations(4);
+///
+/// assert_eq!(drafting.name(), "drafting");
+/// ```
+#[derive(Clone)]
+pub struct EvaluatorOptimizer {
+    name: String,
+    description: String,
+    generator: Arc<Agent>,
+    evaluator: Arc<Agent>,
+    criteria: Option<String>,
+    max_iterations: usize,
+}
+
+impl fmt::Debug for EvaluatorOptimizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EvaluatorOptimizer")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("generator", &self.generator.name)
+            .field("evaluator", &self.evaluator.name)
+            .field("criteria", &self.criteria)
+            .field("max_iterations", &self.max_iterations)
+            .finish()
+    }
+}
+
+impl EvaluatorOptimizer {
+    /// Default iteration limit.
+    pub const DEFAULT_MAX_ITERATIONS: usize = 3;
+
+    /// Create a loop between `generator` and `evaluator`.
+    #[must_use]
+    pub fn new(name: impl Into<String>, generator: Agent, evaluator: Agent) -> Self {
+        let evaluator = if evaluator.output_schema.is_some() {
+            evaluator
+        } else {
+            evaluator.output_schema(Evaluation::output_schema())
+        };
+        Self {
+            name: name.into(),
+            description: String::new(),
+            generator: Arc::new(generator),
+            evaluator: Arc::new(evaluator),
+            criteria: None,
+            max_iterations: Self::DEFAULT_MAX_ITERATIONS,
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Acceptance criteria shown to the evaluator alongside the task.
+    #[must_use]
+    pub fn criteria(mut self, criteria: impl Into<String>) -> Self {
+        self.criteria = Some(criteria.into());
+        self
+    }
+
+    /// Set the maximum number of generate-and-evaluate rounds (at least one).
+    #[must_use]
+    pub const fn max_iterations(mut self, max: usize) -> Self {
+        self.max_iterations = max;
+        self
+    }
+
+    /// Run the loop, returning every round alongside the combined result.
+    ///
+    /// A session in `config` receives only the task and the final output;
+    /// attempts and critiques stay out of it.
+    ///
+    /// # Errors
+    ///
+    /// Propagates generator and evaluator failures, and fails if the
+    /// evaluator's output does not match the [`Evaluation`] schema.
+    pub async fn optimize(
+        &self,
+        input: impl Into<UserInput>,
+        config: RunConfig,
+    ) -> Result<OptimizationResult> {
+        let input = input.into();
+        let (config, session) = detach_session(config);
+        let done = self.optimize_detached(input.clone(), config).await?;
+        save_exchange(session.as_ref(), input, &done.result.output).await;
+        Ok(done)
+    }
+
+    async fn optimize_detached(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Result<OptimizationResult> {
+        let task = input_text(&input);
+        let mut round = Round::new(input);
+        loop {
+            let candidate = self
+                .generator
+                .run(round.next_input(&task), config.clone())
+                .await?;
+            if let Some(done) = self.evaluate(&task, candidate, &config, &mut round).await? {
+                return Ok(done);
+            }
+        }
+    }
+
+    /// Evaluate a candidate, recording the round; `Some` once the loop ends.
+    async fn evaluate(
+        &self,
+        task: &str,
+        candidate: RunResult,
+        config: &RunConfig,
+        round: &mut Round,
+    ) -> Result<Option<OptimizationResult>> {
+        let review = self
+            .evaluator
+            .run(
+                self.evaluator_input(task, &candidate.output),
+                config.clone(),
+            )
+            .await?;
+        let evaluation = Evaluation::from_output(&self.evaluator.name, &review.output)?;
+        round.combined.absorb(review);
+        let (output, agent_name) = round.combined.absorb(candidate);
+
+        let passed = evaluation.passed;
+        round.iterations.push(Iteration {
+            iteration: round.iterations.len() + 1,
+            output: output.clone(),
+            evaluation,
+        });
+        if passed || round.iterations.len() >= self.max_iterations {
+            let round = std::mem::take(round);
+            return Ok(Some(OptimizationResult {
+                result: round.combined.finish(output, agent_name),
+                iterations: round.iterations,
+                passed,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Prompt sent to the evaluator.
+    fn evaluator_input(&self, task: &str, output: &Value) -> UserInput {
+        let criteria = self
+            .criteria
+            .as_ref()
+            .map(|c| format!("Criteria:\n{c}\n\n"))
+            .unwrap_or_default();
+        UserInput::text(format!(
+            "Evaluate the candidate output for the task below.\n\n\
+             Task:\n{task}\n\n{criteria}Candidate output:\n{}",
+            output_text(output)
+        ))
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            // Generator output streams; evaluations are internal and run to completion.
+            let task = input_text(&input);
+            let mut round = Round::new(input);
+            let done = loop {
+                let mut inner = Forwarder::new(
+                    Workflow::run_streamed(&*self.generator, round.next_input(&task), config.clone()),
+                );
+                while let Some(event) = inner.next().await? {
+                    yield event;
+                }
+                let candidate = inner.finish(&self.name)?;
+                if let Some(done) = self.evaluate(&task, candidate, &config, &mut round).await? {
+                    break done;
+                }
+            };
+
+            yield RunEvent::RunCompleted { result: Box::new(done.result) };
+        }
+    }
+}
+
+/// Loop state shared by the blocking and streaming paths.
+#[derive(Default)]
+struct Round {
+    first_input: Option<UserInput>,
+    combined: Combined,
+    iterations: Vec<Iteration>,
+}
+
+impl Round {
+    fn new(input: UserInput) -> Self {
+        Self {
+            first_input: Some(input),
+            ..Self::default()
+        }
+    }
+
+    /// The original input first, then the task with the last attempt and feedback.
+    fn next_input(&mut self, task: &str) -> UserInput {
+        if let Some(input) = self.first_input.take() {
+            return input;
+        }
+        let last = self.iterations.last();
+        UserInput::text(format!(
+            "{task}\n\nPrevious attempt:\n{}\n\nEvaluator feedback:\n{}\n\n\
+             Revise the attempt to address the feedback.",
+            last.map(|i| output_text(&i.output)).unwrap_or_default(),
+            last.map(|i| i.evaluation.feedback.as_str())
+                .unwrap_or_default(),
+        ))
+    }
+}
+
+impl Workflow for EvaluatorOptimizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(async move { Ok(self.optimize(input, config).await?.result) })
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::{collect, started};
+    use super::*;
+    use crate::chat::ResponseFormat;
+    use crate::test_util::ScriptedProvider;
+
+    /// Writer that adds detail once it has been told to.
+    fn writer() -> Agent {
+        Agent::new("writer").provider(ScriptedProvider::reply(|input| {
+            if input.contains("Evaluator feedback:\nadd detail") {
+                "detailed draft".to_owned()
+            } else {
+                "draft".to_owned()
+            }
+        }))
+    }
+
+    /// Editor that only accepts detailed drafts, asserting it gets the schema.
+    fn editor() -> Agent {
+        let provider = Arc::new(ScriptedProvider::new(|req| {
+            assert!(matches!(
+                req.response_format,
+                Some(ResponseFormat::JsonSchema { .. })
+            ));
+            let prompt = crate::test_util::last_user_text(req);
+            let verdict = if prompt.ends_with("Candidate output:\ndetailed draft") {
+                r#"{"passed": true, "feedback": ""}"#
+            } else {
+                r#"{"passed": false, "feedback": "add detail"}"#
+            };
+            crate::chat::ChatResponse::from_text(verdict)
+        }));
+        Agent::new("editor").provider(provider)
+    }
+
+    #[tokio::test]
+    async fn reruns_generator_with_feedback_until_pass() {
+        let workflow = EvaluatorOptimizer::new("drafting", writer(), editor());
+        let outcome = workflow
+            .optimize("write", RunConfig::default())
+            .await
+            .unwrap();
+
+        assert!(outcome.passed);
+        assert_eq!(outcome.iterations.len(), 2);
+        assert_eq!(outcome.iterations[0].output, "draft");
+        assert_eq!(outcome.iterations[0].evaluation.feedback, "add detail");
+        assert!(outcome.iterations[1].evaluation.passed);
+        assert_eq!(outcome.result.text(), Some("detailed draft"));
+        assert_eq!(outcome.result.agent_name, "writer");
+        // Two generator runs plus two evaluations share one total.
+        assert_eq!(outcome.result.usage.total_tokens, 60);
+        assert_eq!(outcome.result.steps, 4);
+    }
+
+    #[tokio::test]
+    async fn session_gets_only_task_and_final_output() {
+        use crate::memory::{InMemorySession, Session};
+
+        let workflow = EvaluatorOptimizer::new("drafting", writer(), editor());
+        let session = Arc::new(InMemorySession::new("s"));
+        let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+        workflow.optimize("write", config).await.unwrap();
+
+        let config = RunConfig::new().session(Arc::<InMemorySession>::clone(&session));
+        collect(workflow.run_streamed("write".into(), config)).await;
+
+        let texts: Vec<String> = session
+            .get_messages(None)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(crate::message::Message::text)
+            .collect();
+        assert_eq!(
+            texts,
+            ["write", "detailed draft", "write", "detailed draft"]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_iteration_limit_without_passing() {
+        let stubborn =
+            Agent::new("stubborn").provider(ScriptedProvider::reply(|_| "draft".to_owned()));
+        let workflow = EvaluatorOptimizer::new("drafting", stubborn, editor()).max_iterations(2);
+        let outcome = workflow
+            .optimize("write", RunConfig::default())
+            .await
+            .unwrap();
+        assert!(!outcome.passed);
+        assert_eq!(outcome.iterations.len(), 2);
+        assert_eq!(outcome.result.text(), Some("draft"));
+    }
+
+    #[tokio::test]
+    async fn criteria_are_shown_to_evaluator() {
+        let evaluator = Agent::new("judge").provider(ScriptedProvider::reply(|prompt| {
+            assert!(prompt.contains("Criteria:\nbe brief\n\nCandidate output:\ndraft"));
+            r#"{"passed": true, "feedback": "ok"}"#.to_owned()
+        }));
+        let workflow = EvaluatorOptimizer::new("d", writer(), evaluator).criteria("be brief");
+        let outcome = workflow
+            .optimize("write", RunConfig::default())
+            .await
+            .unwrap();
+        assert!(outcome.passed);
+        assert_eq!(outcome.iterations.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_evaluation_is_an_error() {
+        let evaluator =
+            Agent::new("judge").provider(ScriptedProvider::reply(|_| "looks fine".to_owned()));
+        let workflow = EvaluatorOptimizer::new("d", writer(), evaluator);
+        let err = workflow
+            .optimize("write", RunConfig::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid evaluation"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn streamed_run_forwards_generator_only() {
+        let workflow = EvaluatorOptimizer::new("drafting", writer(), editor());
+        let (events, result) =
+            collect(workflow.run_streamed("write".into(), RunConfig::default())).await;
+        assert_eq!(started(&events), ["drafting", "writer", "writer"]);
+        assert_eq!(result.text(), Some("detailed draft"));
+        assert_eq!(result.usage.total_tokens, 60);
+    }
+}
@@ -0,0 +1,359 @@
+//! Input routing.
+
+use std::fmt::{self, Write as _};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+
+use super::{
+    Combined, Forwarder, SharedWorkflow, Workflow, input_text, match_name, output_text,
+    run_detached, stream_detached,
+};
+use crate::agent::{AgentError, RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+type SelectFn = dyn Fn(&UserInput) -> Option<String> + Send + Sync;
+
+/// How the route is chosen.
+#[derive(Clone)]
+enum Selector {
+    /// A function returning the route name.
+    Function(Arc<SelectFn>),
+    /// A classifier workflow whose output names the route.
+    Classifier(SharedWorkflow),
+}
+
+/// Sends the input to exactly one of several routes.
+///
+/// Routes are identified by their [`name`](Workflow::name); their
+/// [`description`](Workflow::description) tells a classifier what each route
+/// handles. The route is picked either by a function ([`select`](Self::select))
+/// or by a classifier agent ([`classifier`](Self::classifier)) that is asked to
+/// reply with a route name. If no route matches, the [`fallback`](Self::fallback)
+/// runs, or the workflow fails.
+#[derive(Clone)]
+pub struct RouterWorkflow {
+    name: String,
+    description: String,
+    routes: Vec<SharedWorkflow>,
+    selector: Option<Selector>,
+    fallback: Option<SharedWorkflow>,
+}
+
+impl fmt::Debug for RouterWorkflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let selector = match &self.selector {
+            None => None,
+            Some(Selector::Function(_)) => Some("<function>".to_owned()),
+            Some(Selector::Classifier(w)) => Some(w.name().to_owned()),
+        };
+        f.debug_struct("RouterWorkflow")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field(
+                "routes",
+                &self.routes.iter().map(|r| r.name()).collect::<Vec<_>>(),
+            )
+            .field("selector", &selector)
+            .field("fallback", &self.fallback.as_ref().map(|w| w.name()))
+            .finish()
+    }
+}
+
+impl RouterWorkflow {
+    /// Create a router with no routes.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            routes: Vec::new(),
+            selector: None,
+            fallback: None,
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Add a route.
+    #[must_use]
+    pub fn route(mut self, route: impl Workflow + 'static) -> Self {
+        self.routes.push(Arc::new(route));
+        self
+    }
+
+    /// Add an already shared route.
+    #[must_use]
+    pub fn shared_route(mut self, route: SharedWorkflow) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Pick the route with a function returning its name.
+    #[must_use]
+    pub fn select<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&UserInput) -> Option<String> + Send + Sync + 'static,
+    {
+        self.selector = Some(Selector::Function(Arc::new(f)));
+        self
+    }
+
+    /// Pick the route with a classifier agent.
+    ///
+    /// The classifier receives the route names and descriptions plus the
+    /// input, and must reply with one route name.
+    #[must_use]
+    pub fn classifier(mut self, classifier: impl Workflow + 'static) -> Self {
+        self.selector = Some(Selector::Classifier(Arc::new(classifier)));
+        self
+    }
+
+    /// Route used when the selector names no known route.
+    #[must_use]
+    pub fn fallback(mut self, fallback: impl Workflow + 'static) -> Self {
+        self.fallback = Some(Arc::new(fallback));
+        self
+    }
+
+    /// Returns the number of routes (excluding the fallback).
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Returns `true` if the router has no routes.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Prompt sent to the classifier.
+    fn classifier_input(&self, input: &UserInput) -> UserInput {
+        let mut prompt = String::from(
+            "Choose the route that best handles the input below. \
+             Reply with the route name only.\n\nRoutes:\n",
+        );
+        for route in &self.routes {
+            let _ = writeln!(prompt, "- {}: {}", route.name(), route.description());
+        }
+        let _ = write!(prompt, "\nInput:\n{}", input_text(input));
+        UserInput::text(prompt)
+    }
+
+    /// Find the route named by `choice`.
+    fn find_route(&self, choice: &str) -> Option<&SharedWorkflow> {
+        let names: Vec<&str> = self.routes.iter().map(|r| r.name()).collect();
+        match_name(choice, &names).map(|i| &self.routes[i])
+    }
+
+    /// Resolve the choice to a route, or the fallback.
+    fn resolve(&self, choice: Option<&str>) -> Result<&SharedWorkflow> {
+        choice
+            .and_then(|c| self.find_route(c))
+            .or(self.fallback.as_ref())
+            .ok_or_else(|| {
+                AgentError::runtime(format!(
+                    "workflow '{}': no route matches {:?}",
+                    self.name,
+                    choice.unwrap_or_default()
+                ))
+                .into()
+            })
+    }
+
+    /// Run the selector, returning the choice and any classifier result.
+    async fn choose(
+        &self,
+        input: &UserInput,
+        config: &RunConfig,
+    ) -> Result<(Option<String>, Option<RunResult>)> {
+        if self.routes.is_empty() && self.fallback.is_none() {
+            return Err(
+                AgentError::runtime(format!("workflow '{}' has no routes", self.name)).into(),
+            );
+        }
+        match &self.selector {
+            Some(Selector::Function(f)) => Ok((f(input), None)),
+            Some(Selector::Classifier(classifier)) => {
+                let result = classifier
+                    .run(self.classifier_input(input), config.clone())
+                    .await?;
+                Ok((Some(output_text(&result.output)), Some(result)))
+            }
+            None => Err(AgentError::runtime(format!(
+                "workflow '{}' has no selector; call `select` or `classifier`",
+                self.name
+            ))
+            .into()),
+        }
+    }
+
+    async fn run_inner(&self, input: UserInput, config: RunConfig) -> Result<RunResult> {
+        let mut combined = Combined::default();
+        let (choice, classification) = self.choose(&input, &config).await?;
+        if let Some(result) = classification {
+            combined.absorb(result);
+        }
+        let route = self.resolve(choice.as_deref())?;
+        let (output, agent_name) = combined.absorb(route.run(input, config).await?);
+        Ok(combined.finish(output, agent_name))
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            // The classifier runs to completion: its reply is not user-facing.
+            let mut combined = Combined::default();
+            let (choice, classification) = self.choose(&input, &config).await?;
+            if let Some(result) = classification {
+                combined.absorb(result);
+            }
+            let route = self.resolve(choice.as_deref())?;
+
+            let mut inner = Forwarder::new(route.run_streamed(input, config.clone()));
+            while let Some(event) = inner.next().await? {
+                yield event;
+            }
+            let (output, agent_name) = combined.absorb(inner.finish(&self.name)?);
+
+            yield RunEvent::RunCompleted {
+                result: Box::new(combined.finish(output, agent_name)),
+            };
+        }
+    }
+}
+
+impl Workflow for RouterWorkflow {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(run_detached(input, config, async |input, config| {
+            self.run_inner(input, config).await
+        }))
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::{collect, echo_agent, started};
+    use super::*;
+    use crate::agent::Agent;
+    use crate::test_util::ScriptedProvider;
+
+    fn router() -> RouterWorkflow {
+        RouterWorkflow::new("router")
+            .route(echo_agent("billing"))
+            .route(echo_agent("support"))
+    }
+
+    /// Classifier that answers with a fixed reply.
+    fn classifier(reply: &'static str) -> Agent {
+        Agent::new("classifier").provider(ScriptedProvider::reply(move |_| reply.to_owned()))
+    }
+
+    #[tokio::test]
+    async fn function_selector_picks_route() {
+        let workflow = router().select(|input| {
+            input_text(input)
+                .contains("invoice")
+                .then(|| "billing".to_owned())
+        });
+        let result = workflow
+            .run("invoice missing".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("billing(invoice missing)"));
+        assert_eq!(result.usage.total_tokens, 15);
+    }
+
+    #[tokio::test]
+    async fn classifier_reply_is_matched_leniently() {
+        let workflow = router().classifier(classifier(" \"Support\". "));
+        let result = workflow
+            .run("help".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("support(help)"));
+        assert_eq!(result.agent_name, "support");
+        // Classifier usage is included.
+        assert_eq!(result.usage.total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn classifier_sees_route_descriptions() {
+        let workflow = router().classifier(Agent::new("classifier").provider(
+            ScriptedProvider::reply(|prompt| {
+                assert!(prompt.contains("- billing: The billing agent"));
+                assert!(prompt.ends_with("Input:\nq"));
+                "I'd pick billing".to_owned()
+            }),
+        ));
+        let result = workflow
+            .run("q".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("billing(q)"));
+    }
+
+    #[tokio::test]
+    async fn unknown_route_uses_fallback_or_fails() {
+        let err = router()
+            .select(|_| Some("sales".to_owned()))
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no route matches"));
+
+        let result = router()
+            .select(|_| None)
+            .fallback(echo_agent("general"))
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("general(x)"));
+    }
+
+    #[tokio::test]
+    async fn streamed_run_only_forwards_chosen_route() {
+        let workflow = router().classifier(classifier("billing"));
+        let (events, result) =
+            collect(workflow.run_streamed("x".into(), RunConfig::default())).await;
+        assert_eq!(started(&events), ["router", "billing"]);
+        assert_eq!(result.text(), Some("billing(x)"));
+    }
+}
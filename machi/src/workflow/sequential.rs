@@ -0,0 +1,219 @@
+//! Sequential pipelines.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+
+use super::{
+    Combined, Forwarder, SharedWorkflow, Workflow, output_text, run_detached, stream_detached,
+};
+use crate::agent::{AgentError, RunConfig, RunEvent, RunResult, UserInput};
+use crate::error::Result;
+
+/// Runs steps one after another, feeding each step's output to the next.
+///
+/// The first step receives the workflow input; every later step receives the
+/// previous step's output (rendered as text). The final result carries the
+/// last step's output and the usage of all steps.
+#[derive(Clone)]
+pub struct SequentialWorkflow {
+    name: String,
+    description: String,
+    steps: Vec<SharedWorkflow>,
+}
+
+impl fmt::Debug for SequentialWorkflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SequentialWorkflow")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field(
+                "steps",
+                &self.steps.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl SequentialWorkflow {
+    /// Create an empty pipeline.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Set the description (used when this workflow is a managed agent).
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Append a step.
+    #[must_use]
+    pub fn step(mut self, step: impl Workflow + 'static) -> Self {
+        self.steps.push(Arc::new(step));
+        self
+    }
+
+    /// Append an already shared step.
+    #[must_use]
+    pub fn shared_step(mut self, step: SharedWorkflow) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Returns the number of steps.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns `true` if the pipeline has no steps.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    fn ensure_steps(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            return Err(
+                AgentError::runtime(format!("workflow '{}' has no steps", self.name)).into(),
+            );
+        }
+        Ok(())
+    }
+
+    async fn run_inner(&self, input: UserInput, config: RunConfig) -> Result<RunResult> {
+        self.ensure_steps()?;
+        let mut combined = Combined::default();
+        let mut input = input;
+        let mut last = (serde_json::Value::Null, String::new());
+        for step in &self.steps {
+            let result = step.run(input, config.clone()).await?;
+            last = combined.absorb(result);
+            input = UserInput::text(output_text(&last.0));
+        }
+        Ok(combined.finish(last.0, last.1))
+    }
+
+    // `tail_expr_drop_order`: false positive from the `try_stream!` macro.
+    #[allow(tail_expr_drop_order)]
+    fn run_streamed_inner(
+        &self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> impl Stream<Item = Result<RunEvent>> + Send + '_ {
+        async_stream::try_stream! {
+            self.ensure_steps()?;
+            yield RunEvent::RunStarted { agent_name: self.name.clone() };
+
+            let mut combined = Combined::default();
+            let mut input = input;
+            let mut last = (serde_json::Value::Null, String::new());
+            for step in &self.steps {
+                let mut inner = Forwarder::new(step.run_streamed(input, config.clone()));
+                while let Some(event) = inner.next().await? {
+                    yield event;
+                }
+                last = combined.absorb(inner.finish(&self.name)?);
+                input = UserInput::text(output_text(&last.0));
+            }
+
+            yield RunEvent::RunCompleted {
+                result: Box::new(combined.finish(last.0, last.1)),
+            };
+        }
+    }
+}
+
+impl Workflow for SequentialWorkflow {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn run<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Future<Output = Result<RunResult>> + Send + 'a>> {
+        Box::pin(run_detached(input, config, async |input, config| {
+            self.run_inner(input, config).await
+        }))
+    }
+
+    fn run_streamed<'a>(
+        &'a self,
+        input: UserInput,
+        config: RunConfig,
+    ) -> Pin<Box<dyn Stream<Item = Result<RunEvent>> + Send + 'a>> {
+        stream_detached(input, config, |input, config| {
+            Box::pin(self.run_streamed_inner(input, config))
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+    use super::super::tests::{collect, echo_agent, started};
+    use super::*;
+
+    fn pipeline() -> SequentialWorkflow {
+        SequentialWorkflow::new("pipeline")
+            .step(echo_agent("a"))
+            .step(echo_agent("b"))
+            .step(echo_agent("c"))
+    }
+
+    #[tokio::test]
+    async fn output_feeds_next_step() {
+        let result = pipeline()
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.text(), Some("c(b(a(x)))"));
+        assert_eq!(result.agent_name, "c");
+        assert_eq!(result.steps, 3);
+        assert_eq!(result.usage.total_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn streamed_run_matches_blocking_run() {
+        let workflow = pipeline();
+        let (events, result) =
+            collect(workflow.run_streamed("x".into(), RunConfig::default())).await;
+        assert_eq!(started(&events), ["pipeline", "a", "b", "c"]);
+        assert_eq!(result.text(), Some("c(b(a(x)))"));
+        assert_eq!(result.usage.total_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn empty_pipeline_is_an_error() {
+        let err = SequentialWorkflow::new("empty")
+            .run("x".into(), RunConfig::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no steps"));
+    }
+
+    #[tokio::test]
+    async fn nested_workflows_compose() {
+        let outer = SequentialWorkflow::new("outer")
+            .step(pipeline())
+            .step(echo_agent("d"));
+        let result = outer.run("x".into(), RunConfig::default()).await.unwrap();
+        assert_eq!(result.text(), Some("d(c(b(a(x))))"));
+        assert_eq!(result.steps, 4);
+    }
+}